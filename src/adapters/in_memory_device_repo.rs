@@ -6,7 +6,7 @@ use anyhow::Result;
 use uuid::Uuid;
 
 use crate::domain::Device;
-use crate::repository::DeviceRepository;
+use crate::repository::{board_id_conflict, bump_version, DeviceRepository};
 
 /// In-memory implementation of DeviceRepository using a thread-safe HashMap.
 #[derive(Clone, Default)]
@@ -26,9 +26,13 @@ impl InMemoryDeviceRepository {
 
 #[async_trait::async_trait]
 impl DeviceRepository for InMemoryDeviceRepository {
-    /// Stores a Device in the in-memory map.
+    /// Stores a Device in the in-memory map, checking for a taken `board_id` under the same
+    /// write lock.
     async fn create(&self, device: Device) -> Result<Device> {
         let mut w = self.store.write().await;
+        if w.values().any(|d| d.board_id == device.board_id) {
+            return Err(board_id_conflict(&device.board_id));
+        }
         w.insert(device.id, device.clone());
         Ok(device)
     }
@@ -39,11 +43,76 @@ impl DeviceRepository for InMemoryDeviceRepository {
         Ok(r.get(&id).cloned())
     }
 
+    /// Checks the map's keys under a read lock.
+    async fn exists(&self, id: Uuid) -> Result<bool> {
+        Ok(self.store.read().await.contains_key(&id))
+    }
+
+    /// Looks up every requested ID under a single read lock, preserving request order.
+    async fn find_by_ids(&self, ids: &[Uuid]) -> Result<Vec<Device>> {
+        let r = self.store.read().await;
+        Ok(ids.iter().filter_map(|id| r.get(id).cloned()).collect())
+    }
+
+    /// Scans the map for a Device with a matching `board_id`.
+    async fn find_by_board_id(&self, board_id: &str) -> Result<Option<Device>> {
+        let r = self.store.read().await;
+        Ok(r.values().find(|d| d.board_id == board_id).cloned())
+    }
+
     /// Returns all stored Devices as a vector.
     async fn list(&self) -> Result<Vec<Device>> {
         let r = self.store.read().await;
         Ok(r.values().cloned().collect())
     }
+
+    /// Returns the Devices matching `query` per `Device::matches_text`.
+    async fn search_text(&self, query: &str) -> Result<Vec<Device>> {
+        let query = query.to_lowercase();
+        let r = self.store.read().await;
+        Ok(r.values()
+            .filter(|d| d.matches_text(&query))
+            .cloned()
+            .collect())
+    }
+
+    /// Returns the Devices whose tags contain `tag`.
+    async fn find_by_tag(&self, tag: &str) -> Result<Vec<Device>> {
+        let r = self.store.read().await;
+        Ok(r.values()
+            .filter(|d| d.tags.iter().any(|t| t == tag))
+            .cloned()
+            .collect())
+    }
+
+    /// Returns the Devices at `location`.
+    async fn find_by_location(&self, location: &str) -> Result<Vec<Device>> {
+        let r = self.store.read().await;
+        Ok(r.values().filter(|d| d.is_at(location)).cloned().collect())
+    }
+
+    /// Overwrites an existing Device in the map.
+    async fn update(&self, device: Device) -> Result<Option<Device>> {
+        let mut w = self.store.write().await;
+        match w.get_mut(&device.id) {
+            Some(existing) => {
+                *existing = bump_version(existing, device)?;
+                Ok(Some(existing.clone()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Flips the `archived` flag of a stored Device in place.
+    async fn set_archived(&self, id: Uuid, archived: bool) -> Result<Option<Device>> {
+        let mut w = self.store.write().await;
+        Ok(w.get_mut(&id).map(|device| {
+            device.archived = archived;
+            device.version += 1;
+            device.updated_at = Some(chrono::Utc::now());
+            device.clone()
+        }))
+    }
 }
 
 #[cfg(test)]
@@ -63,4 +132,41 @@ mod test {
         let list = block_on(repo.list()).unwrap();
         assert_eq!(list.len(), 1);
     }
+
+    /// Test that the in-memory store is always ready.
+    #[test]
+    fn is_always_ready() {
+        block_on(InMemoryDeviceRepository::new().ready()).unwrap();
+    }
+
+    /// Test that a batch lookup returns the stored Devices in request order, skipping unknown IDs.
+    #[test]
+    fn find_by_ids_skips_missing() {
+        let repo = InMemoryDeviceRepository::new();
+        let device = |name: &str| Device {
+            board_id: format!("{}-board", name),
+            ..Device::new(name)
+        };
+        let a = block_on(repo.create(device("a"))).unwrap();
+        let b = block_on(repo.create(device("b"))).unwrap();
+        let batch = block_on(repo.find_by_ids(&[b.id, Uuid::new_v4(), a.id])).unwrap();
+        assert_eq!(batch, vec![b, a]);
+    }
+
+    /// Test that a second Device with a registered board_id is rejected and not stored.
+    #[test]
+    fn create_rejects_taken_board_id() {
+        let repo = InMemoryDeviceRepository::new();
+        let device = |name| Device {
+            board_id: "bench-1".to_string(),
+            ..Device::new(name)
+        };
+        block_on(repo.create(device("d1"))).unwrap();
+        let err = block_on(repo.create(device("d2"))).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::service::ServiceError>(),
+            Some(crate::service::ServiceError::Conflict(_))
+        ));
+        assert_eq!(block_on(repo.list()).unwrap().len(), 1);
+    }
 }
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::domain::Device;
+use crate::repository::{board_id_conflict, bump_version, DeviceRepository};
+
+/// DeviceRepository persisted to a single JSON file. All devices are loaded on construction and
+/// the whole collection is rewritten on every mutation, via a temp file renamed over the original
+/// so a crash mid-write never leaves a truncated file behind.
+pub struct JsonFileDeviceRepository {
+    path: PathBuf,
+    store: Mutex<HashMap<Uuid, Device>>,
+}
+
+impl JsonFileDeviceRepository {
+    /// Loads the devices stored at `path`. A missing file starts an empty repository;
+    /// a file that can't be read or parsed is an error.
+    pub async fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let devices: Vec<Device> = match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| anyhow!("Device file {} is corrupt: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+        };
+        Ok(Self {
+            path,
+            store: Mutex::new(devices.into_iter().map(|d| (d.id, d)).collect()),
+        })
+    }
+
+    /// Writes `devices` to a sibling temp file and renames it over the repository file.
+    async fn save(&self, devices: &HashMap<Uuid, Device>) -> Result<()> {
+        let mut sorted: Vec<&Device> = devices.values().collect();
+        sorted.sort_by_key(|d| d.id);
+        let json = serde_json::to_vec_pretty(&sorted)?;
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        tokio::fs::write(&tmp, json)
+            .await
+            .map_err(|e| anyhow!("Failed to write device file: {}", e))?;
+        tokio::fs::rename(&tmp, &self.path)
+            .await
+            .map_err(|e| anyhow!("Failed to replace device file: {}", e))?;
+        Ok(())
+    }
+
+    /// Applies `change` to a copy of the collection and persists it; memory is only updated
+    /// once the file has been written.
+    async fn mutate<T>(&self, change: impl FnOnce(&mut HashMap<Uuid, Device>) -> T) -> Result<T> {
+        let mut store = self.store.lock().await;
+        let mut next = store.clone();
+        let result = change(&mut next);
+        self.save(&next).await?;
+        *store = next;
+        Ok(result)
+    }
+}
+
+#[async_trait::async_trait]
+impl DeviceRepository for JsonFileDeviceRepository {
+    /// Adds a Device and rewrites the file, checking for a taken `board_id` under the same lock.
+    async fn create(&self, device: Device) -> Result<Device> {
+        self.mutate(|devices| {
+            if devices.values().any(|d| d.board_id == device.board_id) {
+                return Err(board_id_conflict(&device.board_id));
+            }
+            devices.insert(device.id, device.clone());
+            Ok(device)
+        })
+        .await?
+    }
+
+    /// Looks up a Device by ID in the loaded collection.
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<Device>> {
+        Ok(self.store.lock().await.get(&id).cloned())
+    }
+
+    /// Checks the loaded collection's keys.
+    async fn exists(&self, id: Uuid) -> Result<bool> {
+        Ok(self.store.lock().await.contains_key(&id))
+    }
+
+    /// Looks up every requested ID, preserving request order.
+    async fn find_by_ids(&self, ids: &[Uuid]) -> Result<Vec<Device>> {
+        let store = self.store.lock().await;
+        Ok(ids.iter().filter_map(|id| store.get(id).cloned()).collect())
+    }
+
+    /// Scans the collection for a Device with a matching `board_id`.
+    async fn find_by_board_id(&self, board_id: &str) -> Result<Option<Device>> {
+        let store = self.store.lock().await;
+        Ok(store.values().find(|d| d.board_id == board_id).cloned())
+    }
+
+    /// Returns every loaded Device.
+    async fn list(&self) -> Result<Vec<Device>> {
+        Ok(self.store.lock().await.values().cloned().collect())
+    }
+
+    /// Returns the Devices matching `query` per `Device::matches_text`.
+    async fn search_text(&self, query: &str) -> Result<Vec<Device>> {
+        let query = query.to_lowercase();
+        let store = self.store.lock().await;
+        Ok(store
+            .values()
+            .filter(|d| d.matches_text(&query))
+            .cloned()
+            .collect())
+    }
+
+    /// Returns the Devices whose tags contain `tag`.
+    async fn find_by_tag(&self, tag: &str) -> Result<Vec<Device>> {
+        let store = self.store.lock().await;
+        Ok(store
+            .values()
+            .filter(|d| d.tags.iter().any(|t| t == tag))
+            .cloned()
+            .collect())
+    }
+
+    /// Returns the Devices at `location`.
+    async fn find_by_location(&self, location: &str) -> Result<Vec<Device>> {
+        let store = self.store.lock().await;
+        Ok(store
+            .values()
+            .filter(|d| d.is_at(location))
+            .cloned()
+            .collect())
+    }
+
+    /// Overwrites an existing Device and rewrites the file.
+    async fn update(&self, device: Device) -> Result<Option<Device>> {
+        self.mutate(|devices| match devices.get_mut(&device.id) {
+            Some(existing) => {
+                *existing = bump_version(existing, device)?;
+                Ok(Some(existing.clone()))
+            }
+            None => Ok(None),
+        })
+        .await?
+    }
+
+    /// Flips the `archived` flag of a Device and rewrites the file.
+    async fn set_archived(&self, id: Uuid, archived: bool) -> Result<Option<Device>> {
+        self.mutate(|devices| {
+            devices.get_mut(&id).map(|device| {
+                device.archived = archived;
+                device.version += 1;
+                device.updated_at = Some(chrono::Utc::now());
+                device.clone()
+            })
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Test that devices survive reopening the file, that a taken board_id is rejected and that
+    /// a corrupt file is rejected.
+    #[tokio::test]
+    async fn persists_across_reopen() {
+        let dir = std::env::temp_dir().join(format!("json-repo-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join("devices.json");
+
+        let repo = JsonFileDeviceRepository::open(&path).await.unwrap();
+        assert!(repo.list().await.unwrap().is_empty());
+        let device = repo.create(Device::new("d1")).await.unwrap();
+        repo.set_archived(device.id, true).await.unwrap().unwrap();
+        let taken = Device {
+            board_id: device.board_id.clone(),
+            ..Device::new("d2")
+        };
+        assert!(repo.create(taken).await.is_err());
+
+        let reopened = JsonFileDeviceRepository::open(&path).await.unwrap();
+        let found = reopened.find_by_id(device.id).await.unwrap().unwrap();
+        assert_eq!(found.name, "d1");
+        assert!(found.archived);
+        assert!(!dir.join("devices.json.tmp").exists());
+
+        tokio::fs::write(&path, "{not json").await.unwrap();
+        assert!(JsonFileDeviceRepository::open(&path).await.is_err());
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -1,3 +1,13 @@
 pub mod in_memory_device_repo;
+pub mod json_file_device_repo;
+#[cfg(feature = "postgres")]
+pub mod postgres_device_repo;
+#[cfg(feature = "redis")]
+pub mod redis_device_repo;
 
 pub use in_memory_device_repo::InMemoryDeviceRepository;
+pub use json_file_device_repo::JsonFileDeviceRepository;
+#[cfg(feature = "postgres")]
+pub use postgres_device_repo::PostgresDeviceRepository;
+#[cfg(feature = "redis")]
+pub use redis_device_repo::RedisDeviceRepository;
@@ -0,0 +1,297 @@
+use anyhow::{anyhow, Result};
+use sqlx::postgres::{PgPool, PgRow};
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::domain::{BoardType, Device};
+use crate::repository::{board_id_conflict, version_conflict, DeviceRepository};
+
+/// PostgreSQL implementation of DeviceRepository, shared by every server node pointing at the same database.
+#[derive(Clone)]
+pub struct PostgresDeviceRepository {
+    pool: PgPool,
+}
+
+impl PostgresDeviceRepository {
+    /// Connects to the database at `url`. `ready` must run before the repository is used, so
+    /// the `devices` table exists.
+    pub async fn connect(url: &str) -> Result<Self> {
+        let pool = PgPool::connect(url)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to PostgreSQL: {}", e))?;
+        Ok(Self { pool })
+    }
+}
+
+/// Name Postgres gives the `UNIQUE` constraint on `devices.board_id`.
+const BOARD_ID_CONSTRAINT: &str = "devices_board_id_key";
+
+/// Turns a violation of the `board_id` unique constraint into `ServiceError::Conflict`. Other
+/// errors, including a duplicate primary key, are passed through.
+fn map_unique_violation(e: sqlx::Error, board_id: &str) -> anyhow::Error {
+    match &e {
+        sqlx::Error::Database(db)
+            if db.is_unique_violation() && db.constraint() == Some(BOARD_ID_CONSTRAINT) =>
+        {
+            board_id_conflict(board_id)
+        }
+        _ => e.into(),
+    }
+}
+
+/// Maps a `devices` row back into a Device entity.
+fn device_from_row(row: &PgRow) -> Result<Device> {
+    Ok(Device {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        board_id: row.try_get("board_id")?,
+        board_type: row
+            .try_get::<Option<String>, _>("board_type")?
+            .map(BoardType::from),
+        project_path: row.try_get("project_path")?,
+        tags: row.try_get("tags")?,
+        default_port: row.try_get("default_port")?,
+        default_env: row.try_get("default_env")?,
+        location: row.try_get("location")?,
+        updated_at: row.try_get("updated_at")?,
+        archived: row.try_get("archived")?,
+        ip_address: row.try_get("ip_address")?,
+        env_vars: serde_json::from_str(row.try_get::<&str, _>("env_vars")?)
+            .map_err(|e| anyhow!("Corrupt env_vars in devices row: {}", e))?,
+        last_seen: row.try_get("last_seen")?,
+        created_at: row.try_get("created_at")?,
+        description: row.try_get("description")?,
+        version: row.try_get::<i64, _>("version")? as u64,
+    })
+}
+
+#[async_trait::async_trait]
+impl DeviceRepository for PostgresDeviceRepository {
+    /// Verifies the connection by creating the `devices` table if needed and adding the columns
+    /// introduced after the initial schema.
+    async fn ready(&self) -> Result<()> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS devices (
+                id UUID PRIMARY KEY,
+                name TEXT NOT NULL,
+                board_id TEXT NOT NULL UNIQUE,
+                board_type TEXT,
+                project_path TEXT,
+                tags TEXT[] NOT NULL DEFAULT '{}',
+                default_port TEXT,
+                archived BOOLEAN NOT NULL DEFAULT FALSE,
+                ip_address TEXT,
+                env_vars TEXT NOT NULL DEFAULT '{}',
+                last_seen TIMESTAMPTZ,
+                created_at TIMESTAMPTZ,
+                version BIGINT NOT NULL DEFAULT 0,
+                description TEXT,
+                default_env TEXT,
+                location TEXT,
+                updated_at TIMESTAMPTZ
+            )",
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| anyhow!("Failed to create devices table: {}", e))?;
+
+        // Columns added after the initial schema, for tables created by older versions
+        for migration in [
+            "ALTER TABLE devices ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}'",
+            "ALTER TABLE devices ADD COLUMN IF NOT EXISTS archived BOOLEAN NOT NULL DEFAULT FALSE",
+            "ALTER TABLE devices ADD COLUMN IF NOT EXISTS default_port TEXT",
+            "ALTER TABLE devices ADD COLUMN IF NOT EXISTS ip_address TEXT",
+            "ALTER TABLE devices ADD COLUMN IF NOT EXISTS env_vars TEXT NOT NULL DEFAULT '{}'",
+            "ALTER TABLE devices ADD COLUMN IF NOT EXISTS last_seen TIMESTAMPTZ",
+            "ALTER TABLE devices ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ",
+            "ALTER TABLE devices ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 0",
+            "ALTER TABLE devices ADD COLUMN IF NOT EXISTS description TEXT",
+            "ALTER TABLE devices ADD COLUMN IF NOT EXISTS default_env TEXT",
+            "ALTER TABLE devices ADD COLUMN IF NOT EXISTS location TEXT",
+            "ALTER TABLE devices ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ",
+        ] {
+            sqlx::query(migration)
+                .execute(&self.pool)
+                .await
+                .map_err(|e| anyhow!("Failed to migrate devices table: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Inserts a Device row; the `UNIQUE` constraint on `board_id` makes a taken id fail with
+    /// `ServiceError::Conflict`.
+    async fn create(&self, device: Device) -> Result<Device> {
+        sqlx::query(
+            "INSERT INTO devices
+                 (id, name, board_id, board_type, project_path, tags, archived, default_port,
+                  ip_address, env_vars, last_seen, created_at, version, description, default_env,
+                  location, updated_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)",
+        )
+        .bind(device.id)
+        .bind(&device.name)
+        .bind(&device.board_id)
+        .bind(device.board_type.as_ref().map(BoardType::as_str))
+        .bind(&device.project_path)
+        .bind(&device.tags)
+        .bind(device.archived)
+        .bind(&device.default_port)
+        .bind(&device.ip_address)
+        .bind(serde_json::to_string(&device.env_vars)?)
+        .bind(device.last_seen)
+        .bind(device.created_at)
+        .bind(device.version as i64)
+        .bind(&device.description)
+        .bind(&device.default_env)
+        .bind(&device.location)
+        .bind(device.updated_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| map_unique_violation(e, &device.board_id))?;
+        Ok(device)
+    }
+
+    /// Selects a Device row by primary key.
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<Device>> {
+        let row = sqlx::query("SELECT * FROM devices WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(device_from_row).transpose()
+    }
+
+    /// Checks for the primary key with `SELECT EXISTS`.
+    async fn exists(&self, id: Uuid) -> Result<bool> {
+        let exists = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM devices WHERE id = $1)")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(exists)
+    }
+
+    /// Selects the Device rows whose ids are in `ids`.
+    async fn find_by_ids(&self, ids: &[Uuid]) -> Result<Vec<Device>> {
+        let rows = sqlx::query("SELECT * FROM devices WHERE id = ANY($1)")
+            .bind(ids)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(device_from_row).collect()
+    }
+
+    /// Selects a Device row by its unique `board_id`.
+    async fn find_by_board_id(&self, board_id: &str) -> Result<Option<Device>> {
+        let row = sqlx::query("SELECT * FROM devices WHERE board_id = $1")
+            .bind(board_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(device_from_row).transpose()
+    }
+
+    /// Selects every Device row.
+    async fn list(&self) -> Result<Vec<Device>> {
+        let rows = sqlx::query("SELECT * FROM devices")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(device_from_row).collect()
+    }
+
+    /// Selects the Device rows whose name or description contains `query` via `ILIKE`, with
+    /// wildcards escaped.
+    async fn search_text(&self, query: &str) -> Result<Vec<Device>> {
+        let escaped = query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let rows = sqlx::query(
+            "SELECT * FROM devices
+             WHERE name ILIKE '%' || $1 || '%' OR description ILIKE '%' || $1 || '%'",
+        )
+        .bind(escaped)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(device_from_row).collect()
+    }
+
+    /// Selects the Device rows whose `tags` array contains `tag`.
+    async fn find_by_tag(&self, tag: &str) -> Result<Vec<Device>> {
+        let rows = sqlx::query("SELECT * FROM devices WHERE $1 = ANY(tags)")
+            .bind(tag)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(device_from_row).collect()
+    }
+
+    /// Selects the Device rows whose trimmed `location` matches `location` ignoring case.
+    async fn find_by_location(&self, location: &str) -> Result<Vec<Device>> {
+        let rows =
+            sqlx::query("SELECT * FROM devices WHERE LOWER(TRIM(location)) = LOWER(TRIM($1))")
+                .bind(location)
+                .fetch_all(&self.pool)
+                .await?;
+        rows.iter().map(device_from_row).collect()
+    }
+
+    /// Updates every column of an existing Device row in one `UPDATE ... WHERE version = $12`,
+    /// so the version check and the write are atomic. When no row matched, the current version
+    /// is read to tell a missing Device from a conflict. A `board_id` taken by another Device
+    /// fails with `ServiceError::Conflict`.
+    async fn update(&self, device: Device) -> Result<Option<Device>> {
+        let updated_at = chrono::Utc::now();
+        let result = sqlx::query(
+            "UPDATE devices
+             SET name = $2, board_id = $3, board_type = $4, project_path = $5, tags = $6,
+                 archived = $7, default_port = $8, ip_address = $9, env_vars = $10,
+                 last_seen = $11, description = $13, default_env = $14,
+                 location = $15, updated_at = $16, version = version + 1
+             WHERE id = $1 AND version = $12",
+        )
+        .bind(device.id)
+        .bind(&device.name)
+        .bind(&device.board_id)
+        .bind(device.board_type.as_ref().map(BoardType::as_str))
+        .bind(&device.project_path)
+        .bind(&device.tags)
+        .bind(device.archived)
+        .bind(&device.default_port)
+        .bind(&device.ip_address)
+        .bind(serde_json::to_string(&device.env_vars)?)
+        .bind(device.last_seen)
+        .bind(device.version as i64)
+        .bind(&device.description)
+        .bind(&device.default_env)
+        .bind(&device.location)
+        .bind(updated_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| map_unique_violation(e, &device.board_id))?;
+        if result.rows_affected() > 0 {
+            return Ok(Some(Device {
+                version: device.version + 1,
+                updated_at: Some(updated_at),
+                ..device
+            }));
+        }
+        let current: Option<i64> = sqlx::query_scalar("SELECT version FROM devices WHERE id = $1")
+            .bind(device.id)
+            .fetch_optional(&self.pool)
+            .await?;
+        match current {
+            Some(current) => Err(version_conflict(current as u64, device.version)),
+            None => Ok(None),
+        }
+    }
+
+    /// Sets the `archived` column of a Device row, increments its version, stamps `updated_at`
+    /// and returns the row.
+    async fn set_archived(&self, id: Uuid, archived: bool) -> Result<Option<Device>> {
+        let row = sqlx::query(
+            "UPDATE devices SET archived = $2, version = version + 1, updated_at = now()
+             WHERE id = $1 RETURNING *",
+        )
+        .bind(id)
+        .bind(archived)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(device_from_row).transpose()
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::{anyhow, Result};
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use uuid::Uuid;
+
+use crate::domain::Device;
+use crate::repository::{board_id_conflict, version_conflict, DeviceRepository};
+
+/// Set holding the id of every stored Device.
+const DEVICES_KEY: &str = "devices";
+
+/// Compare-and-swap for `update`: writes ARGV[1] to KEYS[1] only if the stored Device's version
+/// equals ARGV[2], atomically. Returns -1 if the key is missing, else the stored version (which
+/// equals ARGV[2] exactly when the write happened).
+const UPDATE_SCRIPT: &str = r#"
+local stored = redis.call('GET', KEYS[1])
+if not stored then return -1 end
+local version = cjson.decode(stored).version or 0
+if version == tonumber(ARGV[2]) then redis.call('SET', KEYS[1], ARGV[1]) end
+return version
+"#;
+
+/// Insert for `create`: unless a Device listed in the KEYS[1] set already has board id ARGV[3],
+/// writes ARGV[2] to `device:ARGV[1]` and adds ARGV[1] to the set, atomically. Returns 1 if the
+/// Device was written, 0 if the board id is taken.
+const CREATE_SCRIPT: &str = r#"
+for _, id in ipairs(redis.call('SMEMBERS', KEYS[1])) do
+  local stored = redis.call('GET', 'device:' .. id)
+  if stored and cjson.decode(stored).board_id == ARGV[3] then return 0 end
+end
+redis.call('SET', 'device:' .. ARGV[1], ARGV[2])
+redis.call('SADD', KEYS[1], ARGV[1])
+return 1
+"#;
+
+/// Redis implementation of DeviceRepository, shared by every server node pointing at the same
+/// instance. Each Device is a JSON string under `device:{uuid}`; the `devices` set lists the ids.
+/// Redis has no secondary indexes here, so lookups by board id, name or tag scan every Device.
+#[derive(Clone)]
+pub struct RedisDeviceRepository {
+    conn: ConnectionManager,
+}
+
+impl RedisDeviceRepository {
+    /// Connects to the Redis server at `url` (e.g. `redis://127.0.0.1/`). The connection is
+    /// re-established automatically if it drops later; commands fail while it is down.
+    pub async fn connect(url: &str) -> Result<Self> {
+        let client = redis::Client::open(url).map_err(|e| anyhow!("Invalid Redis URL: {}", e))?;
+        let conn = ConnectionManager::new(client)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to Redis: {}", e))?;
+        Ok(Self { conn })
+    }
+}
+
+/// Key a Device's JSON is stored under.
+fn device_key(id: Uuid) -> String {
+    format!("device:{}", id)
+}
+
+/// Parses the stored JSON values, skipping keys that have no value.
+fn parse_devices(values: Vec<Option<String>>) -> Result<Vec<Device>> {
+    values
+        .into_iter()
+        .flatten()
+        .map(|json| {
+            serde_json::from_str(&json).map_err(|e| anyhow!("Corrupt device in Redis: {}", e))
+        })
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl DeviceRepository for RedisDeviceRepository {
+    /// Verifies the connection with a `PING`.
+    async fn ready(&self) -> Result<()> {
+        let mut conn = self.conn.clone();
+        redis::cmd("PING")
+            .query_async::<()>(&mut conn)
+            .await
+            .map_err(|e| anyhow!("Redis did not answer PING: {}", e))
+    }
+
+    /// Stores the Device's JSON and adds its id to the `devices` set in one script, which first
+    /// scans the stored Devices for its `board_id` (see `CREATE_SCRIPT`).
+    async fn create(&self, device: Device) -> Result<Device> {
+        let created: i64 = redis::Script::new(CREATE_SCRIPT)
+            .key(DEVICES_KEY)
+            .arg(device.id.to_string())
+            .arg(serde_json::to_string(&device)?)
+            .arg(&device.board_id)
+            .invoke_async(&mut self.conn.clone())
+            .await?;
+        match created {
+            1 => Ok(device),
+            _ => Err(board_id_conflict(&device.board_id)),
+        }
+    }
+
+    /// Reads the Device's key.
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<Device>> {
+        let json: Option<String> = self.conn.clone().get(device_key(id)).await?;
+        Ok(parse_devices(vec![json])?.pop())
+    }
+
+    /// Checks the Device's key with `EXISTS`.
+    async fn exists(&self, id: Uuid) -> Result<bool> {
+        Ok(self.conn.clone().exists(device_key(id)).await?)
+    }
+
+    /// Reads every requested key with one `MGET`, preserving request order.
+    async fn find_by_ids(&self, ids: &[Uuid]) -> Result<Vec<Device>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let keys: Vec<String> = ids.iter().map(|id| device_key(*id)).collect();
+        let values: Vec<Option<String>> = redis::cmd("MGET")
+            .arg(keys)
+            .query_async(&mut self.conn.clone())
+            .await?;
+        parse_devices(values)
+    }
+
+    /// Scans every Device for a matching `board_id`.
+    async fn find_by_board_id(&self, board_id: &str) -> Result<Option<Device>> {
+        Ok(self
+            .list()
+            .await?
+            .into_iter()
+            .find(|d| d.board_id == board_id))
+    }
+
+    /// Reads the ids in the `devices` set, then their keys.
+    async fn list(&self) -> Result<Vec<Device>> {
+        let ids: Vec<String> = self.conn.clone().smembers(DEVICES_KEY).await?;
+        let ids: Vec<Uuid> = ids
+            .iter()
+            .filter_map(|id| Uuid::parse_str(id).ok())
+            .collect();
+        self.find_by_ids(&ids).await
+    }
+
+    /// Returns the Devices matching `query` per `Device::matches_text`.
+    async fn search_text(&self, query: &str) -> Result<Vec<Device>> {
+        let query = query.to_lowercase();
+        let mut devices = self.list().await?;
+        devices.retain(|d| d.matches_text(&query));
+        Ok(devices)
+    }
+
+    /// Returns the Devices whose tags contain `tag`.
+    async fn find_by_tag(&self, tag: &str) -> Result<Vec<Device>> {
+        let mut devices = self.list().await?;
+        devices.retain(|d| d.tags.iter().any(|t| t == tag));
+        Ok(devices)
+    }
+
+    /// Returns the Devices at `location`.
+    async fn find_by_location(&self, location: &str) -> Result<Vec<Device>> {
+        let mut devices = self.list().await?;
+        devices.retain(|d| d.is_at(location));
+        Ok(devices)
+    }
+
+    /// Overwrites the Device's key via a Lua script that checks the stored version first.
+    async fn update(&self, device: Device) -> Result<Option<Device>> {
+        let expected = device.version;
+        let device = Device {
+            version: expected + 1,
+            updated_at: Some(chrono::Utc::now()),
+            ..device
+        };
+        let stored: i64 = redis::Script::new(UPDATE_SCRIPT)
+            .key(device_key(device.id))
+            .arg(serde_json::to_string(&device)?)
+            .arg(expected)
+            .invoke_async(&mut self.conn.clone())
+            .await?;
+        match stored {
+            -1 => Ok(None),
+            v if v as u64 == expected => Ok(Some(device)),
+            v => Err(version_conflict(v as u64, expected)),
+        }
+    }
+
+    /// Reads the Device, flips `archived` and writes it back with the version check of `update`.
+    async fn set_archived(&self, id: Uuid, archived: bool) -> Result<Option<Device>> {
+        match self.find_by_id(id).await? {
+            Some(device) => self.update(Device { archived, ..device }).await,
+            None => Ok(None),
+        }
+    }
+}
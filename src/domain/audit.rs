@@ -0,0 +1,27 @@
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Kind of device operation kept in the operation log.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Build,
+    Upload,
+    /// A filesystem image upload (`uploadfs`).
+    UploadFs,
+    Clean,
+}
+
+/// One entry of the operation log: who ran what on which device, and whether it worked.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OperationRecord {
+    /// Unix timestamp (seconds) of when the operation finished.
+    pub timestamp: u64,
+    pub device_id: Uuid,
+    pub operation: Operation,
+    pub success: bool,
+    /// First characters of the API key the request carried, e.g. `"lab4…"`; never the full key.
+    pub api_key_prefix: Option<String>,
+}
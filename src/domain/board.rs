@@ -0,0 +1,187 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// PlatformIO board a device is built for. Common ESP32/ESP8266 boards have their own variant;
+/// any other PlatformIO board id is kept as `Other`. Serializes as the plain board id, so stored
+/// devices and API payloads use the same strings as `platformio.ini`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BoardType {
+    Esp32Dev,
+    #[serde(rename = "esp32-s3-devkitc-1")]
+    Esp32S3DevKitC1,
+    #[serde(rename = "esp32-s3-devkitm-1")]
+    Esp32S3DevKitM1,
+    #[serde(rename = "esp32-c3-devkitm-1")]
+    Esp32C3DevKitM1,
+    #[serde(rename = "esp32-s2-saola-1")]
+    Esp32S2Saola1,
+    #[serde(rename = "esp-wrover-kit")]
+    EspWroverKit,
+    #[serde(rename = "nodemcu-32s")]
+    NodeMcu32S,
+    NodeMcuV2,
+    #[serde(rename = "d1_mini")]
+    D1Mini,
+    #[serde(rename = "esp01_1m")]
+    Esp01_1M,
+    Esp12E,
+    /// Any other PlatformIO board id.
+    #[serde(untagged)]
+    Other(String),
+}
+
+impl BoardType {
+    /// Every variant except `Other`.
+    pub const KNOWN: &'static [BoardType] = &[
+        BoardType::Esp32Dev,
+        BoardType::Esp32S3DevKitC1,
+        BoardType::Esp32S3DevKitM1,
+        BoardType::Esp32C3DevKitM1,
+        BoardType::Esp32S2Saola1,
+        BoardType::EspWroverKit,
+        BoardType::NodeMcu32S,
+        BoardType::NodeMcuV2,
+        BoardType::D1Mini,
+        BoardType::Esp01_1M,
+        BoardType::Esp12E,
+    ];
+
+    /// The PlatformIO board id, e.g. `esp32dev`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            BoardType::Esp32Dev => "esp32dev",
+            BoardType::Esp32S3DevKitC1 => "esp32-s3-devkitc-1",
+            BoardType::Esp32S3DevKitM1 => "esp32-s3-devkitm-1",
+            BoardType::Esp32C3DevKitM1 => "esp32-c3-devkitm-1",
+            BoardType::Esp32S2Saola1 => "esp32-s2-saola-1",
+            BoardType::EspWroverKit => "esp-wrover-kit",
+            BoardType::NodeMcu32S => "nodemcu-32s",
+            BoardType::NodeMcuV2 => "nodemcuv2",
+            BoardType::D1Mini => "d1_mini",
+            BoardType::Esp01_1M => "esp01_1m",
+            BoardType::Esp12E => "esp12e",
+            BoardType::Other(id) => id,
+        }
+    }
+
+    /// Whether this is one of the `KNOWN` boards.
+    pub fn is_known(&self) -> bool {
+        !matches!(self, BoardType::Other(_))
+    }
+
+    /// Checks that an `Other` id is a plausible PlatformIO board id rather than a misspelt
+    /// known one: ids differing from a known id only in case or `-`/`_`/`.` separators, or by a
+    /// single character after ignoring those, are rejected with a suggestion
+    /// (`"esp32-devv"` -> did you mean `esp32dev`). Known variants are always valid.
+    pub fn validate(&self) -> Result<(), String> {
+        let BoardType::Other(id) = self else {
+            return Ok(());
+        };
+        let mut chars = id.chars();
+        let well_formed = id.len() <= 64
+            && chars.next().is_some_and(|c| c.is_ascii_alphanumeric())
+            && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+        if !well_formed {
+            return Err("must be a PlatformIO board id like \"esp32dev\"".to_string());
+        }
+        let normalized = normalize(id);
+        match Self::KNOWN
+            .iter()
+            .find(|known| edit_distance(&normalized, &normalize(known.as_str())) <= 1)
+        {
+            Some(known) => Err(format!(
+                "unknown board \"{}\"; did you mean \"{}\"?",
+                id,
+                known.as_str()
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl From<&str> for BoardType {
+    /// Maps a board id to its variant, falling back to `Other`. Matching is exact, so
+    /// `"ESP32DEV"` becomes `Other` (and fails `validate`).
+    fn from(id: &str) -> Self {
+        Self::KNOWN
+            .iter()
+            .find(|known| known.as_str() == id)
+            .cloned()
+            .unwrap_or_else(|| BoardType::Other(id.to_string()))
+    }
+}
+
+impl From<String> for BoardType {
+    fn from(id: String) -> Self {
+        BoardType::from(id.as_str())
+    }
+}
+
+impl fmt::Display for BoardType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Lowercases `id` and drops separators, so `ESP32_Dev` and `esp32dev` compare equal.
+fn normalize(id: &str) -> Vec<char> {
+    id.chars()
+        .filter(|c| !matches!(c, '_' | '.' | '-'))
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that known ids map to their variants and everything round-trips as a plain string.
+    #[test]
+    fn board_type_serializes_as_platformio_id() {
+        for known in BoardType::KNOWN {
+            let json = serde_json::to_string(known).unwrap();
+            assert_eq!(json, format!("\"{}\"", known.as_str()));
+            assert_eq!(&serde_json::from_str::<BoardType>(&json).unwrap(), known);
+            assert_eq!(&BoardType::from(known.as_str()), known);
+        }
+        let other: BoardType = serde_json::from_str("\"lolin_d32\"").unwrap();
+        assert_eq!(other, BoardType::Other("lolin_d32".to_string()));
+        assert_eq!(serde_json::to_string(&other).unwrap(), "\"lolin_d32\"");
+    }
+
+    /// Test that misspelt known ids are rejected while uncommon boards pass.
+    #[test]
+    fn validate_rejects_near_misses() {
+        assert!(BoardType::Esp32Dev.validate().is_ok());
+        for id in [
+            "lolin_d32",
+            "esp32cam",
+            "esp32doit-devkit-v1",
+            "nodemcu",
+            "esp07",
+        ] {
+            assert!(BoardType::from(id).validate().is_ok(), "{}", id);
+        }
+        let err = BoardType::from("esp32-devv").validate().unwrap_err();
+        assert!(err.contains("did you mean \"esp32dev\""), "{}", err);
+        for id in ["ESP32DEV", "d1-mini", "esp32-s3-devkitc1", "esp32 dev", ""] {
+            assert!(BoardType::from(id).validate().is_err(), "{}", id);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use serde::Serialize;
+
+/// Build counters of a device, as recorded by the build endpoint.
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BuildStats {
+    /// Builds that ran to completion or failure; dry runs and rejected requests aren't counted.
+    pub build_count: u64,
+    /// Whether the most recent build succeeded; `None` until the first build.
+    pub last_build_success: Option<bool>,
+    /// Unix timestamp (seconds) of the most recent build.
+    pub last_build_at: Option<u64>,
+}
+
+/// Totals from the summary line `platformio test` prints last, e.g.
+/// `3 test cases: 1 failed, 2 succeeded in 00:00:04.512`.
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TestSummary {
+    pub total: u32,
+    pub succeeded: u32,
+    pub failed: u32,
+    /// Test cases that crashed or couldn't run.
+    pub errored: u32,
+    pub skipped: u32,
+}
+
+impl TestSummary {
+    /// Whether no test case failed or errored.
+    pub fn passed(&self) -> bool {
+        self.failed == 0 && self.errored == 0
+    }
+}
+
+/// Severity of a compiler diagnostic; GCC's `fatal error` counts as `Error`.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticSeverity {
+    Warning,
+    Error,
+}
+
+/// A compiler warning or error found in build output, as reported by GCC:
+/// `file:line:col: severity: message`.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Diagnostic {
+    /// Path as printed by the compiler, usually relative to the project directory.
+    pub file: String,
+    pub line: u32,
+    pub column: Option<u32>,
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+    /// Lines that followed the diagnostic: the source excerpt, the caret and any `note:`s.
+    pub context: Vec<String>,
+}
@@ -1,13 +1,170 @@
-use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+use crate::domain::BoardType;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Device {
     pub id: Uuid,
     pub name: String,
     pub board_id: String,
-    pub board_type: Option<String>, // ESP32 board type (e.g., "esp32dev", "esp32-s3-devkitc-1")
-    pub project_path: Option<String>, // Path to PlatformIO project directory
+    pub board_type: Option<BoardType>, // ESP32 board type (e.g., "esp32dev", "esp32-s3-devkitc-1")
+    pub project_path: Option<String>,  // Path to PlatformIO project directory
+    #[serde(default)]
+    pub tags: Vec<String>, // Lowercase, de-duplicated grouping labels (e.g. "room-101")
+    #[serde(default)]
+    pub default_port: Option<String>, // Serial port used for uploads when a request names none
+    #[serde(default)]
+    pub default_env: Option<String>, // platformio.ini environment built and uploaded when a request names none
+    #[serde(default)]
+    pub location: Option<String>, // Where the board physically sits, e.g. "Lab 2, bench 4"
+    #[serde(default)]
+    pub archived: bool, // Hidden from default listings; archived devices are never deleted
+    #[serde(default)]
+    pub ip_address: Option<String>, // Network address probed by the ping endpoint (OTA-capable boards)
+    #[serde(default)]
+    pub env_vars: HashMap<String, String>, // Set for PlatformIO builds (e.g. WiFi credentials); never returned by the API
+    #[serde(default)]
+    pub last_seen: Option<DateTime<Utc>>, // Time of the board's most recent heartbeat
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>, // When the device was registered; None for devices stored before this was recorded
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>, // Time of the last change, set by the repository alongside `version`
+    #[serde(default)]
+    pub description: Option<String>, // Free-form note, e.g. "reserved for group B"
+    #[serde(default)]
+    pub version: u64, // Incremented by the repository on every update, for optimistic concurrency
+}
+
+/// Optional fields of a Device registered with `DeviceService::create`.
+#[derive(Debug, Clone, Default)]
+pub struct NewDevice {
+    /// Unique board id; generated from the name when `None`.
+    pub board_id: Option<String>,
+    pub board_type: Option<BoardType>,
+    pub project_path: Option<String>,
+    pub tags: Vec<String>,
+    /// Serial port uploads use when a request doesn't name one.
+    pub default_port: Option<String>,
+    pub description: Option<String>,
+    /// `platformio.ini` environment builds and uploads use.
+    pub default_env: Option<String>,
+    /// Where the board sits.
+    pub location: Option<String>,
+}
+
+/// Partial changes applied by `DeviceService::update`; `None` fields are left unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceUpdate {
+    pub name: Option<String>,
+    pub board_type: Option<BoardType>,
+    pub project_path: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub default_port: Option<String>,
+    pub default_env: Option<String>,
+    pub location: Option<String>,
+    pub ip_address: Option<String>,
+    pub description: Option<String>,
+    /// Replaces all of the device's build environment variables.
+    pub env_vars: Option<HashMap<String, String>>,
+    /// Version the caller last read; the update fails with `ServiceError::Conflict` unless the
+    /// stored Device still has it. `None` updates whatever version is read.
+    pub expected_version: Option<u64>,
+}
+
+/// Filters applied by `DeviceService::search`; every set field must match (AND semantics).
+#[derive(Debug, Clone, Default)]
+pub struct DeviceFilter {
+    /// Case-insensitive substring of the device name or description.
+    pub name: Option<String>,
+    /// Tag the device must carry, compared case-insensitively.
+    pub tag: Option<String>,
+    /// Exact board type.
+    pub board_type: Option<BoardType>,
+    /// Location the device must be at, compared case-insensitively (see `Device::is_at`).
+    pub location: Option<String>,
+    pub include_archived: bool,
+    /// Order of the results.
+    pub sort: DeviceSort,
+}
+
+/// How `DeviceService::import` treats an imported Device that is already registered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ImportConflict {
+    /// Keep the registered Device and ignore the imported one.
+    #[default]
+    Skip,
+    /// Replace the registered Device's fields with the imported ones, keeping its id.
+    Overwrite,
+}
+
+/// Ids of the Devices `DeviceService::import` created, overwrote, skipped or rejected. Created
+/// and overwritten ids are those of the stored Devices; skipped and rejected ids are those in
+/// the import. Rejected Devices have an invalid `project_path` or `default_env`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ImportReport {
+    pub created: Vec<Uuid>,
+    pub overwritten: Vec<Uuid>,
+    pub skipped: Vec<Uuid>,
+    pub rejected: Vec<Uuid>,
+}
+
+/// Field `DeviceService::search` orders its results by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeviceSortKey {
+    #[default]
+    Id,
+    /// Case-insensitive name.
+    Name,
+    /// Devices without a `created_at` sort first.
+    CreatedAt,
+}
+
+/// Result order of `DeviceService::search`; ties are broken by id, so the order is stable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeviceSort {
+    pub key: DeviceSortKey,
+    pub descending: bool,
+}
+
+impl DeviceSort {
+    /// Parses `name`, `created_at` and their `-`-prefixed descending forms.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (descending, key) = match value.strip_prefix('-') {
+            Some(key) => (true, key),
+            None => (false, value),
+        };
+        let key = match key {
+            "name" => DeviceSortKey::Name,
+            "created_at" => DeviceSortKey::CreatedAt,
+            _ => return None,
+        };
+        Some(Self { key, descending })
+    }
+
+    /// Sorts `devices` in this order.
+    pub fn apply(&self, devices: &mut [Device]) {
+        devices.sort_by(|a, b| {
+            let ordering = match self.key {
+                DeviceSortKey::Id => std::cmp::Ordering::Equal,
+                DeviceSortKey::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+                DeviceSortKey::CreatedAt => a.created_at.cmp(&b.created_at),
+            }
+            .then(a.id.cmp(&b.id));
+            if self.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
 }
 
 impl Device {
@@ -19,13 +176,25 @@ impl Device {
             board_id: String::new(),
             board_type: None,
             project_path: None,
+            tags: Vec::new(),
+            default_port: None,
+            default_env: None,
+            location: None,
+            archived: false,
+            ip_address: None,
+            env_vars: HashMap::new(),
+            last_seen: None,
+            created_at: Some(Utc::now()),
+            updated_at: None,
+            description: None,
+            version: 0,
         }
     }
 
     pub fn with_esp32_config(
         name: impl Into<String>,
         board_id: String,
-        board_type: String,
+        board_type: BoardType,
         project_path: String,
     ) -> Self {
         Self {
@@ -34,6 +203,74 @@ impl Device {
             board_id,
             board_type: Some(board_type),
             project_path: Some(project_path),
+            tags: Vec::new(),
+            default_port: None,
+            default_env: None,
+            location: None,
+            archived: false,
+            ip_address: None,
+            env_vars: HashMap::new(),
+            last_seen: None,
+            created_at: Some(Utc::now()),
+            updated_at: None,
+            description: None,
+            version: 0,
         }
     }
+
+    /// Lowercases and trims tags, dropping empty and duplicate entries while keeping first-seen order.
+    pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+        let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+        for tag in tags {
+            let tag = tag.trim().to_lowercase();
+            if !tag.is_empty() && !normalized.contains(&tag) {
+                normalized.push(tag);
+            }
+        }
+        normalized
+    }
+
+    /// Whether the name or description contains `query`, which must already be lowercase.
+    pub fn matches_text(&self, query: &str) -> bool {
+        self.name.to_lowercase().contains(query)
+            || self
+                .description
+                .as_ref()
+                .is_some_and(|d| d.to_lowercase().contains(query))
+    }
+
+    /// Whether the device's location equals `location`, ignoring case and surrounding whitespace.
+    /// Devices without a location are nowhere.
+    pub fn is_at(&self, location: &str) -> bool {
+        self.location
+            .as_ref()
+            .is_some_and(|l| l.trim().to_lowercase() == location.trim().to_lowercase())
+    }
+
+    /// Whether the board sent a heartbeat within the last `window`. Never-seen devices are offline.
+    pub fn is_online(&self, window: Duration) -> bool {
+        let window = chrono::Duration::from_std(window).unwrap_or(chrono::Duration::MAX);
+        self.last_seen
+            .is_some_and(|seen| Utc::now().signed_duration_since(seen) <= window)
+    }
+
+    /// Whether `name` is safe to pass as a `platformio.ini` environment (`-e`): 1 to 64 ASCII
+    /// letters, digits, `_` or `-`.
+    pub fn is_valid_environment_name(name: &str) -> bool {
+        !name.is_empty()
+            && name.len() <= 64
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    }
+
+    /// Whether `name` can be used as an environment variable: an ASCII letter or `_` followed by
+    /// letters, digits or `_`.
+    pub fn is_valid_env_var_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
 }
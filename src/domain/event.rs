@@ -0,0 +1,40 @@
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Device lifecycle event published on the event bus and streamed by `GET /events`.
+/// Serialized with a `type` tag, e.g. `{"type":"build_finished","device_id":"...","success":true}`.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DeviceEvent {
+    /// A device was created (or cloned from another one).
+    DeviceCreated { device_id: Uuid },
+    /// A build of the device's project started.
+    BuildStarted { device_id: Uuid },
+    /// A build of the device's project finished, successfully or not.
+    BuildFinished { device_id: Uuid, success: bool },
+    /// A firmware upload to the device finished, successfully or not.
+    UploadFinished { device_id: Uuid, success: bool },
+}
+
+impl DeviceEvent {
+    /// Device the event is about.
+    pub fn device_id(&self) -> Uuid {
+        match self {
+            Self::DeviceCreated { device_id }
+            | Self::BuildStarted { device_id }
+            | Self::BuildFinished { device_id, .. }
+            | Self::UploadFinished { device_id, .. } => *device_id,
+        }
+    }
+
+    /// SSE event name, matching the serialized `type` tag.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::DeviceCreated { .. } => "device_created",
+            Self::BuildStarted { .. } => "build_started",
+            Self::BuildFinished { .. } => "build_finished",
+            Self::UploadFinished { .. } => "upload_finished",
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use serde::Serialize;
+
+/// Memory usage of one region as reported by PlatformIO's "Checking size" step.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MemoryUsage {
+    pub percent: f64,
+    pub used_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// RAM and flash usage of a built firmware image.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FirmwareSizeInfo {
+    pub ram: Option<MemoryUsage>,
+    pub flash: Option<MemoryUsage>,
+}
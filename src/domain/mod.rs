@@ -1,3 +1,26 @@
+pub mod audit;
+pub mod board;
+pub mod build;
 pub mod device;
+pub mod event;
+pub mod firmware;
+pub mod package;
+pub mod partition;
+pub mod port;
+pub mod project;
+pub mod system;
 
-pub use device::Device;
+pub use audit::{Operation, OperationRecord};
+pub use board::BoardType;
+pub use build::{BuildStats, Diagnostic, DiagnosticSeverity, TestSummary};
+pub use device::{
+    Device, DeviceFilter, DeviceSort, DeviceSortKey, DeviceUpdate, ImportConflict, ImportReport,
+    NewDevice,
+};
+pub use event::DeviceEvent;
+pub use firmware::{FirmwareSizeInfo, MemoryUsage};
+pub use package::{LibraryInfo, PackageUpdate};
+pub use partition::PartitionEntry;
+pub use port::SerialPortInfo;
+pub use project::{BoardDefinition, InitResult, ProjectSize};
+pub use system::SystemInfo;
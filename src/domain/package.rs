@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// An installed PlatformIO package (platform, toolchain, framework or library) with a newer release.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PackageUpdate {
+    pub package: String,
+    pub current: String,
+    pub latest: String,
+}
+
+/// A library installed in a PlatformIO project, as reported by `lib list --json-output`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LibraryInfo {
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+/// One entry of an ESP32 partition table, e.g. `nvs, data, nvs, 0x9000, 0x5000`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PartitionEntry {
+    pub name: String,
+    /// `app`, `data`, or the numeric type for custom partitions.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// E.g. `factory`, `ota_0`, `nvs` or `spiffs`; numeric for unknown subtypes.
+    pub subtype: String,
+    /// Byte offset in flash.
+    pub offset: u32,
+    /// Size in bytes.
+    pub size: u32,
+}
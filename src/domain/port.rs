@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+/// A serial port detected on the server, as reported by `device list --serial --json-output`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SerialPortInfo {
+    pub port: String,
+    #[serde(default)]
+    pub description: String,
+    /// Hardware id such as `USB VID:PID=10C4:EA60`; `n/a` for non-USB ports.
+    #[serde(default)]
+    pub hwid: String,
+}
@@ -0,0 +1,33 @@
+use serde::Serialize;
+
+/// What `platformio project init` set up: the board and environment configured in
+/// `platformio.ini`, the top-level files and directories PlatformIO reported creating, and the
+/// files copied from the server's project template.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct InitResult {
+    pub board: String,
+    pub env_name: Option<String>,
+    pub created_files: Vec<String>,
+    /// Paths relative to the project directory, sorted; empty without `TEMPLATE_DIR`.
+    pub template_files: Vec<String>,
+    /// The project already had an environment for the board, so nothing was run or copied.
+    pub already_initialized: bool,
+}
+
+/// Disk usage of a project directory. `build_bytes` is the `.pio` build directory and
+/// `source_bytes` everything else; symlinks aren't followed.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ProjectSize {
+    pub total_bytes: u64,
+    pub build_bytes: u64,
+    pub source_bytes: u64,
+}
+
+/// A custom PlatformIO board definition (board JSON) and the `id` it declares.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoardDefinition {
+    pub id: String,
+    pub json: String,
+}
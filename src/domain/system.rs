@@ -0,0 +1,20 @@
+use serde::Serialize;
+
+/// The server's PlatformIO environment, as reported by `system info --json-output`.
+/// Fields PlatformIO didn't report are `None`.
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SystemInfo {
+    /// PlatformIO Core version, e.g. `6.1.15`.
+    pub core_version: Option<String>,
+    /// Version of the Python running PlatformIO, e.g. `3.11.4-final.0`.
+    pub python_version: Option<String>,
+    /// PlatformIO's system type, e.g. `linux_x86_64`.
+    pub system: Option<String>,
+    /// Full OS description, e.g. `Linux-6.2.0-x86_64-with-glibc2.35`.
+    pub platform: Option<String>,
+    /// Where PlatformIO keeps its packages and platforms (`~/.platformio` by default).
+    pub core_dir: Option<String>,
+    pub platformio_exe: Option<String>,
+    pub python_exe: Option<String>,
+}
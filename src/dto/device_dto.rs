@@ -1,25 +1,353 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::domain::{
+    BoardType, Device, Diagnostic, FirmwareSizeInfo, ImportConflict, InitResult, LibraryInfo,
+    PackageUpdate, PartitionEntry, SerialPortInfo,
+};
+
+/// Accepts known boards and well-formed ids of other boards that aren't misspelt known ones
+/// (see `BoardType::validate`).
+fn validate_board_type(board_type: &BoardType) -> Result<(), ValidationError> {
+    board_type
+        .validate()
+        .map_err(|msg| ValidationError::new("board_type").with_message(msg.into()))
+}
+
+/// Accepts `platformio.ini` environment names that are safe to pass as `-e` (see
+/// `Device::is_valid_environment_name`).
+fn validate_default_env(env: &str) -> Result<(), ValidationError> {
+    if Device::is_valid_environment_name(env) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("default_env")
+            .with_message("must be 1 to 64 letters, digits, '_' or '-'".into()))
+    }
+}
 
-use crate::domain::Device;
+/// Accepts non-blank paths without NUL bytes or line breaks, up to 4096 bytes.
+fn validate_project_path(path: &str) -> Result<(), ValidationError> {
+    let plausible =
+        !path.trim().is_empty() && path.len() <= 4096 && !path.contains(['\0', '\n', '\r']);
+    if plausible {
+        Ok(())
+    } else {
+        Err(ValidationError::new("project_path")
+            .with_message("must be a non-empty path without control characters".into()))
+    }
+}
 
 // DTO for creating a new Device via API request.
 // Prior to this , a list containing available board types should be fetched from the server.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct DeviceCreateRequest {
+    #[validate(length(min = 1, max = 100, message = "must be between 1 and 100 characters"))]
+    pub name: String,
+    #[validate(custom(function = "validate_board_type"))]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<String>, example = "esp32dev"))]
+    pub board_type: Option<BoardType>,
+    /// Optional; generated by the server from the name when omitted (see `DeviceService::create`).
+    pub board_id: Option<String>,
+    #[validate(custom(function = "validate_project_path"))]
+    pub project_path: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Serial port uploads fall back to when the upload request has no `port`.
+    pub default_port: Option<String>,
+    /// `platformio.ini` environment builds and uploads fall back to when the request has no
+    /// `environment`.
+    #[validate(custom(function = "validate_default_env"))]
+    pub default_env: Option<String>,
+    /// Where the board physically sits, e.g. "Lab 2, bench 4"; matched by
+    /// `GET /devices?location=`.
+    #[validate(length(max = 200, message = "must be at most 200 characters"))]
+    pub location: Option<String>,
+    /// Free-form note such as "flaky USB port"; matched by `GET /devices?search=`.
+    #[validate(length(max = 1000, message = "must be at most 1000 characters"))]
+    pub description: Option<String>,
+}
+
+// DTO for partially updating a Device; omitted fields keep their current value.
+#[derive(Debug, Deserialize, Validate)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DeviceUpdateRequest {
+    pub name: Option<String>,
+    #[validate(custom(function = "validate_board_type"))]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<String>, example = "esp32dev"))]
+    pub board_type: Option<BoardType>,
+    pub project_path: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub default_port: Option<String>,
+    #[validate(custom(function = "validate_default_env"))]
+    pub default_env: Option<String>,
+    #[validate(length(max = 200, message = "must be at most 200 characters"))]
+    pub location: Option<String>,
+    /// IPv4 or IPv6 address used by `GET /devices/:id/ping`.
+    pub ip_address: Option<String>,
+    #[validate(length(max = 1000, message = "must be at most 1000 characters"))]
+    pub description: Option<String>,
+    /// Environment variables set for builds, replacing the current ones. Values are write-only:
+    /// responses list only the names.
+    pub env_vars: Option<HashMap<String, String>>,
+    /// The device's `version` as last read; the update is rejected with 409 if it has changed since.
+    pub version: u64,
+}
+
+/// Format version written to and required of `InventoryDocument::format_version`.
+pub const INVENTORY_FORMAT_VERSION: u32 = 1;
+
+/// Device collection exchanged by `GET /devices/export` and `POST /devices/import`.
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct InventoryDocument {
+    /// Currently always 1; documents of other versions are rejected on import.
+    pub format_version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub devices: Vec<InventoryDevice>,
+}
+
+/// One device of an `InventoryDocument`. Build environment variables aren't exported, as their
+/// values are never returned by the API; neither are heartbeat times or versions.
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct InventoryDevice {
+    pub id: Uuid,
     pub name: String,
-    pub board_type: Option<String>,
+    #[serde(default)]
     pub board_id: String,
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<String>, example = "esp32dev"))]
+    pub board_type: Option<BoardType>,
     pub project_path: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub default_port: Option<String>,
+    #[serde(default)]
+    pub default_env: Option<String>,
+    #[serde(default)]
+    pub location: Option<String>,
+    #[serde(default)]
+    pub archived: bool,
+    pub ip_address: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl From<&Device> for InventoryDevice {
+    fn from(d: &Device) -> Self {
+        InventoryDevice {
+            id: d.id,
+            name: d.name.clone(),
+            board_id: d.board_id.clone(),
+            board_type: d.board_type.clone(),
+            project_path: d.project_path.clone(),
+            tags: d.tags.clone(),
+            default_port: d.default_port.clone(),
+            default_env: d.default_env.clone(),
+            location: d.location.clone(),
+            archived: d.archived,
+            ip_address: d.ip_address.clone(),
+            description: d.description.clone(),
+            created_at: d.created_at,
+        }
+    }
+}
+
+impl From<InventoryDevice> for Device {
+    fn from(d: InventoryDevice) -> Self {
+        Device {
+            id: d.id,
+            board_id: d.board_id,
+            board_type: d.board_type,
+            project_path: d.project_path,
+            tags: d.tags,
+            default_port: d.default_port,
+            default_env: d.default_env,
+            location: d.location,
+            archived: d.archived,
+            ip_address: d.ip_address,
+            description: d.description,
+            created_at: d.created_at,
+            ..Device::new(d.name)
+        }
+    }
+}
+
+/// Query parameters accepted by `POST /devices/import`.
+#[derive(Debug, Default, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+#[serde(default)]
+pub struct ImportQuery {
+    /// Keep the exported device ids instead of assigning new ones.
+    pub preserve_ids: bool,
+    /// `skip` (default) or `overwrite` devices already registered with the same id or board_id.
+    #[cfg_attr(feature = "openapi", param(value_type = Option<String>))]
+    pub on_conflict: ImportConflict,
+}
+
+/// Body of `POST /devices/batch-get`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BatchGetRequest {
+    pub ids: Vec<Uuid>,
+}
+
+/// Body of `POST /devices/:id/clone`.
+#[derive(Debug, Deserialize, Validate)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CloneDeviceRequest {
+    #[validate(length(min = 1, max = 100, message = "must be between 1 and 100 characters"))]
+    pub name: String,
+    /// Optional; generated from the name when omitted, as for `POST /devices`.
+    pub board_id: Option<String>,
+}
+
+/// Query parameters accepted by `GET /devices`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct ListDevicesQuery {
+    /// Only return devices carrying this tag (case-insensitive).
+    pub tag: Option<String>,
+    /// Only return devices whose name or description contains this text (case-insensitive).
+    pub search: Option<String>,
+    /// Only return devices with this board type.
+    #[cfg_attr(feature = "openapi", param(value_type = Option<String>))]
+    pub board_type: Option<BoardType>,
+    /// Only return devices at this location (case-insensitive, surrounding whitespace ignored).
+    pub location: Option<String>,
+    /// Also return archived devices.
+    #[serde(default)]
+    pub include_archived: bool,
+    /// Fill in `initialized` for each device. This checks every device's project directory
+    /// for a `platformio.ini`, i.e. a filesystem lookup per device, so it is opt-in.
+    #[serde(default)]
+    pub check_init: bool,
+    /// Include `_links` in each device.
+    #[serde(default)]
+    pub links: bool,
+    /// `name`, `-name`, `created_at` or `-created_at` (`-` reverses the order); id order by default.
+    pub sort: Option<String>,
+}
+
+/// JSON error body of the device endpoints. 500s carry a `correlation_id` (the request's
+/// `X-Request-Id`) that is also logged alongside the underlying error.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ErrorResponse {
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+}
+
+/// Body of a 422 response: validation messages keyed by field name.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ValidationErrorResponse {
+    pub errors: HashMap<String, Vec<String>>,
+}
+
+impl From<&validator::ValidationErrors> for ValidationErrorResponse {
+    fn from(e: &validator::ValidationErrors) -> Self {
+        let errors = e
+            .field_errors()
+            .into_iter()
+            .map(|(field, errs)| {
+                let messages = errs
+                    .iter()
+                    .map(|err| match &err.message {
+                        Some(msg) => msg.to_string(),
+                        None => err.code.to_string(),
+                    })
+                    .collect();
+                (field.to_string(), messages)
+            })
+            .collect();
+        ValidationErrorResponse { errors }
+    }
 }
 
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct DeviceResponse {
     pub id: Uuid,
     pub name: String,
-    pub board_type: Option<String>,
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<String>, example = "esp32dev"))]
+    pub board_type: Option<BoardType>,
     pub board_id: String,
     pub project_path: Option<String>,
+    pub tags: Vec<String>,
+    pub default_port: Option<String>,
+    pub default_env: Option<String>,
+    pub location: Option<String>,
+    pub archived: bool,
+    pub ip_address: Option<String>,
+    pub description: Option<String>,
+    /// Names of the build environment variables; their values are never returned.
+    pub env_vars: Vec<String>,
+    /// Time of the board's most recent heartbeat.
+    pub last_seen: Option<DateTime<Utc>>,
+    /// When the device was registered; `None` for devices registered before this was recorded.
+    pub created_at: Option<DateTime<Utc>>,
+    /// Time of the most recent change; `None` if the device hasn't changed since it was
+    /// registered.
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Incremented on every change; send it back in `PATCH /devices/:id` to detect lost updates.
+    pub version: u64,
+    /// Whether `last_seen` is within the heartbeat window; `From` leaves it `false` and handlers
+    /// fill it in with `DeviceService::is_online`.
+    pub online: bool,
+    /// Whether the project directory holds a `platformio.ini`; only set when requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initialized: Option<bool>,
+    /// URLs of the device's main endpoints; only set when requested with `?links=true`.
+    #[serde(rename = "_links", skip_serializing_if = "Option::is_none")]
+    pub links: Option<DeviceLinks>,
+}
+
+/// URLs of a device's endpoints, so clients can navigate without hardcoding routes. Each is
+/// the request's base path followed by the route, e.g. `/devices/{id}/build`.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DeviceLinks {
+    #[serde(rename = "self")]
+    pub self_: String,
+    pub build: String,
+    pub upload: String,
+    pub clean: String,
+    pub config: String,
+    pub files: String,
+}
+
+impl DeviceLinks {
+    /// Links for device `id` under `base`, the part of the request path before `/devices`.
+    pub fn new(base: &str, id: Uuid) -> Self {
+        let device = format!("{}/devices/{}", base.trim_end_matches('/'), id);
+        Self {
+            build: format!("{}/build", device),
+            upload: format!("{}/upload", device),
+            clean: format!("{}/clean", device),
+            config: format!("{}/config/validate", device),
+            files: format!("{}/files", device),
+            self_: device,
+        }
+    }
+}
+
+/// Query parameters accepted by `GET /devices/:id`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct DeviceQuery {
+    /// Include `_links`.
+    #[serde(default)]
+    pub links: bool,
 }
 
 /// Converts a Device entity to a DeviceResponse DTO for JSON serialization.
@@ -31,30 +359,571 @@ impl From<&Device> for DeviceResponse {
             name: d.name.clone(),
             board_type: d.board_type.clone(),
             project_path: d.project_path.clone(),
+            tags: d.tags.clone(),
+            default_port: d.default_port.clone(),
+            default_env: d.default_env.clone(),
+            location: d.location.clone(),
+            archived: d.archived,
+            ip_address: d.ip_address.clone(),
+            description: d.description.clone(),
+            env_vars: {
+                let mut names: Vec<String> = d.env_vars.keys().cloned().collect();
+                names.sort();
+                names
+            },
+            last_seen: d.last_seen,
+            created_at: d.created_at,
+            updated_at: d.updated_at,
+            version: d.version,
+            online: false,
+            initialized: None,
+            links: None,
         }
     }
 }
 
+/// Body of `GET /version`. `git_commit` and `rustc_version` are recorded at build time and are
+/// `None` when they couldn't be determined; `platformio_version` is `None` if it isn't installed.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct VersionResponse {
+    pub server_version: String,
+    pub git_commit: Option<String>,
+    pub rustc_version: Option<String>,
+    pub platformio_version: Option<String>,
+}
+
+/// Result of `GET /devices/:id/ping`; `latency_ms` is the TCP handshake time when reachable.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PingResponse {
+    pub reachable: bool,
+    pub latency_ms: Option<u64>,
+}
+
 #[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct BuildRequest {
     pub device_id: Uuid,
+    /// Run `platformio run --target checkprogsize` instead of a full build (see `PlatformIOService::build_project`).
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Pass `-v` to `platformio run`; the (large) verbose output is returned in full.
+    #[serde(default)]
+    pub verbose: bool,
+    /// Extra compiler flags like `-DDEBUG=1`, replacing the ini's `build_flags` for this build.
+    #[serde(default)]
+    pub build_flags: Vec<String>,
+    /// Environment variables for this build only. They are merged over the device's `env_vars`,
+    /// so a key defined in both takes the value given here.
+    #[serde(default)]
+    pub env_vars: HashMap<String, String>,
+    /// Truncate the returned output after this many bytes instead of the server's
+    /// `MAX_OUTPUT_BYTES` (default 256 KiB).
+    pub max_output_bytes: Option<usize>,
+    /// `platformio.ini` environment to build; defaults to the device's `default_env`, else
+    /// every environment.
+    pub environment: Option<String>,
+    /// Remove ANSI color codes from the returned output (default `true`); `false` returns
+    /// PlatformIO's output as printed.
+    pub strip_ansi: Option<bool>,
+}
+
+/// Query parameters accepted by `GET /devices/:id/build/command`, mirroring `BuildRequest`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct BuildCommandQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub verbose: bool,
+    /// Extra compiler flags separated by spaces, e.g. `-DDEBUG=1 -DLAB=2`.
+    pub build_flags: Option<String>,
+    pub environment: Option<String>,
+}
+
+/// Query parameters accepted by `GET /devices/:id/upload/command`, mirroring `UploadRequest`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct UploadCommandQuery {
+    pub port: Option<String>,
+    pub upload_protocol: Option<String>,
+    pub environment: Option<String>,
 }
 
+/// Command the server would run: `argv` starts with the PlatformIO binary and `working_dir` is
+/// the resolved project directory. `env_vars` names the variables set on the process; their
+/// values are not shown.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PlannedCommandResponse {
+    pub argv: Vec<String>,
+    pub working_dir: String,
+    pub env_vars: Vec<String>,
+}
+
+/// Port precedence: `port` if given, else the device's `default_port`, else PlatformIO's
+/// auto-detection.
 #[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct UploadRequest {
     pub device_id: Uuid,
     pub port: Option<String>,
+    /// `esptool` (serial), `espota` (OTA) or `esp-prog` (JTAG); the project's own setting,
+    /// serial esptool by default, when omitted. `espota` uploads to `port`, falling back to the
+    /// device's `ip_address`; `esp-prog` needs no port.
+    pub upload_protocol: Option<String>,
+    /// `platformio.ini` environment to upload; defaults to the device's `default_env`.
+    pub environment: Option<String>,
+    #[serde(default)]
+    pub output_encoding: OutputEncoding,
+}
+
+/// How flashing commands return PlatformIO's output. `text` (the default) replaces bytes that
+/// aren't UTF-8; `base64` puts the exact bytes base64-encoded in `output`; `raw` answers with
+/// the bytes themselves as `application/octet-stream` instead of a CommandResponse.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum OutputEncoding {
+    #[default]
+    Text,
+    Base64,
+    Raw,
+}
+
+/// Body of `POST /devices/upload-batch`. Each device uploads to its `port_map` entry, else its
+/// `default_port`, else PlatformIO's auto-detection.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BatchUploadRequest {
+    pub device_ids: Vec<Uuid>,
+    #[serde(default)]
+    pub port_map: HashMap<Uuid, String>,
+}
+
+/// Outcome of one device's upload within a batch.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BatchUploadResult {
+    pub device_id: Uuid,
+    pub success: bool,
+    pub output: String,
+    pub error: Option<String>,
+}
+
+/// Per-device results of `POST /devices/upload-batch`, in request order.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BatchUploadResponse {
+    pub results: Vec<BatchUploadResult>,
+}
+
+/// Optional body of `POST /devices/:id/upload/stream`; the device comes from the path.
+/// `port` takes precedence over the device's `default_port`, as for `UploadRequest`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UploadStreamRequest {
+    pub port: Option<String>,
+    /// Defaults to the device's `default_env`, as for `UploadRequest`.
+    pub environment: Option<String>,
+}
+
+/// Optional body of `POST /devices/:id/test/stream`; `port` and `environment` default to the
+/// device's `default_port` and `default_env`, as for uploads.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TestStreamRequest {
+    /// Serial port the test firmware is uploaded to and its results read from; ignored by
+    /// `native` environments.
+    pub port: Option<String>,
+    pub environment: Option<String>,
+}
+
+/// Query parameters accepted by `GET /templates/main`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct TemplateQuery {
+    /// Template name, `blink` (default) or `minimal`.
+    pub template: Option<String>,
+}
+
+/// Query parameters accepted by `GET /devices/:id/firmware.bin`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct FirmwareQuery {
+    /// Build environment to download; required when several have been built.
+    pub env: Option<String>,
+    /// Compress the image regardless of `Accept-Encoding`.
+    pub compress: Option<FirmwareCompression>,
+}
+
+/// Encoding a firmware download is compressed with on request; the response then carries
+/// `Content-Encoding: gzip`.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum FirmwareCompression {
+    Gzip,
+}
+
+/// Query parameters accepted by `GET /events`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct EventsQuery {
+    /// Only stream events about this device.
+    pub device_id: Option<Uuid>,
+}
+
+/// Query parameters accepted by `GET /audit`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct AuditQuery {
+    /// Most entries to return (default 50); the log never holds more than `AUDIT_LOG_SIZE`.
+    pub limit: Option<usize>,
+}
+
+/// Optional body of `POST /devices/:id/reset`; `port` overrides the device's `default_port`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ResetRequest {
+    pub port: Option<String>,
+}
+
+/// Query parameters accepted by `GET /devices/:id/partitions`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct PartitionsQuery {
+    /// Serial port of the board; defaults to the device's `default_port`.
+    pub port: Option<String>,
+}
+
+/// Partition table read from a board. `partitions` is `None` when the table couldn't be
+/// parsed, in which case `output` holds esptool's raw output instead of the table as CSV.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PartitionsResponse {
+    pub partitions: Option<Vec<PartitionEntry>>,
+    pub output: String,
+}
+
+/// Body of `POST /devices/:id/erase`; `confirm` must be `true` because erasing is destructive.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct EraseRequest {
+    #[serde(default)]
+    pub confirm: bool,
+    pub port: Option<String>,
+    #[serde(default)]
+    pub output_encoding: OutputEncoding,
+}
+
+/// Optional body of `POST /devices/:id/upload-fs`; `port` overrides the device's `default_port`.
+#[derive(Debug, Default, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UploadFsRequest {
+    pub port: Option<String>,
+    #[serde(default)]
+    pub output_encoding: OutputEncoding,
+}
+
+/// Body of `POST /devices/:id/relocate`. With `move_files` the project directory is moved to
+/// `project_path`, which must not exist yet; otherwise only the device record changes.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RelocateRequest {
+    pub project_path: String,
+    #[serde(default)]
+    pub move_files: bool,
+}
+
+/// Body of `POST /devices/:id/purge`; `confirm` must be `true` because the next build starts
+/// from scratch.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PurgeRequest {
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PurgeResponse {
+    pub success: bool,
+    /// Bytes freed by removing `.pio`; 0 if it didn't exist.
+    pub reclaimed_bytes: u64,
 }
 
 #[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct InitProjectRequest {
     pub device_id: Uuid,
+    /// Falls back to the custom board's `id`, then to the server's `DEFAULT_BOARD`, when omitted.
+    pub board: Option<String>,
+    /// Custom board JSON under the projects root, copied to the project's `boards/` directory
+    /// before init. PlatformIO checks that directory before its registry, so `board` must be the
+    /// file's `id`; registry board names keep working for projects without one.
+    pub board_json_path: Option<String>,
+    /// Replace project files that also exist in the server's `TEMPLATE_DIR`; they're kept by default.
+    #[serde(default)]
+    pub overwrite: bool,
+    /// Re-run init even if `platformio.ini` already has an environment for the board, which
+    /// otherwise returns early with `init.already_initialized` set.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Optional body of `POST /devices/:id/create-main`; `filename` is relative to `src/`
+/// (default `main.cpp`) and must end in `.cpp` or `.ino`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateMainRequest {
+    pub filename: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ScaffoldRequest {
     pub board: String,
+    #[serde(default)]
+    pub template: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct CommandResponse {
     pub success: bool,
     pub output: String,
+    /// Machine-readable reason of a failure, e.g. `PLATFORMIO_UNAVAILABLE`; see `BuildResponse::code`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Result of a build; `firmware_size` is `None` when PlatformIO didn't report usage (e.g. a failed build).
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BuildResponse {
+    pub success: bool,
+    pub output: String,
+    pub error: Option<String>,
+    pub firmware_size: Option<FirmwareSizeInfo>,
+    /// Compiler warnings and errors found in the output, in the order they were printed.
+    pub diagnostics: Vec<Diagnostic>,
+    /// Why the build couldn't start, e.g. `PROJECT_NOT_INITIALIZED` (see `ServiceError::code`);
+    /// absent when the build succeeded or PlatformIO itself failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+}
+
+/// Body of `POST /devices/:id/deploy`: the build options of `BuildRequest` (without `dry_run`)
+/// and the upload options of `UploadRequest`. Port precedence is the same as for uploads.
+#[derive(Debug, Default, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(default)]
+pub struct DeployRequest {
+    pub verbose: bool,
+    pub build_flags: Vec<String>,
+    /// Merged over the device's `env_vars`, as for builds.
+    pub env_vars: HashMap<String, String>,
+    pub max_output_bytes: Option<usize>,
+    /// Environment built and uploaded; defaults to the device's `default_env`.
+    pub environment: Option<String>,
+    /// Applies to the build output, as for `BuildRequest`.
+    pub strip_ansi: Option<bool>,
+    pub port: Option<String>,
+    pub upload_protocol: Option<String>,
+}
+
+/// Overall outcome of a deploy.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum DeployStatus {
+    Succeeded,
+    /// The build failed, so the upload was skipped.
+    BuildFailed,
+    UploadFailed,
+    /// Neither phase ran, e.g. because the project isn't initialized or is busy.
+    NotStarted,
+}
+
+/// Output of one deploy phase.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DeployPhaseResponse {
+    pub success: bool,
+    pub output: String,
+    /// Why the phase failed; see `BuildResponse::code`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// Result of `POST /devices/:id/deploy`. `build` and `upload` are absent for phases that didn't run.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DeployResponse {
+    pub success: bool,
+    pub status: DeployStatus,
+    pub build: Option<DeployPhaseResponse>,
+    pub upload: Option<DeployPhaseResponse>,
+    pub firmware_size: Option<FirmwareSizeInfo>,
+    /// Why the deploy didn't start; see `BuildResponse::code`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Result of a project init; `init` describes what PlatformIO set up.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct InitResponse {
+    pub success: bool,
+    pub output: String,
+    pub error: Option<String>,
+    pub init: Option<InitResult>,
+}
+
+/// Query parameters accepted by `GET /devices/:id/build/log`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct BuildLogQuery {
+    /// How many of the most recent lines to return (default 100).
+    pub lines: Option<usize>,
+}
+
+/// Query parameters accepted by `GET /devices/:id/build/wait`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct BuildWaitQuery {
+    /// Seconds to wait for the running build (default 30, at most 300).
+    pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BuildLogResponse {
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UpdatesResponse {
+    pub updates: Vec<PackageUpdate>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LibrariesResponse {
+    pub libraries: Vec<LibraryInfo>,
+}
+
+/// Body of `POST /devices/:id/pio`: an allowlisted PlatformIO subcommand and its arguments,
+/// e.g. `{"subcommand": "system", "args": ["info"]}`.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PioCommandRequest {
+    pub subcommand: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Result of auto-assigning a device's port. On success `port` is the new `default_port`;
+/// otherwise `error` says why and `ports` lists the candidates to choose from.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AutoPortResponse {
+    pub success: bool,
+    pub port: Option<String>,
+    pub ports: Vec<SerialPortInfo>,
     pub error: Option<String>,
 }
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CancelBuildResponse {
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SourceFilesResponse {
+    pub files: Vec<String>,
+}
+
+/// Result of linting platformio.ini text; `errors` is empty when `valid`.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ConfigValidationResponse {
+    pub valid: bool,
+    pub errors: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(
+        name: &str,
+        board_type: Option<&str>,
+        project_path: Option<&str>,
+    ) -> DeviceCreateRequest {
+        DeviceCreateRequest {
+            name: name.to_string(),
+            board_type: board_type.map(BoardType::from),
+            board_id: None,
+            project_path: project_path.map(str::to_string),
+            tags: Vec::new(),
+            default_port: None,
+            default_env: None,
+            location: None,
+            description: None,
+        }
+    }
+
+    /// Test that create requests are validated per field with readable messages.
+    #[test]
+    fn device_create_request_validation() {
+        assert!(request("lab-01", None, None).validate().is_ok());
+        assert!(request(
+            "lab-01",
+            Some("esp32-s3-devkitc-1"),
+            Some("/srv/projects/lab-01")
+        )
+        .validate()
+        .is_ok());
+
+        let err = request("", Some("esp32 dev"), Some("bad\npath"))
+            .validate()
+            .unwrap_err();
+        let body = ValidationErrorResponse::from(&err);
+        assert_eq!(body.errors.len(), 3);
+        assert_eq!(
+            body.errors["name"],
+            vec!["must be between 1 and 100 characters"]
+        );
+        assert!(body.errors.contains_key("board_type"));
+        assert!(body.errors.contains_key("project_path"));
+
+        let long = DeviceCreateRequest {
+            description: Some("x".repeat(1001)),
+            ..request("lab-01", None, None)
+        };
+        let body = ValidationErrorResponse::from(&long.validate().unwrap_err());
+        assert_eq!(
+            body.errors["description"],
+            vec!["must be at most 1000 characters"]
+        );
+    }
+}
@@ -1,3 +1,17 @@
 pub mod device_dto;
 
-pub use device_dto::{DeviceCreateRequest, DeviceResponse, BuildRequest, UploadRequest, InitProjectRequest, CommandResponse};
+pub use device_dto::{
+    AuditQuery, AutoPortResponse, BatchGetRequest, BatchUploadRequest, BatchUploadResponse,
+    BatchUploadResult, BuildCommandQuery, BuildLogQuery, BuildLogResponse, BuildRequest,
+    BuildResponse, BuildWaitQuery, CancelBuildResponse, CloneDeviceRequest, CommandResponse,
+    ConfigValidationResponse, CreateMainRequest, DeployPhaseResponse, DeployRequest,
+    DeployResponse, DeployStatus, DeviceCreateRequest, DeviceLinks, DeviceQuery, DeviceResponse,
+    DeviceUpdateRequest, EraseRequest, ErrorResponse, EventsQuery, FirmwareCompression,
+    FirmwareQuery, ImportQuery, InitProjectRequest, InitResponse, InventoryDevice,
+    InventoryDocument, LibrariesResponse, ListDevicesQuery, OutputEncoding, PartitionsQuery,
+    PartitionsResponse, PingResponse, PioCommandRequest, PlannedCommandResponse, PurgeRequest,
+    PurgeResponse, RelocateRequest, ResetRequest, ScaffoldRequest, SourceFilesResponse,
+    TemplateQuery, TestStreamRequest, UpdatesResponse, UploadCommandQuery, UploadFsRequest,
+    UploadRequest, UploadStreamRequest, ValidationErrorResponse, VersionResponse,
+    INVENTORY_FORMAT_VERSION,
+};
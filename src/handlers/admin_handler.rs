@@ -0,0 +1,158 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::Extension,
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use tokio::sync::Notify;
+
+use crate::dto::CommandResponse;
+
+/// Admin credentials and the trigger `POST /admin/shutdown` fires.
+/// Without an API key every admin request is rejected, so admin routes are unreachable.
+#[derive(Clone, Default)]
+pub struct AdminContext {
+    api_key: Option<String>,
+    shutdown: Arc<Notify>,
+}
+
+impl AdminContext {
+    /// Admin context accepting `Authorization: Bearer <api_key>`; `None` disables admin routes.
+    pub fn new(api_key: Option<String>) -> Self {
+        Self {
+            api_key: api_key.filter(|k| !k.is_empty()),
+            shutdown: Arc::default(),
+        }
+    }
+
+    /// Admin context using the `ADMIN_API_KEY` env var.
+    pub fn from_env() -> Self {
+        Self::new(std::env::var("ADMIN_API_KEY").ok())
+    }
+
+    /// Whether admin routes can be reached at all.
+    pub fn enabled(&self) -> bool {
+        self.api_key.is_some()
+    }
+
+    /// Resolves once an authorized shutdown request has been accepted.
+    pub async fn shutdown_requested(&self) {
+        self.shutdown.notified().await
+    }
+
+    /// The `Authorization: Bearer` token, if it matches the admin API key (compared in
+    /// constant time). Unverified tokens are `None`, so callers never trust a client-chosen key.
+    pub fn verified_key<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        let expected = self.api_key.as_ref()?;
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))?;
+        let matches = token.len() == expected.len()
+            && token
+                .bytes()
+                .zip(expected.bytes())
+                .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+                == 0;
+        matches.then_some(token)
+    }
+
+    /// Whether the request carries the admin API key.
+    pub(crate) fn authorized(&self, headers: &HeaderMap) -> bool {
+        self.verified_key(headers).is_some()
+    }
+}
+
+/// HTTP handler to start a graceful shutdown, as if the process received Ctrl+C.
+/// Requires the admin bearer token; in-flight requests, including builds, are drained first.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/admin/shutdown",
+    tag = "admin",
+    responses(
+        (status = 202, description = "Graceful shutdown started", body = CommandResponse),
+        (status = 401, description = "Missing or wrong admin API key", body = CommandResponse),
+    )
+))]
+pub async fn shutdown(
+    Extension(admin): Extension<AdminContext>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if !admin.authorized(&headers) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(CommandResponse {
+                success: false,
+                output: "".to_string(),
+                code: None,
+                error: Some("Admin authentication required".to_string()),
+            }),
+        )
+            .into_response();
+    }
+
+    admin.shutdown.notify_one();
+    (
+        StatusCode::ACCEPTED,
+        Json(CommandResponse {
+            success: true,
+            output: "Shutting down after in-flight requests finish".to_string(),
+            code: None,
+            error: None,
+        }),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        headers
+    }
+
+    async fn status(admin: &AdminContext, headers: HeaderMap) -> StatusCode {
+        shutdown(Extension(admin.clone()), headers)
+            .await
+            .into_response()
+            .status()
+    }
+
+    /// Test that shutdown needs the exact admin key and then fires the shutdown trigger.
+    #[tokio::test]
+    async fn shutdown_requires_admin_key() {
+        let disabled = AdminContext::new(None);
+        assert_eq!(
+            status(&disabled, bearer("")).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        let admin = AdminContext::new(Some("s3cret".to_string()));
+        assert_eq!(
+            status(&admin, HeaderMap::new()).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(&admin, bearer("s3cre")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(&admin, bearer("wrong!")).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        assert_eq!(status(&admin, bearer("s3cret")).await, StatusCode::ACCEPTED);
+        tokio::time::timeout(Duration::from_secs(1), admin.shutdown_requested())
+            .await
+            .expect("shutdown was not triggered");
+    }
+}
@@ -0,0 +1,56 @@
+use axum::{
+    extract::{Extension, Query},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::domain::OperationRecord;
+use crate::dto::{AuditQuery, CommandResponse};
+use crate::handlers::AdminContext;
+use crate::service::OperationLog;
+
+/// Entries returned by `GET /audit` without a `limit`.
+const DEFAULT_LIMIT: usize = 50;
+
+/// API key the request was made with, when its `Authorization: Bearer` token is the verified
+/// admin key; any other token is not recorded, so clients cannot write made-up keys into the
+/// log. Only passed to `OperationLog::record`, which keeps just a prefix.
+pub(crate) fn request_api_key<'a>(admin: &AdminContext, headers: &'a HeaderMap) -> Option<&'a str> {
+    admin.verified_key(headers)
+}
+
+/// HTTP handler listing the most recent builds, uploads and cleans, newest first.
+/// The log is in memory and sized by `AUDIT_LOG_SIZE`, so it starts empty after a restart.
+/// Requires the admin bearer token, like `POST /admin/shutdown`.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/audit",
+    tag = "audit",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "Recent operations, newest first", body = [OperationRecord]),
+        (status = 401, description = "Missing or wrong admin API key", body = CommandResponse),
+    )
+))]
+pub async fn audit_log(
+    Extension(operation_log): Extension<std::sync::Arc<OperationLog>>,
+    Extension(admin): Extension<AdminContext>,
+    headers: HeaderMap,
+    Query(query): Query<AuditQuery>,
+) -> Response {
+    if !admin.authorized(&headers) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(CommandResponse {
+                success: false,
+                output: "".to_string(),
+                code: None,
+                error: Some("Admin authentication required".to_string()),
+            }),
+        )
+            .into_response();
+    }
+    Json::<Vec<OperationRecord>>(operation_log.recent(query.limit.unwrap_or(DEFAULT_LIMIT)))
+        .into_response()
+}
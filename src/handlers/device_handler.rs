@@ -1,70 +1,822 @@
-use axum::{extract::Extension, http::StatusCode, response::IntoResponse, Json};
+use axum::{
+    extract::{Extension, OriginalUri, Query},
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    response::IntoResponse,
+    Json,
+};
 use uuid::Uuid;
+use validator::Validate;
 
-use crate::dto::{DeviceCreateRequest, DeviceResponse};
-use crate::service::DeviceService;
+use crate::domain::{Device, DeviceEvent, DeviceFilter, DeviceSort, DeviceUpdate, NewDevice};
+use crate::dto::{
+    BatchGetRequest, CloneDeviceRequest, DeviceCreateRequest, DeviceLinks, DeviceQuery,
+    DeviceResponse, DeviceUpdateRequest, ImportQuery, InventoryDocument, ListDevicesQuery,
+    RelocateRequest, ValidationErrorResponse, INVENTORY_FORMAT_VERSION,
+};
+use crate::handlers::error::{error_response, internal_error, service_error_status};
+use crate::handlers::JsonFormat;
+use crate::middleware::RequestId;
+use crate::service::{DeviceService, EventBus, PlatformIOService};
 
 /// HTTP handler to create a new device.
-/// Calls DeviceService::create with payload data, returns JSON DeviceResponse on success.
+/// Validates the payload (422 with per-field messages on failure), then calls DeviceService::create
+/// and returns JSON DeviceResponse on success. A replayed `Idempotency-Key` header returns the
+/// device the key originally created with 200 instead of creating a duplicate.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices",
+    tag = "device",
+    request_body = DeviceCreateRequest,
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Replays return the original device for 24 hours"),
+    ),
+    responses(
+        (status = 200, description = "Idempotency-Key replayed; the originally created device", body = DeviceResponse),
+        (status = 201, description = "Device created", body = DeviceResponse),
+        (status = 409, description = "board_id already registered", body = crate::dto::ErrorResponse),
+        (status = 422, description = "Validation failed", body = ValidationErrorResponse),
+        (status = 500, description = "Repository error", body = crate::dto::ErrorResponse),
+    )
+))]
 pub async fn create_device(
     Extension(service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(event_bus): Extension<std::sync::Arc<EventBus>>,
+    format: JsonFormat,
+    request_id: Option<Extension<RequestId>>,
+    headers: HeaderMap,
     Json(payload): Json<DeviceCreateRequest>,
 ) -> impl IntoResponse {
+    let idempotency_key = headers
+        .get("idempotency-key")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    if let Some(key) = &idempotency_key {
+        match service.replay_create(key).await {
+            Ok(Some(device)) => {
+                return (
+                    StatusCode::OK,
+                    format.json(device_response(&service, &device)),
+                )
+                    .into_response()
+            }
+            Ok(None) => {}
+            Err(e) => {
+                return internal_error(
+                    "failed to look up idempotency key",
+                    &e,
+                    request_id.as_deref(),
+                )
+            }
+        }
+    }
+
+    if let Err(e) = payload.validate() {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ValidationErrorResponse::from(&e)),
+        )
+            .into_response();
+    }
+
+    let new = NewDevice {
+        board_id: payload.board_id,
+        board_type: payload.board_type,
+        project_path: payload.project_path,
+        tags: payload.tags,
+        default_port: payload.default_port,
+        description: payload.description,
+        default_env: payload.default_env,
+        location: payload.location,
+    };
+    match service.create(payload.name, new).await {
+        Ok(device) => {
+            if let Some(key) = &idempotency_key {
+                service.remember_create(key, device.id);
+            }
+            event_bus.publish(DeviceEvent::DeviceCreated {
+                device_id: device.id,
+            });
+            (
+                StatusCode::CREATED,
+                format.json(device_response(&service, &device)),
+            )
+                .into_response()
+        }
+        Err(e) => match service_error_status(&e) {
+            Some(status) => error_response(status, e.to_string()),
+            None => internal_error("failed to create device", &e, request_id.as_deref()),
+        },
+    }
+}
+
+/// HTTP handler to clone a device.
+/// Validates the payload, then calls DeviceService::clone_device and returns the new device with 201.
+/// The clone gets the source's board type and tags but no project path.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/clone",
+    tag = "device",
+    request_body = CloneDeviceRequest,
+    params(
+        ("id" = Uuid, Path, description = "ID of the device to clone"),
+    ),
+    responses(
+        (status = 201, description = "Device cloned", body = DeviceResponse),
+        (status = 400, description = "Invalid device ID", body = crate::dto::ErrorResponse),
+        (status = 404, description = "Source device not found", body = crate::dto::ErrorResponse),
+        (status = 409, description = "board_id already registered", body = crate::dto::ErrorResponse),
+        (status = 422, description = "Validation failed", body = ValidationErrorResponse),
+        (status = 500, description = "Repository error", body = crate::dto::ErrorResponse),
+    )
+))]
+pub async fn clone_device(
+    Extension(service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(event_bus): Extension<std::sync::Arc<EventBus>>,
+    format: JsonFormat,
+    request_id: Option<Extension<RequestId>>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Json(payload): Json<CloneDeviceRequest>,
+) -> impl IntoResponse {
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return error_response(StatusCode::BAD_REQUEST, "invalid uuid");
+    };
+    if let Err(e) = payload.validate() {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ValidationErrorResponse::from(&e)),
+        )
+            .into_response();
+    }
+
     match service
-        .create(payload.name, payload.board_id, payload.board_type, payload.project_path)
+        .clone_device(id, payload.name, payload.board_id)
         .await
     {
-        Ok(device) => (StatusCode::CREATED, Json(DeviceResponse::from(&device))).into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("failed to create device: {}", e),
-        )
-            .into_response(),
+        Ok(device) => {
+            event_bus.publish(DeviceEvent::DeviceCreated {
+                device_id: device.id,
+            });
+            (
+                StatusCode::CREATED,
+                format.json(device_response(&service, &device)),
+            )
+                .into_response()
+        }
+        Err(e) => match service_error_status(&e) {
+            Some(status) => error_response(status, e.to_string()),
+            None => internal_error("failed to clone device", &e, request_id.as_deref()),
+        },
     }
 }
 
 /// HTTP handler to retrieve a device by ID.
 /// Parses UUID from path, calls DeviceService::get, handles not-found and errors.
+/// With `?links=true` the response carries `_links` to the device's endpoints.
+/// 200 responses carry `ETag` and `Last-Modified`; a matching `If-None-Match`, or else an
+/// `If-Modified-Since` no older than `Last-Modified`, gets an empty 304 instead.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/{id}",
+    tag = "device",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a previous response"),
+        ("If-Modified-Since" = Option<String>, Header, description = "Last-Modified of a previous response"),
+        DeviceQuery,
+    ),
+    responses(
+        (status = 200, description = "Device found", body = DeviceResponse),
+        (status = 304, description = "Device unchanged since the conditional headers' response"),
+        (status = 400, description = "Invalid device ID", body = crate::dto::ErrorResponse),
+        (status = 404, description = "Device not found", body = crate::dto::ErrorResponse),
+        (status = 500, description = "Repository error", body = crate::dto::ErrorResponse),
+    )
+))]
 pub async fn get_device(
     Extension(service): Extension<std::sync::Arc<DeviceService>>,
+    format: JsonFormat,
+    request_id: Option<Extension<RequestId>>,
+    OriginalUri(uri): OriginalUri,
     axum::extract::Path(id): axum::extract::Path<String>,
+    Query(query): Query<DeviceQuery>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let parsed = Uuid::parse_str(&id);
-    if let Err(_) = parsed {
-        return (StatusCode::BAD_REQUEST, "invalid uuid").into_response();
+    if parsed.is_err() {
+        return error_response(StatusCode::BAD_REQUEST, "invalid uuid");
     }
     let id = parsed.unwrap();
 
     match service.get(id).await {
-        Ok(Some(device)) => (StatusCode::OK, Json(DeviceResponse::from(&device))).into_response(),
-        Ok(None) => (StatusCode::NOT_FOUND, "not found").into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("failed to find device: {}", e),
-        )
-            .into_response(),
+        Ok(Some(device)) => {
+            let mut response = device_response(&service, &device);
+            let etag = device_etag(&device, response.online);
+            let last_modified = service.last_modified(&device);
+            let mut http_response = if is_not_modified(&headers, &etag, last_modified) {
+                StatusCode::NOT_MODIFIED.into_response()
+            } else {
+                if query.links {
+                    response.links = Some(DeviceLinks::new(base_path(&uri), device.id));
+                }
+                (StatusCode::OK, format.json(response)).into_response()
+            };
+            let response_headers = http_response.headers_mut();
+            if let Ok(value) = HeaderValue::from_str(&etag) {
+                response_headers.insert(header::ETAG, value);
+            }
+            if let Some(value) =
+                last_modified.and_then(|t| HeaderValue::from_str(&http_date(t)).ok())
+            {
+                response_headers.insert(header::LAST_MODIFIED, value);
+            }
+            http_response
+        }
+        Ok(None) => error_response(StatusCode::NOT_FOUND, "not found"),
+        Err(e) => internal_error("failed to find device", &e, request_id.as_deref()),
+    }
+}
+
+/// HTTP handler probing whether a device ID is taken, without fetching the device.
+/// Parses UUID from path, calls DeviceService::exists; responses carry no body.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    head,
+    path = "/devices/{id}",
+    tag = "device",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Device exists"),
+        (status = 400, description = "Invalid device ID"),
+        (status = 404, description = "Device not found"),
+        (status = 500, description = "Repository error"),
+    )
+))]
+pub async fn device_exists(
+    Extension(service): Extension<std::sync::Arc<DeviceService>>,
+    request_id: Option<Extension<RequestId>>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    match service.exists(id).await {
+        Ok(true) => StatusCode::OK.into_response(),
+        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+        // The body is dropped for HEAD, but the 500 is still logged with its correlation id
+        Err(e) => internal_error("failed to check device", &e, request_id.as_deref()),
     }
 }
 
 /// HTTP handler to list all devices.
-/// Calls DeviceService::list, returns JSON array of DeviceResponse on success.
+/// Calls DeviceService::search with the `?search=`, `?tag=` and `?board_type=` filters (all must
+/// match), returns JSON array of DeviceResponse on success.
+/// Archived devices are skipped unless `?include_archived=true`. With `?check_init=true` each
+/// response also reports whether the project has been initialized (one filesystem check per device),
+/// and with `?links=true` carries `_links` to the device's endpoints.
+/// `?sort=` orders by name or creation time (400 for anything else); id order by default.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices",
+    tag = "device",
+    params(ListDevicesQuery),
+    responses(
+        (status = 200, description = "Matching devices", body = [DeviceResponse]),
+        (status = 400, description = "Unknown sort key", body = crate::dto::ErrorResponse),
+        (status = 500, description = "Repository error", body = crate::dto::ErrorResponse),
+    )
+))]
 pub async fn list_devices(
     Extension(service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    format: JsonFormat,
+    request_id: Option<Extension<RequestId>>,
+    OriginalUri(uri): OriginalUri,
+    Query(query): Query<ListDevicesQuery>,
+) -> impl IntoResponse {
+    let sort = match query.sort.as_deref().map(DeviceSort::parse) {
+        Some(Some(sort)) => sort,
+        Some(None) => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "sort must be one of name, -name, created_at or -created_at",
+            )
+        }
+        None => DeviceSort::default(),
+    };
+    let filter = DeviceFilter {
+        name: query.search,
+        tag: query.tag,
+        board_type: query.board_type,
+        location: query.location,
+        include_archived: query.include_archived,
+        sort,
+    };
+    match service.search(&filter).await {
+        Ok(list) => {
+            let mut responses = Vec::with_capacity(list.len());
+            for device in &list {
+                let mut response = device_response(&service, device);
+                if query.check_init {
+                    response.initialized = Some(match &device.project_path {
+                        Some(path) => pio_service.is_initialized(path).await,
+                        None => false,
+                    });
+                }
+                if query.links {
+                    response.links = Some(DeviceLinks::new(base_path(&uri), device.id));
+                }
+                responses.push(response);
+            }
+            (StatusCode::OK, format.json(responses)).into_response()
+        }
+        Err(e) => internal_error("failed to list devices", &e, request_id.as_deref()),
+    }
+}
+
+/// HTTP handler to fetch several devices at once.
+/// Calls DeviceService::get_many with the requested IDs, returns the found devices (missing IDs are skipped).
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/batch-get",
+    tag = "device",
+    request_body = BatchGetRequest,
+    responses(
+        (status = 200, description = "Devices found; missing IDs are skipped", body = [DeviceResponse]),
+        (status = 500, description = "Repository error", body = crate::dto::ErrorResponse),
+    )
+))]
+pub async fn batch_get_devices(
+    Extension(service): Extension<std::sync::Arc<DeviceService>>,
+    format: JsonFormat,
+    Json(payload): Json<BatchGetRequest>,
 ) -> impl IntoResponse {
-    match service.list().await {
+    match service.get_many(&payload.ids).await {
         Ok(list) => (
             StatusCode::OK,
-            Json(
+            format.json(
                 list.iter()
-                    .map(|d| DeviceResponse::from(d))
+                    .map(|d| device_response(&service, d))
                     .collect::<Vec<_>>(),
             ),
         )
             .into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("failed to list devices: {}", e),
+            format!("failed to get devices: {}", e),
         )
             .into_response(),
     }
 }
+
+/// HTTP handler to export every device, archived ones included, as an InventoryDocument.
+/// The document can be fed to `POST /devices/import` on this or another server.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/export",
+    tag = "device",
+    responses(
+        (status = 200, description = "All devices", body = InventoryDocument),
+        (status = 500, description = "Repository error", body = crate::dto::ErrorResponse),
+    )
+))]
+pub async fn export_devices(
+    Extension(service): Extension<std::sync::Arc<DeviceService>>,
+    format: JsonFormat,
+    request_id: Option<Extension<RequestId>>,
+) -> impl IntoResponse {
+    match service.list(true).await {
+        Ok(list) => {
+            let document = InventoryDocument {
+                format_version: INVENTORY_FORMAT_VERSION,
+                exported_at: chrono::Utc::now(),
+                devices: list.iter().map(Into::into).collect(),
+            };
+            (StatusCode::OK, format.json(document)).into_response()
+        }
+        Err(e) => internal_error("failed to export devices", &e, request_id.as_deref()),
+    }
+}
+
+/// HTTP handler to recreate the devices of an InventoryDocument.
+/// Calls DeviceService::import with `?preserve_ids=` and `?on_conflict=skip|overwrite`; returns
+/// which devices were created, overwritten, skipped or rejected as invalid. Devices before a
+/// repository failure stay imported.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/import",
+    tag = "device",
+    request_body = InventoryDocument,
+    params(ImportQuery),
+    responses(
+        (status = 200, description = "Devices imported", body = crate::domain::ImportReport),
+        (status = 400, description = "Unsupported format_version", body = crate::dto::ErrorResponse),
+        (status = 500, description = "Repository error", body = crate::dto::ErrorResponse),
+    )
+))]
+pub async fn import_devices(
+    Extension(service): Extension<std::sync::Arc<DeviceService>>,
+    format: JsonFormat,
+    request_id: Option<Extension<RequestId>>,
+    Query(query): Query<ImportQuery>,
+    Json(payload): Json<InventoryDocument>,
+) -> impl IntoResponse {
+    if payload.format_version != INVENTORY_FORMAT_VERSION {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "unsupported format_version {}; expected {}",
+                payload.format_version, INVENTORY_FORMAT_VERSION
+            ),
+        );
+    }
+    let devices = payload.devices.into_iter().map(Device::from).collect();
+    match service
+        .import(devices, query.preserve_ids, query.on_conflict)
+        .await
+    {
+        Ok(report) => (StatusCode::OK, format.json(report)).into_response(),
+        Err(e) => internal_error("failed to import devices", &e, request_id.as_deref()),
+    }
+}
+
+/// HTTP handler to partially update a device.
+/// Parses UUID from path, calls DeviceService::update with the provided fields, handles not-found and errors.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    patch,
+    path = "/devices/{id}",
+    tag = "device",
+    request_body = DeviceUpdateRequest,
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Device updated", body = DeviceResponse),
+        (status = 400, description = "Invalid device ID", body = String),
+        (status = 404, description = "Device not found", body = String),
+        (status = 409, description = "Device was modified since `version` was read", body = String),
+        (status = 422, description = "Validation failed", body = ValidationErrorResponse),
+        (status = 500, description = "Repository error", body = String),
+    )
+))]
+pub async fn update_device(
+    Extension(service): Extension<std::sync::Arc<DeviceService>>,
+    format: JsonFormat,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Json(payload): Json<DeviceUpdateRequest>,
+) -> impl IntoResponse {
+    let parsed = Uuid::parse_str(&id);
+    if parsed.is_err() {
+        return (StatusCode::BAD_REQUEST, "invalid uuid").into_response();
+    }
+    let id = parsed.unwrap();
+
+    if let Err(e) = payload.validate() {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ValidationErrorResponse::from(&e)),
+        )
+            .into_response();
+    }
+
+    let changes = DeviceUpdate {
+        name: payload.name,
+        board_type: payload.board_type,
+        project_path: payload.project_path,
+        tags: payload.tags,
+        default_port: payload.default_port,
+        default_env: payload.default_env,
+        location: payload.location,
+        ip_address: payload.ip_address,
+        description: payload.description,
+        env_vars: payload.env_vars,
+        expected_version: Some(payload.version),
+    };
+    match service.update(id, changes).await {
+        Ok(Some(device)) => (
+            StatusCode::OK,
+            format.json(device_response(&service, &device)),
+        )
+            .into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "not found").into_response(),
+        Err(e) => match service_error_status(&e) {
+            Some(status) => (status, e.to_string()).into_response(),
+            None => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("failed to update device: {}", e),
+            )
+                .into_response(),
+        },
+    }
+}
+
+/// HTTP handler to archive a device, hiding it from default listings.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/archive",
+    tag = "device",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Device archived", body = DeviceResponse),
+        (status = 400, description = "Invalid device ID", body = String),
+        (status = 404, description = "Device not found", body = String),
+        (status = 500, description = "Repository error", body = String),
+    )
+))]
+pub async fn archive_device(
+    Extension(service): Extension<std::sync::Arc<DeviceService>>,
+    format: JsonFormat,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    set_archived(&service, format, &id, true).await
+}
+
+/// HTTP handler to unarchive a device.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/unarchive",
+    tag = "device",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Device unarchived", body = DeviceResponse),
+        (status = 400, description = "Invalid device ID", body = String),
+        (status = 404, description = "Device not found", body = String),
+        (status = 500, description = "Repository error", body = String),
+    )
+))]
+pub async fn unarchive_device(
+    Extension(service): Extension<std::sync::Arc<DeviceService>>,
+    format: JsonFormat,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    set_archived(&service, format, &id, false).await
+}
+
+/// Parses UUID from path, calls DeviceService::set_archived, handles not-found and errors.
+async fn set_archived(
+    service: &DeviceService,
+    format: JsonFormat,
+    id: &str,
+    archived: bool,
+) -> axum::response::Response {
+    let parsed = Uuid::parse_str(id);
+    if parsed.is_err() {
+        return (StatusCode::BAD_REQUEST, "invalid uuid").into_response();
+    }
+    let id = parsed.unwrap();
+
+    match service.set_archived(id, archived).await {
+        Ok(Some(device)) => (
+            StatusCode::OK,
+            format.json(device_response(service, &device)),
+        )
+            .into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "not found").into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("failed to archive device: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// HTTP handler recording a heartbeat from a board running lab firmware.
+/// Calls DeviceService::record_heartbeat and returns the device, which is now online.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/heartbeat",
+    tag = "device",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Heartbeat recorded", body = DeviceResponse),
+        (status = 400, description = "Invalid device ID", body = crate::dto::ErrorResponse),
+        (status = 404, description = "Device not found", body = crate::dto::ErrorResponse),
+        (status = 500, description = "Repository error", body = crate::dto::ErrorResponse),
+    )
+))]
+pub async fn heartbeat(
+    Extension(service): Extension<std::sync::Arc<DeviceService>>,
+    format: JsonFormat,
+    request_id: Option<Extension<RequestId>>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return error_response(StatusCode::BAD_REQUEST, "invalid uuid");
+    };
+
+    match service.record_heartbeat(id).await {
+        Ok(Some(device)) => (
+            StatusCode::OK,
+            format.json(device_response(&service, &device)),
+        )
+            .into_response(),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, "not found"),
+        Err(e) => internal_error("failed to record heartbeat", &e, request_id.as_deref()),
+    }
+}
+
+/// HTTP handler giving a device a new board_id, e.g. after its board was physically replaced.
+/// Calls DeviceService::regenerate_board_id and returns the device with the new board_id.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/regenerate-board-id",
+    tag = "device",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "New board_id assigned", body = DeviceResponse),
+        (status = 400, description = "Invalid device ID", body = crate::dto::ErrorResponse),
+        (status = 404, description = "Device not found", body = crate::dto::ErrorResponse),
+        (status = 409, description = "Device was modified concurrently", body = crate::dto::ErrorResponse),
+        (status = 500, description = "Repository error", body = crate::dto::ErrorResponse),
+    )
+))]
+pub async fn regenerate_board_id(
+    Extension(service): Extension<std::sync::Arc<DeviceService>>,
+    format: JsonFormat,
+    request_id: Option<Extension<RequestId>>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return error_response(StatusCode::BAD_REQUEST, "invalid uuid");
+    };
+
+    match service.regenerate_board_id(id).await {
+        Ok(Some(device)) => (
+            StatusCode::OK,
+            format.json(device_response(&service, &device)),
+        )
+            .into_response(),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, "not found"),
+        Err(e) => match service_error_status(&e) {
+            Some(status) => error_response(status, e.to_string()),
+            None => internal_error("failed to regenerate board_id", &e, request_id.as_deref()),
+        },
+    }
+}
+
+/// HTTP handler to point a device at a new project directory.
+/// Validates that the new path is under the projects root, updates the device record, then with
+/// `move_files` moves the old directory there; the record update is rolled back if the move fails.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/relocate",
+    tag = "device",
+    request_body = RelocateRequest,
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Device relocated", body = DeviceResponse),
+        (status = 400, description = "Invalid device ID or project path, or nothing to move", body = crate::dto::ErrorResponse),
+        (status = 404, description = "Device or project directory not found", body = crate::dto::ErrorResponse),
+        (status = 409, description = "New path already exists or the project is busy", body = crate::dto::ErrorResponse),
+        (status = 500, description = "Repository or filesystem error", body = crate::dto::ErrorResponse),
+    )
+))]
+pub async fn relocate_device(
+    Extension(service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    format: JsonFormat,
+    request_id: Option<Extension<RequestId>>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Json(payload): Json<RelocateRequest>,
+) -> impl IntoResponse {
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return error_response(StatusCode::BAD_REQUEST, "invalid uuid");
+    };
+    if let Err(e) = pio_service
+        .resolve_project_path(&payload.project_path)
+        .await
+    {
+        return match service_error_status(&e) {
+            Some(status) => error_response(status, e.to_string()),
+            None => internal_error("failed to resolve project path", &e, request_id.as_deref()),
+        };
+    }
+
+    let device = match service.get(id).await {
+        Ok(Some(device)) => device,
+        Ok(None) => return error_response(StatusCode::NOT_FOUND, "not found"),
+        Err(e) => return internal_error("failed to find device", &e, request_id.as_deref()),
+    };
+    let old_path = match (&device.project_path, payload.move_files) {
+        (Some(path), _) => Some(path.clone()),
+        (None, true) => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "device has no project path to move",
+            )
+        }
+        (None, false) => None,
+    };
+
+    let relocated = DeviceUpdate {
+        project_path: Some(payload.project_path.clone()),
+        ..Default::default()
+    };
+    let updated = match service.update(id, relocated).await {
+        Ok(Some(device)) => device,
+        Ok(None) => return error_response(StatusCode::NOT_FOUND, "not found"),
+        Err(e) => {
+            return match service_error_status(&e) {
+                Some(status) => error_response(status, e.to_string()),
+                None => internal_error("failed to update device", &e, request_id.as_deref()),
+            }
+        }
+    };
+
+    if let (true, Some(old_path)) = (payload.move_files, old_path) {
+        if let Err(e) = pio_service
+            .move_project(&old_path, &payload.project_path)
+            .await
+        {
+            let restored = DeviceUpdate {
+                project_path: Some(old_path),
+                ..Default::default()
+            };
+            if let Err(rollback) = service.update(id, restored).await {
+                return internal_error(
+                    "failed to roll back device relocation",
+                    &rollback,
+                    request_id.as_deref(),
+                );
+            }
+            return match service_error_status(&e) {
+                Some(status) => error_response(status, e.to_string()),
+                None => internal_error(
+                    "failed to move project directory",
+                    &e,
+                    request_id.as_deref(),
+                ),
+            };
+        }
+    }
+
+    (
+        StatusCode::OK,
+        format.json(device_response(&service, &updated)),
+    )
+        .into_response()
+}
+
+/// The part of the request path before `/devices`, so links keep any prefix the API is served under.
+fn base_path(uri: &Uri) -> &str {
+    let path = uri.path();
+    path.find("/devices").map_or("", |i| &path[..i])
+}
+
+/// Weak ETag of a device's representation: its `version`, which every write increments, and
+/// whether it's `online`, which can change without a write.
+fn device_etag(device: &Device, online: bool) -> String {
+    format!(
+        "W/\"{}-{}\"",
+        device.version,
+        if online { "online" } else { "offline" }
+    )
+}
+
+/// Formats `time` as an HTTP date, e.g. `Tue, 13 Oct 2026 08:00:00 GMT`.
+fn http_date(time: chrono::DateTime<chrono::Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Whether a conditional GET can be answered with 304. `If-None-Match` wins when present and
+/// matches `*` or `etag` (weakly compared); otherwise `If-Modified-Since` must be at or after
+/// `last_modified`, compared in whole seconds as HTTP dates carry no more.
+fn is_not_modified(
+    headers: &HeaderMap,
+    etag: &str,
+    last_modified: Option<chrono::DateTime<chrono::Utc>>,
+) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        return if_none_match.to_str().is_ok_and(|tags| {
+            tags.split(',')
+                .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+        });
+    }
+    let since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| chrono::DateTime::parse_from_rfc2822(v).ok());
+    match (since, last_modified) {
+        (Some(since), Some(modified)) => modified.timestamp() <= since.timestamp(),
+        _ => false,
+    }
+}
+
+/// DeviceResponse for `device` with `online` derived from the service's heartbeat window.
+fn device_response(service: &DeviceService, device: &Device) -> DeviceResponse {
+    DeviceResponse {
+        online: service.is_online(device),
+        ..DeviceResponse::from(device)
+    }
+}
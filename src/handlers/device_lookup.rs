@@ -0,0 +1,152 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use uuid::Uuid;
+
+use crate::domain::Device;
+use crate::dto::CommandResponse;
+use crate::handlers::error::{service_error_code, service_error_status};
+use crate::service::DeviceService;
+
+/// Failed `CommandResponse`, the error body of the ESP32, file, stream and watch handlers.
+pub(crate) fn command_error(status: StatusCode, error: impl Into<String>) -> Response {
+    (
+        status,
+        Json(CommandResponse {
+            success: false,
+            output: "".to_string(),
+            code: None,
+            error: Some(error.into()),
+        }),
+    )
+        .into_response()
+}
+
+/// Failed `CommandResponse` for an error of a service call: a `ServiceError` gets its status
+/// (see `service_error_status`), message and `code`; anything else is a 500 prefixed with
+/// `context`, e.g. "Upload failed".
+pub(crate) fn service_command_error(e: &anyhow::Error, context: &str) -> Response {
+    let (status, error) = match service_error_status(e) {
+        Some(status) => (status, e.to_string()),
+        None => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("{}: {}", context, e),
+        ),
+    };
+    (
+        status,
+        Json(CommandResponse {
+            success: false,
+            output: "".to_string(),
+            code: service_error_code(e),
+            error: Some(error),
+        }),
+    )
+        .into_response()
+}
+
+/// Error of the lookup helpers, rendered as a failed `CommandResponse`.
+pub(crate) struct CommandError {
+    status: StatusCode,
+    error: String,
+}
+
+impl CommandError {
+    fn new(status: StatusCode, error: impl Into<String>) -> Self {
+        Self {
+            status,
+            error: error.into(),
+        }
+    }
+}
+
+impl IntoResponse for CommandError {
+    fn into_response(self) -> Response {
+        command_error(self.status, self.error)
+    }
+}
+
+/// Parses a device ID taken from the path; 400 "Invalid device ID" if it isn't a UUID.
+pub(crate) fn parse_device_id(device_id: &str) -> Result<Uuid, CommandError> {
+    Uuid::parse_str(device_id)
+        .map_err(|_| CommandError::new(StatusCode::BAD_REQUEST, "Invalid device ID"))
+}
+
+/// Fetches the device; 404 "Device not found" if it doesn't exist, 500 if the lookup fails.
+pub(crate) async fn find_device(
+    device_service: &DeviceService,
+    device_id: Uuid,
+) -> Result<Device, CommandError> {
+    match device_service.get(device_id).await {
+        Ok(Some(device)) => Ok(device),
+        Ok(None) => Err(CommandError::new(StatusCode::NOT_FOUND, "Device not found")),
+        Err(e) => Err(CommandError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to get device: {}", e),
+        )),
+    }
+}
+
+/// Fetches the device like `find_device` and returns it with its project path; 400 "Device has
+/// no project path configured" if it has none.
+pub(crate) async fn find_project(
+    device_service: &DeviceService,
+    device_id: Uuid,
+) -> Result<(Device, String), CommandError> {
+    let device = find_device(device_service, device_id).await?;
+    match device.project_path.clone() {
+        Some(project_path) => Ok((device, project_path)),
+        None => Err(CommandError::new(
+            StatusCode::BAD_REQUEST,
+            "Device has no project path configured",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::InMemoryDeviceRepository;
+    use crate::domain::{BoardType, NewDevice};
+    use std::sync::Arc;
+
+    fn status_of<T>(result: Result<T, CommandError>) -> StatusCode {
+        match result {
+            Ok(_) => StatusCode::OK,
+            Err(e) => e.into_response().status(),
+        }
+    }
+
+    /// Test that each lookup failure maps to its status and a configured device is returned.
+    #[tokio::test]
+    async fn lookups_map_failures_to_statuses() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()));
+        let bare = service.create("bare", NewDevice::default()).await.unwrap();
+        let configured = service
+            .create(
+                "lab",
+                NewDevice {
+                    board_type: Some(BoardType::Esp32Dev),
+                    project_path: Some("lab".to_string()),
+                    ..NewDevice::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(status_of(parse_device_id("nope")), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            status_of(find_device(&service, Uuid::new_v4()).await),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status_of(find_project(&service, bare.id).await),
+            StatusCode::BAD_REQUEST
+        );
+        let (device, project_path) = find_project(&service, configured.id).await.ok().unwrap();
+        assert_eq!(device.id, configured.id);
+        assert_eq!(project_path, "lab");
+    }
+}
@@ -0,0 +1,59 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use uuid::Uuid;
+
+use crate::dto::ErrorResponse;
+use crate::middleware::RequestId;
+use crate::service::ServiceError;
+
+/// Maps a `ServiceError` carried by `e` to its HTTP status code.
+/// Returns `None` for any other error, which handlers report as a 500.
+pub(crate) fn service_error_status(e: &anyhow::Error) -> Option<StatusCode> {
+    let status = match e.downcast_ref::<ServiceError>()? {
+        ServiceError::Conflict(_)
+        | ServiceError::Cancelled(_)
+        | ServiceError::NotInitialized(_) => StatusCode::CONFLICT,
+        ServiceError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+        ServiceError::NotFound(_) => StatusCode::NOT_FOUND,
+        ServiceError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        ServiceError::Forbidden(_) | ServiceError::SandboxViolation(_) => StatusCode::FORBIDDEN,
+        ServiceError::PlatformIOUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    Some(status)
+}
+
+/// Machine-readable code of a `ServiceError` carried by `e`, for the `code` of error bodies.
+pub(crate) fn service_error_code(e: &anyhow::Error) -> Option<String> {
+    e.downcast_ref::<ServiceError>()
+        .map(|e| e.code().to_string())
+}
+
+/// JSON error response without a correlation id, for client errors.
+pub(crate) fn error_response(status: StatusCode, error: impl Into<String>) -> Response {
+    let body = ErrorResponse {
+        error: error.into(),
+        correlation_id: None,
+    };
+    (status, Json(body)).into_response()
+}
+
+/// Logs `e` with the request's id (or a fresh one outside the request-id middleware) and returns
+/// a 500 whose body carries the same id, so a failed request can be matched to its log line.
+pub(crate) fn internal_error(
+    context: &str,
+    e: &anyhow::Error,
+    request_id: Option<&RequestId>,
+) -> Response {
+    let correlation_id = request_id
+        .map(|id| id.0.clone())
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    tracing::error!(%correlation_id, error = %e, "{}", context);
+    let body = ErrorResponse {
+        error: format!("{}: {}", context, e),
+        correlation_id: Some(correlation_id),
+    };
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+}
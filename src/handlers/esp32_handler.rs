@@ -1,211 +1,1527 @@
-use axum::{extract::Extension, http::StatusCode, response::IntoResponse, Json};
+use axum::{
+    extract::{Extension, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use tokio::task::JoinSet;
 use uuid::Uuid;
 
-use crate::dto::{BuildRequest, CommandResponse, InitProjectRequest, UploadRequest};
-use crate::service::{DeviceService, PlatformIOService};
+use crate::domain::{DeviceEvent, DeviceUpdate, Operation};
+use crate::dto::{
+    AutoPortResponse, BatchUploadRequest, BatchUploadResponse, BatchUploadResult,
+    BuildCommandQuery, BuildLogQuery, BuildLogResponse, BuildRequest, BuildResponse,
+    BuildWaitQuery, CancelBuildResponse, CommandResponse, CreateMainRequest, DeployPhaseResponse,
+    DeployRequest, DeployResponse, DeployStatus, EraseRequest, InitProjectRequest, InitResponse,
+    LibrariesResponse, OutputEncoding, PartitionsQuery, PartitionsResponse, PioCommandRequest,
+    PlannedCommandResponse, PurgeRequest, PurgeResponse, ResetRequest, ScaffoldRequest,
+    TemplateQuery, UpdatesResponse, UploadCommandQuery, UploadFsRequest, UploadRequest,
+};
+use crate::handlers::audit_handler::request_api_key;
+use crate::handlers::device_lookup::{
+    command_error, find_device, find_project, parse_device_id, service_command_error,
+};
+use crate::handlers::error::{service_error_code, service_error_status};
+use crate::handlers::AdminContext;
+use crate::service::platformio_service::{
+    basic_main_content, parse_partition_csv, DEFAULT_MAIN_FILENAME,
+};
+use crate::service::{
+    parse_diagnostics, BuildOptions, BuildStatsService, BuildWait, DeployPhase, DeviceService,
+    EventBus, InitOptions, OperationLog, PlannedCommand, PlatformIOService, ServiceError,
+    UploadProtocol,
+};
 
 /// HTTP handler to build firmware for a device.
 /// Fetches the device, validates project path, calls PlatformIOService::build_project.
+/// Builds that actually ran (not dry runs, conflicts or invalid projects) are counted in BuildStatsService.
+/// Every build that isn't a dry run publishes `build_started` and `build_finished` events.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/build",
+    tag = "esp32",
+    request_body = BuildRequest,
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Build succeeded", body = BuildResponse),
+        (status = 400, description = "Missing project path or unsafe build flags", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 409, description = "Build already running or cancelled, or project not initialized (code PROJECT_NOT_INITIALIZED)", body = BuildResponse),
+        (status = 500, description = "Build failed", body = BuildResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = BuildResponse),
+    )
+))]
+#[allow(clippy::too_many_arguments)]
 pub async fn build_firmware(
     Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
     Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    Extension(build_stats): Extension<std::sync::Arc<BuildStatsService>>,
+    Extension(event_bus): Extension<std::sync::Arc<EventBus>>,
+    Extension(operation_log): Extension<std::sync::Arc<OperationLog>>,
+    Extension(admin): Extension<AdminContext>,
+    headers: HeaderMap,
     Json(payload): Json<BuildRequest>,
 ) -> impl IntoResponse {
-    // Get device
-    let device = match device_service.get(payload.device_id).await {
-        Ok(Some(d)) => d,
-        Ok(None) => {
-            return (
-                StatusCode::NOT_FOUND,
-                Json(CommandResponse {
+    // Get device and its project path
+    let (device, project_path) = match find_project(&device_service, payload.device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    // Build project; request env vars override the device's
+    let mut env_vars = device.env_vars;
+    env_vars.extend(payload.env_vars);
+    let options = BuildOptions {
+        dry_run: payload.dry_run,
+        verbose: payload.verbose,
+        build_flags: payload.build_flags,
+        env_vars,
+        max_output_bytes: payload.max_output_bytes,
+        environment: payload.environment.or(device.default_env),
+        keep_ansi: payload.strip_ansi == Some(false),
+    };
+    if !payload.dry_run {
+        event_bus.publish(DeviceEvent::BuildStarted {
+            device_id: device.id,
+        });
+    }
+    let result = pio_service.build_project(&project_path, &options).await;
+    if !payload.dry_run {
+        event_bus.publish(DeviceEvent::BuildFinished {
+            device_id: device.id,
+            success: result.is_ok(),
+        });
+        operation_log.record(
+            device.id,
+            Operation::Build,
+            result.is_ok(),
+            request_api_key(&admin, &headers),
+        );
+        match &result {
+            Ok(_) => build_stats.record_build(device.id, true),
+            Err(e) => match e.downcast_ref::<ServiceError>() {
+                None | Some(ServiceError::Cancelled(_)) => {
+                    build_stats.record_build(device.id, false)
+                }
+                Some(_) => {}
+            },
+        }
+    }
+    match result {
+        Ok(build) => (
+            StatusCode::OK,
+            Json(BuildResponse {
+                success: true,
+                output: build.output,
+                error: None,
+                firmware_size: build.firmware_size,
+                diagnostics: build.diagnostics,
+                code: None,
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            let (status, error) = match service_error_status(&e) {
+                Some(status) => (status, e.to_string()),
+                None => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Build failed: {}", e),
+                ),
+            };
+            (
+                status,
+                Json(BuildResponse {
                     success: false,
                     output: "".to_string(),
-                    error: Some("Device not found".to_string()),
+                    diagnostics: parse_diagnostics(&error),
+                    error: Some(error),
+                    firmware_size: None,
+                    code: service_error_code(&e),
                 }),
             )
                 .into_response()
         }
-        Err(e) => {
+    }
+}
+
+/// HTTP handler to upload firmware to a device.
+/// Fetches the device, validates project path, calls PlatformIOService::upload_firmware.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/upload",
+    tag = "esp32",
+    request_body = UploadRequest,
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Upload succeeded; with `output_encoding: raw` the output bytes as application/octet-stream", body = CommandResponse),
+        (status = 400, description = "Invalid device ID, missing project path or invalid input", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 500, description = "Operation failed", body = CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = CommandResponse),
+    )
+))]
+pub async fn upload_firmware(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    Extension(event_bus): Extension<std::sync::Arc<EventBus>>,
+    Extension(operation_log): Extension<std::sync::Arc<OperationLog>>,
+    Extension(admin): Extension<AdminContext>,
+    headers: HeaderMap,
+    Json(payload): Json<UploadRequest>,
+) -> impl IntoResponse {
+    // Get device and its project path
+    let (device, project_path) = match find_project(&device_service, payload.device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    // Upload firmware; the request port overrides the device's default port, and OTA
+    // uploads go to the device's IP address
+    let protocol = match payload
+        .upload_protocol
+        .as_deref()
+        .map(UploadProtocol::parse)
+    {
+        Some(Ok(protocol)) => Some(protocol),
+        Some(Err(e)) => return command_error(StatusCode::BAD_REQUEST, e.to_string()),
+        None => None,
+    };
+    let port = match protocol {
+        Some(UploadProtocol::Espota) => payload.port.or(device.ip_address),
+        _ => payload.port.or(device.default_port),
+    };
+    let environment = payload.environment.or(device.default_env);
+    let result = pio_service
+        .upload_firmware(
+            &project_path,
+            port.as_deref(),
+            protocol,
+            environment.as_deref(),
+        )
+        .await;
+    event_bus.publish(DeviceEvent::UploadFinished {
+        device_id: device.id,
+        success: result.is_ok(),
+    });
+    operation_log.record(
+        device.id,
+        Operation::Upload,
+        result.is_ok(),
+        request_api_key(&admin, &headers),
+    );
+    match result {
+        Ok(output) => output_response(payload.output_encoding, output),
+        Err(e) => service_command_error(&e, "Upload failed"),
+    }
+}
+
+/// Successful response carrying flashing output in the requested encoding.
+fn output_response(encoding: OutputEncoding, output: Vec<u8>) -> Response {
+    let output = match encoding {
+        OutputEncoding::Raw => {
             return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(CommandResponse {
-                    success: false,
-                    output: "".to_string(),
-                    error: Some(format!("Failed to get device: {}", e)),
-                }),
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "application/octet-stream")],
+                output,
             )
                 .into_response()
         }
+        OutputEncoding::Base64 => BASE64_STANDARD.encode(output),
+        OutputEncoding::Text => String::from_utf8_lossy(&output).into_owned(),
     };
+    (
+        StatusCode::OK,
+        Json(CommandResponse {
+            success: true,
+            output,
+            code: None,
+            error: None,
+        }),
+    )
+        .into_response()
+}
 
-    // Check if device has project path
-    let project_path = match device.project_path {
-        Some(p) => p,
-        None => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(CommandResponse {
+/// HTTP handler to build a device's firmware and, only if the build succeeds, upload it.
+/// Parses UUID from path, fetches device, calls PlatformIOService::deploy, which holds the
+/// project's build and upload locks for the whole sequence. The response reports each phase's
+/// output and duration; builds are counted and events published as for separate calls.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/deploy",
+    tag = "esp32",
+    request_body = DeployRequest,
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Build and upload succeeded", body = DeployResponse),
+        (status = 400, description = "Invalid device ID, missing project path or invalid options", body = DeployResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 409, description = "Build or upload already running, build cancelled, or project not initialized (code PROJECT_NOT_INITIALIZED)", body = DeployResponse),
+        (status = 500, description = "Build or upload failed", body = DeployResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = DeployResponse),
+    )
+))]
+#[allow(clippy::too_many_arguments)]
+pub async fn deploy(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    Extension(build_stats): Extension<std::sync::Arc<BuildStatsService>>,
+    Extension(event_bus): Extension<std::sync::Arc<EventBus>>,
+    Extension(operation_log): Extension<std::sync::Arc<OperationLog>>,
+    Extension(admin): Extension<AdminContext>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    headers: HeaderMap,
+    Json(payload): Json<DeployRequest>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (device, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    let protocol = match payload
+        .upload_protocol
+        .as_deref()
+        .map(UploadProtocol::parse)
+    {
+        Some(Ok(protocol)) => Some(protocol),
+        Some(Err(e)) => return not_deployed(StatusCode::BAD_REQUEST, &e),
+        None => None,
+    };
+    let port = match protocol {
+        Some(UploadProtocol::Espota) => payload.port.or(device.ip_address),
+        _ => payload.port.or(device.default_port),
+    };
+    let mut env_vars = device.env_vars;
+    env_vars.extend(payload.env_vars);
+    let options = BuildOptions {
+        dry_run: false,
+        verbose: payload.verbose,
+        build_flags: payload.build_flags,
+        env_vars,
+        max_output_bytes: payload.max_output_bytes,
+        environment: payload.environment.or(device.default_env),
+        keep_ansi: payload.strip_ansi == Some(false),
+    };
+
+    event_bus.publish(DeviceEvent::BuildStarted {
+        device_id: device.id,
+    });
+    let deployed = pio_service
+        .deploy(&project_path, &options, port.as_deref(), protocol)
+        .await;
+    let output = match deployed {
+        Ok(output) => output,
+        Err(e) => {
+            event_bus.publish(DeviceEvent::BuildFinished {
+                device_id: device.id,
+                success: false,
+            });
+            let status = service_error_status(&e).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            return not_deployed(status, &e);
+        }
+    };
+
+    event_bus.publish(DeviceEvent::BuildFinished {
+        device_id: device.id,
+        success: output.build.result.is_ok(),
+    });
+    let api_key = request_api_key(&admin, &headers);
+    operation_log.record(
+        device.id,
+        Operation::Build,
+        output.build.result.is_ok(),
+        api_key,
+    );
+    match &output.build.result {
+        Ok(_) => build_stats.record_build(device.id, true),
+        Err(e) => match e.downcast_ref::<ServiceError>() {
+            None | Some(ServiceError::Cancelled(_)) => build_stats.record_build(device.id, false),
+            Some(_) => {}
+        },
+    }
+    if let Some(upload) = &output.upload {
+        event_bus.publish(DeviceEvent::UploadFinished {
+            device_id: device.id,
+            success: upload.result.is_ok(),
+        });
+        operation_log.record(device.id, Operation::Upload, upload.result.is_ok(), api_key);
+    }
+
+    let firmware_size = output
+        .build
+        .result
+        .as_ref()
+        .ok()
+        .and_then(|b| b.firmware_size.clone());
+    let (build_status, build) = phase_response(output.build, |b| b.output, "Build failed");
+    let (status, deploy_status, upload) = match output.upload {
+        None => (build_status, DeployStatus::BuildFailed, None),
+        Some(upload) => {
+            let (status, upload) = phase_response(
+                upload,
+                |bytes| String::from_utf8_lossy(&bytes).into_owned(),
+                "Upload failed",
+            );
+            let deploy_status = if upload.success {
+                DeployStatus::Succeeded
+            } else {
+                DeployStatus::UploadFailed
+            };
+            (status, deploy_status, Some(upload))
+        }
+    };
+    (
+        status,
+        Json(DeployResponse {
+            success: deploy_status == DeployStatus::Succeeded,
+            status: deploy_status,
+            build: Some(build),
+            upload,
+            firmware_size,
+            code: None,
+            error: None,
+        }),
+    )
+        .into_response()
+}
+
+/// Response for a deploy that failed before either phase ran.
+fn not_deployed(status: StatusCode, e: &anyhow::Error) -> Response {
+    (
+        status,
+        Json(DeployResponse {
+            success: false,
+            status: DeployStatus::NotStarted,
+            build: None,
+            upload: None,
+            firmware_size: None,
+            code: service_error_code(e),
+            error: Some(e.to_string()),
+        }),
+    )
+        .into_response()
+}
+
+/// Status and response body for a finished deploy phase; failures that aren't service errors
+/// are 500s with their message prefixed by `failure`.
+fn phase_response<T>(
+    phase: DeployPhase<T>,
+    output: impl FnOnce(T) -> String,
+    failure: &str,
+) -> (StatusCode, DeployPhaseResponse) {
+    let duration_ms = phase.duration.as_millis() as u64;
+    match phase.result {
+        Ok(value) => (
+            StatusCode::OK,
+            DeployPhaseResponse {
+                success: true,
+                output: output(value),
+                code: None,
+                error: None,
+                duration_ms,
+            },
+        ),
+        Err(e) => {
+            let (status, error) = match service_error_status(&e) {
+                Some(status) => (status, e.to_string()),
+                None => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("{}: {}", failure, e),
+                ),
+            };
+            (
+                status,
+                DeployPhaseResponse {
                     success: false,
-                    output: "".to_string(),
-                    error: Some("Device has no project path configured".to_string()),
-                }),
+                    output: String::new(),
+                    code: service_error_code(&e),
+                    error: Some(error),
+                    duration_ms,
+                },
             )
-                .into_response()
         }
+    }
+}
+
+/// HTTP handler to flash the same firmware to many devices.
+/// Uploads to every listed device concurrently (duplicates are flashed once); the upload slots
+/// (`PIO_MAX_CONCURRENT_UPLOADS`) still cap how many run at a time. A failing device doesn't stop the others; each gets its
+/// own result, so the response is 200 even if every upload failed.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/upload-batch",
+    tag = "esp32",
+    request_body = BatchUploadRequest,
+    responses(
+        (status = 200, description = "Per-device upload results", body = BatchUploadResponse),
+    )
+))]
+pub async fn upload_batch(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    Extension(event_bus): Extension<std::sync::Arc<EventBus>>,
+    Extension(operation_log): Extension<std::sync::Arc<OperationLog>>,
+    Extension(admin): Extension<AdminContext>,
+    headers: HeaderMap,
+    Json(mut payload): Json<BatchUploadRequest>,
+) -> impl IntoResponse {
+    let mut device_ids = Vec::with_capacity(payload.device_ids.len());
+    for id in payload.device_ids {
+        if !device_ids.contains(&id) {
+            device_ids.push(id);
+        }
+    }
+
+    let mut uploads = JoinSet::new();
+    for (index, device_id) in device_ids.iter().copied().enumerate() {
+        let device_service = device_service.clone();
+        let pio_service = pio_service.clone();
+        let event_bus = event_bus.clone();
+        let operation_log = operation_log.clone();
+        let api_key = request_api_key(&admin, &headers).map(str::to_string);
+        let port = payload.port_map.remove(&device_id);
+        uploads.spawn(async move {
+            let result = upload_one(
+                &device_service,
+                &pio_service,
+                &event_bus,
+                &operation_log,
+                api_key.as_deref(),
+                device_id,
+                port,
+            )
+            .await;
+            (index, result)
+        });
+    }
+
+    let mut results: Vec<Option<BatchUploadResult>> = device_ids.iter().map(|_| None).collect();
+    while let Some(joined) = uploads.join_next().await {
+        if let Ok((index, result)) = joined {
+            results[index] = Some(result);
+        }
+    }
+    let results = results
+        .into_iter()
+        .zip(&device_ids)
+        .map(|(result, device_id)| {
+            result.unwrap_or_else(|| BatchUploadResult {
+                device_id: *device_id,
+                success: false,
+                output: "".to_string(),
+                error: Some("Upload task failed".to_string()),
+            })
+        })
+        .collect();
+    (StatusCode::OK, Json(BatchUploadResponse { results })).into_response()
+}
+
+/// Uploads to one device of a batch; `port` overrides the device's default port.
+/// Publishes `upload_finished` and records the upload in the operation log if it was attempted.
+async fn upload_one(
+    device_service: &DeviceService,
+    pio_service: &PlatformIOService,
+    event_bus: &EventBus,
+    operation_log: &OperationLog,
+    api_key: Option<&str>,
+    device_id: Uuid,
+    port: Option<String>,
+) -> BatchUploadResult {
+    let failed = |error: String| BatchUploadResult {
+        device_id,
+        success: false,
+        output: "".to_string(),
+        error: Some(error),
+    };
+    let device = match device_service.get(device_id).await {
+        Ok(Some(d)) => d,
+        Ok(None) => return failed("Device not found".to_string()),
+        Err(e) => return failed(format!("Failed to get device: {}", e)),
+    };
+    let Some(project_path) = device.project_path else {
+        return failed("Device has no project path configured".to_string());
+    };
+    let port = port.or(device.default_port);
+    let result = pio_service
+        .upload_firmware(
+            &project_path,
+            port.as_deref(),
+            None,
+            device.default_env.as_deref(),
+        )
+        .await;
+    event_bus.publish(DeviceEvent::UploadFinished {
+        device_id,
+        success: result.is_ok(),
+    });
+    operation_log.record(device_id, Operation::Upload, result.is_ok(), api_key);
+    match result {
+        Ok(output) => BatchUploadResult {
+            device_id,
+            success: true,
+            output: String::from_utf8_lossy(&output).into_owned(),
+            error: None,
+        },
+        Err(e) => failed(match service_error_status(&e) {
+            Some(_) => e.to_string(),
+            None => format!("Upload failed: {}", e),
+        }),
+    }
+}
+
+/// HTTP handler to initialize a PlatformIO project for a device.
+/// Fetches the device, validates project path, loads the custom board JSON if one is given,
+/// resolves the board (request, then the custom board's id, then `DEFAULT_BOARD`), calls
+/// PlatformIOService::init_project. Retrying is safe: a project already initialized for the
+/// board is left alone unless `force` is set.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/init",
+    tag = "esp32",
+    request_body = InitProjectRequest,
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Project initialized", body = InitResponse),
+        (status = 400, description = "Invalid device ID, missing project path or invalid input", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 500, description = "Operation failed", body = CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = CommandResponse),
+    )
+))]
+pub async fn init_project(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    Json(payload): Json<InitProjectRequest>,
+) -> impl IntoResponse {
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, payload.device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    let custom_board = match &payload.board_json_path {
+        Some(path) => match pio_service.load_board_definition(path).await {
+            Ok(definition) => Some(definition),
+            Err(e) => {
+                return (
+                    service_error_status(&e).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                    Json(CommandResponse {
+                        success: false,
+                        output: "".to_string(),
+                        code: service_error_code(&e),
+                        error: Some(e.to_string()),
+                    }),
+                )
+                    .into_response()
+            }
+        },
+        None => None,
+    };
+
+    let requested = payload
+        .board
+        .as_deref()
+        .or(custom_board.as_ref().map(|d| d.id.as_str()));
+    let board = match pio_service.resolve_board(requested) {
+        Ok(board) => board,
+        Err(e) => return command_error(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+
+    // Initialize project
+    match pio_service
+        .init_project(
+            &project_path,
+            &board,
+            custom_board.as_ref(),
+            &InitOptions {
+                overwrite: payload.overwrite,
+                force: payload.force,
+            },
+        )
+        .await
+    {
+        Ok(init) => (
+            StatusCode::OK,
+            Json(InitResponse {
+                success: true,
+                output: if init.result.already_initialized {
+                    init.output
+                } else {
+                    format!("Initialized project for board {}\n{}", board, init.output)
+                },
+                error: None,
+                init: Some(init.result),
+            }),
+        )
+            .into_response(),
+        Err(e) => service_command_error(&e, "Project initialization failed"),
+    }
+}
+
+/// HTTP handler to create a basic main.cpp (or the body's `filename` under `src/`) for a device.
+/// Parses UUID from path, fetches device, validates project path, calls PlatformIOService::create_basic_main.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/create-main",
+    tag = "esp32",
+    request_body = CreateMainRequest,
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Main file written", body = CommandResponse),
+        (status = 400, description = "Invalid device ID, missing project path or invalid filename", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 500, description = "Operation failed", body = CommandResponse),
+    )
+))]
+pub async fn create_basic_main(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    payload: Option<Json<CreateMainRequest>>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    // Create basic main file
+    let filename = payload.and_then(|Json(p)| p.filename);
+    match pio_service
+        .create_basic_main(&project_path, None, filename.as_deref())
+        .await
+    {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(CommandResponse {
+                success: true,
+                output: format!(
+                    "Basic {} created successfully",
+                    filename.as_deref().unwrap_or(DEFAULT_MAIN_FILENAME)
+                ),
+                code: None,
+                error: None,
+            }),
+        )
+            .into_response(),
+        Err(e) => service_command_error(&e, "Failed to create main file"),
+    }
+}
+
+/// HTTP handler to scaffold a device's project: `init` followed by `create-main` in one call.
+/// Parses UUID from path, fetches device, validates project path, calls PlatformIOService::scaffold_project.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/scaffold",
+    tag = "esp32",
+    request_body = ScaffoldRequest,
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Project initialized and main.cpp written", body = CommandResponse),
+        (status = 400, description = "Invalid device ID, missing project path or invalid input", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 500, description = "Operation failed", body = CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = CommandResponse),
+    )
+))]
+pub async fn scaffold_project(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    Json(payload): Json<ScaffoldRequest>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
     };
 
-    // Build project
-    match pio_service.build_project(&project_path).await {
+    // Initialize project and write main.cpp
+    match pio_service
+        .scaffold_project(&project_path, &payload.board, payload.template.as_deref())
+        .await
+    {
         Ok(output) => (
             StatusCode::OK,
             Json(CommandResponse {
                 success: true,
                 output,
+                code: None,
                 error: None,
             }),
         )
             .into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
+        Err(e) => service_command_error(&e, "Project scaffolding failed"),
+    }
+}
+
+/// HTTP handler to clean build files for a device.
+/// Parses UUID from path, fetches device, validates project path, calls PlatformIOService::clean_project.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/clean",
+    tag = "esp32",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Build files removed", body = CommandResponse),
+        (status = 400, description = "Invalid device ID, missing project path or invalid input", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 500, description = "Operation failed", body = CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = CommandResponse),
+    )
+))]
+pub async fn clean_project(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    Extension(operation_log): Extension<std::sync::Arc<OperationLog>>,
+    Extension(admin): Extension<AdminContext>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    // Clean project
+    let result = pio_service.clean_project(&project_path).await;
+    operation_log.record(
+        device_id,
+        Operation::Clean,
+        result.is_ok(),
+        request_api_key(&admin, &headers),
+    );
+    match result {
+        Ok(output) => (
+            StatusCode::OK,
             Json(CommandResponse {
-                success: false,
-                output: "".to_string(),
-                error: Some(format!("Build failed: {}", e)),
+                success: true,
+                output,
+                code: None,
+                error: None,
+            }),
+        )
+            .into_response(),
+        Err(e) => service_command_error(&e, "Clean failed"),
+    }
+}
+
+/// HTTP handler to delete a device's whole `.pio` directory.
+/// Requires `"confirm": true` in the body, then calls PlatformIOService::purge_build_dir and reports
+/// the bytes reclaimed.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/purge",
+    tag = "esp32",
+    request_body = PurgeRequest,
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = ".pio removed", body = PurgeResponse),
+        (status = 400, description = "Not confirmed, invalid device ID, missing project path or invalid input", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 409, description = "Project is building or uploading", body = CommandResponse),
+        (status = 500, description = "Operation failed", body = CommandResponse),
+    )
+))]
+pub async fn purge_project(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    Json(payload): Json<PurgeRequest>,
+) -> impl IntoResponse {
+    if !payload.confirm {
+        return command_error(
+            StatusCode::BAD_REQUEST,
+            "Purging .pio requires \"confirm\": true",
+        );
+    }
+
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    // Remove .pio
+    match pio_service.purge_build_dir(&project_path).await {
+        Ok(reclaimed_bytes) => (
+            StatusCode::OK,
+            Json(PurgeResponse {
+                success: true,
+                reclaimed_bytes,
+            }),
+        )
+            .into_response(),
+        Err(e) => service_command_error(&e, "Purge failed"),
+    }
+}
+
+/// HTTP handler to report the disk usage of a device's project.
+/// Parses UUID from path, fetches device, validates project path, calls PlatformIOService::project_size.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/{id}/size",
+    tag = "esp32",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Project disk usage", body = crate::domain::ProjectSize),
+        (status = 400, description = "Invalid device ID, missing project path or invalid input", body = CommandResponse),
+        (status = 404, description = "Device or project directory not found", body = CommandResponse),
+        (status = 500, description = "Operation failed", body = CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = CommandResponse),
+    )
+))]
+pub async fn project_size(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    match pio_service.project_size(&project_path).await {
+        Ok(size) => (StatusCode::OK, Json(size)).into_response(),
+        Err(e) => service_command_error(&e, "Failed to measure project"),
+    }
+}
+
+/// HTTP handler to pull the latest project source from git.
+/// Parses UUID from path, fetches device, validates project path, calls PlatformIOService::git_pull.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/git/pull",
+    tag = "esp32",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Source pulled; output of git pull", body = CommandResponse),
+        (status = 400, description = "Invalid device ID, missing project path or not a git repository", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 409, description = "A build or upload is running for the project", body = CommandResponse),
+        (status = 500, description = "Operation failed or timed out", body = CommandResponse),
+    )
+))]
+pub async fn git_pull(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    match pio_service.git_pull(&project_path).await {
+        Ok(output) => (
+            StatusCode::OK,
+            Json(CommandResponse {
+                success: true,
+                output,
+                code: None,
+                error: None,
+            }),
+        )
+            .into_response(),
+        Err(e) => service_command_error(&e, "git pull failed"),
+    }
+}
+
+/// HTTP handler to cancel an in-progress build for a device.
+/// Parses UUID from path, fetches device, calls PlatformIOService::cancel_build for its project path.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/build/cancel",
+    tag = "esp32",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Whether a running build was cancelled", body = CancelBuildResponse),
+        (status = 400, description = "Invalid device ID or missing project path", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 500, description = "Repository error", body = CommandResponse),
+    )
+))]
+pub async fn cancel_build(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device
+    let device = match find_device(&device_service, device_id).await {
+        Ok(device) => device,
+        Err(e) => return e.into_response(),
+    };
+
+    // A device without a project path can never have a build running
+    let cancelled = match device.project_path {
+        Some(p) => pio_service.cancel_build(&p),
+        None => false,
+    };
+
+    (StatusCode::OK, Json(CancelBuildResponse { cancelled })).into_response()
+}
+
+/// HTTP handler to show the command a build of the device would run, without running it.
+/// Applies the device's env vars and the query's options like `POST /devices/:id/build`;
+/// calls PlatformIOService::build_command.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/{id}/build/command",
+    tag = "esp32",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+        BuildCommandQuery,
+    ),
+    responses(
+        (status = 200, description = "Command a build would run", body = PlannedCommandResponse),
+        (status = 400, description = "Invalid device ID, missing project path or unsafe build flags", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 500, description = "Failed to resolve the command", body = CommandResponse),
+    )
+))]
+pub async fn build_command(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    Query(query): Query<BuildCommandQuery>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (device, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    let options = BuildOptions {
+        dry_run: query.dry_run,
+        verbose: query.verbose,
+        build_flags: query
+            .build_flags
+            .map(|flags| flags.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default(),
+        env_vars: device.env_vars,
+        max_output_bytes: None,
+        environment: query.environment.or(device.default_env),
+        keep_ansi: false,
+    };
+    planned_command_response(pio_service.build_command(&project_path, &options).await)
+}
+
+/// HTTP handler to show the command an upload to the device would run, without running it.
+/// Uses the query's port and protocol with the same fallbacks as `POST /upload`; calls
+/// PlatformIOService::upload_command.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/{id}/upload/command",
+    tag = "esp32",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+        UploadCommandQuery,
+    ),
+    responses(
+        (status = 200, description = "Command an upload would run", body = PlannedCommandResponse),
+        (status = 400, description = "Invalid device ID, missing project path, unsupported protocol or no OTA address", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 500, description = "Failed to resolve the command", body = CommandResponse),
+    )
+))]
+pub async fn upload_command(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    Query(query): Query<UploadCommandQuery>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (device, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    let protocol = match query.upload_protocol.as_deref().map(UploadProtocol::parse) {
+        Some(Ok(protocol)) => Some(protocol),
+        Some(Err(e)) => return command_error(StatusCode::BAD_REQUEST, e.to_string()),
+        None => None,
+    };
+    let port = match protocol {
+        Some(UploadProtocol::Espota) => query.port.or(device.ip_address),
+        _ => query.port.or(device.default_port),
+    };
+    planned_command_response(
+        pio_service
+            .upload_command(
+                &project_path,
+                port.as_deref(),
+                protocol,
+                query.environment.or(device.default_env).as_deref(),
+            )
+            .await,
+    )
+}
+
+/// Response for a resolved command, or the error that prevented resolving it.
+fn planned_command_response(result: anyhow::Result<PlannedCommand>) -> Response {
+    match result {
+        Ok(command) => (
+            StatusCode::OK,
+            Json(PlannedCommandResponse {
+                argv: command.argv,
+                working_dir: command.working_dir.to_string_lossy().into_owned(),
+                env_vars: command.env_vars,
             }),
         )
             .into_response(),
+        Err(e) => service_command_error(&e, "Failed to resolve command"),
     }
 }
 
-/// HTTP handler to upload firmware to a device.
-/// Fetches the device, validates project path, calls PlatformIOService::upload_firmware.
-pub async fn upload_firmware(
+/// HTTP handler to long-poll for the completion of a device's running build, for clients that
+/// can't consume the event stream. Blocks up to `?timeout=` seconds (default 30, at most 300)
+/// and returns the build's result like `GET /devices/:id/build/latest`; 204 if no build is
+/// running and 408 if it is still running when the timeout elapses.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/{id}/build/wait",
+    tag = "esp32",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+        BuildWaitQuery,
+    ),
+    responses(
+        (status = 200, description = "Result of the build that was running", body = BuildResponse),
+        (status = 204, description = "No build is running"),
+        (status = 400, description = "Invalid device ID or missing project path", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 408, description = "Build still running after the timeout", body = CommandResponse),
+    )
+))]
+pub async fn wait_for_build(
     Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
     Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
-    Json(payload): Json<UploadRequest>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    Query(query): Query<BuildWaitQuery>,
 ) -> impl IntoResponse {
-    // Get device
-    let device = match device_service.get(payload.device_id).await {
-        Ok(Some(d)) => d,
-        Ok(None) => {
-            return (
-                StatusCode::NOT_FOUND,
-                Json(CommandResponse {
-                    success: false,
-                    output: "".to_string(),
-                    error: Some("Device not found".to_string()),
-                }),
-            )
-                .into_response()
-        }
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(CommandResponse {
-                    success: false,
-                    output: "".to_string(),
-                    error: Some(format!("Failed to get device: {}", e)),
-                }),
-            )
-                .into_response()
-        }
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
     };
 
-    // Check if device has project path
-    let project_path = match device.project_path {
-        Some(p) => p,
-        None => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(CommandResponse {
-                    success: false,
-                    output: "".to_string(),
-                    error: Some("Device has no project path configured".to_string()),
-                }),
-            )
-                .into_response()
-        }
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
     };
 
-    // Upload firmware
+    let timeout = query.timeout.unwrap_or(30).min(300);
     match pio_service
-        .upload_firmware(&project_path, payload.port.as_deref())
+        .wait_for_build(&project_path, std::time::Duration::from_secs(timeout))
         .await
     {
-        Ok(output) => (
+        BuildWait::Finished(Ok(build)) => (
             StatusCode::OK,
-            Json(CommandResponse {
+            Json(BuildResponse {
                 success: true,
-                output,
+                output: build.output,
                 error: None,
+                firmware_size: build.firmware_size,
+                diagnostics: build.diagnostics,
+                code: None,
             }),
         )
             .into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(CommandResponse {
+        BuildWait::Finished(Err(e)) => (
+            StatusCode::OK,
+            Json(BuildResponse {
                 success: false,
                 output: "".to_string(),
-                error: Some(format!("Upload failed: {}", e)),
+                error: Some(format!("Build failed: {}", e)),
+                firmware_size: None,
+                diagnostics: parse_diagnostics(&e),
+                code: None,
             }),
         )
             .into_response(),
+        BuildWait::Idle => StatusCode::NO_CONTENT.into_response(),
+        BuildWait::TimedOut => command_error(
+            StatusCode::REQUEST_TIMEOUT,
+            format!("Build still running after {}s", timeout),
+        ),
     }
 }
 
-/// HTTP handler to initialize a PlatformIO project for a device.
-/// Fetches the device, validates project path, calls PlatformIOService::init_project.
-pub async fn init_project(
+/// HTTP handler to fetch the tail of the most recent build log for a device.
+/// Returns the last `?lines=` lines (default 100), or 404 if no build has run yet.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/{id}/build/log",
+    tag = "esp32",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+        BuildLogQuery,
+    ),
+    responses(
+        (status = 200, description = "Tail of the latest build log", body = BuildLogResponse),
+        (status = 400, description = "Invalid device ID or missing project path", body = CommandResponse),
+        (status = 404, description = "Device not found or no build has run", body = CommandResponse),
+        (status = 500, description = "Repository error", body = CommandResponse),
+    )
+))]
+pub async fn build_log(
     Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
     Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
-    Json(payload): Json<InitProjectRequest>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    Query(query): Query<BuildLogQuery>,
 ) -> impl IntoResponse {
-    // Get device
-    let device = match device_service.get(payload.device_id).await {
-        Ok(Some(d)) => d,
-        Ok(None) => {
-            return (
-                StatusCode::NOT_FOUND,
-                Json(CommandResponse {
-                    success: false,
-                    output: "".to_string(),
-                    error: Some("Device not found".to_string()),
-                }),
-            )
-                .into_response()
-        }
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(CommandResponse {
-                    success: false,
-                    output: "".to_string(),
-                    error: Some(format!("Failed to get device: {}", e)),
-                }),
-            )
-                .into_response()
-        }
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
     };
 
-    // Check if device has project path
-    let project_path = match device.project_path {
-        Some(p) => p,
-        None => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(CommandResponse {
-                    success: false,
-                    output: "".to_string(),
-                    error: Some("Device has no project path configured".to_string()),
-                }),
-            )
-                .into_response()
-        }
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    match pio_service.build_log(&project_path, query.lines.unwrap_or(100)) {
+        Some(lines) => (StatusCode::OK, Json(BuildLogResponse { lines })).into_response(),
+        None => command_error(
+            StatusCode::NOT_FOUND,
+            "No build has run for this device yet",
+        ),
+    }
+}
+
+/// HTTP handler to upload a device's filesystem image (SPIFFS/LittleFS built from `data/`).
+/// Uses the body's `port`, falling back to the device's default port; calls
+/// PlatformIOService::upload_fs, which can't overlap an upload or erase of the same project.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/upload-fs",
+    tag = "esp32",
+    request_body = Option<UploadFsRequest>,
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Filesystem image uploaded; with `output_encoding: raw` the output bytes as application/octet-stream", body = CommandResponse),
+        (status = 400, description = "Invalid device ID or missing project path", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 409, description = "An upload is already running for this device", body = CommandResponse),
+        (status = 500, description = "Upload failed", body = CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = CommandResponse),
+    )
+))]
+pub async fn upload_fs(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    Extension(operation_log): Extension<std::sync::Arc<OperationLog>>,
+    Extension(admin): Extension<AdminContext>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    headers: HeaderMap,
+    payload: Option<Json<UploadFsRequest>>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (device, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+    let port = payload.port.or(device.default_port);
+    let result = pio_service.upload_fs(&project_path, port.as_deref()).await;
+    operation_log.record(
+        device_id,
+        Operation::UploadFs,
+        result.is_ok(),
+        request_api_key(&admin, &headers),
+    );
+    match result {
+        Ok(output) => output_response(payload.output_encoding, output),
+        Err(e) => service_command_error(&e, "Filesystem upload failed"),
+    }
+}
+
+/// HTTP handler to erase the entire flash of a device.
+/// Requires `"confirm": true` in the body, then calls PlatformIOService::erase_flash.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/erase",
+    tag = "esp32",
+    request_body = EraseRequest,
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Flash erased; with `output_encoding: raw` the output bytes as application/octet-stream", body = CommandResponse),
+        (status = 400, description = "Not confirmed, invalid device ID or missing project path", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 500, description = "Erase failed", body = CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = CommandResponse),
+    )
+))]
+pub async fn erase_flash(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    Json(payload): Json<EraseRequest>,
+) -> impl IntoResponse {
+    if !payload.confirm {
+        return command_error(
+            StatusCode::BAD_REQUEST,
+            "Erasing flash requires \"confirm\": true",
+        );
+    }
+
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    // Erase flash
+    match pio_service
+        .erase_flash(&project_path, payload.port.as_deref())
+        .await
+    {
+        Ok(output) => output_response(payload.output_encoding, output),
+        Err(e) => service_command_error(&e, "Erase failed"),
+    }
+}
+
+/// HTTP handler to get a device's build statistics.
+/// Parses UUID from path, fetches device, returns BuildStatsService::get. Stats are kept in
+/// memory and reset when the server restarts.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/{id}/stats",
+    tag = "esp32",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Build statistics since server start", body = crate::domain::BuildStats),
+        (status = 400, description = "Invalid device ID", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 500, description = "Failed to get device", body = CommandResponse),
+    )
+))]
+pub async fn get_build_stats(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(build_stats): Extension<std::sync::Arc<BuildStatsService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    match find_device(&device_service, device_id).await {
+        Ok(device) => (StatusCode::OK, Json(build_stats.get(device.id))).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// HTTP handler to list outdated PlatformIO packages for a device's project.
+/// Parses UUID from path, fetches device, validates project path, calls PlatformIOService::check_updates.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/{id}/updates",
+    tag = "esp32",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Outdated packages; empty when up to date", body = UpdatesResponse),
+        (status = 400, description = "Invalid device ID, missing project path or invalid input", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 500, description = "Operation failed", body = CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = CommandResponse),
+    )
+))]
+pub async fn check_updates(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    match pio_service.check_updates(&project_path).await {
+        Ok(updates) => (StatusCode::OK, Json(UpdatesResponse { updates })).into_response(),
+        Err(e) => service_command_error(&e, "Update check failed"),
+    }
+}
+
+/// HTTP handler to list the libraries installed in a device's project.
+/// Parses UUID from path, fetches device, validates project path, calls PlatformIOService::list_libraries.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/{id}/libraries",
+    tag = "esp32",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Installed libraries; empty when none", body = LibrariesResponse),
+        (status = 400, description = "Invalid device ID, missing project path or invalid input", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 500, description = "Operation failed", body = CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = CommandResponse),
+    )
+))]
+pub async fn list_libraries(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    match pio_service.list_libraries(&project_path).await {
+        Ok(libraries) => (StatusCode::OK, Json(LibrariesResponse { libraries })).into_response(),
+        Err(e) => service_command_error(&e, "Failed to list libraries"),
+    }
+}
+
+/// HTTP handler to run an allowlisted PlatformIO subcommand in a device's project directory.
+/// Parses UUID from path, fetches device, validates project path, calls PlatformIOService::run_subcommand.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/pio",
+    tag = "esp32",
+    request_body = PioCommandRequest,
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Command output", body = CommandResponse),
+        (status = 400, description = "Invalid device ID, missing project path or unsafe arguments", body = CommandResponse),
+        (status = 403, description = "Subcommand, action or option not allowed", body = CommandResponse),
+        (status = 404, description = "Device or project directory not found", body = CommandResponse),
+        (status = 500, description = "Command failed", body = CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = CommandResponse),
+    )
+))]
+pub async fn run_pio_subcommand(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    Json(payload): Json<PioCommandRequest>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
     };
 
-    // Initialize project
     match pio_service
-        .init_project(&project_path, &payload.board)
+        .run_subcommand(&project_path, &payload.subcommand, &payload.args)
         .await
     {
         Ok(output) => (
@@ -213,192 +1529,238 @@ pub async fn init_project(
             Json(CommandResponse {
                 success: true,
                 output,
+                code: None,
                 error: None,
             }),
         )
             .into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(CommandResponse {
-                success: false,
-                output: "".to_string(),
-                error: Some(format!("Project initialization failed: {}", e)),
-            }),
-        )
-            .into_response(),
+        Err(e) => service_command_error(&e, "PlatformIO command failed"),
     }
 }
 
-/// HTTP handler to create a basic main.cpp for a device.
-/// Parses UUID from path, fetches device, validates project path, calls PlatformIOService::create_basic_main.
-pub async fn create_basic_main(
+/// HTTP handler to set a device's default port to the only connected serial port.
+/// Parses UUID from path, fetches device, calls PlatformIOService::list_serial_ports; with exactly
+/// one port it is stored via DeviceService::update, otherwise 409 lists the ports to choose from.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/autoport",
+    tag = "esp32",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Default port set to the only connected port", body = AutoPortResponse),
+        (status = 400, description = "Invalid device ID", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 409, description = "No port or several ports connected", body = AutoPortResponse),
+        (status = 500, description = "Operation failed", body = CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = CommandResponse),
+    )
+))]
+pub async fn autoport(
     Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
     Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
     axum::extract::Path(device_id): axum::extract::Path<String>,
 ) -> impl IntoResponse {
-    let parsed = Uuid::parse_str(&device_id);
-    if let Err(_) = parsed {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(e) = find_device(&device_service, device_id).await {
+        return e.into_response();
+    }
+
+    let ports = match pio_service.list_serial_ports().await {
+        Ok(ports) => ports,
+        Err(e) => return service_command_error(&e, "Failed to list serial ports"),
+    };
+    if ports.len() != 1 {
+        let error = if ports.is_empty() {
+            "No serial ports detected".to_string()
+        } else {
+            format!(
+                "{} serial ports detected; set default_port to one of them",
+                ports.len()
+            )
+        };
         return (
-            StatusCode::BAD_REQUEST,
-            Json(CommandResponse {
+            StatusCode::CONFLICT,
+            Json(AutoPortResponse {
                 success: false,
-                output: "".to_string(),
-                error: Some("Invalid device ID".to_string()),
+                port: None,
+                ports,
+                error: Some(error),
             }),
         )
             .into_response();
     }
-    let device_id = parsed.unwrap();
+
+    let port = ports[0].port.clone();
+    let changes = DeviceUpdate {
+        default_port: Some(port.clone()),
+        ..Default::default()
+    };
+    match device_service.update(device_id, changes).await {
+        Ok(Some(_)) => (
+            StatusCode::OK,
+            Json(AutoPortResponse {
+                success: true,
+                port: Some(port),
+                ports,
+                error: None,
+            }),
+        )
+            .into_response(),
+        Ok(None) => command_error(StatusCode::NOT_FOUND, "Device not found"),
+        Err(e) => command_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to update device: {}", e),
+        ),
+    }
+}
+
+/// HTTP handler to reset a device by toggling DTR/RTS on its serial port, without re-flashing.
+/// Uses the body's `port`, falling back to the device's default port; calls PlatformIOService::reset_device.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/reset",
+    tag = "esp32",
+    request_body = ResetRequest,
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Device reset", body = CommandResponse),
+        (status = 400, description = "Invalid device ID, no port or port can't be opened", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 500, description = "Reset failed", body = CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = CommandResponse),
+    )
+))]
+pub async fn reset_device(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    payload: Option<Json<ResetRequest>>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
 
     // Get device
-    let device = match device_service.get(device_id).await {
-        Ok(Some(d)) => d,
-        Ok(None) => {
-            return (
-                StatusCode::NOT_FOUND,
-                Json(CommandResponse {
-                    success: false,
-                    output: "".to_string(),
-                    error: Some("Device not found".to_string()),
-                }),
-            )
-                .into_response()
-        }
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(CommandResponse {
-                    success: false,
-                    output: "".to_string(),
-                    error: Some(format!("Failed to get device: {}", e)),
-                }),
-            )
-                .into_response()
-        }
+    let device = match find_device(&device_service, device_id).await {
+        Ok(device) => device,
+        Err(e) => return e.into_response(),
     };
 
-    // Check if device has project path
-    let project_path = match device.project_path {
+    // The request port overrides the device's default port
+    let port = match payload.and_then(|Json(p)| p.port).or(device.default_port) {
         Some(p) => p,
         None => {
-            return (
+            return command_error(
                 StatusCode::BAD_REQUEST,
-                Json(CommandResponse {
-                    success: false,
-                    output: "".to_string(),
-                    error: Some("Device has no project path configured".to_string()),
-                }),
+                "No port given and device has no default port",
             )
-                .into_response()
         }
     };
 
-    // Create basic main file
-    match pio_service.create_basic_main(&project_path).await {
-        Ok(_) => (
+    match pio_service.reset_device(&port).await {
+        Ok(output) => (
             StatusCode::OK,
             Json(CommandResponse {
                 success: true,
-                output: "Basic main.cpp created successfully".to_string(),
+                output,
+                code: None,
                 error: None,
             }),
         )
             .into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(CommandResponse {
-                success: false,
-                output: "".to_string(),
-                error: Some(format!("Failed to create main file: {}", e)),
-            }),
-        )
-            .into_response(),
+        Err(e) => service_command_error(&e, "Device reset failed"),
     }
 }
 
-/// HTTP handler to clean build files for a device.
-/// Parses UUID from path, fetches device, validates project path, calls PlatformIOService::clean_project.
-pub async fn clean_project(
+/// HTTP handler to read the partition table of a device's board with esptool.
+/// Uses `?port=`, falling back to the device's default port; calls
+/// PlatformIOService::read_partitions and parses the table into entries when it can.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/{id}/partitions",
+    tag = "esp32",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+        PartitionsQuery,
+    ),
+    responses(
+        (status = 200, description = "Partition table; `partitions` is null if it couldn't be parsed", body = PartitionsResponse),
+        (status = 400, description = "Invalid device ID, no port or missing project path", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 409, description = "An upload to the device is in progress", body = CommandResponse),
+        (status = 500, description = "Reading the partition table failed", body = CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = CommandResponse),
+    )
+))]
+pub async fn read_partitions(
     Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
     Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
     axum::extract::Path(device_id): axum::extract::Path<String>,
+    Query(query): Query<PartitionsQuery>,
 ) -> impl IntoResponse {
-    let parsed = Uuid::parse_str(&device_id);
-    if let Err(_) = parsed {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(CommandResponse {
-                success: false,
-                output: "".to_string(),
-                error: Some("Invalid device ID".to_string()),
-            }),
-        )
-            .into_response();
-    }
-    let device_id = parsed.unwrap();
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
 
-    // Get device
-    let device = match device_service.get(device_id).await {
-        Ok(Some(d)) => d,
-        Ok(None) => {
-            return (
-                StatusCode::NOT_FOUND,
-                Json(CommandResponse {
-                    success: false,
-                    output: "".to_string(),
-                    error: Some("Device not found".to_string()),
-                }),
-            )
-                .into_response()
-        }
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(CommandResponse {
-                    success: false,
-                    output: "".to_string(),
-                    error: Some(format!("Failed to get device: {}", e)),
-                }),
-            )
-                .into_response()
-        }
+    // Get device and its project path, which provides esptool
+    let (device, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
     };
 
-    // Check if device has project path
-    let project_path = match device.project_path {
+    // The query port overrides the device's default port
+    let port = match query.port.or(device.default_port) {
         Some(p) => p,
         None => {
-            return (
+            return command_error(
                 StatusCode::BAD_REQUEST,
-                Json(CommandResponse {
-                    success: false,
-                    output: "".to_string(),
-                    error: Some("Device has no project path configured".to_string()),
-                }),
+                "No port given and device has no default port",
             )
-                .into_response()
         }
     };
 
-    // Clean project
-    match pio_service.clean_project(&project_path).await {
+    match pio_service.read_partitions(&project_path, &port).await {
         Ok(output) => (
             StatusCode::OK,
-            Json(CommandResponse {
-                success: true,
+            Json(PartitionsResponse {
+                partitions: parse_partition_csv(&output),
                 output,
-                error: None,
             }),
         )
             .into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(CommandResponse {
-                success: false,
-                output: "".to_string(),
-                error: Some(format!("Clean failed: {}", e)),
-            }),
+        Err(e) => service_command_error(&e, "Reading partition table failed"),
+    }
+}
+
+/// HTTP handler to preview the `main.cpp` that create-main would write for `?template=`.
+/// Returns the source as `text/plain`; unknown templates are a 400.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/templates/main",
+    tag = "esp32",
+    params(TemplateQuery),
+    responses(
+        (status = 200, description = "Template source", body = String, content_type = "text/plain"),
+        (status = 400, description = "Unknown template", body = CommandResponse),
+    )
+))]
+pub async fn preview_main_template(Query(query): Query<TemplateQuery>) -> impl IntoResponse {
+    match basic_main_content(query.template.as_deref()) {
+        Ok(source) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            source,
         )
             .into_response(),
+        Err(e) => command_error(StatusCode::BAD_REQUEST, e.to_string()),
     }
 }
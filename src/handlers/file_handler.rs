@@ -0,0 +1,279 @@
+use async_compression::tokio::bufread::GzipEncoder;
+use axum::{
+    body::{Bytes, StreamBody},
+    extract::{Extension, Query},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use tokio::io::BufReader;
+use tokio_util::io::ReaderStream;
+
+use crate::dto::{
+    CommandResponse, ConfigValidationResponse, FirmwareCompression, FirmwareQuery,
+    SourceFilesResponse,
+};
+use crate::handlers::device_lookup::{
+    command_error, find_device, find_project, parse_device_id, service_command_error,
+};
+use crate::service::{validate_platformio_ini, DeviceService, PlatformIOService};
+
+/// HTTP handler to list the source files of a device's project.
+/// Parses UUID from path, fetches device, validates project path, calls PlatformIOService::list_source_files.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/{id}/files",
+    tag = "file",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Files under src/", body = SourceFilesResponse),
+        (status = 400, description = "Invalid device ID, missing project path or invalid input", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 500, description = "Operation failed", body = CommandResponse),
+    )
+))]
+pub async fn list_source_files(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    // List files
+    match pio_service.list_source_files(&project_path).await {
+        Ok(files) => (StatusCode::OK, Json(SourceFilesResponse { files })).into_response(),
+        Err(e) => command_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to list files: {}", e),
+        ),
+    }
+}
+
+/// HTTP handler to read one source file of a device's project as plain text.
+/// The path is relative to the project's `src/` directory; returns 404 if the file doesn't exist.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/{id}/files/{path}",
+    tag = "file",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+        ("path" = String, Path, description = "File path relative to src/"),
+    ),
+    responses(
+        (status = 200, description = "File contents", body = String, content_type = "text/plain"),
+        (status = 400, description = "Invalid device ID, missing project path or invalid file path", body = CommandResponse),
+        (status = 404, description = "Device or file not found", body = CommandResponse),
+        (status = 500, description = "Read failed", body = CommandResponse),
+    )
+))]
+pub async fn read_source_file(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path((device_id, file_path)): axum::extract::Path<(String, String)>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    // Read file
+    match pio_service.read_file(&project_path, &file_path).await {
+        Ok(contents) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            contents,
+        )
+            .into_response(),
+        Err(e) => service_command_error(&e, "Failed to read file"),
+    }
+}
+
+/// HTTP handler to download the firmware image of a device's last build.
+/// Calls PlatformIOService::find_firmware (`?env=` picks the environment when several were built)
+/// and streams the file as an attachment; returns 404 if nothing has been built.
+/// Like every response it is compressed as negotiated by `Accept-Encoding`; `?compress=gzip`
+/// gzips it even for clients that don't send the header. Either way the file is compressed
+/// chunk by chunk as it is streamed, never held in memory whole.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/{id}/firmware.bin",
+    tag = "file",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+        FirmwareQuery,
+    ),
+    responses(
+        (status = 200, description = "Firmware image; gzipped with `Content-Encoding: gzip` when requested", body = Vec<u8>, content_type = "application/octet-stream"),
+        (status = 400, description = "Invalid device ID, missing project path, or several environments built and no valid env given", body = CommandResponse),
+        (status = 404, description = "Device or firmware not found", body = CommandResponse),
+        (status = 500, description = "Read failed", body = CommandResponse),
+    )
+))]
+pub async fn download_firmware(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    Query(query): Query<FirmwareQuery>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    // Open the firmware
+    let opened = match pio_service
+        .find_firmware(&project_path, query.env.as_deref())
+        .await
+    {
+        Ok((env, path)) => tokio::fs::File::open(&path)
+            .await
+            .map(|file| (env, file))
+            .map_err(|e| anyhow::anyhow!("Failed to open firmware: {}", e)),
+        Err(e) => Err(e),
+    };
+    match opened {
+        Ok((env, file)) => {
+            let disposition = format!("attachment; filename=\"{}-firmware.bin\"", env);
+            let headers = [
+                (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                (header::CONTENT_DISPOSITION, disposition),
+            ];
+            match query.compress {
+                // Setting Content-Encoding keeps the compression layer from encoding it again.
+                Some(FirmwareCompression::Gzip) => (
+                    StatusCode::OK,
+                    headers,
+                    [(header::CONTENT_ENCODING, "gzip")],
+                    StreamBody::new(ReaderStream::new(GzipEncoder::new(BufReader::new(file)))),
+                )
+                    .into_response(),
+                None => (
+                    StatusCode::OK,
+                    headers,
+                    StreamBody::new(ReaderStream::new(file)),
+                )
+                    .into_response(),
+            }
+        }
+        Err(e) => service_command_error(&e, "Failed to read firmware"),
+    }
+}
+
+/// HTTP handler to save one source file of a device's project from the raw request body.
+/// The path is relative to the project's `src/` directory; parent directories are created as needed.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    put,
+    path = "/devices/{id}/files/{path}",
+    tag = "file",
+    request_body(content = String, content_type = "text/plain"),
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+        ("path" = String, Path, description = "File path relative to src/"),
+    ),
+    responses(
+        (status = 200, description = "File written", body = CommandResponse),
+        (status = 400, description = "Invalid device ID, missing project path or invalid file path", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 413, description = "File too large", body = CommandResponse),
+        (status = 500, description = "Write failed", body = CommandResponse),
+    )
+))]
+pub async fn write_source_file(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path((device_id, file_path)): axum::extract::Path<(String, String)>,
+    body: Bytes,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (_, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    // Write file
+    match pio_service
+        .write_file(&project_path, &file_path, &body)
+        .await
+    {
+        Ok(()) => (
+            StatusCode::OK,
+            Json(CommandResponse {
+                success: true,
+                output: format!("{} saved", file_path),
+                code: None,
+                error: None,
+            }),
+        )
+            .into_response(),
+        Err(e) => service_command_error(&e, "Failed to write file"),
+    }
+}
+
+/// HTTP handler to lint platformio.ini text from the raw request body without writing it.
+/// Always answers 200 for a known device; problems are listed in `errors`.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/config/validate",
+    tag = "file",
+    request_body(content = String, content_type = "text/plain"),
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Validation result", body = ConfigValidationResponse),
+        (status = 400, description = "Invalid device ID", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 500, description = "Operation failed", body = CommandResponse),
+    )
+))]
+pub async fn validate_config(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    body: String,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(e) = find_device(&device_service, device_id).await {
+        return e.into_response();
+    }
+
+    let errors = validate_platformio_ini(&body);
+    (
+        StatusCode::OK,
+        Json(ConfigValidationResponse {
+            valid: errors.is_empty(),
+            errors,
+        }),
+    )
+        .into_response()
+}
@@ -0,0 +1,128 @@
+use std::convert::Infallible;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+/// How the client wants JSON bodies formatted: pretty-printed when the query has `pretty=true`
+/// (or `pretty=1`) or the `Accept` header carries a `pretty` parameter, e.g.
+/// `Accept: application/json; pretty=true`. Compact otherwise.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonFormat {
+    pub pretty: bool,
+}
+
+impl JsonFormat {
+    /// Wraps `value` to be serialized in this format.
+    pub fn json<T: Serialize>(self, value: T) -> FormattedJson<T> {
+        FormattedJson {
+            value,
+            pretty: self.pretty,
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for JsonFormat {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let query = parts.uri.query().unwrap_or_default().split('&');
+        let accept = parts
+            .headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split([',', ';']));
+        let pretty = query.chain(accept).any(is_pretty_flag);
+        Ok(Self { pretty })
+    }
+}
+
+/// Matches `pretty`, `pretty=true` and `pretty=1`.
+fn is_pretty_flag(param: &str) -> bool {
+    let mut parts = param.trim().splitn(2, '=');
+    parts.next() == Some("pretty")
+        && matches!(
+            parts.next().map(|v| v.trim_matches('"')),
+            None | Some("true") | Some("1")
+        )
+}
+
+/// JSON response body like `axum::Json`, but pretty-printed when requested via `JsonFormat`.
+pub struct FormattedJson<T> {
+    value: T,
+    pretty: bool,
+}
+
+impl<T: Serialize> IntoResponse for FormattedJson<T> {
+    fn into_response(self) -> Response {
+        let body = if self.pretty {
+            serde_json::to_string_pretty(&self.value)
+        } else {
+            serde_json::to_string(&self.value)
+        };
+        match body {
+            Ok(body) => (
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/json"),
+                )],
+                body,
+            )
+                .into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::HttpBody;
+    use axum::http::Request;
+
+    async fn body_of(response: Response) -> String {
+        let mut body = response.into_body();
+        let chunk = body.data().await.unwrap().unwrap();
+        String::from_utf8(chunk.to_vec()).unwrap()
+    }
+
+    async fn format_of(request: Request<()>) -> JsonFormat {
+        let (mut parts, _) = request.into_parts();
+        JsonFormat::from_request_parts(&mut parts, &())
+            .await
+            .unwrap()
+    }
+
+    /// Test that the query flag and the Accept parameter select pretty output, compact by default.
+    #[tokio::test]
+    async fn pretty_is_opt_in() {
+        let compact = format_of(Request::get("/devices?tag=lab").body(()).unwrap()).await;
+        assert!(!compact.pretty);
+        let off = format_of(Request::get("/devices?pretty=false").body(()).unwrap()).await;
+        assert!(!off.pretty);
+        let query = format_of(
+            Request::get("/devices?tag=lab&pretty=true")
+                .body(())
+                .unwrap(),
+        )
+        .await;
+        assert!(query.pretty);
+        let accept = Request::get("/devices")
+            .header("accept", "application/json; pretty=1")
+            .body(())
+            .unwrap();
+        assert!(format_of(accept).await.pretty);
+
+        let value = serde_json::json!({ "name": "lab" });
+        let pretty_body = body_of(query.json(&value).into_response()).await;
+        assert_eq!(pretty_body, "{\n  \"name\": \"lab\"\n}");
+        let compact_body = body_of(compact.json(&value).into_response()).await;
+        assert_eq!(compact_body, r#"{"name":"lab"}"#);
+    }
+}
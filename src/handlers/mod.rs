@@ -1,13 +1,59 @@
+pub mod admin_handler;
+pub mod audit_handler;
 pub mod device_handler;
+mod device_lookup;
+mod error;
 pub mod esp32_handler;
+pub mod file_handler;
+mod json_format;
+pub mod network_handler;
+#[cfg(feature = "openapi")]
+pub mod openapi_handler;
+pub mod stream_handler;
+pub mod system_handler;
+pub mod watch_handler;
 
+pub use admin_handler::{shutdown, AdminContext};
+pub use audit_handler::audit_log;
 pub use device_handler::{
-    create_device,
-     get_device, list_devices};
+    archive_device, batch_get_devices, clone_device, create_device, unarchive_device,
+     device_exists, export_devices, get_device, heartbeat, import_devices, list_devices, regenerate_board_id,
+     relocate_device, update_device};
 pub use esp32_handler::{
     build_firmware,
+    build_log,
+    get_build_stats,
+    cancel_build,
+    wait_for_build,
+    build_command,
+    upload_command,
+    check_updates,
+    upload_batch,
     upload_firmware,
+    upload_fs,
+    deploy,
     init_project,
     clean_project,
     create_basic_main,
+    erase_flash,
+    git_pull,
+    list_libraries,
+    preview_main_template,
+    project_size,
+    purge_project,
+    reset_device,
+    read_partitions,
+    autoport,
+    run_pio_subcommand,
+    scaffold_project,
 };
+#[cfg(feature = "openapi")]
+pub use openapi_handler::openapi_json;
+pub use json_format::{FormattedJson, JsonFormat};
+pub use file_handler::{
+    download_firmware, list_source_files, read_source_file, validate_config, write_source_file,
+};
+pub use network_handler::ping_device;
+pub use stream_handler::{events, test_stream, upload_firmware_stream};
+pub use system_handler::{system_info, version};
+pub use watch_handler::{latest_build, start_watch, stop_watch};
@@ -0,0 +1,65 @@
+use axum::{extract::Extension, http::StatusCode, response::IntoResponse, Json};
+use uuid::Uuid;
+
+use crate::dto::PingResponse;
+use crate::handlers::error::{error_response, internal_error, service_error_status};
+use crate::middleware::RequestId;
+use crate::service::network_service::DEFAULT_PING_TIMEOUT;
+use crate::service::{DeviceService, NetworkService};
+
+/// HTTP handler to check whether a device is reachable over the network.
+/// Parses UUID from path, fetches device, probes its `ip_address` with NetworkService::ping.
+/// An unreachable device is a 200 with `reachable: false`.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/{id}/ping",
+    tag = "network",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Probe finished", body = PingResponse),
+        (status = 400, description = "Invalid device ID or no IP address configured", body = crate::dto::ErrorResponse),
+        (status = 404, description = "Device not found", body = crate::dto::ErrorResponse),
+        (status = 500, description = "Repository error", body = crate::dto::ErrorResponse),
+    )
+))]
+pub async fn ping_device(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(network_service): Extension<std::sync::Arc<NetworkService>>,
+    request_id: Option<Extension<RequestId>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    let Ok(device_id) = Uuid::parse_str(&device_id) else {
+        return error_response(StatusCode::BAD_REQUEST, "Invalid device ID");
+    };
+    let device = match device_service.get(device_id).await {
+        Ok(Some(d)) => d,
+        Ok(None) => return error_response(StatusCode::NOT_FOUND, "Device not found"),
+        Err(e) => return internal_error("failed to get device", &e, request_id.as_deref()),
+    };
+    let Some(ip_address) = device.ip_address else {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "Device has no IP address configured",
+        );
+    };
+
+    match network_service
+        .ping(&ip_address, DEFAULT_PING_TIMEOUT)
+        .await
+    {
+        Ok(latency) => (
+            StatusCode::OK,
+            Json(PingResponse {
+                reachable: latency.is_some(),
+                latency_ms: latency.map(|l| l.as_millis() as u64),
+            }),
+        )
+            .into_response(),
+        Err(e) => match service_error_status(&e) {
+            Some(status) => error_response(status, e.to_string()),
+            None => internal_error("ping failed", &e, request_id.as_deref()),
+        },
+    }
+}
@@ -0,0 +1,182 @@
+use axum::Json;
+use utoipa::OpenApi;
+
+use crate::domain::{
+    BuildStats, DeviceEvent, Diagnostic, DiagnosticSeverity, FirmwareSizeInfo, ImportConflict,
+    ImportReport, InitResult, LibraryInfo, MemoryUsage, Operation, OperationRecord, PackageUpdate,
+    PartitionEntry, ProjectSize, SerialPortInfo, SystemInfo, TestSummary,
+};
+use crate::dto::{
+    AutoPortResponse, BatchGetRequest, BatchUploadRequest, BatchUploadResponse, BatchUploadResult,
+    BuildLogResponse, BuildRequest, BuildResponse, CancelBuildResponse, CloneDeviceRequest,
+    CommandResponse, ConfigValidationResponse, CreateMainRequest, DeployPhaseResponse,
+    DeployRequest, DeployResponse, DeployStatus, DeviceCreateRequest, DeviceLinks, DeviceResponse,
+    DeviceUpdateRequest, EraseRequest, ErrorResponse, FirmwareCompression, InitProjectRequest,
+    InitResponse, InventoryDevice, InventoryDocument, LibrariesResponse, OutputEncoding,
+    PartitionsResponse, PingResponse, PioCommandRequest, PlannedCommandResponse, PurgeRequest,
+    PurgeResponse, RelocateRequest, ResetRequest, ScaffoldRequest, SourceFilesResponse,
+    TestStreamRequest, UpdatesResponse, UploadFsRequest, UploadRequest, UploadStreamRequest,
+    ValidationErrorResponse, VersionResponse,
+};
+use crate::handlers::{
+    admin_handler, audit_handler, device_handler, esp32_handler, file_handler, network_handler,
+    stream_handler, system_handler, watch_handler,
+};
+
+/// OpenAPI description of every route, assembled from the `utoipa::path` annotations on the handlers.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "IoT Remote Lab Server"),
+    paths(
+        device_handler::create_device,
+        device_handler::list_devices,
+        device_handler::batch_get_devices,
+        device_handler::export_devices,
+        device_handler::import_devices,
+        device_handler::clone_device,
+        device_handler::get_device,
+        device_handler::device_exists,
+        device_handler::update_device,
+        device_handler::archive_device,
+        device_handler::unarchive_device,
+        device_handler::heartbeat,
+        device_handler::regenerate_board_id,
+        device_handler::relocate_device,
+        esp32_handler::build_firmware,
+        esp32_handler::cancel_build,
+        esp32_handler::build_log,
+        esp32_handler::wait_for_build,
+        esp32_handler::build_command,
+        esp32_handler::upload_command,
+        esp32_handler::get_build_stats,
+        esp32_handler::upload_firmware,
+        esp32_handler::deploy,
+        esp32_handler::upload_batch,
+        esp32_handler::init_project,
+        esp32_handler::clean_project,
+        esp32_handler::purge_project,
+        esp32_handler::project_size,
+        esp32_handler::git_pull,
+        esp32_handler::create_basic_main,
+        esp32_handler::scaffold_project,
+        esp32_handler::upload_fs,
+        esp32_handler::erase_flash,
+        esp32_handler::reset_device,
+        esp32_handler::read_partitions,
+        esp32_handler::autoport,
+        esp32_handler::run_pio_subcommand,
+        esp32_handler::check_updates,
+        esp32_handler::list_libraries,
+        esp32_handler::preview_main_template,
+        file_handler::list_source_files,
+        file_handler::read_source_file,
+        file_handler::download_firmware,
+        file_handler::write_source_file,
+        file_handler::validate_config,
+        network_handler::ping_device,
+        stream_handler::upload_firmware_stream,
+        stream_handler::test_stream,
+        stream_handler::events,
+        watch_handler::start_watch,
+        watch_handler::stop_watch,
+        watch_handler::latest_build,
+        system_handler::version,
+        system_handler::system_info,
+        admin_handler::shutdown,
+        audit_handler::audit_log,
+    ),
+    components(schemas(
+        BatchGetRequest,
+        BatchUploadRequest,
+        BatchUploadResponse,
+        BatchUploadResult,
+        BuildLogResponse,
+        BuildRequest,
+        BuildResponse,
+        BuildStats,
+        DeviceEvent,
+        CancelBuildResponse,
+        CloneDeviceRequest,
+        CommandResponse,
+        ConfigValidationResponse,
+        CreateMainRequest,
+        DeployPhaseResponse,
+        DeployRequest,
+        DeployResponse,
+        DeployStatus,
+        DeviceCreateRequest,
+        DeviceResponse,
+        DeviceLinks,
+        DeviceUpdateRequest,
+        Diagnostic,
+        DiagnosticSeverity,
+        EraseRequest,
+        ErrorResponse,
+        FirmwareSizeInfo,
+        ImportConflict,
+        ImportReport,
+        InventoryDevice,
+        InventoryDocument,
+        InitProjectRequest,
+        InitResponse,
+        PurgeRequest,
+        PurgeResponse,
+        RelocateRequest,
+        InitResult,
+        MemoryUsage,
+        Operation,
+        OperationRecord,
+        PackageUpdate,
+        PlannedCommandResponse,
+        PartitionEntry,
+        PartitionsResponse,
+        ProjectSize,
+        SerialPortInfo,
+        SystemInfo,
+        TestStreamRequest,
+        TestSummary,
+        AutoPortResponse,
+        PioCommandRequest,
+        LibraryInfo,
+        LibrariesResponse,
+        OutputEncoding,
+        FirmwareCompression,
+        PingResponse,
+        ResetRequest,
+        ScaffoldRequest,
+        SourceFilesResponse,
+        UpdatesResponse,
+        UploadFsRequest,
+        UploadRequest,
+        UploadStreamRequest,
+        ValidationErrorResponse,
+        VersionResponse,
+    ))
+)]
+pub struct ApiDoc;
+
+/// HTTP handler serving the OpenAPI spec as JSON.
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that routes are documented with their path parameters and response codes.
+    #[test]
+    fn spec_documents_routes() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let build = &spec["paths"]["/devices/{id}/build"]["post"];
+        assert_eq!(build["parameters"][0]["name"], "id");
+        assert_eq!(build["parameters"][0]["in"], "path");
+        assert!(build["responses"]["404"].is_object());
+        assert!(spec["paths"]["/devices"]["get"]["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|p| p["name"] == "include_archived"));
+        assert!(spec["components"]["schemas"]["DeviceResponse"].is_object());
+    }
+}
@@ -0,0 +1,227 @@
+use axum::{
+    extract::{Extension, Query},
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Json,
+};
+use serde_json::json;
+use tokio_stream::{
+    wrappers::{BroadcastStream, ReceiverStream},
+    StreamExt,
+};
+
+use crate::domain::{DeviceEvent, Operation, TestSummary};
+use crate::dto::{EventsQuery, TestStreamRequest, UploadStreamRequest};
+use crate::handlers::audit_handler::request_api_key;
+use crate::handlers::device_lookup::{find_project, parse_device_id, service_command_error};
+use crate::handlers::AdminContext;
+use crate::service::platformio_service::{parse_test_summary, parse_upload_progress};
+use crate::service::{DeviceService, EventBus, OperationLog, PlatformIOService, StreamEvent};
+
+/// Converts a streamed PlatformIO event into an SSE event.
+/// Lines are sent as `log` events, esptool progress lines as `progress` events, and the exit as `done`.
+fn upload_event(event: StreamEvent) -> Result<Event, serde_json::Error> {
+    match event {
+        StreamEvent::Line(line) => match parse_upload_progress(&line) {
+            Some(percent) => Event::default()
+                .event("progress")
+                .json_data(json!({ "percent": percent })),
+            None => Event::default()
+                .event("log")
+                .json_data(json!({ "line": line })),
+        },
+        StreamEvent::Exit { success } => Event::default()
+            .event("done")
+            .json_data(json!({ "success": success })),
+    }
+}
+
+/// HTTP handler to upload firmware to a device while streaming progress as Server-Sent Events.
+/// Fetches the device, validates project path, calls PlatformIOService::upload_firmware_stream.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/upload/stream",
+    tag = "stream",
+    request_body = UploadStreamRequest,
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Server-Sent Events: `progress`, `log` and a final `done`", body = String, content_type = "text/event-stream"),
+        (status = 400, description = "Invalid device ID, missing project path or invalid input", body = crate::dto::CommandResponse),
+        (status = 404, description = "Device not found", body = crate::dto::CommandResponse),
+        (status = 500, description = "Operation failed", body = crate::dto::CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = crate::dto::CommandResponse),
+    )
+))]
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_firmware_stream(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    Extension(event_bus): Extension<std::sync::Arc<EventBus>>,
+    Extension(operation_log): Extension<std::sync::Arc<OperationLog>>,
+    Extension(admin): Extension<AdminContext>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    headers: HeaderMap,
+    payload: Option<Json<UploadStreamRequest>>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (device, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    // Start streamed upload
+    let (port, environment) = match payload {
+        Some(Json(p)) => (p.port, p.environment),
+        None => (None, None),
+    };
+    let port = port.or(device.default_port);
+    let environment = environment.or(device.default_env);
+    match pio_service
+        .upload_firmware_stream(&project_path, port.as_deref(), environment.as_deref())
+        .await
+    {
+        Ok(rx) => {
+            let device_id = device.id;
+            let api_key = request_api_key(&admin, &headers).map(str::to_string);
+            let stream = ReceiverStream::new(rx).map(move |event| {
+                if let StreamEvent::Exit { success } = event {
+                    event_bus.publish(DeviceEvent::UploadFinished { device_id, success });
+                    operation_log.record(device_id, Operation::Upload, success, api_key.as_deref());
+                }
+                upload_event(event)
+            });
+            Sse::new(stream)
+                .keep_alive(KeepAlive::default())
+                .into_response()
+        }
+        Err(e) => service_command_error(&e, "Upload failed"),
+    }
+}
+
+/// Converts a streamed `platformio test` event into an SSE event, remembering the summary line
+/// in `summary`. Lines are sent as `log` events. The exit is a `done` event with the parsed
+/// summary, or an `error` event when the run failed without printing one (a crashed runner or
+/// test firmware).
+fn test_event(
+    event: StreamEvent,
+    summary: &mut Option<TestSummary>,
+) -> Result<Event, serde_json::Error> {
+    match event {
+        StreamEvent::Line(line) => {
+            if let Some(parsed) = parse_test_summary(&line) {
+                *summary = Some(parsed);
+            }
+            Event::default()
+                .event("log")
+                .json_data(json!({ "line": line }))
+        }
+        StreamEvent::Exit { success } => match summary.take() {
+            Some(summary) => Event::default().event("done").json_data(json!({
+                "success": success && summary.passed(),
+                "summary": summary,
+            })),
+            None if success => Event::default()
+                .event("done")
+                .json_data(json!({ "success": true, "summary": null })),
+            None => Event::default().event("error").json_data(json!({
+                "success": false,
+                "error": "Test run failed without a summary; the test runner or firmware crashed",
+            })),
+        },
+    }
+}
+
+/// HTTP handler running a device's unit tests while streaming the output as Server-Sent Events.
+/// Fetches the device, validates project path, calls PlatformIOService::test_project_stream.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/test/stream",
+    tag = "stream",
+    request_body = TestStreamRequest,
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Server-Sent Events: `log`, then a final `done` with the test summary or `error` if the run crashed", body = String, content_type = "text/event-stream"),
+        (status = 400, description = "Invalid device ID, missing project path or invalid input", body = crate::dto::CommandResponse),
+        (status = 404, description = "Device not found", body = crate::dto::CommandResponse),
+        (status = 409, description = "A build or upload is running for the project", body = crate::dto::CommandResponse),
+        (status = 500, description = "Operation failed", body = crate::dto::CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = crate::dto::CommandResponse),
+    )
+))]
+pub async fn test_stream(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    payload: Option<Json<TestStreamRequest>>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    let (device, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    let (port, environment) = match payload {
+        Some(Json(p)) => (p.port, p.environment),
+        None => (None, None),
+    };
+    let port = port.or(device.default_port);
+    let environment = environment.or(device.default_env);
+    match pio_service
+        .test_project_stream(&project_path, port.as_deref(), environment.as_deref())
+        .await
+    {
+        Ok(rx) => {
+            let mut summary = None;
+            let stream = ReceiverStream::new(rx).map(move |event| test_event(event, &mut summary));
+            Sse::new(stream)
+                .keep_alive(KeepAlive::default())
+                .into_response()
+        }
+        Err(e) => service_command_error(&e, "Test run failed"),
+    }
+}
+
+/// HTTP handler streaming device lifecycle events as Server-Sent Events.
+/// Each event is named after its `type` and carries the event as JSON. With `device_id` only
+/// that device's events are sent. A client that falls too far behind is disconnected rather
+/// than slowing down publishers; it can reconnect, but the missed events are gone.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/events",
+    tag = "stream",
+    params(EventsQuery),
+    responses(
+        (status = 200, description = "Server-Sent Events, one per DeviceEvent", body = DeviceEvent, content_type = "text/event-stream"),
+    )
+))]
+pub async fn events(
+    Extension(event_bus): Extension<std::sync::Arc<EventBus>>,
+    Query(query): Query<EventsQuery>,
+) -> impl IntoResponse {
+    let stream = BroadcastStream::new(event_bus.subscribe())
+        .filter(move |item| match (item, query.device_id) {
+            (Ok(event), Some(id)) => event.device_id() == id,
+            _ => true,
+        })
+        .map_while(|item| {
+            item.ok()
+                .map(|event: DeviceEvent| Event::default().event(event.name()).json_data(&event))
+        });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,52 @@
+use axum::{
+    extract::Extension,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::dto::VersionResponse;
+use crate::handlers::device_lookup::service_command_error;
+use crate::service::PlatformIOService;
+
+/// HTTP handler reporting the server build and the PlatformIO version it drives.
+/// Purely informational: always 200, with `platformio_version` null when PlatformIO is missing.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/version",
+    tag = "system",
+    responses(
+        (status = 200, description = "Server and dependency versions", body = VersionResponse),
+    )
+))]
+pub async fn version(
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+) -> Json<VersionResponse> {
+    Json(VersionResponse {
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        git_commit: option_env!("BUILD_GIT_COMMIT").map(str::to_string),
+        rustc_version: option_env!("BUILD_RUSTC_VERSION").map(str::to_string),
+        platformio_version: pio_service.pio_version().await,
+    })
+}
+
+/// HTTP handler describing the PlatformIO toolchain on the server, for debugging host-specific
+/// build problems remotely. Calls PlatformIOService::system_info, which caches for a minute.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/system/info",
+    tag = "system",
+    responses(
+        (status = 200, description = "PlatformIO environment of the server", body = crate::domain::SystemInfo),
+        (status = 500, description = "PlatformIO's system info couldn't be read", body = crate::dto::CommandResponse),
+        (status = 503, description = "PlatformIO is not installed (code `PLATFORMIO_UNAVAILABLE`)", body = crate::dto::CommandResponse),
+    )
+))]
+pub async fn system_info(
+    Extension(pio_service): Extension<std::sync::Arc<PlatformIOService>>,
+) -> Response {
+    match pio_service.system_info().await {
+        Ok(info) => (StatusCode::OK, Json(info)).into_response(),
+        Err(e) => service_command_error(&e, "Failed to read system info"),
+    }
+}
@@ -0,0 +1,163 @@
+use axum::{extract::Extension, http::StatusCode, response::IntoResponse, Json};
+
+use crate::dto::{BuildResponse, CommandResponse};
+use crate::handlers::device_lookup::{
+    command_error, find_project, parse_device_id, service_command_error,
+};
+use crate::service::{parse_diagnostics, BuildOptions, DeviceService, WatchService};
+
+/// HTTP handler to start auto-building a device's project when its files change.
+/// Parses UUID from path, fetches device, validates project path, calls WatchService::start.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    path = "/devices/{id}/watch",
+    tag = "watch",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Watcher started", body = CommandResponse),
+        (status = 400, description = "Invalid device ID, missing project path or invalid project path", body = CommandResponse),
+        (status = 404, description = "Device not found", body = CommandResponse),
+        (status = 409, description = "Device is already being watched", body = CommandResponse),
+        (status = 500, description = "Watcher failed to start", body = CommandResponse),
+    )
+))]
+pub async fn start_watch(
+    Extension(device_service): Extension<std::sync::Arc<DeviceService>>,
+    Extension(watch_service): Extension<std::sync::Arc<WatchService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    // Get device and its project path
+    let (device, project_path) = match find_project(&device_service, device_id).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    // Start watcher
+    match watch_service
+        .start(
+            device_id,
+            &project_path,
+            BuildOptions {
+                env_vars: device.env_vars,
+                environment: device.default_env,
+                ..Default::default()
+            },
+        )
+        .await
+    {
+        Ok(()) => (
+            StatusCode::OK,
+            Json(CommandResponse {
+                success: true,
+                output: format!("Watching {}", project_path),
+                code: None,
+                error: None,
+            }),
+        )
+            .into_response(),
+        Err(e) => service_command_error(&e, "Failed to start watcher"),
+    }
+}
+
+/// HTTP handler to stop auto-building a device's project.
+/// Returns 404 if no watcher is running for the device.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    delete,
+    path = "/devices/{id}/watch",
+    tag = "watch",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Watcher stopped", body = CommandResponse),
+        (status = 400, description = "Invalid device ID", body = CommandResponse),
+        (status = 404, description = "Device is not being watched", body = CommandResponse),
+    )
+))]
+pub async fn stop_watch(
+    Extension(watch_service): Extension<std::sync::Arc<WatchService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    if watch_service.stop(device_id) {
+        (
+            StatusCode::OK,
+            Json(CommandResponse {
+                success: true,
+                output: "Watcher stopped".to_string(),
+                code: None,
+                error: None,
+            }),
+        )
+            .into_response()
+    } else {
+        command_error(StatusCode::NOT_FOUND, "Device is not being watched")
+    }
+}
+
+/// HTTP handler to fetch the result of the latest watcher-triggered build for a device.
+/// Returns 404 if the watcher hasn't built anything yet.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    path = "/devices/{id}/build/latest",
+    tag = "watch",
+    params(
+        ("id" = Uuid, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 200, description = "Result of the latest watcher build", body = BuildResponse),
+        (status = 400, description = "Invalid device ID", body = CommandResponse),
+        (status = 404, description = "No watcher build yet", body = CommandResponse),
+    )
+))]
+pub async fn latest_build(
+    Extension(watch_service): Extension<std::sync::Arc<WatchService>>,
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    let device_id = match parse_device_id(&device_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    match watch_service.latest_build(device_id) {
+        Some(Ok(build)) => (
+            StatusCode::OK,
+            Json(BuildResponse {
+                success: true,
+                output: build.output,
+                error: None,
+                firmware_size: build.firmware_size,
+                diagnostics: build.diagnostics,
+                code: None,
+            }),
+        )
+            .into_response(),
+        Some(Err(e)) => (
+            StatusCode::OK,
+            Json(BuildResponse {
+                success: false,
+                output: "".to_string(),
+                error: Some(format!("Build failed: {}", e)),
+                firmware_size: None,
+                diagnostics: parse_diagnostics(&e),
+                code: None,
+            }),
+        )
+            .into_response(),
+        None => command_error(
+            StatusCode::NOT_FOUND,
+            "No watcher build has run for this device",
+        ),
+    }
+}
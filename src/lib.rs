@@ -4,5 +4,6 @@ pub mod repository;
 pub mod adapters;
 pub mod service;
 pub mod handlers;
+pub mod middleware;
 
 // adapters/* lives in src/adapters/*.rs - re-exported by adapters/mod.rs
@@ -2,65 +2,1748 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 
 use axum::{
-    routing::{get, post},
+    body::HttpBody,
+    extract::DefaultBodyLimit,
+    http::Request,
+    middleware,
+    routing::{get, post, MethodRouter},
     Extension, Router, Server,
 };
+use axum_server::tls_rustls::RustlsConfig;
+use tower_http::compression::predicate::{And, DefaultPredicate, NotForContentType, Predicate};
+use tower_http::compression::CompressionLayer;
+use tower_http::limit::RequestBodyLimitLayer;
 use tower_http::trace::TraceLayer;
 
-use iot_remote_lab_server::adapters::InMemoryDeviceRepository;
+#[cfg(feature = "postgres")]
+use iot_remote_lab_server::adapters::PostgresDeviceRepository;
+#[cfg(feature = "redis")]
+use iot_remote_lab_server::adapters::RedisDeviceRepository;
+use iot_remote_lab_server::adapters::{InMemoryDeviceRepository, JsonFileDeviceRepository};
 use iot_remote_lab_server::handlers::{
-    build_firmware, clean_project, create_basic_main, create_device, get_device, init_project,
-    list_devices, upload_firmware,
+    archive_device, audit_log, autoport, batch_get_devices, build_command, build_firmware,
+    build_log, cancel_build, check_updates, clean_project, clone_device, create_basic_main,
+    create_device, deploy, device_exists, download_firmware, erase_flash, events, export_devices,
+    get_build_stats, get_device, git_pull, heartbeat, import_devices, init_project, latest_build,
+    list_devices, list_libraries, list_source_files, ping_device, preview_main_template,
+    project_size, purge_project, read_partitions, read_source_file, regenerate_board_id,
+    relocate_device, reset_device, run_pio_subcommand, scaffold_project, shutdown, start_watch,
+    stop_watch, system_info, test_stream, unarchive_device, update_device, upload_batch,
+    upload_command, upload_firmware, upload_firmware_stream, upload_fs, validate_config, version,
+    wait_for_build, write_source_file, AdminContext,
 };
-use iot_remote_lab_server::service::{DeviceService, PlatformIOService};
+use iot_remote_lab_server::middleware::{rate_limit, request_id, RateLimiter, RequestId};
+use iot_remote_lab_server::repository::DeviceRepository;
+use iot_remote_lab_server::service::{
+    BuildStatsService, DeviceService, EventBus, NetworkService, OperationLog, PlatformIOService,
+    TempCleanupService, WatchService,
+};
+
+/// Services injected into every handler as `Extension`s.
+struct Services {
+    device: Arc<DeviceService>,
+    pio: Arc<PlatformIOService>,
+    watch: Arc<WatchService>,
+    build_stats: Arc<BuildStatsService>,
+    operations: Arc<OperationLog>,
+    network: Arc<NetworkService>,
+    events: Arc<EventBus>,
+}
+
+impl Services {
+    /// Services around `repo` with default configuration, as used by `main` and the tests.
+    fn new(repo: Arc<dyn DeviceRepository + Send + Sync>) -> Self {
+        let pio = Arc::new(PlatformIOService::new());
+        Self {
+            device: Arc::new(DeviceService::new(repo).with_pio_service(pio.clone())),
+            watch: Arc::new(WatchService::new(pio.clone())),
+            pio,
+            build_stats: Arc::new(BuildStatsService::new()),
+            operations: Arc::new(OperationLog::from_env()),
+            network: Arc::new(NetworkService::new()),
+            events: Arc::new(EventBus::new()),
+        }
+    }
+}
 
-/// Entry point of the application. Initializes services, checks for PlatformIO installation,
-/// sets up routes, and starts the HTTP server on 127.0.0.1:3000.
+/// Request body cap when `MAX_BODY_BYTES` is unset; firmware images are a few MB at most.
+const DEFAULT_MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Entry point of the application. Waits for the device repository to be ready (see
+/// `DeviceRepository::ready`), initializes services, checks for PlatformIO installation,
+/// sets up routes, and starts the server on 127.0.0.1:3000: HTTPS when `TLS_CERT` and `TLS_KEY`
+/// are set (see `tls_config`), plain HTTP otherwise.
 #[tokio::main]
 async fn main() {
-    // repository adapter (in-memory for demo)
-    let repo = InMemoryDeviceRepository::new();
-    let device_service = Arc::new(DeviceService::new(Arc::new(repo)));
-    let pio_service = Arc::new(PlatformIOService::new());
+    tracing_subscriber::fmt::init();
+
+    let repository = build_repository().await;
+    if let Err(e) = repository.ready().await {
+        eprintln!("Error: device repository is not ready: {}", e);
+        std::process::exit(1);
+    }
+    let services = Services::new(repository);
+    let pio_service = services.pio.clone();
+    let watch_service = services.watch.clone();
 
     // Check if PlatformIO is available
-    match std::process::Command::new("platformio")
-        .arg("--version")
-        .output()
-    {
-        Ok(output) if output.status.success() => {
-            println!("PlatformIO is available");
+    match pio_service.check_pio_installed().await {
+        Ok(()) => {
+            println!("PlatformIO is available ({})", pio_service.binary());
         }
-        _ => {
-            eprintln!("Warning: PlatformIO not found. ESP32 operations will fail. Please install PlatformIO: https://platformio.org/install");
+        Err(_) => {
+            eprintln!("Warning: PlatformIO not found. ESP32 operations will fail. Please install PlatformIO or set PLATFORMIO_BIN: https://platformio.org/install");
+        }
+    }
+
+    println!(
+        "Resolving project paths under {}",
+        pio_service.projects_root().display()
+    );
+    check_projects_root(&pio_service).await;
+    if let Some(template) = pio_service.template_dir() {
+        if template.is_dir() {
+            println!("Seeding new projects from {}", template.display());
+        } else {
+            eprintln!(
+                "Warning: TEMPLATE_DIR {} is not a directory; project init will fail",
+                template.display()
+            );
         }
     }
 
-    let app = register_routes(device_service.clone(), pio_service.clone())
-        .layer(Extension(device_service))
-        .layer(Extension(pio_service))
-        .layer(TraceLayer::new_for_http());
-    println!("Listening on http://127.0.0.1:3000");
+    let temp_cleanup = Arc::new(TempCleanupService::from_env());
+    println!(
+        "Removing temp upload files older than {}s from {}",
+        temp_cleanup.retention().as_secs(),
+        temp_cleanup.dir().display()
+    );
+    temp_cleanup.spawn();
+
+    let admin = AdminContext::from_env();
+    if !admin.enabled() {
+        println!("ADMIN_API_KEY is not set; admin endpoints are disabled");
+    }
+
+    let max_body_bytes = max_body_bytes();
+    println!("Limiting request bodies to {} bytes", max_body_bytes);
+
+    let disabled = disabled_endpoints();
+    if !disabled.is_empty() {
+        println!("Disabled endpoints: {}", disabled.join(", "));
+    }
+
+    let app = register_routes(services, admin.clone(), max_body_bytes, &disabled);
+    let app = match RateLimiter::from_env() {
+        Some(limiter) => {
+            println!(
+                "Rate limiting to {} requests per {}s per client",
+                limiter.requests(),
+                limiter.window().as_secs()
+            );
+            let limiter = Arc::new(limiter.with_admin(admin.clone()));
+            limiter.spawn_sweeper();
+            app.layer(middleware::from_fn_with_state(limiter, rate_limit))
+        }
+        None => app,
+    }
+    .layer(TraceLayer::new_for_http().make_span_with(request_span))
+    .layer(middleware::from_fn(request_id));
 
     let addr: SocketAddr = "127.0.0.1:3000".parse().unwrap();
+    let make_service = app.into_make_service_with_connect_info::<SocketAddr>();
+
+    match tls_config().await {
+        Some(config) => {
+            println!("Listening on https://{} (TLS enabled)", addr);
+            let handle = axum_server::Handle::new();
+            tokio::spawn({
+                let handle = handle.clone();
+                async move {
+                    shutdown_signal(admin).await;
+                    handle.graceful_shutdown(None);
+                }
+            });
+            axum_server::bind_rustls(addr, config)
+                .handle(handle)
+                .serve(make_service)
+                .await
+                .unwrap();
+        }
+        None => {
+            println!("Listening on http://{} (TLS disabled)", addr);
+            Server::bind(&addr)
+                .serve(make_service)
+                .with_graceful_shutdown(shutdown_signal(admin))
+                .await
+                .unwrap();
+        }
+    }
+
+    // Tear down file watchers so no rebuilds start after the server stops
+    watch_service.stop_all();
+}
+
+/// Exits the process if the projects root can't be created or written to, instead of failing
+/// every init and scaffold later. Setting `SKIP_PROJECTS_ROOT_CHECK` skips the check, e.g. for
+/// roots that are mounted only after startup.
+async fn check_projects_root(pio_service: &PlatformIOService) {
+    if std::env::var_os("SKIP_PROJECTS_ROOT_CHECK").is_some() {
+        println!("SKIP_PROJECTS_ROOT_CHECK is set; not checking the projects root");
+        return;
+    }
+    if let Err(e) = pio_service.check_projects_root().await {
+        eprintln!(
+            "Error: {}; set PROJECTS_ROOT to a writable directory or SKIP_PROJECTS_ROOT_CHECK to start anyway",
+            e
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Resolves when the process receives Ctrl+C or an admin calls `POST /admin/shutdown`,
+/// starting graceful shutdown.
+async fn shutdown_signal(admin: AdminContext) {
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result.expect("failed to install Ctrl+C handler"),
+        _ = admin.shutdown_requested() => println!("Shutdown requested via admin endpoint"),
+    }
+    println!("Shutting down");
+}
+
+/// Tracing span for a request, tagged with the id assigned by the request-id middleware.
+fn request_span<B>(request: &Request<B>) -> tracing::Span {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.as_str())
+        .unwrap_or_default();
+    tracing::info_span!(
+        "http_request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id = %request_id,
+    )
+}
+
+/// Loads the PEM certificate chain and private key named by `TLS_CERT` and `TLS_KEY`. Returns
+/// `None` (plain HTTP) when neither is set; exits the process if only one is set or the files
+/// can't be loaded, rather than silently serving without TLS.
+async fn tls_config() -> Option<RustlsConfig> {
+    let (cert, key) = match (std::env::var("TLS_CERT"), std::env::var("TLS_KEY")) {
+        (Ok(cert), Ok(key)) => (cert, key),
+        (Err(_), Err(_)) => return None,
+        _ => {
+            eprintln!("Error: TLS_CERT and TLS_KEY must be set together");
+            std::process::exit(1);
+        }
+    };
+    match RustlsConfig::from_pem_file(&cert, &key).await {
+        Ok(config) => Some(config),
+        Err(e) => {
+            eprintln!(
+                "Error: failed to load TLS certificate {} or key {}: {}",
+                cert, key, e
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Reads the request body cap from `MAX_BODY_BYTES`, falling back to 16 MiB.
+fn max_body_bytes() -> usize {
+    std::env::var("MAX_BODY_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_BODY_BYTES)
+}
+
+/// Reads the route names listed in the comma-separated `DISABLED_ENDPOINTS` (see `route_name` and
+/// `disables`), e.g. `flash,purge` for a read-only monitoring deployment.
+fn disabled_endpoints() -> Vec<String> {
+    std::env::var("DISABLED_ENDPOINTS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Name of the route at `path` as used by `DISABLED_ENDPOINTS`: the path without its leading
+/// `/`, and without `/devices/:id/` for per-device routes. `/devices/:id/upload` is `upload`,
+/// `/devices/:id/build/cancel` is `build/cancel` and `/devices/import` is `devices/import`.
+fn route_name(path: &str) -> &str {
+    path.strip_prefix("/devices/:id/")
+        .unwrap_or_else(|| path.trim_start_matches('/'))
+}
+
+/// Names for `DISABLED_ENDPOINTS` that stand for several routes. `flash` covers every route that
+/// can write to a board's flash.
+const ENDPOINT_GROUPS: &[(&str, &[&str])] = &[(
+    "flash",
+    &[
+        "upload",
+        "upload-fs",
+        "deploy",
+        "erase",
+        "devices/upload-batch",
+    ],
+)];
+
+/// Whether the disabled `name` covers the route named `route`: the route itself, its sub-routes
+/// (`upload` covers `upload/stream` and `upload/command`, `build` all of `build/*`), or any
+/// route of the group `name` (see `ENDPOINT_GROUPS`).
+fn disables(name: &str, route: &str) -> bool {
+    let covers = |name: &str| {
+        route
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    };
+    covers(name)
+        || ENDPOINT_GROUPS
+            .iter()
+            .filter(|(group, _)| *group == name)
+            .any(|(_, members)| members.iter().any(|member| covers(member)))
+}
 
-    Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+/// `Router` builder leaving out every route covered by a name in `disabled` (see `disables`), so
+/// a disabled route answers 404 like any unknown path, whichever method is used.
+struct Routes<'a, B> {
+    router: Router<(), B>,
+    disabled: &'a [String],
+    skipped: Vec<&'a str>,
 }
+
+impl<'a, B: HttpBody + Send + 'static> Routes<'a, B> {
+    /// Empty router skipping the routes named in `disabled`.
+    fn new(disabled: &'a [String]) -> Self {
+        Self {
+            router: Router::new(),
+            disabled,
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Adds `method_router` at `path` unless the route is disabled.
+    fn route(mut self, path: &str, method_router: MethodRouter<(), B>) -> Self {
+        let before = self.skipped.len();
+        for name in self.disabled {
+            if disables(name, route_name(path)) {
+                self.skipped.push(name);
+            }
+        }
+        if self.skipped.len() == before {
+            self.router = self.router.route(path, method_router);
+        }
+        self
+    }
+
+    /// The router, warning about disabled names that matched no route (likely typos).
+    fn into_router(self) -> Router<(), B> {
+        for name in self.disabled {
+            if !self.skipped.contains(&name.as_str()) {
+                eprintln!("Warning: DISABLED_ENDPOINTS names no endpoint \"{}\"", name);
+            }
+        }
+        self.router
+    }
+}
+
+/// Selects the repository adapter: PostgreSQL when built with the `postgres` feature and
+/// `DATABASE_URL` is set, else Redis when built with the `redis` feature and `REDIS_URL` is set,
+/// else a JSON file when `DEVICES_FILE` is set, otherwise in-memory.
+async fn build_repository() -> Arc<dyn DeviceRepository + Send + Sync> {
+    #[cfg(feature = "postgres")]
+    if let Ok(url) = std::env::var("DATABASE_URL") {
+        let repo = PostgresDeviceRepository::connect(&url)
+            .await
+            .expect("failed to initialize PostgreSQL repository");
+        println!("Using PostgreSQL device repository");
+        return Arc::new(repo);
+    }
+
+    #[cfg(feature = "redis")]
+    if let Ok(url) = std::env::var("REDIS_URL") {
+        let repo = RedisDeviceRepository::connect(&url)
+            .await
+            .expect("failed to initialize Redis repository");
+        println!("Using Redis device repository");
+        return Arc::new(repo);
+    }
+
+    if let Ok(path) = std::env::var("DEVICES_FILE") {
+        let repo = JsonFileDeviceRepository::open(&path)
+            .await
+            .expect("failed to load device file");
+        println!("Using JSON file device repository at {}", path);
+        return Arc::new(repo);
+    }
+
+    // repository adapter (in-memory for demo)
+    Arc::new(InMemoryDeviceRepository::new())
+}
+
+/// Response compression (gzip or brotli, as negotiated via `Accept-Encoding`) with tower-http's
+/// default exclusions plus Server-Sent Events, whose frames must reach the client unbuffered.
+fn compression_layer() -> CompressionLayer<And<DefaultPredicate, NotForContentType>> {
+    CompressionLayer::new().compress_when(
+        DefaultPredicate::new().and(NotForContentType::const_new("text/event-stream")),
+    )
+}
+
 /// Defines and returns the Axum router with all API routes configured.
 /// Routes include device CRUD and ESP32 operations, with services injected via Extension.
+/// Request bodies larger than `max_body_bytes` are rejected with 413 Payload Too Large.
+/// Responses are compressed when the client accepts it (see `compression_layer`).
+/// Routes named in `disabled` are left out (see `Routes`).
 fn register_routes(
-    device_service: Arc<DeviceService>,
-    pio_service: Arc<PlatformIOService>,
+    services: Services,
+    admin: AdminContext,
+    max_body_bytes: usize,
+    disabled: &[String],
 ) -> Router {
-    Router::new()
+    let routes = Routes::new(disabled)
         .route("/devices", post(create_device).get(list_devices))
-        .route("/devices/:id", get(get_device))
+        .route("/devices/batch-get", post(batch_get_devices))
+        .route("/devices/export", get(export_devices))
+        .route("/devices/import", post(import_devices))
+        .route("/devices/upload-batch", post(upload_batch))
+        .route(
+            "/devices/:id",
+            get(get_device).head(device_exists).patch(update_device),
+        )
+        .route("/devices/:id/clone", post(clone_device))
+        .route("/devices/:id/archive", post(archive_device))
+        .route("/devices/:id/unarchive", post(unarchive_device))
+        .route("/devices/:id/heartbeat", post(heartbeat))
+        .route(
+            "/devices/:id/regenerate-board-id",
+            post(regenerate_board_id),
+        )
+        .route("/devices/:id/relocate", post(relocate_device))
         .route("/devices/:id/build", post(build_firmware))
+        .route("/devices/:id/build/cancel", post(cancel_build))
+        .route("/devices/:id/build/latest", get(latest_build))
+        .route("/devices/:id/build/log", get(build_log))
+        .route("/devices/:id/build/wait", get(wait_for_build))
+        .route("/devices/:id/build/command", get(build_command))
+        .route("/devices/:id/upload/command", get(upload_command))
+        .route("/devices/:id/stats", get(get_build_stats))
+        .route("/devices/:id/ping", get(ping_device))
         .route("/devices/:id/upload", post(upload_firmware))
+        .route("/devices/:id/upload/stream", post(upload_firmware_stream))
+        .route("/devices/:id/test/stream", post(test_stream))
+        .route("/devices/:id/upload-fs", post(upload_fs))
+        .route("/devices/:id/deploy", post(deploy))
         .route("/devices/:id/init", post(init_project))
         .route("/devices/:id/clean", post(clean_project))
+        .route("/devices/:id/purge", post(purge_project))
+        .route("/devices/:id/size", get(project_size))
+        .route("/devices/:id/git/pull", post(git_pull))
         .route("/devices/:id/create-main", post(create_basic_main))
+        .route("/devices/:id/scaffold", post(scaffold_project))
+        .route("/devices/:id/erase", post(erase_flash))
+        .route("/devices/:id/reset", post(reset_device))
+        .route("/devices/:id/partitions", get(read_partitions))
+        .route("/devices/:id/autoport", post(autoport))
+        .route("/devices/:id/pio", post(run_pio_subcommand))
+        .route("/devices/:id/updates", get(check_updates))
+        .route("/devices/:id/libraries", get(list_libraries))
+        .route("/devices/:id/firmware.bin", get(download_firmware))
+        .route("/devices/:id/config/validate", post(validate_config))
+        .route("/devices/:id/files", get(list_source_files))
+        .route(
+            "/devices/:id/files/*path",
+            get(read_source_file).put(write_source_file),
+        )
+        .route("/devices/:id/watch", post(start_watch).delete(stop_watch))
+        .route("/events", get(events))
+        .route("/templates/main", get(preview_main_template))
+        .route("/version", get(version))
+        .route("/system/info", get(system_info))
+        .route("/audit", get(audit_log))
+        .route("/admin/shutdown", post(shutdown));
+
+    #[cfg(feature = "openapi")]
+    let routes = routes.route(
+        "/openapi.json",
+        get(iot_remote_lab_server::handlers::openapi_json),
+    );
+
+    routes
+        .into_router()
+        .layer(Extension(services.device))
+        .layer(Extension(services.pio))
+        .layer(Extension(services.watch))
+        .layer(Extension(services.build_stats))
+        .layer(Extension(services.operations))
+        .layer(Extension(services.network))
+        .layer(Extension(services.events))
+        .layer(Extension(admin))
+        // Replace axum's 2 MB extractor default with the configured cap
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(max_body_bytes))
+        .layer(compression_layer())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use iot_remote_lab_server::domain::{BoardType, DeviceUpdate, NewDevice};
+    use iot_remote_lab_server::service::{MockPlatformIORunner, RunOutput};
+    use std::path::{Path, PathBuf};
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    /// Projects root for tests: a fresh directory under the system temp dir, deleted with
+    /// everything in it when the guard is dropped. Derefs to its path.
+    struct TempProjectsRoot(PathBuf);
+
+    impl TempProjectsRoot {
+        /// Creates `<temp dir>/<prefix>-<uuid>` and the (possibly nested) `projects` inside it.
+        fn new(prefix: &str, projects: &[&str]) -> Self {
+            let path = std::env::temp_dir().join(format!("{}-{}", prefix, Uuid::new_v4()));
+            std::fs::create_dir_all(&path).unwrap();
+            for project in projects {
+                std::fs::create_dir_all(path.join(project)).unwrap();
+            }
+            Self(path)
+        }
+    }
+
+    impl std::ops::Deref for TempProjectsRoot {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempProjectsRoot {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// App with one device, "lab", whose initialized esp32dev project `lab` lives in a temp
+    /// projects root and whose PlatformIO commands are answered by `mock`.
+    async fn lab_app(mock: Arc<MockPlatformIORunner>) -> (Router, Uuid, TempProjectsRoot) {
+        lab_app_with(mock, None, AdminContext::default()).await
+    }
+
+    /// `lab_app` with the device's `default_port` and the admin context set.
+    async fn lab_app_with(
+        mock: Arc<MockPlatformIORunner>,
+        default_port: Option<&str>,
+        admin: AdminContext,
+    ) -> (Router, Uuid, TempProjectsRoot) {
+        let root = TempProjectsRoot::new("lab-app", &["lab"]);
+        tokio::fs::write(root.join("lab/platformio.ini"), "[env:esp32dev]\n")
+            .await
+            .unwrap();
+        let mut services = Services::new(Arc::new(InMemoryDeviceRepository::new()));
+        services.pio = Arc::new(
+            PlatformIOService::new()
+                .with_projects_root(&*root)
+                .with_runner(mock),
+        );
+        let device = services
+            .device
+            .create(
+                "lab",
+                NewDevice {
+                    board_type: Some(BoardType::Esp32Dev),
+                    project_path: Some("lab".to_string()),
+                    default_port: default_port.map(str::to_string),
+                    ..NewDevice::default()
+                },
+            )
+            .await
+            .unwrap();
+        (register_routes(services, admin, 1024, &[]), device.id, root)
+    }
+
+    /// Test that bodies over the configured limit are rejected before reaching a handler.
+    #[tokio::test]
+    async fn oversized_body_is_rejected() {
+        let app = register_routes(
+            Services::new(Arc::new(InMemoryDeviceRepository::new())),
+            AdminContext::default(),
+            1024,
+            &[],
+        );
+
+        let request = |size: usize| {
+            Request::post("/devices")
+                .header("content-type", "application/json")
+                .body(Body::from(format!(r#"{{"name":"{}"}}"#, "x".repeat(size))))
+                .unwrap()
+        };
+        let response = app.clone().oneshot(request(2048)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let response = app.oneshot(request(10)).await.unwrap();
+        assert_ne!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    /// Test that disabled endpoints answer 404 while the rest stay registered.
+    #[tokio::test]
+    async fn disabled_endpoints_are_not_found() {
+        let services = Services::new(Arc::new(InMemoryDeviceRepository::new()));
+        let device = services
+            .device
+            .create("lab", NewDevice::default())
+            .await
+            .unwrap();
+        let disabled = ["purge".to_string(), "version".to_string()];
+        let app = register_routes(services, AdminContext::default(), 1024, &disabled);
+        let send = |request: Request<Body>| app.clone().oneshot(request);
+
+        let purge = Request::post(format!("/devices/{}/purge", device.id))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(purge).await.unwrap().status(), StatusCode::NOT_FOUND);
+        let version = Request::get("/version").body(Body::empty()).unwrap();
+        assert_eq!(send(version).await.unwrap().status(), StatusCode::NOT_FOUND);
+
+        let clean = Request::post(format!("/devices/{}/clean", device.id))
+            .body(Body::empty())
+            .unwrap();
+        assert_ne!(send(clean).await.unwrap().status(), StatusCode::NOT_FOUND);
+        let get = Request::get(format!("/devices/{}", device.id))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(get).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(route_name("/devices/:id/build/cancel"), "build/cancel");
+        assert_eq!(route_name("/devices/import"), "devices/import");
+    }
+
+    /// Test that a disabled name also covers its sub-routes, and a group name all its routes.
+    #[tokio::test]
+    async fn disabled_names_cover_sub_routes_and_groups() {
+        let status = |disabled: &'static [&'static str],
+                      method: &'static str,
+                      path: &'static str| async move {
+            let services = Services::new(Arc::new(InMemoryDeviceRepository::new()));
+            let device = services
+                .device
+                .create("lab", NewDevice::default())
+                .await
+                .unwrap();
+            let disabled: Vec<String> = disabled.iter().map(|name| name.to_string()).collect();
+            let app = register_routes(services, AdminContext::default(), 1024, &disabled);
+            let request = Request::builder()
+                .method(method)
+                .uri(path.replace(":id", &device.id.to_string()))
+                .body(Body::empty())
+                .unwrap();
+            app.oneshot(request).await.unwrap().status()
+        };
+
+        for path in ["/devices/:id/upload", "/devices/:id/upload/stream"] {
+            assert_eq!(
+                status(&["upload"], "POST", path).await,
+                StatusCode::NOT_FOUND
+            );
+        }
+        assert_ne!(
+            status(&["upload"], "POST", "/devices/:id/upload-fs").await,
+            StatusCode::NOT_FOUND
+        );
+        for path in [
+            "/devices/:id/upload/stream",
+            "/devices/:id/upload-fs",
+            "/devices/:id/deploy",
+            "/devices/:id/erase",
+        ] {
+            assert_eq!(
+                status(&["flash"], "POST", path).await,
+                StatusCode::NOT_FOUND
+            );
+        }
+        // Falls through to `/devices/:id`, which has no POST handler
+        assert_eq!(
+            status(&["flash"], "POST", "/devices/upload-batch").await,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert_ne!(
+            status(&["flash"], "POST", "/devices/:id/build").await,
+            StatusCode::NOT_FOUND
+        );
+        assert!(disables("build", "build/cancel"));
+        assert!(!disables("build", "build-all"));
+    }
+
+    /// Test that large JSON responses are compressed while event streams are left as-is.
+    #[tokio::test]
+    async fn responses_are_compressed_except_event_streams() {
+        let services = Services::new(Arc::new(InMemoryDeviceRepository::new()));
+        for i in 0..50 {
+            services
+                .device
+                .create(format!("lab-{}", i), NewDevice::default())
+                .await
+                .unwrap();
+        }
+        let app = register_routes(services, AdminContext::default(), 1024, &[]);
+        let request = Request::get("/devices")
+            .header("accept-encoding", "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-encoding"], "gzip");
+
+        let sse = Router::new()
+            .route(
+                "/",
+                get(|| async {
+                    (
+                        [("content-type", "text/event-stream")],
+                        "data: progress\n\n".repeat(100),
+                    )
+                }),
+            )
+            .layer(compression_layer());
+        let request = Request::get("/")
+            .header("accept-encoding", "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let response = sse.oneshot(request).await.unwrap();
+        assert!(response.headers().get("content-encoding").is_none());
+    }
+
+    /// Test that `/events` streams published events as JSON, filtered by `device_id`.
+    #[tokio::test]
+    async fn events_are_streamed_and_filtered() {
+        use axum::body::HttpBody;
+        use iot_remote_lab_server::domain::DeviceEvent;
+
+        let services = Services::new(Arc::new(InMemoryDeviceRepository::new()));
+        let events = services.events.clone();
+        let app = register_routes(services, AdminContext::default(), 1024, &[]);
+        let device_id = Uuid::new_v4();
+        let request = Request::get(format!("/events?device_id={}", device_id))
+            .body(Body::empty())
+            .unwrap();
+        let mut response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+
+        events.publish(DeviceEvent::BuildStarted {
+            device_id: Uuid::new_v4(),
+        });
+        events.publish(DeviceEvent::BuildFinished {
+            device_id,
+            success: true,
+        });
+        let chunk = response.body_mut().data().await.unwrap().unwrap();
+        let frame = String::from_utf8(chunk.to_vec()).unwrap();
+        assert!(frame.starts_with("event:build_finished\n"), "{}", frame);
+        assert!(frame.contains(&format!(
+            r#"data:{{"type":"build_finished","device_id":"{}","success":true}}"#,
+            device_id
+        )));
+    }
+
+    /// Test that relocating with `move_files` moves the directory, and that a failed move leaves
+    /// the device record pointing at the old path.
+    #[tokio::test]
+    async fn relocate_moves_project_and_rolls_back() {
+        let root = TempProjectsRoot::new("relocate", &["old", "taken"]);
+        let mut services = Services::new(Arc::new(InMemoryDeviceRepository::new()));
+        services.pio = Arc::new(PlatformIOService::new().with_projects_root(&*root));
+        let device = services
+            .device
+            .create(
+                "lab",
+                NewDevice {
+                    board_type: Some(BoardType::Esp32Dev),
+                    project_path: Some("old".to_string()),
+                    ..NewDevice::default()
+                },
+            )
+            .await
+            .unwrap();
+        let device_service = services.device.clone();
+        let app = register_routes(services, AdminContext::default(), 1024, &[]);
+        let relocate = |path: &str| {
+            Request::post(format!("/devices/{}/relocate", device.id))
+                .header("content-type", "application/json")
+                .body(Body::from(format!(
+                    r#"{{"project_path":"{}","move_files":true}}"#,
+                    path
+                )))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(relocate("taken")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let stored = device_service.get(device.id).await.unwrap().unwrap();
+        assert_eq!(stored.project_path.as_deref(), Some("old"));
+
+        let response = app.oneshot(relocate("moved")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let stored = device_service.get(device.id).await.unwrap().unwrap();
+        assert_eq!(stored.project_path.as_deref(), Some("moved"));
+        assert!(root.join("moved").is_dir() && !root.join("old").exists());
+    }
+
+    /// Sends a build and an upload for a device whose PlatformIO commands are answered by
+    /// `mock`, returning both statuses and bodies.
+    async fn build_and_upload(mock: Arc<MockPlatformIORunner>) -> [(StatusCode, String); 2] {
+        use axum::body::HttpBody;
+
+        let (app, id, _root) = lab_app(mock).await;
+
+        let mut results = Vec::new();
+        for (path, body) in [
+            ("build", format!(r#"{{"device_id":"{}"}}"#, id)),
+            (
+                "upload",
+                format!(r#"{{"device_id":"{}","port":"/dev/ttyUSB0"}}"#, id),
+            ),
+        ] {
+            let request = Request::post(format!("/devices/{}/{}", id, path))
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            let mut response = app.clone().oneshot(request).await.unwrap();
+            let mut text = Vec::new();
+            while let Some(chunk) = response.body_mut().data().await {
+                text.extend_from_slice(&chunk.unwrap());
+            }
+            results.push((response.status(), String::from_utf8(text).unwrap()));
+        }
+        results.try_into().unwrap()
+    }
+
+    /// Test that build and upload report PlatformIO's output on success.
+    #[tokio::test]
+    async fn build_and_upload_succeed_with_mock_runner() {
+        let mock = Arc::new(
+            MockPlatformIORunner::new()
+                .respond(
+                    &["run", "--target", "upload"],
+                    RunOutput::ok("Hard resetting\n"),
+                )
+                .respond(&["run"], RunOutput::ok("[SUCCESS] Took 1.00 seconds\n")),
+        );
+        let [(build_status, build), (upload_status, upload)] = build_and_upload(mock.clone()).await;
+
+        assert_eq!(build_status, StatusCode::OK);
+        assert!(build.contains(r#""success":true"#), "{}", build);
+        assert!(build.contains("[SUCCESS] Took 1.00 seconds"), "{}", build);
+        assert_eq!(upload_status, StatusCode::OK);
+        assert!(upload.contains("Hard resetting"), "{}", upload);
+        let calls = mock.calls();
+        assert!(calls.contains(&vec![
+            "run".to_string(),
+            "--target".to_string(),
+            "upload".to_string(),
+            "--upload-port".to_string(),
+            "/dev/ttyUSB0".to_string(),
+        ]));
+    }
+
+    /// Test that failed PlatformIO commands become 500 responses carrying their error output.
+    #[tokio::test]
+    async fn build_and_upload_fail_with_mock_runner() {
+        let mock = Arc::new(
+            MockPlatformIORunner::new()
+                .respond(
+                    &["run", "--target", "upload"],
+                    RunOutput::failed("Failed to connect to ESP32\n"),
+                )
+                .respond(&["run"], RunOutput::failed("src/main.cpp:3: error\n")),
+        );
+        let [(build_status, build), (upload_status, upload)] = build_and_upload(mock).await;
+
+        assert_eq!(build_status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(build.contains(r#""success":false"#), "{}", build);
+        assert!(build.contains("src/main.cpp:3: error"), "{}", build);
+        assert_eq!(upload_status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(upload.contains("Failed to connect to ESP32"), "{}", upload);
+    }
+
+    /// Test that builds and cleans are listed by `/audit`, newest first, with only a prefix of
+    /// a verified key, and that `/audit` needs the admin key.
+    #[tokio::test]
+    async fn audit_lists_recent_operations() {
+        use axum::body::HttpBody;
+
+        let mock = MockPlatformIORunner::new()
+            .respond(&["run", "--target", "clean"], RunOutput::failed("busy\n"));
+        let admin = AdminContext::new(Some("teacher-secret".to_string()));
+        let (app, id, _root) = lab_app_with(Arc::new(mock), None, admin).await;
+
+        let build = Request::post(format!("/devices/{}/build", id))
+            .header("content-type", "application/json")
+            .header("authorization", "Bearer lab4-0123456789")
+            .body(Body::from(format!(r#"{{"device_id":"{}"}}"#, id)))
+            .unwrap();
+        let clean = Request::post(format!("/devices/{}/clean", id))
+            .header("authorization", "Bearer teacher-secret")
+            .body(Body::empty())
+            .unwrap();
+        for request in [build, clean] {
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        let unauthorized = Request::get("/audit").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(unauthorized).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let audit = |uri: &str| {
+            let request = Request::get(uri)
+                .header("authorization", "Bearer teacher-secret")
+                .body(Body::empty())
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let mut response = app.oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = response.body_mut().data().await.unwrap().unwrap();
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+            }
+        };
+        let entries = audit("/audit").await;
+        assert_eq!(entries.as_array().unwrap().len(), 2);
+        assert_eq!(entries[0]["operation"], "clean");
+        assert_eq!(entries[0]["success"], false);
+        assert_eq!(entries[0]["api_key_prefix"], "teac…");
+        assert_eq!(entries[1]["operation"], "build");
+        assert_eq!(entries[1]["success"], true);
+        assert_eq!(entries[1]["device_id"], id.to_string());
+        assert_eq!(entries[1]["api_key_prefix"], serde_json::Value::Null);
+        assert!(!entries.to_string().contains("secret"));
+
+        assert_eq!(audit("/audit?limit=1").await.as_array().unwrap().len(), 1);
+    }
+
+    /// Test that `upload-fs` needs no body and flashes the image to the device's default port.
+    #[tokio::test]
+    async fn upload_fs_uses_default_port() {
+        use axum::body::HttpBody;
+
+        let mock = Arc::new(MockPlatformIORunner::new().respond(
+            &["run", "--target", "uploadfs"],
+            RunOutput::ok("Building LittleFS image\n"),
+        ));
+        let (app, id, _root) =
+            lab_app_with(mock.clone(), Some("/dev/ttyUSB1"), AdminContext::default()).await;
+
+        let request = Request::post(format!("/devices/{}/upload-fs", id))
+            .body(Body::empty())
+            .unwrap();
+        let mut response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.body_mut().data().await.unwrap().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["output"], "Building LittleFS image\n");
+        assert_eq!(
+            mock.calls().last().unwrap(),
+            &[
+                "run",
+                "--target",
+                "uploadfs",
+                "--upload-port",
+                "/dev/ttyUSB1"
+            ]
+        );
+    }
+
+    /// Test that test runs stream their output and end with the summary, or an error on a crash.
+    #[tokio::test]
+    async fn test_stream_reports_summary_or_crash() {
+        use axum::body::HttpBody;
+
+        let mock = Arc::new(
+            MockPlatformIORunner::new()
+                .respond(
+                    &["test", "-e", "crash"],
+                    RunOutput::failed("Guru Meditation Error: Core 1 panic'ed\n"),
+                )
+                .respond(
+                    &["test"],
+                    RunOutput::ok(
+                        "test_led\t[FAILED]\n2 test cases: 1 failed, 1 succeeded in 00:00:02.100\n",
+                    ),
+                ),
+        );
+        let (app, id, _root) = lab_app(mock.clone()).await;
+        let run = |body: &'static str| {
+            let request = Request::post(format!("/devices/{}/test/stream", id))
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let mut response = app.oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let mut text = String::new();
+                while let Some(chunk) = response.body_mut().data().await {
+                    text.push_str(std::str::from_utf8(&chunk.unwrap()).unwrap());
+                }
+                text
+            }
+        };
+
+        let events = run("{}").await;
+        assert!(
+            events.contains(r#"data:{"line":"test_led\t[FAILED]"}"#),
+            "{}",
+            events
+        );
+        assert!(events.contains("event:done\n"), "{}", events);
+        assert!(events.contains(r#""success":false"#), "{}", events);
+        assert!(
+            events.contains(r#""failed":1"#) && events.contains(r#""total":2"#),
+            "{}",
+            events
+        );
+
+        let events = run(r#"{"environment":"crash"}"#).await;
+        assert!(events.contains("event:error\n"), "{}", events);
+        assert!(!events.contains("event:done"), "{}", events);
+        assert_eq!(mock.calls().last().unwrap(), &["test", "-e", "crash"]);
+    }
+
+    /// Test that a missing PlatformIO is a 503 with `PLATFORMIO_UNAVAILABLE` on every command.
+    #[tokio::test]
+    async fn missing_platformio_is_service_unavailable() {
+        let missing = || {
+            Arc::new(
+                MockPlatformIORunner::new()
+                    .respond(&["--version"], RunOutput::failed("command not found\n")),
+            )
+        };
+        let [(build_status, build), (upload_status, upload)] = build_and_upload(missing()).await;
+        for (status, body) in [(build_status, build), (upload_status, upload)] {
+            assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+            assert!(
+                body.contains(r#""code":"PLATFORMIO_UNAVAILABLE""#),
+                "{}",
+                body
+            );
+            assert!(body.contains("PLATFORMIO_BIN"), "{}", body);
+        }
+
+        let (status, body) = deploy_with(missing()).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["build"]["code"], "PLATFORMIO_UNAVAILABLE");
+    }
+
+    /// Deploys a device whose PlatformIO commands are answered by `mock`, returning the status
+    /// and JSON body.
+    async fn deploy_with(mock: Arc<MockPlatformIORunner>) -> (StatusCode, serde_json::Value) {
+        use axum::body::HttpBody;
+
+        let (app, id, _root) =
+            lab_app_with(mock, Some("/dev/ttyUSB0"), AdminContext::default()).await;
+
+        let request = Request::post(format!("/devices/{}/deploy", id))
+            .header("content-type", "application/json")
+            .body(Body::from("{}"))
+            .unwrap();
+        let mut response = app.oneshot(request).await.unwrap();
+        let mut body = Vec::new();
+        while let Some(chunk) = response.body_mut().data().await {
+            body.extend_from_slice(&chunk.unwrap());
+        }
+        (response.status(), serde_json::from_slice(&body).unwrap())
+    }
+
+    /// Test that deploy uploads after a successful build and skips the upload after a failed one.
+    #[tokio::test]
+    async fn deploy_uploads_only_after_successful_build() {
+        let mock = Arc::new(
+            MockPlatformIORunner::new()
+                .respond(
+                    &["run", "--target", "upload"],
+                    RunOutput::ok("Hard resetting\n"),
+                )
+                .respond(&["run"], RunOutput::ok("[SUCCESS] Took 1.00 seconds\n")),
+        );
+        let (status, body) = deploy_with(mock.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "succeeded");
+        assert_eq!(body["success"], true);
+        assert!(body["build"]["output"]
+            .as_str()
+            .unwrap()
+            .contains("[SUCCESS]"));
+        assert!(body["build"]["duration_ms"].is_u64());
+        assert!(body["upload"]["output"]
+            .as_str()
+            .unwrap()
+            .contains("Hard resetting"));
+        assert!(mock
+            .calls()
+            .last()
+            .unwrap()
+            .ends_with(&["--upload-port".to_string(), "/dev/ttyUSB0".to_string()]));
+
+        let mock = Arc::new(
+            MockPlatformIORunner::new()
+                .respond(&["run"], RunOutput::failed("src/main.cpp:3: error\n")),
+        );
+        let (status, body) = deploy_with(mock.clone()).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["status"], "build_failed");
+        assert!(body["build"]["error"]
+            .as_str()
+            .unwrap()
+            .contains("src/main.cpp:3: error"));
+        assert!(body["upload"].is_null());
+        let upload = "upload".to_string();
+        assert!(!mock.calls().iter().any(|args| args.contains(&upload)));
+    }
+
+    /// Test that reading partitions needs a port, and falls back to esptool's raw output when no
+    /// table was dumped.
+    #[tokio::test]
+    async fn partitions_require_port() {
+        use axum::body::HttpBody;
+
+        let mock = Arc::new(MockPlatformIORunner::new().respond(
+            &["pkg", "exec"],
+            RunOutput::ok("A fatal error occurred: Failed to connect to ESP32\n"),
+        ));
+        let (app, id, _root) = lab_app(mock.clone()).await;
+
+        let request = Request::get(format!("/devices/{}/partitions", id))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let request = Request::get(format!("/devices/{}/partitions?port=/dev/ttyUSB0", id))
+            .body(Body::empty())
+            .unwrap();
+        let mut response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.body_mut().data().await.unwrap().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["partitions"].is_null());
+        assert!(body["output"]
+            .as_str()
+            .unwrap()
+            .contains("Failed to connect"));
+        let read = mock.calls().pop().unwrap();
+        assert!(read.contains(&"read_flash".to_string()), "{:?}", read);
+        assert!(read.contains(&"/dev/ttyUSB0".to_string()), "{:?}", read);
+    }
+
+    /// Test that the command endpoints apply device defaults and request options without running
+    /// anything.
+    #[tokio::test]
+    async fn planned_commands_apply_defaults() {
+        use axum::body::HttpBody;
+
+        let root = TempProjectsRoot::new("planned", &[]);
+        let mock = Arc::new(MockPlatformIORunner::new());
+        let mut services = Services::new(Arc::new(InMemoryDeviceRepository::new()));
+        services.pio = Arc::new(
+            PlatformIOService::with_binary("pio")
+                .with_projects_root(&*root)
+                .with_runner(mock.clone()),
+        );
+        let device = services
+            .device
+            .create(
+                "lab",
+                NewDevice {
+                    board_type: Some(BoardType::Esp32Dev),
+                    project_path: Some("lab".to_string()),
+                    default_port: Some("/dev/ttyUSB0".to_string()),
+                    ..NewDevice::default()
+                },
+            )
+            .await
+            .unwrap();
+        let env_vars = [("WIFI_SSID".to_string(), "lab-net".to_string())].into();
+        let changes = DeviceUpdate {
+            env_vars: Some(env_vars),
+            ..Default::default()
+        };
+        services.device.update(device.id, changes).await.unwrap();
+        let app = register_routes(services, AdminContext::default(), 1024, &[]);
+
+        let get = |uri: String| {
+            let app = app.clone();
+            async move {
+                let request = Request::get(uri).body(Body::empty()).unwrap();
+                let mut response = app.oneshot(request).await.unwrap();
+                let body = response.body_mut().data().await.unwrap().unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                (response.status(), body)
+            }
+        };
+        let (status, body) = get(format!(
+            "/devices/{}/build/command?verbose=true&build_flags=-DDEBUG=1%20-DLAB=2",
+            device.id
+        ))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["argv"],
+            serde_json::json!([
+                "pio",
+                "run",
+                "-v",
+                "--project-option",
+                "build_flags=-DDEBUG=1 -DLAB=2"
+            ])
+        );
+        assert!(body["working_dir"].as_str().unwrap().ends_with("/lab"));
+        assert_eq!(body["env_vars"], serde_json::json!(["WIFI_SSID"]));
+        assert!(!body.to_string().contains("lab-net"));
+
+        let (status, body) = get(format!("/devices/{}/upload/command", device.id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["argv"],
+            serde_json::json!([
+                "pio",
+                "run",
+                "--target",
+                "upload",
+                "--upload-port",
+                "/dev/ttyUSB0"
+            ])
+        );
+        let (status, _) = get(format!(
+            "/devices/{}/upload/command?upload_protocol=espota",
+            device.id
+        ))
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = get(format!(
+            "/devices/{}/build/command?build_flags=-DX=%22a%22",
+            device.id
+        ))
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(mock.calls().is_empty());
+    }
+
+    /// Test that builds and uploads use the device's `default_env` unless the request names an
+    /// environment, and that unsafe names are rejected.
+    #[tokio::test]
+    async fn default_env_applies_unless_overridden() {
+        use axum::body::HttpBody;
+
+        let root = TempProjectsRoot::new("default-env", &[]);
+        let mut services = Services::new(Arc::new(InMemoryDeviceRepository::new()));
+        services.pio = Arc::new(
+            PlatformIOService::with_binary("pio")
+                .with_projects_root(&*root)
+                .with_runner(Arc::new(MockPlatformIORunner::new())),
+        );
+        let app = register_routes(services, AdminContext::default(), 1024, &[]);
+        let send = |request: Request<Body>| {
+            let app = app.clone();
+            async move {
+                let mut response = app.oneshot(request).await.unwrap();
+                let body = response.body_mut().data().await.unwrap().unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+                (response.status(), body)
+            }
+        };
+        let create = |default_env: &str| {
+            Request::post("/devices")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    serde_json::json!({
+                        "name": "lab",
+                        "board_type": "esp32dev",
+                        "project_path": "lab",
+                        "default_env": default_env,
+                    })
+                    .to_string(),
+                ))
+                .unwrap()
+        };
+
+        let (status, _) = send(create("esp32dev; rm -rf /")).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let (status, device) = send(create("esp32dev")).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(device["default_env"], "esp32dev");
+        let id = device["id"].as_str().unwrap();
+
+        let argv = |uri: String| {
+            let send = &send;
+            async move {
+                let (status, body) = send(Request::get(uri).body(Body::empty()).unwrap()).await;
+                assert_eq!(status, StatusCode::OK, "{}", body);
+                body["argv"].clone()
+            }
+        };
+        assert_eq!(
+            argv(format!("/devices/{}/build/command", id)).await,
+            serde_json::json!(["pio", "run", "-e", "esp32dev"])
+        );
+        assert_eq!(
+            argv(format!("/devices/{}/build/command?environment=debug", id)).await,
+            serde_json::json!(["pio", "run", "-e", "debug"])
+        );
+        assert_eq!(
+            argv(format!("/devices/{}/upload/command", id)).await,
+            serde_json::json!(["pio", "run", "--target", "upload", "-e", "esp32dev"])
+        );
+        let (status, _) = send(
+            Request::get(format!("/devices/{}/build/command?environment=a%20b", id))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let patch = |default_env: &str| {
+            Request::patch(format!("/devices/{}", id))
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    serde_json::json!({ "default_env": default_env, "version": device["version"] })
+                        .to_string(),
+                ))
+                .unwrap()
+        };
+        let (status, _) = send(patch("../env")).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let (status, updated) = send(patch("esp32-s3")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated["default_env"], "esp32-s3");
+    }
+
+    /// Test that `?compress=gzip` gzips the firmware exactly once, while plain downloads stay raw.
+    #[tokio::test]
+    async fn firmware_download_can_be_gzipped() {
+        use axum::body::HttpBody;
+        use tokio::io::AsyncReadExt;
+
+        let (app, id, root) = lab_app(Arc::new(MockPlatformIORunner::new())).await;
+        let build_dir = root.join("lab/.pio/build/esp32dev");
+        tokio::fs::create_dir_all(&build_dir).await.unwrap();
+        let firmware: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        tokio::fs::write(build_dir.join("firmware.bin"), &firmware)
+            .await
+            .unwrap();
+
+        for (query, accept) in [
+            ("", None),
+            ("?compress=gzip", None),
+            ("?compress=gzip", Some("gzip")),
+        ] {
+            let mut request = Request::get(format!("/devices/{}/firmware.bin{}", id, query));
+            if let Some(accept) = accept {
+                request = request.header("accept-encoding", accept);
+            }
+            let mut response = app
+                .clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let encoding = response.headers().get("content-encoding").cloned();
+            let mut body = Vec::new();
+            while let Some(chunk) = response.body_mut().data().await {
+                body.extend_from_slice(&chunk.unwrap());
+            }
+            if query.is_empty() {
+                assert!(encoding.is_none());
+                assert_eq!(body, firmware);
+                continue;
+            }
+            assert_eq!(encoding.unwrap(), "gzip");
+            assert!(body.len() < firmware.len());
+            let mut decoded = Vec::new();
+            async_compression::tokio::bufread::GzipDecoder::new(&body[..])
+                .read_to_end(&mut decoded)
+                .await
+                .unwrap();
+            assert_eq!(decoded, firmware);
+        }
+    }
+
+    /// Test that autoport stores the only connected port and answers 409 with the list otherwise.
+    #[tokio::test]
+    async fn autoport_assigns_only_port() {
+        use axum::body::HttpBody;
+
+        let one = r#"[{"port": "/dev/ttyUSB0", "description": "CP2102", "hwid": "USB VID:PID=10C4:EA60"}]"#;
+        let two = r#"[{"port": "/dev/ttyUSB0"}, {"port": "/dev/ttyACM0"}]"#;
+        for (ports, expected) in [(one, StatusCode::OK), (two, StatusCode::CONFLICT)] {
+            let mock =
+                MockPlatformIORunner::new().respond(&["device", "list"], RunOutput::ok(ports));
+            let mut services = Services::new(Arc::new(InMemoryDeviceRepository::new()));
+            services.pio = Arc::new(
+                PlatformIOService::new()
+                    .with_projects_root(std::env::temp_dir())
+                    .with_runner(Arc::new(mock)),
+            );
+            let device = services
+                .device
+                .create("lab", NewDevice::default())
+                .await
+                .unwrap();
+            let device_service = services.device.clone();
+            let app = register_routes(services, AdminContext::default(), 1024, &[]);
+
+            let request = Request::post(format!("/devices/{}/autoport", device.id))
+                .body(Body::empty())
+                .unwrap();
+            let mut response = app.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), expected);
+            let body = response.body_mut().data().await.unwrap().unwrap();
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            let stored = device_service.get(device.id).await.unwrap().unwrap();
+            if expected == StatusCode::OK {
+                assert!(body.contains(r#""port":"/dev/ttyUSB0""#), "{}", body);
+                assert_eq!(stored.default_port.as_deref(), Some("/dev/ttyUSB0"));
+            } else {
+                assert!(body.contains("/dev/ttyACM0"), "{}", body);
+                assert_eq!(stored.default_port, None);
+            }
+        }
+    }
+
+    /// Test that building a project without `platformio.ini` is a 409 with its own code and never
+    /// runs PlatformIO, while a compile error stays a 500 carrying the compiler output.
+    #[tokio::test]
+    async fn uninitialized_project_is_distinct_from_build_failure() {
+        use axum::body::HttpBody;
+
+        let mock = Arc::new(MockPlatformIORunner::new().respond(
+            &["run"],
+            RunOutput::failed("src/main.cpp:1:1: error: 'x' was not declared\n"),
+        ));
+        let (app, id, root) = lab_app(mock.clone()).await;
+        tokio::fs::remove_file(root.join("lab/platformio.ini"))
+            .await
+            .unwrap();
+        let build = || {
+            Request::post(format!("/devices/{}/build", id))
+                .header("content-type", "application/json")
+                .body(Body::from(format!(r#"{{"device_id":"{}"}}"#, id)))
+                .unwrap()
+        };
+
+        let mut response = app.clone().oneshot(build()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = response.body_mut().data().await.unwrap().unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(
+            body.contains(r#""code":"PROJECT_NOT_INITIALIZED""#),
+            "{}",
+            body
+        );
+        assert!(body.contains("/scaffold"), "{}", body);
+        assert!(mock.calls().is_empty());
+
+        tokio::fs::write(root.join("lab/platformio.ini"), "[env:esp32dev]\n")
+            .await
+            .unwrap();
+        let mut response = app.oneshot(build()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = response.body_mut().data().await.unwrap().unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("'x' was not declared"), "{}", body);
+        assert!(!body.contains(r#""code""#), "{}", body);
+    }
+
+    /// Test that `HEAD /devices/:id` answers 200 or 404 without a body.
+    #[tokio::test]
+    async fn head_probes_device_existence() {
+        use axum::body::HttpBody;
+
+        let services = Services::new(Arc::new(InMemoryDeviceRepository::new()));
+        let device = services
+            .device
+            .create("lab", NewDevice::default())
+            .await
+            .unwrap();
+        let app = register_routes(services, AdminContext::default(), 1024, &[]);
+        let head = |id: String| {
+            Request::head(format!("/devices/{}", id))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        for (id, expected) in [
+            (device.id.to_string(), StatusCode::OK),
+            (uuid::Uuid::new_v4().to_string(), StatusCode::NOT_FOUND),
+            ("not-a-uuid".to_string(), StatusCode::BAD_REQUEST),
+        ] {
+            let mut response = app.clone().oneshot(head(id)).await.unwrap();
+            assert_eq!(response.status(), expected);
+            assert!(response.body_mut().data().await.is_none());
+        }
+    }
+
+    /// Test that `_links` appear only when requested and keep the prefix the API is served under.
+    #[tokio::test]
+    async fn device_links_are_opt_in() {
+        use axum::body::HttpBody;
+
+        let services = Services::new(Arc::new(InMemoryDeviceRepository::new()));
+        let device = services
+            .device
+            .create("lab", NewDevice::default())
+            .await
+            .unwrap();
+        let app = Router::new().nest(
+            "/api",
+            register_routes(services, AdminContext::default(), 1024, &[]),
+        );
+        let get = |uri: String| {
+            let app = app.clone();
+            async move {
+                let request = Request::get(uri).body(Body::empty()).unwrap();
+                let mut response = app.oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let mut body = Vec::new();
+                while let Some(chunk) = response.body_mut().data().await {
+                    body.extend_from_slice(&chunk.unwrap());
+                }
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+            }
+        };
+
+        let plain = get(format!("/api/devices/{}", device.id)).await;
+        assert!(plain.get("_links").is_none());
+        let linked = get(format!("/api/devices/{}?links=true", device.id)).await;
+        let base = format!("/api/devices/{}", device.id);
+        assert_eq!(linked["_links"]["self"], base);
+        assert_eq!(linked["_links"]["build"], format!("{}/build", base));
+        assert_eq!(linked["_links"]["upload"], format!("{}/upload", base));
+        assert_eq!(linked["_links"]["clean"], format!("{}/clean", base));
+        assert_eq!(
+            linked["_links"]["config"],
+            format!("{}/config/validate", base)
+        );
+        assert_eq!(linked["_links"]["files"], format!("{}/files", base));
+        let list = get("/api/devices?links=true".to_string()).await;
+        assert_eq!(list[0]["_links"]["self"], base);
+        assert!(get("/api/devices".to_string()).await[0]
+            .get("_links")
+            .is_none());
+    }
+
+    /// Test that misspelt board types are rejected and `?board_type=` filters by board.
+    #[tokio::test]
+    async fn board_type_is_validated_and_filterable() {
+        use axum::body::HttpBody;
+
+        let app = register_routes(
+            Services::new(Arc::new(InMemoryDeviceRepository::new())),
+            AdminContext::default(),
+            1024,
+            &[],
+        );
+        let create = |body: &'static str| {
+            Request::post("/devices")
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(create(r#"{"name":"a","board_type":"esp32-devv"}"#))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        for body in [
+            r#"{"name":"a","board_type":"esp32dev","project_path":"a"}"#,
+            r#"{"name":"b","board_type":"lolin_d32","project_path":"b"}"#,
+        ] {
+            let response = app.clone().oneshot(create(body)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        let request = Request::get("/devices?board_type=lolin_d32")
+            .body(Body::empty())
+            .unwrap();
+        let mut response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.body_mut().data().await.unwrap().unwrap();
+        let devices: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0]["board_type"], "lolin_d32");
+    }
+
+    /// Test that `location` is returned and `?location=` matches it case-insensitively.
+    #[tokio::test]
+    async fn location_is_filterable() {
+        use axum::body::HttpBody;
+
+        let app = register_routes(
+            Services::new(Arc::new(InMemoryDeviceRepository::new())),
+            AdminContext::default(),
+            1024,
+            &[],
+        );
+        for body in [
+            r#"{"name":"a","location":"Lab 2, bench 4"}"#,
+            r#"{"name":"b","location":"Lab 3"}"#,
+            r#"{"name":"c"}"#,
+        ] {
+            let request = Request::post("/devices")
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        let request = Request::get("/devices?location=lab%202,%20BENCH%204")
+            .body(Body::empty())
+            .unwrap();
+        let mut response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.body_mut().data().await.unwrap().unwrap();
+        let devices: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0]["name"], "a");
+        assert_eq!(devices[0]["location"], "Lab 2, bench 4");
+    }
+
+    /// Test that unchanged devices get 304s for their ETag or Last-Modified and changed ones 200s.
+    #[tokio::test]
+    async fn get_device_honors_conditional_headers() {
+        let services = Services::new(Arc::new(InMemoryDeviceRepository::new()));
+        let device_service = services.device.clone();
+        let device = device_service
+            .create("lab", NewDevice::default())
+            .await
+            .unwrap();
+        let app = register_routes(services, AdminContext::default(), 1024, &[]);
+        let get = |header: Option<(&'static str, String)>| {
+            let mut request = Request::get(format!("/devices/{}", device.id));
+            if let Some((name, value)) = header {
+                request = request.header(name, value);
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        let response = get(None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()["etag"].to_str().unwrap().to_string();
+        let last_modified = response.headers()["last-modified"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(last_modified.ends_with(" GMT"), "{}", last_modified);
+
+        let response = get(Some(("if-none-match", etag.clone()))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()["etag"], etag.as_str());
+        let response = get(Some(("if-none-match", r#""other""#.to_string())))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = get(Some(("if-modified-since", last_modified.clone())))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        let earlier = "Thu, 01 Jan 2015 00:00:00 GMT".to_string();
+        let response = get(Some(("if-modified-since", earlier))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let changes = DeviceUpdate {
+            name: Some("renamed".to_string()),
+            ..Default::default()
+        };
+        let updated = device_service
+            .update(device.id, changes)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(updated.updated_at.is_some());
+        let response = get(Some(("if-none-match", etag.clone()))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()["etag"], etag.as_str());
+    }
+
+    /// Test that an exported inventory imports into another server with its ids preserved.
+    #[tokio::test]
+    async fn inventory_export_imports_elsewhere() {
+        use axum::body::HttpBody;
+
+        let source = Services::new(Arc::new(InMemoryDeviceRepository::new()));
+        let device = source
+            .device
+            .create(
+                "lab",
+                NewDevice {
+                    tags: vec!["room-1".to_string()],
+                    ..NewDevice::default()
+                },
+            )
+            .await
+            .unwrap();
+        let source = register_routes(source, AdminContext::default(), 1024, &[]);
+        let target = register_routes(
+            Services::new(Arc::new(InMemoryDeviceRepository::new())),
+            AdminContext::default(),
+            1024 * 1024,
+            &[],
+        );
+        let read = |mut response: axum::response::Response| async move {
+            let mut body = Vec::new();
+            while let Some(chunk) = response.body_mut().data().await {
+                body.extend_from_slice(&chunk.unwrap());
+            }
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+
+        let request = Request::get("/devices/export").body(Body::empty()).unwrap();
+        let document = read(source.oneshot(request).await.unwrap()).await;
+        assert_eq!(document["format_version"], 1);
+        let import = |query: &str| {
+            Request::post(format!("/devices/import?{}", query))
+                .header("content-type", "application/json")
+                .body(Body::from(document.to_string()))
+                .unwrap()
+        };
+
+        let response = target
+            .clone()
+            .oneshot(import("preserve_ids=true"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let report = read(response).await;
+        assert_eq!(report["created"], serde_json::json!([device.id]));
+        let report = read(
+            target
+                .clone()
+                .oneshot(import("preserve_ids=true"))
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(report["skipped"], serde_json::json!([device.id]));
+        let report = read(
+            target
+                .clone()
+                .oneshot(import("preserve_ids=true&on_conflict=overwrite"))
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(report["overwritten"], serde_json::json!([device.id]));
+
+        let request = Request::get(format!("/devices/{}", device.id))
+            .body(Body::empty())
+            .unwrap();
+        let imported = read(target.oneshot(request).await.unwrap()).await;
+        assert_eq!(imported["board_id"], device.board_id.as_str());
+        assert_eq!(imported["tags"], serde_json::json!(["room-1"]));
+    }
 }
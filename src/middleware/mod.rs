@@ -0,0 +1,5 @@
+pub mod rate_limit;
+pub mod request_id;
+
+pub use rate_limit::{rate_limit, RateLimiter};
+pub use request_id::{request_id, RequestId};
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tokio::task::JoinHandle;
+
+use crate::dto::ErrorResponse;
+use crate::handlers::AdminContext;
+
+/// Paths that are never rate limited.
+const EXEMPT_PATHS: &[&str] = &["/health"];
+
+/// Window length when `RATE_LIMIT_WINDOW_SECS` is unset.
+const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Tokens left for one client and when they were last topped up.
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+/// Token-bucket rate limiter: each client may burst up to `requests` requests, and regains
+/// `requests` tokens per `window`, one at a time.
+pub struct RateLimiter {
+    requests: u32,
+    window: Duration,
+    buckets: Mutex<HashMap<String, Bucket>>,
+    admin: AdminContext,
+}
+
+impl RateLimiter {
+    /// Limiter allowing `requests` requests per `window` to each client (at least one).
+    pub fn new(requests: u32, window: Duration) -> Self {
+        Self {
+            requests: requests.max(1),
+            window,
+            buckets: Mutex::default(),
+            admin: AdminContext::default(),
+        }
+    }
+
+    /// Gives requests carrying `admin`'s API key a bucket of their own instead of sharing their
+    /// peer IP's.
+    pub fn with_admin(mut self, admin: AdminContext) -> Self {
+        self.admin = admin;
+        self
+    }
+
+    /// Limiter configured by `RATE_LIMIT_REQUESTS` and `RATE_LIMIT_WINDOW_SECS` (default 60).
+    /// Returns `None`, disabling rate limiting, when `RATE_LIMIT_REQUESTS` is unset or invalid.
+    pub fn from_env() -> Option<Self> {
+        let requests = std::env::var("RATE_LIMIT_REQUESTS").ok()?.parse().ok()?;
+        let window = std::env::var("RATE_LIMIT_WINDOW_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_WINDOW);
+        Some(Self::new(requests, window))
+    }
+
+    /// Requests allowed per window.
+    pub fn requests(&self) -> u32 {
+        self.requests
+    }
+
+    /// Length of the window.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Takes a token from `key`'s bucket. When it is empty, returns how long until the next
+    /// token is available.
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        let capacity = f64::from(self.requests);
+        let per_token = self.window.as_secs_f64() / capacity;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            refilled: now,
+        });
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed / per_token).min(capacity);
+        bucket.refilled = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) * per_token))
+        }
+    }
+
+    /// Drops buckets that have been idle for a whole window; they would be full again anyway.
+    pub fn sweep(&self) {
+        let window = self.window;
+        self.buckets
+            .lock()
+            .unwrap()
+            .retain(|_, bucket| bucket.refilled.elapsed() < window);
+    }
+
+    /// Number of clients currently tracked.
+    pub fn tracked_clients(&self) -> usize {
+        self.buckets.lock().unwrap().len()
+    }
+
+    /// Spawns a task running `sweep` once per window for as long as the limiter is alive.
+    pub fn spawn_sweeper(self: &Arc<Self>) -> JoinHandle<()> {
+        let limiter = Arc::downgrade(self);
+        let window = self.window;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(window);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match limiter.upgrade() {
+                    Some(limiter) => limiter.sweep(),
+                    None => break,
+                }
+            }
+        })
+    }
+}
+
+/// Tower middleware (via `axum::middleware::from_fn_with_state`) enforcing the limiter.
+/// Clients are keyed by their bearer token when it is a configured API key (see `with_admin`),
+/// else by peer IP (needs `into_make_service_with_connect_info`), so made-up tokens cannot buy
+/// fresh buckets. Over-limit requests get 429 with `Retry-After`.
+pub async fn rate_limit<B>(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if EXEMPT_PATHS.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let key = match limiter.admin.verified_key(request.headers()) {
+        Some(token) => format!("key:{}", token),
+        None => match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+            None => "anonymous".to_string(),
+        },
+    };
+
+    match limiter.check(&key) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            let secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            let mut response = (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(ErrorResponse {
+                    error: format!("Rate limit exceeded; retry in {} seconds", secs),
+                    correlation_id: None,
+                }),
+            )
+                .into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    /// Test that each client gets its own bucket, which refills over the window.
+    #[test]
+    fn buckets_limit_per_client() {
+        let limiter = RateLimiter::new(2, Duration::from_millis(200));
+        assert!(limiter.check("a").is_ok());
+        assert!(limiter.check("a").is_ok());
+        let retry = limiter.check("a").unwrap_err();
+        assert!(retry <= Duration::from_millis(100));
+        assert!(limiter.check("b").is_ok());
+
+        std::thread::sleep(Duration::from_millis(120));
+        assert!(limiter.check("a").is_ok());
+
+        std::thread::sleep(Duration::from_millis(220));
+        limiter.sweep();
+        assert_eq!(limiter.tracked_clients(), 0);
+    }
+
+    /// Test that over-limit requests get 429 with Retry-After and `/health` is exempt.
+    #[tokio::test]
+    async fn middleware_rejects_over_limit() {
+        let limiter = Arc::new(
+            RateLimiter::new(1, Duration::from_secs(60))
+                .with_admin(AdminContext::new(Some("k1".to_string()))),
+        );
+        let app = Router::new()
+            .route("/devices", get(|| async { "ok" }))
+            .route("/health", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(limiter, rate_limit));
+        let send = |path: &str, token: &str| {
+            app.clone().oneshot(
+                Request::get(path)
+                    .header("authorization", format!("Bearer {}", token))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        assert_eq!(
+            send("/devices", "k1").await.unwrap().status(),
+            StatusCode::OK
+        );
+        let response = send("/devices", "k1").await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "60");
+        assert_eq!(
+            send("/devices", "k2").await.unwrap().status(),
+            StatusCode::OK
+        );
+        for _ in 0..3 {
+            assert_eq!(
+                send("/health", "k1").await.unwrap().status(),
+                StatusCode::OK
+            );
+        }
+    }
+
+    /// Test that tokens which are not a configured API key are limited by peer IP together.
+    #[tokio::test]
+    async fn unknown_tokens_share_ip_bucket() {
+        let limiter = Arc::new(
+            RateLimiter::new(1, Duration::from_secs(60))
+                .with_admin(AdminContext::new(Some("admin-key".to_string()))),
+        );
+        let app = Router::new()
+            .route("/devices", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(limiter, rate_limit));
+        let send = |ip: [u8; 4], token: &str| {
+            let mut request = Request::get("/devices")
+                .header("authorization", format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap();
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from((ip, 4000))));
+            app.clone().oneshot(request)
+        };
+
+        let ip = [10, 0, 0, 1];
+        assert_eq!(
+            send(ip, "made-up-1").await.unwrap().status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(ip, "made-up-2").await.unwrap().status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            send(ip, "admin-key").await.unwrap().status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            send([10, 0, 0, 2], "made-up-2").await.unwrap().status(),
+            StatusCode::OK
+        );
+    }
+}
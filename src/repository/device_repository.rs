@@ -3,6 +3,7 @@ use async_trait::async_trait;
 use uuid::Uuid;
 
 use crate::domain::Device;
+use crate::service::ServiceError;
 
 /*
 * Repository & Adapters
@@ -13,10 +14,64 @@ use crate::domain::Device;
 /// Requires Send + Sync for async compatibility.
 #[async_trait]
 pub trait DeviceRepository: Send + Sync {
-    /// Persists a new Device and returns it.
+    /// Prepares the adapter for use, e.g. by verifying connectivity and running migrations;
+    /// `main` awaits it before serving traffic and exits if it fails. No-op by default.
+    async fn ready(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Persists a new Device and returns it. Fails with `ServiceError::Conflict` if its
+    /// `board_id` is already registered, checked atomically with the insert.
     async fn create(&self, device: Device) -> Result<Device>;
     /// Retrieves a Device by its UUID, if it exists.
     async fn find_by_id(&self, id: Uuid) -> Result<Option<Device>>;
+    /// Whether a Device with the UUID exists, without loading it.
+    async fn exists(&self, id: Uuid) -> Result<bool>;
+    /// Retrieves the Devices with the given UUIDs, skipping ids that don't exist.
+    async fn find_by_ids(&self, ids: &[Uuid]) -> Result<Vec<Device>>;
+    /// Retrieves a Device by its `board_id`, if one is registered.
+    async fn find_by_board_id(&self, board_id: &str) -> Result<Option<Device>>;
     /// Retrieves all persisted Devices.
     async fn list(&self) -> Result<Vec<Device>>;
+    /// Retrieves all Devices whose name or description contains `query`, compared
+    /// case-insensitively.
+    async fn search_text(&self, query: &str) -> Result<Vec<Device>>;
+    /// Retrieves all Devices carrying the given (already normalized) tag.
+    async fn find_by_tag(&self, tag: &str) -> Result<Vec<Device>>;
+    /// Retrieves all Devices located at `location`, compared case-insensitively (see
+    /// `Device::is_at`).
+    async fn find_by_location(&self, location: &str) -> Result<Vec<Device>>;
+    /// Replaces a persisted Device if its stored `version` still equals `device.version`
+    /// (compare-and-swap), storing and returning it with `version` incremented and `updated_at`
+    /// set to now. Returns `None` if no Device with its id exists; a version mismatch fails
+    /// with `ServiceError::Conflict`.
+    async fn update(&self, device: Device) -> Result<Option<Device>>;
+    /// Sets a Device's `archived` flag, increments its `version` and sets `updated_at`,
+    /// returning `None` if no Device with that id exists.
+    async fn set_archived(&self, id: Uuid, archived: bool) -> Result<Option<Device>>;
+}
+
+/// The compare-and-swap step of `DeviceRepository::update` for adapters holding the Devices in
+/// memory: `device` with its version incremented and `updated_at` set to now if `stored` still
+/// has `device.version`, else `ServiceError::Conflict`.
+pub fn bump_version(stored: &Device, mut device: Device) -> Result<Device> {
+    if stored.version != device.version {
+        return Err(version_conflict(stored.version, device.version));
+    }
+    device.version += 1;
+    device.updated_at = Some(chrono::Utc::now());
+    Ok(device)
+}
+
+/// Conflict raised when a Device's `board_id` is already registered to another Device.
+pub fn board_id_conflict(board_id: &str) -> anyhow::Error {
+    ServiceError::Conflict(format!("board_id '{}' is already registered", board_id)).into()
+}
+
+/// Conflict raised when an update expected `expected` but the stored Device is at `current`.
+pub fn version_conflict(current: u64, expected: u64) -> anyhow::Error {
+    ServiceError::Conflict(format!(
+        "Device was modified concurrently: expected version {}, current version is {}",
+        expected, current
+    ))
+    .into()
 }
@@ -1,3 +1,3 @@
 pub mod device_repository;
 
-pub use device_repository::DeviceRepository;
+pub use device_repository::{board_id_conflict, bump_version, version_conflict, DeviceRepository};
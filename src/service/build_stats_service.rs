@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use uuid::Uuid;
+
+use crate::domain::BuildStats;
+
+/// Service keeping per-device build counters. Stats are held in memory only and start
+/// from zero on every server restart.
+#[derive(Clone, Default)]
+pub struct BuildStatsService {
+    stats: Arc<Mutex<HashMap<Uuid, BuildStats>>>,
+}
+
+impl BuildStatsService {
+    /// Constructor for BuildStatsService, starting with no recorded builds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a finished build of the device and remembers its outcome and time.
+    pub fn record_build(&self, device_id: Uuid, success: bool) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let mut stats = self.stats.lock().unwrap();
+        let entry = stats.entry(device_id).or_default();
+        entry.build_count += 1;
+        entry.last_build_success = Some(success);
+        entry.last_build_at = Some(now);
+    }
+
+    /// Returns the device's build stats; all empty if it hasn't been built since startup.
+    pub fn get(&self, device_id: Uuid) -> BuildStats {
+        self.stats
+            .lock()
+            .unwrap()
+            .get(&device_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that builds are counted per device and the last outcome wins.
+    #[test]
+    fn records_builds_per_device() {
+        let service = BuildStatsService::new();
+        let device_id = Uuid::new_v4();
+        assert_eq!(service.get(device_id), BuildStats::default());
+
+        service.record_build(device_id, true);
+        service.record_build(device_id, false);
+        let stats = service.get(device_id);
+        assert_eq!(stats.build_count, 2);
+        assert_eq!(stats.last_build_success, Some(false));
+        assert!(stats.last_build_at.is_some());
+        assert_eq!(service.get(Uuid::new_v4()).build_count, 0);
+    }
+}
@@ -1,40 +1,212 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Component, Path};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
-use crate::domain::Device;
-use crate::repository::DeviceRepository;
+use crate::domain::{Device, DeviceFilter, DeviceUpdate, ImportConflict, ImportReport, NewDevice};
+use crate::repository::{board_id_conflict, DeviceRepository};
+use crate::service::{PlatformIOService, ServiceError};
+
+/// How long an `Idempotency-Key` keeps resolving to the device it created.
+const DEFAULT_IDEMPOTENCY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long a device counts as online after a heartbeat when `HEARTBEAT_TIMEOUT_SECS` is unset.
+const DEFAULT_HEARTBEAT_WINDOW: Duration = Duration::from_secs(60);
 
 #[derive(Clone)]
 pub struct DeviceService {
     repository: Arc<dyn DeviceRepository + Send + Sync>,
+    /// Idempotency keys of recent creates, mapped to the created device and when it was created.
+    idempotency_keys: Arc<Mutex<HashMap<String, (Uuid, Instant)>>>,
+    idempotency_ttl: Duration,
+    /// How long a device counts as online after its last heartbeat.
+    heartbeat_window: Duration,
+    /// Resolves `project_path`s so ones outside the projects root are rejected on write.
+    pio_service: Option<Arc<PlatformIOService>>,
 }
 
 impl DeviceService {
-    /// Constructor for DeviceService, injecting the repository dependency.
+    /// Constructor for DeviceService, injecting the repository dependency. The heartbeat window
+    /// comes from the `HEARTBEAT_TIMEOUT_SECS` env var (default 60).
     pub fn new(repository: Arc<dyn DeviceRepository + Send + Sync>) -> Self {
-        Self { repository }
+        let heartbeat_window = std::env::var("HEARTBEAT_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_HEARTBEAT_WINDOW);
+        Self {
+            repository,
+            idempotency_keys: Arc::default(),
+            idempotency_ttl: DEFAULT_IDEMPOTENCY_TTL,
+            heartbeat_window,
+            pio_service: None,
+        }
+    }
+
+    /// Confines `project_path`s set through `create` and `update` to the projects root of
+    /// `pio_service` (see `PlatformIOService::check_project_path`).
+    pub fn with_pio_service(mut self, pio_service: Arc<PlatformIOService>) -> Self {
+        self.pio_service = Some(pio_service);
+        self
+    }
+
+    /// Replaces how long idempotency keys are remembered (default 24 hours).
+    pub fn with_idempotency_ttl(mut self, ttl: Duration) -> Self {
+        self.idempotency_ttl = ttl;
+        self
+    }
+
+    /// Replaces how long a device counts as online after a heartbeat.
+    pub fn with_heartbeat_window(mut self, window: Duration) -> Self {
+        self.heartbeat_window = window;
+        self
+    }
+
+    /// Whether the device sent a heartbeat within the heartbeat window. Derived on every read;
+    /// nothing in storage flips a device offline.
+    pub fn is_online(&self, device: &Device) -> bool {
+        device.is_online(self.heartbeat_window)
+    }
+
+    /// When the device as returned by the API last changed: its last write, or the moment it
+    /// dropped out of the heartbeat window if that came later, since going offline changes
+    /// `online` without a write. `None` for devices stored before either was recorded.
+    pub fn last_modified(&self, device: &Device) -> Option<DateTime<Utc>> {
+        let changed = device.updated_at.or(device.created_at);
+        let window = chrono::Duration::from_std(self.heartbeat_window).ok();
+        let went_offline = device
+            .last_seen
+            .zip(window)
+            .and_then(|(seen, window)| seen.checked_add_signed(window))
+            .filter(|offline_at| *offline_at <= Utc::now());
+        changed.max(went_offline)
+    }
+
+    /// Returns the device previously created under `key`, if the key hasn't expired.
+    /// Expired keys are dropped on every lookup. Keys are kept in memory only.
+    pub async fn replay_create(&self, key: &str) -> Result<Option<Device>> {
+        let id = {
+            let mut keys = self.idempotency_keys.lock().unwrap();
+            let ttl = self.idempotency_ttl;
+            keys.retain(|_, (_, created)| created.elapsed() < ttl);
+            keys.get(key).map(|(id, _)| *id)
+        };
+        match id {
+            Some(id) => self.repository.find_by_id(id).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Records that `key` created device `id`, so `replay_create` returns it until the key expires.
+    pub fn remember_create(&self, key: &str, id: Uuid) {
+        self.idempotency_keys
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), (id, Instant::now()));
+    }
+
+    /// Creates and persists a new Device named `name`, with the optional fields in `new`.
+    ///
+    /// When `new.board_id` is `None` an id is generated as `<slug>-<token>`, where `<slug>` is
+    /// the name lowercased with runs of non-alphanumeric characters collapsed to `-`, and
+    /// `<token>` is 8 random hex characters (e.g. `"Lab Board #3"` -> `"lab-board-3-1f2e3d4c"`).
+    /// A supplied `board_id` that is already registered fails with `ServiceError::Conflict`.
+    /// Tags are normalized with `Device::normalize_tags` and `location` is trimmed, blank
+    /// meaning none. A `project_path` rejected by `validate_project_path` or a `default_env`
+    /// that isn't `Device::is_valid_environment_name` fails with `ServiceError::InvalidInput`.
+    pub async fn create(&self, name: impl Into<String>, new: NewDevice) -> Result<Device> {
+        let name = name.into();
+        if let Some(path) = &new.project_path {
+            self.validate_project_path(path).await?;
+        }
+        if let Some(env) = &new.default_env {
+            validate_default_env(env)?;
+        }
+        let board_id = self.claim_board_id(new.board_id, &name).await?;
+
+        let mut device = if let (Some(board), Some(path)) = (new.board_type, new.project_path) {
+            Device::with_esp32_config(name, board_id, board, path)
+        } else {
+            Device {
+                board_id,
+                ..Device::new(name)
+            }
+        };
+        device.tags = Device::normalize_tags(new.tags);
+        device.default_port = new.default_port;
+        device.default_env = new.default_env;
+        device.location = new.location.and_then(normalize_location);
+        device.description = new.description;
+        self.repository.create(device).await
     }
 
-    pub async fn create(
+    /// Creates a new Device with the source Device's `board_type` and `tags`.
+    ///
+    /// `project_path` is left unset rather than shared, since two devices building in one
+    /// directory would overwrite each other's firmware; set it with `update` or `scaffold`.
+    /// `default_port` isn't copied either, as a sibling board sits on its own serial port.
+    /// `new_board_id` follows the same rules as in `create`. Fails with `ServiceError::NotFound`
+    /// if the source doesn't exist.
+    pub async fn clone_device(
         &self,
-        name: impl Into<String>,
-        board_id: String,
-        board_type: Option<String>,
-        project_path: Option<String>,
+        source_id: Uuid,
+        new_name: impl Into<String>,
+        new_board_id: Option<String>,
     ) -> Result<Device> {
-        let device = if let (Some(board), Some(path)) = (board_type, project_path) {
-            Device::with_esp32_config(
-                name,
-                 board_id,
-                 board,
-                  path,
-                )
-        } else {
-            Device::new(name)
+        let source = self
+            .repository
+            .find_by_id(source_id)
+            .await?
+            .ok_or_else(|| ServiceError::NotFound("Device not found".to_string()))?;
+        let name = new_name.into();
+        let board_id = self.claim_board_id(new_board_id, &name).await?;
+        let device = Device {
+            board_id,
+            board_type: source.board_type,
+            tags: source.tags,
+            ..Device::new(name)
         };
-        self.repository.create(device.clone()).await
+        self.repository.create(device).await
+    }
+
+    /// Returns `board_id` if it's not registered yet (`ServiceError::Conflict` otherwise), or a
+    /// freshly generated one when it's `None`.
+    async fn claim_board_id(&self, board_id: Option<String>, name: &str) -> Result<String> {
+        match board_id {
+            Some(id) => {
+                if self.repository.find_by_board_id(&id).await?.is_some() {
+                    return Err(board_id_conflict(&id));
+                }
+                Ok(id)
+            }
+            None => self.generate_board_id(name).await,
+        }
+    }
+
+    /// Generates a `board_id` from the device name that is not yet registered.
+    async fn generate_board_id(&self, name: &str) -> Result<String> {
+        let slug = slugify(name);
+        loop {
+            let token = &Uuid::new_v4().simple().to_string()[..8];
+            let candidate = if slug.is_empty() {
+                token.to_string()
+            } else {
+                format!("{}-{}", slug, token)
+            };
+            if self
+                .repository
+                .find_by_board_id(&candidate)
+                .await?
+                .is_none()
+            {
+                return Ok(candidate);
+            }
+        }
     }
 
     /// Retrieves a Device by ID via the repository.
@@ -42,16 +214,303 @@ impl DeviceService {
         self.repository.find_by_id(id).await
     }
 
-    /// Lists all Devices via the repository.
-    pub async fn list(&self) -> Result<Vec<Device>> {
-        self.repository.list().await
+    /// Whether a Device with the ID exists; cheaper than `get` as the Device isn't loaded.
+    pub async fn exists(&self, id: Uuid) -> Result<bool> {
+        self.repository.exists(id).await
+    }
+
+    /// Retrieves the Devices with the given IDs in one repository call, skipping missing ones.
+    pub async fn get_many(&self, ids: &[Uuid]) -> Result<Vec<Device>> {
+        self.repository.find_by_ids(ids).await
+    }
+
+    /// Lists all Devices via the repository; archived ones only when `include_archived` is set.
+    pub async fn list(&self, include_archived: bool) -> Result<Vec<Device>> {
+        let devices = self.repository.list().await?;
+        Ok(filter_archived(devices, include_archived))
+    }
+
+    /// Lists the Devices carrying `tag`, compared case-insensitively.
+    /// Archived ones are included only when `include_archived` is set.
+    pub async fn list_by_tag(&self, tag: &str, include_archived: bool) -> Result<Vec<Device>> {
+        let devices = self
+            .repository
+            .find_by_tag(&tag.trim().to_lowercase())
+            .await?;
+        Ok(filter_archived(devices, include_archived))
+    }
+
+    /// Lists the Devices matching every set field of `filter`, in `filter.sort` order. The most
+    /// selective repository query (name search, then tag, then location) narrows the
+    /// candidates; remaining filters apply in memory.
+    pub async fn search(&self, filter: &DeviceFilter) -> Result<Vec<Device>> {
+        let tag = filter.tag.as_ref().map(|t| t.trim().to_lowercase());
+        let location = filter.location.as_deref();
+        let candidates = match (&filter.name, &tag, location) {
+            (Some(name), _, _) => self.repository.search_text(name).await?,
+            (None, Some(tag), _) => self.repository.find_by_tag(tag).await?,
+            (None, None, Some(location)) => self.repository.find_by_location(location).await?,
+            (None, None, None) => self.repository.list().await?,
+        };
+        let matching = candidates
+            .into_iter()
+            .filter(|d| tag.as_ref().is_none_or(|t| d.tags.contains(t)))
+            .filter(|d| location.is_none_or(|l| d.is_at(l)))
+            .filter(|d| {
+                filter
+                    .board_type
+                    .as_ref()
+                    .is_none_or(|b| d.board_type.as_ref() == Some(b))
+            })
+            .collect();
+        let mut devices = filter_archived(matching, filter.include_archived);
+        filter.sort.apply(&mut devices);
+        Ok(devices)
+    }
+
+    /// Sets a Device's `last_seen` to now. Returns `None` if the Device doesn't exist.
+    pub async fn record_heartbeat(&self, id: Uuid) -> Result<Option<Device>> {
+        match self.repository.find_by_id(id).await? {
+            Some(device) => {
+                self.repository
+                    .update(Device {
+                        last_seen: Some(Utc::now()),
+                        ..device
+                    })
+                    .await
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Replaces a Device's `board_id` with a freshly generated, unregistered one (as `create` does
+    /// when none is given), e.g. after its board was physically swapped. Returns `None` if the
+    /// Device doesn't exist; a concurrent change fails with `ServiceError::Conflict`.
+    pub async fn regenerate_board_id(&self, id: Uuid) -> Result<Option<Device>> {
+        match self.repository.find_by_id(id).await? {
+            Some(device) => {
+                let board_id = self.generate_board_id(&device.name).await?;
+                self.repository.update(Device { board_id, ..device }).await
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Archives or unarchives a Device. Returns `None` if the Device doesn't exist.
+    pub async fn set_archived(&self, id: Uuid, archived: bool) -> Result<Option<Device>> {
+        self.repository.set_archived(id, archived).await
+    }
+
+    /// Applies the set fields of `changes` to a Device. Returns `None` if the Device doesn't
+    /// exist. An `ip_address` that isn't an IPv4/IPv6 address fails with
+    /// `ServiceError::InvalidInput`, as do a `project_path` rejected by `validate_project_path`
+    /// and an invalid `default_env`. With `expected_version`, the repository's compare-and-swap
+    /// fails with `ServiceError::Conflict` unless the stored Device is still at that version.
+    pub async fn update(&self, id: Uuid, changes: DeviceUpdate) -> Result<Option<Device>> {
+        let mut device = match self.repository.find_by_id(id).await? {
+            Some(d) => d,
+            None => return Ok(None),
+        };
+        if let Some(version) = changes.expected_version {
+            device.version = version;
+        }
+        if let Some(name) = changes.name {
+            device.name = name;
+        }
+        if let Some(board_type) = changes.board_type {
+            device.board_type = Some(board_type);
+        }
+        if let Some(project_path) = changes.project_path {
+            self.validate_project_path(&project_path).await?;
+            device.project_path = Some(project_path);
+        }
+        if let Some(tags) = changes.tags {
+            device.tags = Device::normalize_tags(tags);
+        }
+        if let Some(default_port) = changes.default_port {
+            device.default_port = Some(default_port);
+        }
+        if let Some(default_env) = changes.default_env {
+            validate_default_env(&default_env)?;
+            device.default_env = Some(default_env);
+        }
+        if let Some(location) = changes.location {
+            device.location = normalize_location(location);
+        }
+        if let Some(description) = changes.description {
+            device.description = Some(description);
+        }
+        if let Some(ip_address) = changes.ip_address {
+            if ip_address.parse::<IpAddr>().is_err() {
+                return Err(ServiceError::InvalidInput(format!(
+                    "'{}' is not a valid IP address",
+                    ip_address
+                ))
+                .into());
+            }
+            device.ip_address = Some(ip_address);
+        }
+        if let Some(env_vars) = changes.env_vars {
+            if let Some(name) = env_vars.keys().find(|k| !Device::is_valid_env_var_name(k)) {
+                return Err(ServiceError::InvalidInput(format!(
+                    "'{}' is not a valid environment variable name",
+                    name
+                ))
+                .into());
+            }
+            device.env_vars = env_vars;
+        }
+        self.repository.update(device).await
+    }
+
+    /// Rejects a `project_path` containing NUL bytes or line breaks, which would corrupt logs and
+    /// can't name a real directory, or `..` segments. With a PlatformIO service set (see
+    /// `with_pio_service`) the path must also resolve inside its projects root after
+    /// canonicalization. Failures are `ServiceError::InvalidInput`.
+    pub async fn validate_project_path(&self, project_path: &str) -> Result<()> {
+        if project_path.contains(['\0', '\n', '\r']) {
+            return Err(ServiceError::InvalidInput(
+                "project_path must not contain NUL bytes or line breaks".to_string(),
+            )
+            .into());
+        }
+        if Path::new(project_path)
+            .components()
+            .any(|c| c == Component::ParentDir)
+        {
+            return Err(ServiceError::InvalidInput(
+                "project_path must not contain '..' segments".to_string(),
+            )
+            .into());
+        }
+        if let Some(pio_service) = &self.pio_service {
+            pio_service.check_project_path(project_path).await?;
+        }
+        Ok(())
+    }
+
+    /// Recreates exported Devices, in order. With `preserve_ids` each Device keeps its id;
+    /// otherwise it gets a fresh one. A Device collides with a registered one that has the same
+    /// id (only with `preserve_ids`) or `board_id`, and `on_conflict` decides whether the
+    /// registered Device is kept or overwritten; overwriting keeps its id, `env_vars` and
+    /// `last_seen`. Devices without a `board_id` get a generated one, and tags are normalized.
+    /// Devices whose `project_path` or `default_env` `create` would refuse are rejected without
+    /// being stored.
+    pub async fn import(
+        &self,
+        devices: Vec<Device>,
+        preserve_ids: bool,
+        on_conflict: ImportConflict,
+    ) -> Result<ImportReport> {
+        let mut report = ImportReport::default();
+        for mut device in devices {
+            match self.validate_imported(&device).await {
+                Ok(()) => {}
+                Err(e) if matches!(e.downcast_ref(), Some(ServiceError::InvalidInput(_))) => {
+                    report.rejected.push(device.id);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+            device.tags = Device::normalize_tags(device.tags);
+            let existing = if preserve_ids {
+                self.repository.find_by_id(device.id).await?
+            } else {
+                None
+            };
+            let existing = match existing {
+                Some(existing) => Some(existing),
+                None if device.board_id.is_empty() => None,
+                None => self.repository.find_by_board_id(&device.board_id).await?,
+            };
+            match (existing, on_conflict) {
+                (Some(_), ImportConflict::Skip) => report.skipped.push(device.id),
+                (Some(existing), ImportConflict::Overwrite) => {
+                    let device = Device {
+                        id: existing.id,
+                        env_vars: existing.env_vars,
+                        last_seen: existing.last_seen,
+                        version: existing.version,
+                        ..device
+                    };
+                    if let Some(stored) = self.repository.update(device).await? {
+                        report.overwritten.push(stored.id);
+                    }
+                }
+                (None, _) => {
+                    if !preserve_ids {
+                        device.id = Uuid::new_v4();
+                    }
+                    if device.board_id.is_empty() {
+                        device.board_id = self.generate_board_id(&device.name).await?;
+                    }
+                    device.version = 0;
+                    let stored = self.repository.create(device).await?;
+                    report.created.push(stored.id);
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Checks an imported Device's `project_path` and `default_env` as `create` does.
+    async fn validate_imported(&self, device: &Device) -> Result<()> {
+        if let Some(path) = &device.project_path {
+            self.validate_project_path(path).await?;
+        }
+        if let Some(env) = &device.default_env {
+            validate_default_env(env)?;
+        }
+        Ok(())
+    }
+}
+
+/// Drops archived Devices unless `include_archived` is set.
+fn filter_archived(devices: Vec<Device>, include_archived: bool) -> Vec<Device> {
+    if include_archived {
+        return devices;
+    }
+    devices.into_iter().filter(|d| !d.archived).collect()
+}
+
+/// Rejects a `default_env` that isn't `Device::is_valid_environment_name` with
+/// `ServiceError::InvalidInput`, since it is passed to PlatformIO as `-e`.
+fn validate_default_env(env: &str) -> Result<()> {
+    if Device::is_valid_environment_name(env) {
+        Ok(())
+    } else {
+        Err(ServiceError::InvalidInput(format!(
+            "'{}' is not a valid PlatformIO environment name",
+            env
+        ))
+        .into())
     }
 }
 
+/// Trims a location, treating a blank one as unset.
+fn normalize_location(location: String) -> Option<String> {
+    let location = location.trim();
+    (!location.is_empty()).then(|| location.to_string())
+}
+
+/// Lowercases `name` and collapses every run of non-alphanumeric characters into a single `-`.
+fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::adapters::InMemoryDeviceRepository;
+    use crate::domain::{BoardType, DeviceSort};
     use tokio_test::block_on;
 
     /// Test for creating a device and retrieving it.
@@ -59,8 +518,633 @@ mod tests {
     fn create_and_get() {
         let repo = InMemoryDeviceRepository::new();
         let service = DeviceService::new(Arc::new(repo));
-        let created = block_on(service.create("my-device", "board-id-123".to_string(), None::<String>, None::<String>)).unwrap();
+        let created = block_on(service.create(
+            "my-device",
+            NewDevice {
+                board_id: Some("board-id-123".to_string()),
+                ..NewDevice::default()
+            },
+        ))
+        .unwrap();
         let got = block_on(service.get(created.id)).unwrap().unwrap();
         assert_eq!(got.name, "my-device");
+        assert_eq!(got.board_id, "board-id-123");
+    }
+
+    /// Test that an IP address is stored on update and a hostname is rejected.
+    #[test]
+    fn update_validates_ip_address() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()));
+        let created = block_on(service.create("lab", NewDevice::default())).unwrap();
+
+        let changes = DeviceUpdate {
+            ip_address: Some("192.168.1.50".to_string()),
+            ..Default::default()
+        };
+        let updated = block_on(service.update(created.id, changes))
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.ip_address.as_deref(), Some("192.168.1.50"));
+        let changes = DeviceUpdate {
+            ip_address: Some("lab-board.local".to_string()),
+            ..Default::default()
+        };
+        let err = block_on(service.update(created.id, changes)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::InvalidInput(_))
+        ));
+    }
+
+    /// Test that `default_port` is unset by default and is stored on create and update.
+    #[test]
+    fn default_port_round_trips() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()));
+        let created = block_on(service.create("lab", NewDevice::default())).unwrap();
+        assert_eq!(created.default_port, None);
+
+        let changes = DeviceUpdate {
+            default_port: Some("/dev/ttyUSB0".to_string()),
+            ..Default::default()
+        };
+        block_on(service.update(created.id, changes)).unwrap();
+        let got = block_on(service.get(created.id)).unwrap().unwrap();
+        assert_eq!(got.default_port.as_deref(), Some("/dev/ttyUSB0"));
+
+        let created = block_on(service.create(
+            "lab-2",
+            NewDevice {
+                default_port: Some("/dev/ttyACM0".to_string()),
+                ..NewDevice::default()
+            },
+        ))
+        .unwrap();
+        let got = block_on(service.get(created.id)).unwrap().unwrap();
+        assert_eq!(got.default_port.as_deref(), Some("/dev/ttyACM0"));
+    }
+
+    /// Test that every update bumps the version and a stale `expected_version` is a conflict.
+    #[test]
+    fn update_checks_expected_version() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()));
+        let created = block_on(service.create("lab", NewDevice::default())).unwrap();
+        assert_eq!(created.version, 0);
+
+        let rename = |name: &str, version| DeviceUpdate {
+            name: Some(name.to_string()),
+            expected_version: Some(version),
+            ..Default::default()
+        };
+        let updated = block_on(service.update(created.id, rename("first", 0)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.version, 1);
+        let err = block_on(service.update(created.id, rename("stale", 0))).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Conflict(_))
+        ));
+        let archived = block_on(service.set_archived(created.id, true))
+            .unwrap()
+            .unwrap();
+        assert_eq!(archived.version, 2);
+
+        let stored = block_on(service.get(created.id)).unwrap().unwrap();
+        assert_eq!((stored.name.as_str(), stored.version), ("first", 2));
+    }
+
+    /// Test that omitted board ids are generated from the name and duplicates are rejected.
+    #[test]
+    fn board_id_generation_and_uniqueness() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()));
+        let a = block_on(service.create("Lab Board #3", NewDevice::default())).unwrap();
+        let b = block_on(service.create("Lab Board #3", NewDevice::default())).unwrap();
+        assert!(a.board_id.starts_with("lab-board-3-"));
+        assert_eq!(a.board_id.len(), "lab-board-3-".len() + 8);
+        assert_ne!(a.board_id, b.board_id);
+
+        let err = block_on(service.create(
+            "dup",
+            NewDevice {
+                board_id: Some(a.board_id.clone()),
+                ..NewDevice::default()
+            },
+        ))
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Conflict(_))
+        ));
+    }
+
+    /// Test that tags are normalized on create/update and filterable case-insensitively.
+    #[test]
+    fn tags_are_normalized_and_filterable() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()));
+        let tags = vec![
+            "Room-101".to_string(),
+            " room-101 ".to_string(),
+            "Exp1".to_string(),
+        ];
+        let a = block_on(service.create(
+            "a",
+            NewDevice {
+                tags,
+                ..NewDevice::default()
+            },
+        ))
+        .unwrap();
+        block_on(service.create(
+            "b",
+            NewDevice {
+                tags: vec!["exp2".to_string()],
+                ..NewDevice::default()
+            },
+        ))
+        .unwrap();
+        assert_eq!(a.tags, vec!["room-101", "exp1"]);
+
+        let found = block_on(service.list_by_tag("ROOM-101", false)).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, a.id);
+
+        let changes = DeviceUpdate {
+            tags: Some(vec!["EXP2".to_string()]),
+            ..Default::default()
+        };
+        let updated = block_on(service.update(a.id, changes)).unwrap().unwrap();
+        assert_eq!(updated.tags, vec!["exp2"]);
+        assert_eq!(
+            block_on(service.list_by_tag("exp2", false)).unwrap().len(),
+            2
+        );
+        assert!(
+            block_on(service.update(Uuid::new_v4(), DeviceUpdate::default()))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    /// Test that archived devices are hidden from default listings until unarchived.
+    #[test]
+    fn archived_devices_are_hidden() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()));
+        let a = block_on(service.create(
+            "a",
+            NewDevice {
+                tags: vec!["lab".to_string()],
+                ..NewDevice::default()
+            },
+        ))
+        .unwrap();
+        block_on(service.create(
+            "b",
+            NewDevice {
+                tags: vec!["lab".to_string()],
+                ..NewDevice::default()
+            },
+        ))
+        .unwrap();
+
+        let archived = block_on(service.set_archived(a.id, true)).unwrap().unwrap();
+        assert!(archived.archived);
+        assert_eq!(block_on(service.list(false)).unwrap().len(), 1);
+        assert_eq!(block_on(service.list(true)).unwrap().len(), 2);
+        assert_eq!(
+            block_on(service.list_by_tag("lab", false)).unwrap().len(),
+            1
+        );
+        assert!(block_on(service.get(a.id)).unwrap().unwrap().archived);
+
+        block_on(service.set_archived(a.id, false))
+            .unwrap()
+            .unwrap();
+        assert_eq!(block_on(service.list(false)).unwrap().len(), 2);
+        assert!(block_on(service.set_archived(Uuid::new_v4(), true))
+            .unwrap()
+            .is_none());
+    }
+
+    /// Test that a replayed idempotency key returns the original device until the key expires.
+    #[test]
+    fn idempotency_key_replay_and_expiry() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()))
+            .with_idempotency_ttl(Duration::from_millis(50));
+        assert!(block_on(service.replay_create("key-1")).unwrap().is_none());
+
+        let created = block_on(service.create("a", NewDevice::default())).unwrap();
+        service.remember_create("key-1", created.id);
+        let replayed = block_on(service.replay_create("key-1")).unwrap().unwrap();
+        assert_eq!(replayed.id, created.id);
+        assert!(block_on(service.replay_create("key-2")).unwrap().is_none());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(block_on(service.replay_create("key-1")).unwrap().is_none());
+    }
+
+    /// Test that a heartbeat marks a device online until the window passes.
+    #[test]
+    fn heartbeat_marks_device_online() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()))
+            .with_heartbeat_window(Duration::from_millis(50));
+        let created = block_on(service.create("a", NewDevice::default())).unwrap();
+        assert!(!service.is_online(&created));
+
+        let seen = block_on(service.record_heartbeat(created.id))
+            .unwrap()
+            .unwrap();
+        assert!(seen.last_seen.is_some());
+        assert!(service.is_online(&seen));
+        assert_eq!(
+            block_on(service.get(created.id))
+                .unwrap()
+                .unwrap()
+                .last_seen,
+            seen.last_seen
+        );
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(!service.is_online(&seen));
+        assert!(block_on(service.record_heartbeat(Uuid::new_v4()))
+            .unwrap()
+            .is_none());
+    }
+
+    /// Test that search results follow the requested order, with ties and the default by id.
+    #[test]
+    fn search_results_are_sorted() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()));
+        let b = block_on(service.create("bravo", NewDevice::default())).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        let a = block_on(service.create("Alpha", NewDevice::default())).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        let c = block_on(service.create("charlie", NewDevice::default())).unwrap();
+        let names = |sort: &str| {
+            let filter = DeviceFilter {
+                sort: DeviceSort::parse(sort).unwrap(),
+                ..Default::default()
+            };
+            let found = block_on(service.search(&filter)).unwrap();
+            found.into_iter().map(|d| d.name).collect::<Vec<_>>()
+        };
+
+        assert_eq!(names("name"), ["Alpha", "bravo", "charlie"]);
+        assert_eq!(names("-name"), ["charlie", "bravo", "Alpha"]);
+        assert_eq!(names("created_at"), ["bravo", "Alpha", "charlie"]);
+        assert_eq!(names("-created_at"), ["charlie", "Alpha", "bravo"]);
+        assert!(DeviceSort::parse("id").is_none());
+        assert!(DeviceSort::parse("-size").is_none());
+
+        let mut ids = vec![a.id, b.id, c.id];
+        ids.sort();
+        let found = block_on(service.search(&DeviceFilter::default())).unwrap();
+        assert_eq!(found.iter().map(|d| d.id).collect::<Vec<_>>(), ids);
+    }
+
+    /// Test that name search matches substrings of names and descriptions case-insensitively and
+    /// ANDs with other filters.
+    #[test]
+    fn search_by_partial_name() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()));
+        block_on(service.create(
+            "esp32-lab-03",
+            NewDevice {
+                board_type: Some(BoardType::Esp32Dev),
+                project_path: Some("p3".to_string()),
+                tags: vec!["room-1".to_string()],
+                ..NewDevice::default()
+            },
+        ))
+        .unwrap();
+        block_on(service.create("ESP32-LAB-04", NewDevice::default())).unwrap();
+        block_on(service.create(
+            "office",
+            NewDevice {
+                tags: vec!["room-1".to_string()],
+                description: Some("Flaky USB port".to_string()),
+                ..NewDevice::default()
+            },
+        ))
+        .unwrap();
+
+        let by_name = |name: &str| DeviceFilter {
+            name: Some(name.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(block_on(service.search(&by_name("lab"))).unwrap().len(), 2);
+        assert!(block_on(service.search(&by_name("garage")))
+            .unwrap()
+            .is_empty());
+        let found = block_on(service.search(&by_name("usb"))).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].name, "office");
+
+        let filter = DeviceFilter {
+            tag: Some("ROOM-1".to_string()),
+            ..by_name("Lab")
+        };
+        let found = block_on(service.search(&filter)).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].name, "esp32-lab-03");
+
+        let filter = DeviceFilter {
+            board_type: Some(BoardType::Esp32Dev),
+            ..Default::default()
+        };
+        assert_eq!(block_on(service.search(&filter)).unwrap().len(), 1);
+    }
+
+    /// Test that cloning copies the board config but not the project path, and checks the source.
+    #[test]
+    fn clone_device_copies_config() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()));
+        let source = block_on(service.create(
+            "lab-01",
+            NewDevice {
+                board_id: Some("b-01".to_string()),
+                board_type: Some(BoardType::Esp32Dev),
+                project_path: Some("lab-01".to_string()),
+                tags: vec!["room-1".to_string()],
+                default_port: Some("/dev/ttyUSB0".to_string()),
+                ..NewDevice::default()
+            },
+        ))
+        .unwrap();
+
+        let clone = block_on(service.clone_device(source.id, "lab-02", None)).unwrap();
+        assert_ne!(clone.id, source.id);
+        assert_eq!(clone.name, "lab-02");
+        assert!(clone.board_id.starts_with("lab-02-"));
+        assert_eq!(clone.board_type, Some(BoardType::Esp32Dev));
+        assert_eq!(clone.tags, vec!["room-1".to_string()]);
+        assert_eq!(clone.project_path, None);
+        assert_eq!(clone.default_port, None);
+
+        let err = block_on(service.clone_device(source.id, "lab-03", Some("b-01".to_string())))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Conflict(_))
+        ));
+        let err = block_on(service.clone_device(Uuid::new_v4(), "lab-04", None)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::NotFound(_))
+        ));
+    }
+
+    /// Test that regenerating a board_id replaces it with a new one and reports missing devices.
+    #[test]
+    fn regenerate_board_id_assigns_new_id() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()));
+        let device = block_on(service.create(
+            "Lab Board",
+            NewDevice {
+                board_id: Some("old-board".to_string()),
+                ..NewDevice::default()
+            },
+        ))
+        .unwrap();
+
+        let updated = block_on(service.regenerate_board_id(device.id))
+            .unwrap()
+            .unwrap();
+        assert_ne!(updated.board_id, "old-board");
+        assert!(updated.board_id.starts_with("lab-board-"));
+        assert!(block_on(service.repository.find_by_board_id("old-board"))
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            block_on(service.get(device.id)).unwrap().unwrap().board_id,
+            updated.board_id
+        );
+        assert!(block_on(service.regenerate_board_id(Uuid::new_v4()))
+            .unwrap()
+            .is_none());
+    }
+
+    /// Test that create and update reject unsafe project paths and paths outside the projects root.
+    #[test]
+    fn project_path_is_validated() {
+        let root = std::env::temp_dir().join(format!("projects-{}", Uuid::new_v4()));
+        let pio = PlatformIOService::new().with_projects_root(&root);
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()))
+            .with_pio_service(Arc::new(pio));
+        let create = |path: &str| {
+            block_on(service.create(
+                "lab",
+                NewDevice {
+                    board_type: Some(BoardType::Esp32Dev),
+                    project_path: Some(path.to_string()),
+                    ..NewDevice::default()
+                },
+            ))
+        };
+
+        for path in [
+            "../outside",
+            "lab/../../etc",
+            "lab\nINFO forged",
+            "lab\0",
+            "/etc",
+        ] {
+            let err = create(path).unwrap_err();
+            assert!(
+                matches!(
+                    err.downcast_ref::<ServiceError>(),
+                    Some(ServiceError::InvalidInput(_))
+                ),
+                "{:?}",
+                path
+            );
+        }
+        let inside = root.join("lab-02");
+        for path in ["lab-01", "labs/lab-01", inside.to_str().unwrap()] {
+            assert_eq!(create(path).unwrap().project_path.as_deref(), Some(path));
+        }
+        assert!(!root.exists());
+
+        let device = create("lab-03").unwrap();
+        let changes = DeviceUpdate {
+            project_path: Some("../lab-03".to_string()),
+            ..Default::default()
+        };
+        assert!(block_on(service.update(device.id, changes)).is_err());
+        let changes = DeviceUpdate {
+            project_path: Some("lab-04".to_string()),
+            ..Default::default()
+        };
+        let updated = block_on(service.update(device.id, changes))
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.project_path.as_deref(), Some("lab-04"));
+    }
+
+    /// Test that `default_env` is stored on create and update only if it's a safe environment name.
+    #[test]
+    fn default_env_is_validated() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()));
+        let create = |env: &str| {
+            block_on(service.create(
+                "lab",
+                NewDevice {
+                    default_env: Some(env.to_string()),
+                    ..NewDevice::default()
+                },
+            ))
+        };
+
+        for env in ["", "esp32 dev", "-e;reboot", "../env", "env:esp32dev"] {
+            let err = create(env).unwrap_err();
+            assert!(
+                matches!(
+                    err.downcast_ref::<ServiceError>(),
+                    Some(ServiceError::InvalidInput(_))
+                ),
+                "{:?}",
+                env
+            );
+        }
+        let device = create("esp32dev").unwrap();
+        assert_eq!(device.default_env.as_deref(), Some("esp32dev"));
+        let changes = DeviceUpdate {
+            default_env: Some("debug build".to_string()),
+            ..Default::default()
+        };
+        assert!(block_on(service.update(device.id, changes)).is_err());
+        let changes = DeviceUpdate {
+            default_env: Some("esp32-s3_debug".to_string()),
+            ..Default::default()
+        };
+        let updated = block_on(service.update(device.id, changes))
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.default_env.as_deref(), Some("esp32-s3_debug"));
+    }
+
+    /// Test that locations are trimmed and filtered case-insensitively, alone or with a tag.
+    #[test]
+    fn search_by_location() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()));
+        let create = |name: &str, tag: &str, location: Option<&str>| {
+            block_on(service.create(
+                name,
+                NewDevice {
+                    tags: vec![tag.to_string()],
+                    location: location.map(str::to_string),
+                    ..NewDevice::default()
+                },
+            ))
+            .unwrap()
+        };
+        let a = create("a", "exp1", Some("  Lab 2, Bench 4 "));
+        let b = create("b", "exp2", Some("lab 2, bench 4"));
+        create("c", "exp1", Some("Lab 3"));
+        create("d", "exp1", None);
+        assert_eq!(a.location.as_deref(), Some("Lab 2, Bench 4"));
+
+        let names = |location: &str, tag: Option<&str>| {
+            let filter = DeviceFilter {
+                location: Some(location.to_string()),
+                tag: tag.map(str::to_string),
+                ..Default::default()
+            };
+            let mut names: Vec<String> = block_on(service.search(&filter))
+                .unwrap()
+                .into_iter()
+                .map(|d| d.name)
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names("LAB 2, BENCH 4", None), vec!["a", "b"]);
+        assert_eq!(names(" lab 2, bench 4", Some("exp2")), vec!["b"]);
+        assert!(names("Lab", None).is_empty());
+
+        let changes = DeviceUpdate {
+            location: Some("  ".to_string()),
+            ..Default::default()
+        };
+        let updated = block_on(service.update(b.id, changes)).unwrap().unwrap();
+        assert!(updated.location.is_none());
+        assert_eq!(names("lab 2, bench 4", None), vec!["a"]);
+    }
+
+    /// Test that import skips or overwrites collisions by id or board_id and can keep ids.
+    #[test]
+    fn import_handles_collisions() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()));
+        let registered = block_on(service.create(
+            "lab-01",
+            NewDevice {
+                board_id: Some("b-01".to_string()),
+                ..NewDevice::default()
+            },
+        ))
+        .unwrap();
+        let same_id = Device {
+            name: "renamed".to_string(),
+            board_id: "b-99".to_string(),
+            ..registered.clone()
+        };
+        let same_board = Device {
+            board_id: "b-01".to_string(),
+            ..Device::new("other")
+        };
+        let fresh = Device {
+            board_id: String::new(),
+            tags: vec!["Lab".to_string()],
+            ..Device::new("fresh")
+        };
+
+        let report = block_on(service.import(
+            vec![same_id.clone(), same_board.clone(), fresh.clone()],
+            true,
+            ImportConflict::Skip,
+        ))
+        .unwrap();
+        assert_eq!(report.skipped, vec![same_id.id, same_board.id]);
+        assert_eq!(report.created, vec![fresh.id]);
+        let created = block_on(service.get(fresh.id)).unwrap().unwrap();
+        assert!(created.board_id.starts_with("fresh-"));
+        assert_eq!(created.tags, vec!["lab".to_string()]);
+
+        let report =
+            block_on(service.import(vec![same_id], true, ImportConflict::Overwrite)).unwrap();
+        assert_eq!(report.overwritten, vec![registered.id]);
+        let overwritten = block_on(service.get(registered.id)).unwrap().unwrap();
+        assert_eq!(overwritten.name, "renamed");
+        assert_eq!(overwritten.version, registered.version + 1);
+
+        let report =
+            block_on(service.import(vec![Device::new("copy")], false, ImportConflict::Skip))
+                .unwrap();
+        assert_eq!(report.created.len(), 1);
+        assert_eq!(block_on(service.list(true)).unwrap().len(), 3);
+    }
+
+    /// Test that import rejects Devices with an unsafe `project_path` or `default_env`.
+    #[test]
+    fn import_rejects_invalid_devices() {
+        let service = DeviceService::new(Arc::new(InMemoryDeviceRepository::new()));
+        let escaping = Device {
+            project_path: Some("../outside".to_string()),
+            ..Device::new("escaping")
+        };
+        let bad_env = Device {
+            default_env: Some("esp32dev; rm -rf /".to_string()),
+            ..Device::new("bad-env")
+        };
+        let valid = Device::new("valid");
+
+        let report = block_on(service.import(
+            vec![escaping.clone(), bad_env.clone(), valid.clone()],
+            true,
+            ImportConflict::Skip,
+        ))
+        .unwrap();
+        assert_eq!(report.rejected, vec![escaping.id, bad_env.id]);
+        assert_eq!(report.created, vec![valid.id]);
+        assert_eq!(block_on(service.list(true)).unwrap().len(), 1);
     }
 }
@@ -0,0 +1,62 @@
+use std::fmt;
+
+/// Errors raised by the service layer that handlers map to specific HTTP status codes.
+/// They travel inside `anyhow::Error` and are recovered with `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServiceError {
+    /// The request conflicts with existing state (e.g. a duplicate `board_id`).
+    Conflict(String),
+    /// The request refers to something unusable, such as a project path that isn't a PlatformIO project.
+    InvalidInput(String),
+    /// The requested resource (e.g. a project file) does not exist.
+    NotFound(String),
+    /// The request payload exceeds a configured size limit.
+    TooLarge(String),
+    /// The operation was cancelled before it completed (e.g. a build killed via the cancel endpoint).
+    Cancelled(String),
+    /// The project has no `platformio.ini` yet and must be scaffolded or initialized first.
+    NotInitialized(String),
+    /// The request asks for something the server never allows (e.g. a PlatformIO subcommand
+    /// that isn't allowlisted).
+    Forbidden(String),
+    /// A command was about to run in a directory outside the projects root, e.g. because a
+    /// symlink in the project path now points elsewhere.
+    SandboxViolation(String),
+    /// The PlatformIO binary is missing or won't run, so no PlatformIO command can be served.
+    PlatformIOUnavailable(String),
+}
+
+impl ServiceError {
+    /// Stable machine-readable code for the error kind, e.g. `PROJECT_NOT_INITIALIZED`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServiceError::Conflict(_) => "CONFLICT",
+            ServiceError::InvalidInput(_) => "INVALID_INPUT",
+            ServiceError::NotFound(_) => "NOT_FOUND",
+            ServiceError::TooLarge(_) => "TOO_LARGE",
+            ServiceError::Cancelled(_) => "CANCELLED",
+            ServiceError::NotInitialized(_) => "PROJECT_NOT_INITIALIZED",
+            ServiceError::Forbidden(_) => "FORBIDDEN",
+            ServiceError::SandboxViolation(_) => "SANDBOX_VIOLATION",
+            ServiceError::PlatformIOUnavailable(_) => "PLATFORMIO_UNAVAILABLE",
+        }
+    }
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceError::Conflict(msg)
+            | ServiceError::InvalidInput(msg)
+            | ServiceError::NotFound(msg)
+            | ServiceError::TooLarge(msg)
+            | ServiceError::Cancelled(msg)
+            | ServiceError::NotInitialized(msg)
+            | ServiceError::Forbidden(msg)
+            | ServiceError::SandboxViolation(msg)
+            | ServiceError::PlatformIOUnavailable(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ServiceError {}
@@ -0,0 +1,78 @@
+use tokio::sync::broadcast;
+
+use crate::domain::DeviceEvent;
+
+/// Events buffered per subscriber before it counts as lagging.
+const DEFAULT_CAPACITY: usize = 256;
+
+/// In-process fan-out of device lifecycle events to every subscriber.
+/// Publishing never blocks: a subscriber that falls more than the channel capacity behind
+/// loses the oldest events and sees `RecvError::Lagged` on its next receive.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<DeviceEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    /// Constructor for EventBus, buffering 256 events per subscriber.
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// EventBus buffering `capacity` events per subscriber (at least one).
+    pub fn with_capacity(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    /// Sends the event to every current subscriber; it is dropped if there are none.
+    pub fn publish(&self, event: DeviceEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Receives every event published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<DeviceEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Number of live subscribers.
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast::error::RecvError;
+    use uuid::Uuid;
+
+    /// Test that subscribers get published events and a slow one lags instead of blocking.
+    #[tokio::test]
+    async fn subscribers_receive_and_slow_ones_lag() {
+        let bus = EventBus::with_capacity(2);
+        bus.publish(DeviceEvent::DeviceCreated {
+            device_id: Uuid::new_v4(),
+        });
+
+        let mut rx = bus.subscribe();
+        let device_id = Uuid::new_v4();
+        for _ in 0..3 {
+            bus.publish(DeviceEvent::BuildStarted { device_id });
+        }
+        assert!(matches!(rx.recv().await, Err(RecvError::Lagged(1))));
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            DeviceEvent::BuildStarted { device_id }
+        );
+
+        drop(rx);
+        assert_eq!(bus.subscriber_count(), 0);
+    }
+}
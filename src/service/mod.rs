@@ -1,5 +1,25 @@
+pub mod build_stats_service;
 pub mod device_service;
+pub mod error;
+pub mod event_bus;
+pub mod network_service;
+pub mod operation_log;
+pub mod pio_runner;
 pub mod platformio_service;
+pub mod temp_cleanup_service;
+pub mod watch_service;
 
+pub use build_stats_service::BuildStatsService;
 pub use device_service::DeviceService;
-pub use platformio_service::PlatformIOService;
+pub use error::ServiceError;
+pub use event_bus::EventBus;
+pub use network_service::NetworkService;
+pub use operation_log::OperationLog;
+pub use pio_runner::{MockPlatformIORunner, PlatformIORunner, ProcessRunner, RunOutput};
+pub use platformio_service::{
+    parse_diagnostics, validate_platformio_ini, BuildOptions, BuildOutput, BuildWait, DeployOutput,
+    DeployPhase, InitOptions, InitOutput, PlannedCommand, PlatformIOService, StreamEvent,
+    UploadProtocol,
+};
+pub use temp_cleanup_service::TempCleanupService;
+pub use watch_service::WatchService;
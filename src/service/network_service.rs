@@ -0,0 +1,94 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tokio::net::TcpStream;
+
+use crate::service::ServiceError;
+
+/// ArduinoOTA's default port on ESP32 boards, used when `OTA_PORT` is unset.
+const DEFAULT_OTA_PORT: u16 = 3232;
+
+/// How long a probe waits for the TCP handshake.
+pub const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Service probing whether networked devices are online before OTA uploads.
+#[derive(Debug, Clone)]
+pub struct NetworkService {
+    /// TCP port probed on the device, from `OTA_PORT`.
+    port: u16,
+}
+
+impl Default for NetworkService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetworkService {
+    /// Constructor probing the port named by the `OTA_PORT` env var (default 3232).
+    pub fn new() -> Self {
+        let port = std::env::var("OTA_PORT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_OTA_PORT);
+        Self { port }
+    }
+
+    /// Replaces the probed TCP port.
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Opens a TCP connection to the probed port on `ip` and returns the handshake time, or
+    /// `None` if the connection is refused or doesn't complete within `timeout`. A device that
+    /// is up but not listening for OTA counts as unreachable. An `ip` that isn't an IP address
+    /// fails with `ServiceError::InvalidInput`.
+    pub async fn ping(&self, ip: &str, timeout: Duration) -> Result<Option<Duration>> {
+        let ip: IpAddr = ip.parse().map_err(|_| {
+            ServiceError::InvalidInput(format!("'{}' is not a valid IP address", ip))
+        })?;
+        let started = Instant::now();
+        match tokio::time::timeout(timeout, TcpStream::connect(SocketAddr::new(ip, self.port)))
+            .await
+        {
+            Ok(Ok(_)) => Ok(Some(started.elapsed())),
+            Ok(Err(_)) | Err(_) => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that a listening port is reachable, a closed one isn't, and bad addresses are rejected.
+    #[tokio::test]
+    async fn ping_probes_tcp_port() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let service = NetworkService::new().with_port(port);
+        assert!(service
+            .ping("127.0.0.1", DEFAULT_PING_TIMEOUT)
+            .await
+            .unwrap()
+            .is_some());
+
+        drop(listener);
+        assert!(service
+            .ping("127.0.0.1", DEFAULT_PING_TIMEOUT)
+            .await
+            .unwrap()
+            .is_none());
+
+        let err = service
+            .ping("not-an-ip", DEFAULT_PING_TIMEOUT)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::InvalidInput(_))
+        ));
+    }
+}
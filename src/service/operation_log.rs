@@ -0,0 +1,129 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use uuid::Uuid;
+
+use crate::domain::{Operation, OperationRecord};
+
+/// Entries kept when `AUDIT_LOG_SIZE` is unset.
+const DEFAULT_CAPACITY: usize = 1000;
+
+/// Most characters of an API key kept in a record.
+const KEY_PREFIX_LEN: usize = 4;
+
+/// In-memory ring buffer of recent builds, uploads and cleans, for seeing who did what without
+/// a database. Once full, each new record evicts the oldest; the log starts empty on every
+/// server restart.
+#[derive(Debug)]
+pub struct OperationLog {
+    capacity: usize,
+    records: Mutex<VecDeque<OperationRecord>>,
+}
+
+impl Default for OperationLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl OperationLog {
+    /// Operation log keeping the last `capacity` records (at least one).
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Operation log sized by the `AUDIT_LOG_SIZE` env var (default 1000).
+    pub fn from_env() -> Self {
+        let capacity = std::env::var("AUDIT_LOG_SIZE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_CAPACITY);
+        Self::new(capacity)
+    }
+
+    /// How many records are kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Records a finished operation, timestamped now. Only a prefix of `api_key` is stored
+    /// (see `redact_api_key`).
+    pub fn record(
+        &self,
+        device_id: Uuid,
+        operation: Operation,
+        success: bool,
+        api_key: Option<&str>,
+    ) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let record = OperationRecord {
+            timestamp,
+            device_id,
+            operation,
+            success,
+            api_key_prefix: api_key.and_then(redact_api_key),
+        };
+        let mut records = self.records.lock().unwrap();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// The `limit` most recent records, newest first.
+    pub fn recent(&self, limit: usize) -> Vec<OperationRecord> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Keeps the first characters of an API key, at most four and never more than half of it,
+/// followed by `…`; `None` for an empty key.
+fn redact_api_key(key: &str) -> Option<String> {
+    let len = key.chars().count();
+    if len == 0 {
+        return None;
+    }
+    let prefix: String = key.chars().take(KEY_PREFIX_LEN.min(len / 2)).collect();
+    Some(format!("{}…", prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that the log keeps only the newest records and never stores a full API key.
+    #[test]
+    fn keeps_newest_records_with_redacted_keys() {
+        let log = OperationLog::new(2);
+        let device_id = Uuid::new_v4();
+        log.record(device_id, Operation::Build, true, Some("lab4-secret-key"));
+        log.record(device_id, Operation::Upload, false, None);
+        log.record(device_id, Operation::Clean, true, Some("abc"));
+
+        let recent = log.recent(50);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].operation, Operation::Clean);
+        assert_eq!(recent[0].api_key_prefix.as_deref(), Some("a…"));
+        assert_eq!(recent[1].operation, Operation::Upload);
+        assert!(!recent[1].success && recent[1].api_key_prefix.is_none());
+        assert_eq!(log.recent(1).len(), 1);
+
+        assert_eq!(redact_api_key("lab4-secret-key").as_deref(), Some("lab4…"));
+        assert_eq!(redact_api_key(""), None);
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Mutex;
+
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+use crate::service::StreamEvent;
+
+/// Captured result of one finished PlatformIO command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunOutput {
+    /// Whether the command exited successfully.
+    pub success: bool,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl RunOutput {
+    /// A successful run that printed `stdout`.
+    pub fn ok(stdout: impl Into<Vec<u8>>) -> Self {
+        Self {
+            success: true,
+            stdout: stdout.into(),
+            stderr: Vec::new(),
+        }
+    }
+
+    /// A failed run that printed `stderr`.
+    pub fn failed(stderr: impl Into<Vec<u8>>) -> Self {
+        Self {
+            success: false,
+            stdout: Vec::new(),
+            stderr: stderr.into(),
+        }
+    }
+}
+
+/// Executes PlatformIO commands on behalf of `PlatformIOService`. `ProcessRunner` spawns the
+/// real binary; `MockPlatformIORunner` answers with canned output so tests need no PlatformIO.
+#[async_trait::async_trait]
+pub trait PlatformIORunner: Send + Sync {
+    /// Runs `binary args...` in `dir` with `env` set and waits for it to exit. Fails only if the
+    /// command couldn't be started. Dropping the future stops the command.
+    async fn run(
+        &self,
+        binary: &str,
+        dir: &Path,
+        args: &[&str],
+        env: &HashMap<String, String>,
+    ) -> std::io::Result<RunOutput>;
+
+    /// Starts `binary args...` in `dir` and forwards its stdout/stderr lines over a channel,
+    /// ending with `StreamEvent::Exit`. Dropping the receiver stops the command.
+    ///
+    /// The default waits for `run` and then replays its stdout and stderr lines.
+    async fn stream(
+        &self,
+        binary: &str,
+        dir: &Path,
+        args: &[&str],
+    ) -> std::io::Result<mpsc::Receiver<StreamEvent>> {
+        let output = self.run(binary, dir, args, &HashMap::new()).await?;
+        let text = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let lines: Vec<String> = text.lines().map(str::to_string).collect();
+        let (tx, rx) = mpsc::channel(lines.len() + 1);
+        for line in lines {
+            let _ = tx.try_send(StreamEvent::Line(line));
+        }
+        let _ = tx.try_send(StreamEvent::Exit {
+            success: output.success,
+        });
+        Ok(rx)
+    }
+}
+
+/// Runner that spawns the PlatformIO binary as a child process.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessRunner;
+
+impl ProcessRunner {
+    /// Spawns the command with piped output; it is killed when the handle is dropped.
+    fn spawn(
+        binary: &str,
+        dir: &Path,
+        args: &[&str],
+        env: &HashMap<String, String>,
+    ) -> std::io::Result<tokio::process::Child> {
+        Command::new(binary)
+            .args(args)
+            .envs(env)
+            .current_dir(dir)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+    }
+}
+
+#[async_trait::async_trait]
+impl PlatformIORunner for ProcessRunner {
+    async fn run(
+        &self,
+        binary: &str,
+        dir: &Path,
+        args: &[&str],
+        env: &HashMap<String, String>,
+    ) -> std::io::Result<RunOutput> {
+        // Dropping the wait future drops the child, which kills it (kill_on_drop).
+        let output = Self::spawn(binary, dir, args, env)?
+            .wait_with_output()
+            .await?;
+        Ok(RunOutput {
+            success: output.status.success(),
+            stdout: output.stdout,
+            stderr: output.stderr,
+        })
+    }
+
+    async fn stream(
+        &self,
+        binary: &str,
+        dir: &Path,
+        args: &[&str],
+    ) -> std::io::Result<mpsc::Receiver<StreamEvent>> {
+        let mut child = Self::spawn(binary, dir, args, &HashMap::new())?;
+        let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+        let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+        let (tx, rx) = mpsc::channel(64);
+
+        tokio::spawn(async move {
+            let (mut stdout_open, mut stderr_open) = (true, true);
+            loop {
+                // `next_line` is cancel-safe, so losing the race in select! drops no output.
+                let line = tokio::select! {
+                    line = stdout.next_line(), if stdout_open => line.ok().flatten().or_else(|| {
+                        stdout_open = false;
+                        None
+                    }),
+                    line = stderr.next_line(), if stderr_open => line.ok().flatten().or_else(|| {
+                        stderr_open = false;
+                        None
+                    }),
+                    else => break,
+                };
+                if let Some(line) = line {
+                    if tx.send(StreamEvent::Line(line)).await.is_err() {
+                        // Client went away; dropping the child kills it.
+                        return;
+                    }
+                }
+            }
+            let success = child.wait().await.map(|s| s.success()).unwrap_or(false);
+            let _ = tx.send(StreamEvent::Exit { success }).await;
+        });
+
+        Ok(rx)
+    }
+}
+
+/// `--version` answer of a `MockPlatformIORunner` that wasn't given one.
+const MOCK_VERSION_OUTPUT: &str = "PlatformIO Core, version 6.1.15\n";
+
+/// Runner for tests that returns canned output instead of running anything, and records the
+/// arguments of every command it was asked to run.
+///
+/// A command gets the output of the first response whose arguments it starts with. Unmatched
+/// commands succeed with no output, except `--version`, which reports PlatformIO 6.1.15.
+#[derive(Debug, Default)]
+pub struct MockPlatformIORunner {
+    responses: Vec<(Vec<String>, RunOutput)>,
+    calls: Mutex<Vec<Vec<String>>>,
+}
+
+impl MockPlatformIORunner {
+    /// Mock where every command succeeds with no output.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers commands whose arguments start with `args_prefix` with `output`.
+    pub fn respond(mut self, args_prefix: &[&str], output: RunOutput) -> Self {
+        let prefix = args_prefix.iter().map(|a| a.to_string()).collect();
+        self.responses.push((prefix, output));
+        self
+    }
+
+    /// Arguments of every command run so far, oldest first.
+    pub fn calls(&self) -> Vec<Vec<String>> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl PlatformIORunner for MockPlatformIORunner {
+    async fn run(
+        &self,
+        _binary: &str,
+        _dir: &Path,
+        args: &[&str],
+        _env: &HashMap<String, String>,
+    ) -> std::io::Result<RunOutput> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let response = self
+            .responses
+            .iter()
+            .find(|(prefix, _)| args.starts_with(prefix))
+            .map(|(_, output)| output.clone());
+        let output = match response {
+            Some(output) => output,
+            None if args == ["--version"] => RunOutput::ok(MOCK_VERSION_OUTPUT),
+            None => RunOutput::ok(""),
+        };
+        self.calls.lock().unwrap().push(args);
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that the mock answers by argument prefix, falls back to success, and records calls.
+    #[tokio::test]
+    async fn mock_matches_prefix_and_records_calls() {
+        let mock = MockPlatformIORunner::new()
+            .respond(&["run", "--target", "upload"], RunOutput::failed("no port"))
+            .respond(&["run"], RunOutput::ok("SUCCESS\n"));
+        let dir = Path::new(".");
+        let env = HashMap::new();
+
+        let upload = mock
+            .run("pio", dir, &["run", "--target", "upload"], &env)
+            .await
+            .unwrap();
+        assert_eq!(upload, RunOutput::failed("no port"));
+        let build = mock.run("pio", dir, &["run", "-v"], &env).await.unwrap();
+        assert_eq!(build, RunOutput::ok("SUCCESS\n"));
+        let other = mock.run("pio", dir, &["pkg", "list"], &env).await.unwrap();
+        assert!(other.success && other.stdout.is_empty());
+
+        let mut events = mock.stream("pio", dir, &["run"]).await.unwrap();
+        assert_eq!(
+            events.recv().await,
+            Some(StreamEvent::Line("SUCCESS".to_string()))
+        );
+        assert_eq!(
+            events.recv().await,
+            Some(StreamEvent::Exit { success: true })
+        );
+        assert_eq!(mock.calls().len(), 4);
+        assert_eq!(mock.calls()[3], ["run"]);
+    }
+}
@@ -1,68 +1,65 @@
 use anyhow::{anyhow, Result};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Component, Path, PathBuf};
 use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 use tokio::process::Command;
+use tokio::sync::{mpsc, oneshot, watch, OwnedSemaphorePermit, Semaphore};
 
-/// Service for handling PlatformIO operations like building, uploading, and initializing ESP32 projects.
-#[derive(Clone)]
-pub struct PlatformIOService;
+use crate::domain::{
+    BoardDefinition, Device, Diagnostic, DiagnosticSeverity, FirmwareSizeInfo, InitResult,
+    LibraryInfo, MemoryUsage, PackageUpdate, PartitionEntry, ProjectSize, SerialPortInfo,
+    SystemInfo, TestSummary,
+};
+use crate::service::{PlatformIORunner, ProcessRunner, RunOutput, ServiceError};
 
-impl PlatformIOService {
-    /// Constructor (no-op, as it's a stateless service).
-    pub fn new() -> Self {
-        Self
-    }
+/// Kill handles for in-flight builds, keyed by project path. The `u64` identifies which build
+/// registered the entry so a finished build never removes a newer build's handle.
+type RunningBuilds = Arc<Mutex<HashMap<String, (u64, oneshot::Sender<()>)>>>;
 
-    /// Build the PlatformIO project for a device
-    /// Builds the PlatformIO project at the given path.
-    pub async fn build_project(&self, project_path: &str) -> Result<String> {
-        self.run_pio_command(project_path, &["run"]).await
-    }
+/// Id and outcome of the latest build to release each project's build lock, keyed by project
+/// path. The outcome is `None` when the lock was held by an operation that doesn't build.
+type FinishedBuilds = Arc<Mutex<HashMap<String, watch::Sender<Option<FinishedBuild>>>>>;
 
-    /// Upload firmware to ESP32 device
-    /// Uploads firmware to the ESP32 device.
-    pub async fn upload_firmware(&self, project_path: &str, port: Option<&str>) -> Result<String> {
-        let mut args = vec!["run", "--target", "upload"];
-        if let Some(p) = port {
-            args.extend_from_slice(&["--upload-port", p]);
-        }
-        self.run_pio_command(project_path, &args).await
-    }
+/// Build id and result published by `BuildGuard`.
+type FinishedBuild = (u64, Option<Result<BuildOutput, String>>);
 
-    /// Clean the PlatformIO project
-    /// Cleans build files in the PlatformIO project.
-    pub async fn clean_project(&self, project_path: &str) -> Result<String> {
-        self.run_pio_command(project_path, &["run", "--target", "clean"])
-            .await
-    }
+/// Project paths with an upload or erase in progress.
+type RunningUploads = Arc<Mutex<HashSet<String>>>;
 
-    /// Get project information
-    /// Retrieves PlatformIO project configuration info.
-    pub async fn get_project_info(&self, project_path: &str) -> Result<String> {
-        self.run_pio_command(project_path, &["project", "config"])
-            .await
-    }
+/// Combined output lines of the most recent build, keyed by project path.
+type BuildLogs = Arc<Mutex<HashMap<String, VecDeque<String>>>>;
 
-    /// Initialize a new PlatformIO project
-    /// Initializes a new PlatformIO project for the given board.
-    pub async fn init_project(&self, project_path: &str, board: &str) -> Result<String> {
-        // Create directory if it doesn't exist
-        tokio::fs::create_dir_all(project_path)
-            .await
-            .map_err(|e| anyhow!("Failed to create project directory: {}", e))?;
+static NEXT_BUILD_ID: AtomicU64 = AtomicU64::new(0);
 
-        self.run_pio_command(project_path, &["project", "init", "--board", board])
-            .await
-    }
+/// How many directory levels below `src/` are walked when listing source files.
+const MAX_SOURCE_DEPTH: usize = 8;
 
-    /// Create a basic ESP32 main.cpp file
-    /// Generates a basic `main.cpp` file for ESP32 (blinking LED example).
-    pub async fn create_basic_main(&self, project_path: &str) -> Result<()> {
-        let src_dir = format!("{}/src", project_path);
-        tokio::fs::create_dir_all(&src_dir)
-            .await
-            .map_err(|e| anyhow!("Failed to create src directory: {}", e))?;
+/// Largest source file accepted by `write_file`.
+pub const MAX_SOURCE_FILE_BYTES: usize = 1024 * 1024;
+
+/// Lines of build output kept per project when `BUILD_LOG_LINES` is unset.
+const DEFAULT_BUILD_LOG_LINES: usize = 500;
+
+/// Command output returned when `MAX_OUTPUT_BYTES` is unset; the rest is cut off.
+const DEFAULT_MAX_OUTPUT_BYTES: usize = 256 * 1024;
+
+/// Directory project paths are resolved against when `PROJECTS_ROOT` is unset.
+const DEFAULT_PROJECTS_ROOT: &str = "./projects";
 
-        let main_cpp_content = r#"#include <Arduino.h>
+/// Template used by `create_basic_main` when none is requested.
+const DEFAULT_MAIN_TEMPLATE: &str = "blink";
+
+/// File under `src/` that `create_basic_main` writes when no filename is requested.
+pub const DEFAULT_MAIN_FILENAME: &str = "main.cpp";
+
+/// Extensions accepted for the entry file written by `create_basic_main`.
+const MAIN_EXTENSIONS: &[&str] = &["cpp", "ino"];
+
+/// Basic ESP32 program that blinks the built-in LED.
+const BLINK_MAIN: &str = r#"#include <Arduino.h>
 
 // Basic ESP32 program
 void setup() {
@@ -81,68 +78,4502 @@ void loop() {
 }
 "#;
 
-        let main_path = format!("{}/main.cpp", src_dir);
-        tokio::fs::write(&main_path, main_cpp_content)
+/// Empty Arduino skeleton.
+const MINIMAL_MAIN: &str = r#"#include <Arduino.h>
+
+void setup() {
+}
+
+void loop() {
+}
+"#;
+
+/// PlatformIO subcommands `run_subcommand` accepts, each with the actions (its first argument)
+/// allowed for it; an empty list allows any arguments. Only read-only commands are listed.
+pub const ALLOWED_SUBCOMMANDS: &[(&str, &[&str])] = &[
+    ("boards", &[]),
+    ("check", &[]),
+    ("device", &["list"]),
+    ("pkg", &["list", "outdated", "show", "search"]),
+    ("project", &["config", "metadata"]),
+    ("settings", &["get"]),
+    ("system", &["info"]),
+];
+
+/// Options that would make a subcommand read or write outside the project directory. Matched as
+/// prefixes, so attached values (`-d/`, `--project-dir=/`) are caught too.
+const FORBIDDEN_OPTIONS: &[&str] = &[
+    "-d",
+    "--project-dir",
+    "-c",
+    "--project-conf",
+    "--json-output-path",
+];
+
+/// How long a git command may run before it is killed, so a remote that never answers can't
+/// hold a project's locks forever.
+const GIT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How to fix a missing PlatformIO, appended to `ServiceError::PlatformIOUnavailable` messages.
+const INSTALL_HINT: &str =
+    "Install PlatformIO Core (https://docs.platformio.org/en/latest/core/installation/) \
+     or set PLATFORMIO_BIN to the path of its `platformio` binary.";
+
+/// Binary used when `PLATFORMIO_BIN` is unset.
+const DEFAULT_BINARY: &str = "platformio";
+
+/// Short alias tried when the default binary isn't found.
+const FALLBACK_BINARY: &str = "pio";
+
+/// How long `system_info` answers from its cache before asking PlatformIO again.
+const SYSTEM_INFO_TTL: Duration = Duration::from_secs(60);
+
+/// Service for handling PlatformIO operations like building, uploading, and initializing ESP32 projects.
+#[derive(Clone)]
+pub struct PlatformIOService {
+    running_builds: RunningBuilds,
+    finished_builds: FinishedBuilds,
+    running_uploads: RunningUploads,
+    /// Binary from `PLATFORMIO_BIN`, or `"platformio"`.
+    configured_binary: String,
+    /// Binary that answered `--version`, remembered after the first successful check.
+    resolved_binary: Arc<OnceLock<String>>,
+    /// Version reported by the resolved binary's `--version`, cached alongside it.
+    version: Arc<OnceLock<String>>,
+    /// Last `system_info` answer and when it was fetched; reused for `SYSTEM_INFO_TTL`.
+    system_info: Arc<Mutex<Option<(Instant, SystemInfo)>>>,
+    build_logs: BuildLogs,
+    /// Maximum lines kept per build log; older lines are dropped first.
+    build_log_lines: usize,
+    /// Directory every device `project_path` is resolved against and confined to.
+    projects_root: PathBuf,
+    /// Permits for build processes; builds beyond the limit wait for a free permit.
+    build_slots: Arc<Semaphore>,
+    /// Permits for upload-type processes (uploads, erases, flash reads), which contend on the
+    /// serial ports and USB bus rather than the CPU; limited separately from builds.
+    upload_slots: Arc<Semaphore>,
+    /// Operations currently waiting for a `build_slots` or `upload_slots` permit.
+    queued: Arc<AtomicUsize>,
+    /// Board used by `init` requests that don't name one, from `DEFAULT_BOARD`.
+    default_board: Option<String>,
+    /// Directory whose contents seed every initialized project, from `TEMPLATE_DIR`.
+    template_dir: Option<PathBuf>,
+    /// Command output returned beyond this many bytes is truncated, from `MAX_OUTPUT_BYTES`.
+    max_output_bytes: usize,
+    /// Whether commands are refused unless their working directory canonicalizes to a path
+    /// inside the projects root, from `PIO_SANDBOX` (default `true`).
+    sandbox: bool,
+    /// Executes the PlatformIO commands; `ProcessRunner` unless replaced with `with_runner`.
+    runner: Arc<dyn PlatformIORunner>,
+}
+
+impl Default for PlatformIOService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How `build_project` runs `platformio run`.
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    /// Run `--target checkprogsize` instead of a full build.
+    pub dry_run: bool,
+    /// Pass `-v` for full compiler command lines.
+    pub verbose: bool,
+    /// Extra compiler flags such as `-DDEBUG=1` (see `build_args`).
+    pub build_flags: Vec<String>,
+    /// Environment variables set on the PlatformIO process, e.g. for `${sysenv.WIFI_SSID}`.
+    /// Their values are masked in the returned output and the build log.
+    pub env_vars: HashMap<String, String>,
+    /// Output returned (on success or in the error) beyond this many bytes is truncated;
+    /// `None` uses the service's `MAX_OUTPUT_BYTES`.
+    pub max_output_bytes: Option<usize>,
+    /// `platformio.ini` environment to build (`-e`); every environment when `None`.
+    pub environment: Option<String>,
+    /// Return output with PlatformIO's ANSI color codes left in (see `strip_ansi`).
+    pub keep_ansi: bool,
+}
+
+/// How `init_project` treats a project that already has files.
+#[derive(Debug, Clone, Default)]
+pub struct InitOptions {
+    /// Replace project files that the template directory also has.
+    pub overwrite: bool,
+    /// Run init even when `platformio.ini` already has an environment for the board.
+    pub force: bool,
+}
+
+/// How firmware reaches the board, passed to PlatformIO as the `upload_protocol` project option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadProtocol {
+    /// Serial upload through esptool; the port is a serial device and may be auto-detected.
+    Esptool,
+    /// Over-the-air upload; the port is required and is the board's IP address or hostname.
+    Espota,
+    /// JTAG upload through an ESP-Prog probe; no port is used.
+    EspProg,
+}
+
+impl UploadProtocol {
+    /// Protocol names accepted by `parse`.
+    pub const SUPPORTED: &'static [&'static str] = &["esptool", "espota", "esp-prog"];
+
+    /// Parses a PlatformIO protocol name, failing with `ServiceError::InvalidInput` for
+    /// anything outside `SUPPORTED`.
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "esptool" => Ok(Self::Esptool),
+            "espota" => Ok(Self::Espota),
+            "esp-prog" => Ok(Self::EspProg),
+            _ => Err(ServiceError::InvalidInput(format!(
+                "Unsupported upload protocol '{}'; supported: {}",
+                name,
+                Self::SUPPORTED.join(", ")
+            ))
+            .into()),
+        }
+    }
+
+    /// PlatformIO's name for the protocol.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Esptool => "esptool",
+            Self::Espota => "espota",
+            Self::EspProg => "esp-prog",
+        }
+    }
+}
+
+/// Output of a successful build along with the firmware size PlatformIO reported and the
+/// compiler warnings found in it.
+#[derive(Debug, Clone)]
+pub struct BuildOutput {
+    pub output: String,
+    pub firmware_size: Option<FirmwareSizeInfo>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// A PlatformIO command as it would be run: the full argv starting with the binary, the
+/// directory it runs in, and the names of the environment variables set on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedCommand {
+    pub argv: Vec<String>,
+    pub working_dir: PathBuf,
+    pub env_vars: Vec<String>,
+}
+
+/// One phase of a deploy: its result and how long it ran.
+#[derive(Debug)]
+pub struct DeployPhase<T> {
+    pub result: Result<T>,
+    pub duration: Duration,
+}
+
+/// Outcome of `PlatformIOService::wait_for_build`.
+#[derive(Debug, Clone)]
+pub enum BuildWait {
+    /// No build was running for the project.
+    Idle,
+    /// The build finished with this output or error message.
+    Finished(Result<BuildOutput, String>),
+    /// The build was still running when the timeout elapsed.
+    TimedOut,
+}
+
+/// Outcome of `PlatformIOService::deploy`. `upload` is `None` when the build failed, since the
+/// upload is then skipped.
+#[derive(Debug)]
+pub struct DeployOutput {
+    pub build: DeployPhase<BuildOutput>,
+    pub upload: Option<DeployPhase<Vec<u8>>>,
+}
+
+/// Raw output of `project init` along with what it set up.
+#[derive(Debug, Clone)]
+pub struct InitOutput {
+    pub output: String,
+    pub result: InitResult,
+}
+
+/// One item of a streamed PlatformIO command: an output line, then a final `Exit`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// A line of combined stdout/stderr output.
+    Line(String),
+    /// The process finished; `success` reflects its exit status.
+    Exit { success: bool },
+}
+
+/// Removes a build's kill handle from the tracking map when the build finishes or is dropped,
+/// then wakes `wait_for_build` callers.
+struct BuildGuard {
+    running_builds: RunningBuilds,
+    finished_builds: FinishedBuilds,
+    project_path: String,
+    id: u64,
+}
+
+impl BuildGuard {
+    /// Publishes the build's result to `wait_for_build` callers. Only the first call per build
+    /// counts; dropping the guard publishes `None` if this was never called.
+    fn finish(&self, result: Option<Result<BuildOutput, String>>) {
+        let mut finished = self.finished_builds.lock().unwrap();
+        finished
+            .entry(self.project_path.clone())
+            .or_insert_with(|| watch::channel(None).0)
+            .send_if_modified(|latest| {
+                if matches!(latest, Some((id, _)) if *id == self.id) {
+                    return false;
+                }
+                *latest = Some((self.id, result));
+                true
+            });
+    }
+
+    /// Publishes a build result, keeping only the error message of a failure.
+    fn finish_build(&self, result: &Result<BuildOutput>) {
+        self.finish(Some(match result {
+            Ok(build) => Ok(build.clone()),
+            Err(e) => Err(e.to_string()),
+        }));
+    }
+}
+
+impl Drop for BuildGuard {
+    fn drop(&mut self) {
+        {
+            let mut builds = self.running_builds.lock().unwrap();
+            if matches!(builds.get(&self.project_path), Some((id, _)) if *id == self.id) {
+                builds.remove(&self.project_path);
+            }
+        }
+        self.finish(None);
+    }
+}
+
+/// Releases a project's upload lock when the upload or erase finishes or is dropped.
+struct UploadGuard {
+    running_uploads: RunningUploads,
+    project_path: String,
+}
+
+impl Drop for UploadGuard {
+    fn drop(&mut self) {
+        self.running_uploads
+            .lock()
+            .unwrap()
+            .remove(&self.project_path);
+    }
+}
+
+impl PlatformIOService {
+    /// Constructor using the binary named by the `PLATFORMIO_BIN` env var (default `"platformio"`),
+    /// keeping the last `BUILD_LOG_LINES` lines (default 500, at least 1) of each project's
+    /// build log, and resolving project paths under `PROJECTS_ROOT` (default `./projects`). At most
+    /// `PIO_MAX_CONCURRENT_BUILDS` builds and `PIO_MAX_CONCURRENT_UPLOADS` uploads, erases and
+    /// flash reads run at once; each defaults to `PIO_MAX_CONCURRENT`, or else the number of
+    /// CPUs. Set the upload limit to 1 when boards share a USB hub.
+    /// `DEFAULT_BOARD` (unset by default) is the board for init requests that don't name one.
+    /// `TEMPLATE_DIR` (unset by default) is copied into every project on init. Command output
+    /// is truncated after `MAX_OUTPUT_BYTES` bytes (default 256 KiB). `PIO_SANDBOX=false` turns
+    /// off the check that commands run inside the projects root (see `with_sandbox`).
+    pub fn new() -> Self {
+        let binary = std::env::var("PLATFORMIO_BIN").unwrap_or_else(|_| DEFAULT_BINARY.to_string());
+        let mut service = Self::with_binary(binary);
+        if let Ok(root) = std::env::var("PROJECTS_ROOT") {
+            service.projects_root = PathBuf::from(root);
+        }
+        if let Some(lines) = std::env::var("BUILD_LOG_LINES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            service = service.with_build_log_lines(lines);
+        }
+        if let Some(bytes) = std::env::var("MAX_OUTPUT_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            service.max_output_bytes = bytes;
+        }
+        let permits = |var: &str| std::env::var(var).ok().and_then(|v| v.parse().ok());
+        if let Some(permits) = permits("PIO_MAX_CONCURRENT") {
+            service = service.with_max_concurrent(permits);
+        }
+        if let Some(permits) = permits("PIO_MAX_CONCURRENT_BUILDS") {
+            service = service.with_max_concurrent_builds(permits);
+        }
+        if let Some(permits) = permits("PIO_MAX_CONCURRENT_UPLOADS") {
+            service = service.with_max_concurrent_uploads(permits);
+        }
+        if let Ok(board) = std::env::var("DEFAULT_BOARD") {
+            service = service.with_default_board(board);
+        }
+        if let Ok(dir) = std::env::var("TEMPLATE_DIR") {
+            service = service.with_template_dir(dir);
+        }
+        if let Some(enabled) = std::env::var("PIO_SANDBOX")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            service = service.with_sandbox(enabled);
+        }
+        service
+    }
+
+    /// Constructor using an explicit PlatformIO binary name or path.
+    pub fn with_binary(binary: impl Into<String>) -> Self {
+        Self {
+            running_builds: RunningBuilds::default(),
+            finished_builds: FinishedBuilds::default(),
+            running_uploads: RunningUploads::default(),
+            configured_binary: binary.into(),
+            resolved_binary: Arc::new(OnceLock::new()),
+            version: Arc::new(OnceLock::new()),
+            system_info: Arc::default(),
+            build_logs: BuildLogs::default(),
+            build_log_lines: DEFAULT_BUILD_LOG_LINES,
+            projects_root: PathBuf::from(DEFAULT_PROJECTS_ROOT),
+            build_slots: Arc::new(Semaphore::new(default_max_concurrent())),
+            upload_slots: Arc::new(Semaphore::new(default_max_concurrent())),
+            queued: Arc::default(),
+            default_board: None,
+            template_dir: None,
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+            sandbox: true,
+            runner: Arc::new(ProcessRunner),
+        }
+    }
+
+    /// Replaces the runner executing PlatformIO commands, e.g. with a `MockPlatformIORunner`.
+    pub fn with_runner(mut self, runner: Arc<dyn PlatformIORunner>) -> Self {
+        self.runner = runner;
+        self
+    }
+
+    /// Sets the board used when an init request doesn't name one.
+    pub fn with_default_board(mut self, board: impl Into<String>) -> Self {
+        self.default_board = Some(board.into());
+        self
+    }
+
+    /// Enables or disables the working-directory sandbox. When enabled (the default), every
+    /// command's directory is canonicalized right before it runs and the command is refused
+    /// with `ServiceError::SandboxViolation` if it resolves outside the projects root, which
+    /// catches symlinks swapped in after the project path was validated.
+    pub fn with_sandbox(mut self, enabled: bool) -> Self {
+        self.sandbox = enabled;
+        self
+    }
+
+    /// Sets the directory copied into every project on init.
+    pub fn with_template_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.template_dir = Some(dir.into());
+        self
+    }
+
+    /// Directory copied into every project on init, if configured.
+    pub fn template_dir(&self) -> Option<&Path> {
+        self.template_dir.as_deref()
+    }
+
+    /// Picks the requested board, falling back to the default board. Fails with
+    /// `ServiceError::InvalidInput` if neither is set.
+    pub fn resolve_board(&self, requested: Option<&str>) -> Result<String> {
+        requested
+            .or(self.default_board.as_deref())
+            .map(str::to_string)
+            .ok_or_else(|| {
+                ServiceError::InvalidInput(
+                    "No board given and DEFAULT_BOARD is not set".to_string(),
+                )
+                .into()
+            })
+    }
+
+    /// Replaces both the build and the upload limit with `permits` (at least one each).
+    pub fn with_max_concurrent(self, permits: usize) -> Self {
+        self.with_max_concurrent_builds(permits)
+            .with_max_concurrent_uploads(permits)
+    }
+
+    /// Replaces the number of builds allowed to run at once (at least one).
+    pub fn with_max_concurrent_builds(mut self, permits: usize) -> Self {
+        self.build_slots = Arc::new(Semaphore::new(permits.max(1)));
+        self
+    }
+
+    /// Replaces the number of uploads, erases and flash reads allowed to run at once (at least
+    /// one).
+    pub fn with_max_concurrent_uploads(mut self, permits: usize) -> Self {
+        self.upload_slots = Arc::new(Semaphore::new(permits.max(1)));
+        self
+    }
+
+    /// Number of build/upload operations waiting for a free process slot.
+    pub fn queued_operations(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// Waits for a free build slot; the slot is released when the permit drops.
+    async fn acquire_build_slot(&self) -> Result<OwnedSemaphorePermit> {
+        self.acquire_slot(&self.build_slots).await
+    }
+
+    /// Waits for a free upload slot; the slot is released when the permit drops.
+    async fn acquire_upload_slot(&self) -> Result<OwnedSemaphorePermit> {
+        self.acquire_slot(&self.upload_slots).await
+    }
+
+    /// Waits for a permit of `slots`, counting the wait in `queued_operations` until the permit
+    /// is granted or the wait is dropped.
+    async fn acquire_slot(&self, slots: &Arc<Semaphore>) -> Result<OwnedSemaphorePermit> {
+        struct Waiting<'a>(&'a AtomicUsize);
+        impl Drop for Waiting<'_> {
+            fn drop(&mut self) {
+                self.0.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        let _waiting = Waiting(&self.queued);
+        slots
+            .clone()
+            .acquire_owned()
             .await
-            .map_err(|e| anyhow!("Failed to write main.cpp: {}", e))?;
+            .map_err(|e| anyhow!("Process slots are unavailable: {}", e))
+    }
+
+    /// Replaces the number of lines kept per build log (at least one).
+    pub fn with_build_log_lines(mut self, lines: usize) -> Self {
+        self.build_log_lines = lines.max(1);
+        self
+    }
+
+    /// Replaces the number of output bytes returned before it is truncated.
+    pub fn with_max_output_bytes(mut self, bytes: usize) -> Self {
+        self.max_output_bytes = bytes;
+        self
+    }
+
+    /// Replaces the projects root that device project paths are resolved against.
+    pub fn with_projects_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.projects_root = root.into();
+        self
+    }
+
+    /// The configured projects root, as given (not canonicalized).
+    pub fn projects_root(&self) -> &Path {
+        &self.projects_root
+    }
 
+    /// Checks that projects can be created under the projects root: creates the root if it is
+    /// missing, then writes and removes a probe file in it.
+    pub async fn check_projects_root(&self) -> Result<()> {
+        let root = &self.projects_root;
+        tokio::fs::create_dir_all(root)
+            .await
+            .map_err(|e| anyhow!("cannot create projects root {}: {}", root.display(), e))?;
+        let probe = root.join(format!(".write-check-{}", uuid::Uuid::new_v4()));
+        tokio::fs::write(&probe, b"")
+            .await
+            .map_err(|e| anyhow!("projects root {} is not writable: {}", root.display(), e))?;
+        let _ = tokio::fs::remove_file(&probe).await;
         Ok(())
     }
 
-    /// Run a PlatformIO command and return the output
-    /// Helper to execute a PlatformIO command and capture output.
-    async fn run_pio_command(&self, project_path: &str, args: &[&str]) -> Result<String> {
-        // Check if platformio is installed
-        self.check_pio_installed().await?;
+    /// Resolves a device's `project_path` against the projects root, creating the root if needed.
+    /// Relative paths are joined onto the root; absolute paths are accepted only if they lie inside
+    /// it. Anything that escapes the root after canonicalization (`..`, symlinks) fails with
+    /// `ServiceError::InvalidInput`. The project directory itself need not exist yet.
+    pub async fn resolve_project_path(&self, project_path: &str) -> Result<PathBuf> {
+        tokio::fs::create_dir_all(&self.projects_root)
+            .await
+            .map_err(|e| anyhow!("Failed to create projects root: {}", e))?;
+        let root = tokio::fs::canonicalize(&self.projects_root)
+            .await
+            .map_err(|e| anyhow!("Projects root is not accessible: {}", e))?;
+        confine_project_path(&root, project_path).await
+    }
+
+    /// Checks that `project_path` resolves inside the projects root like `resolve_project_path`
+    /// does, but without creating anything. While the root doesn't exist yet, its absolute path
+    /// stands in for the canonical one.
+    pub async fn check_project_path(&self, project_path: &str) -> Result<()> {
+        let root = match tokio::fs::canonicalize(&self.projects_root).await {
+            Ok(root) => root,
+            Err(_) => std::path::absolute(&self.projects_root)
+                .map_err(|e| anyhow!("Projects root is not accessible: {}", e))?,
+        };
+        confine_project_path(&root, project_path).await.map(|_| ())
+    }
+
+    /// The PlatformIO binary commands are run with.
+    pub fn binary(&self) -> &str {
+        self.resolved_binary
+            .get()
+            .map(String::as_str)
+            .unwrap_or(&self.configured_binary)
+    }
+
+    /// Build the PlatformIO project for a device
+    /// Builds the PlatformIO project at the given path. The build can be stopped with
+    /// `cancel_build`, in which case this returns `ServiceError::Cancelled`. A project without a
+    /// `platformio.ini` fails with `ServiceError::NotInitialized` before PlatformIO is run.
+    ///
+    /// With `dry_run` this runs `platformio run --target checkprogsize` instead of `platformio run`:
+    /// sources are compiled and linked to verify the configuration and that the program fits the
+    /// board, but no firmware image is generated.
+    ///
+    /// With `verbose`, `-v` is passed for full compiler command lines. That output can be large;
+    /// it is returned up to `max_output_bytes` (see `truncate_output`), while the stored build log
+    /// keeps only the last `BUILD_LOG_LINES`. The firmware size is parsed before truncation.
+    ///
+    /// Unsafe `build_flags` fail with `ServiceError::InvalidInput` before anything runs, as do
+    /// `env_vars` names that aren't valid variable names.
+    pub async fn build_project(
+        &self,
+        project_path: &str,
+        options: &BuildOptions,
+    ) -> Result<BuildOutput> {
+        let args = checked_build_args(options)?;
+        let project_dir = self.resolve_built_project(project_path).await?;
+        let (guard, cancel) = self.track_build(project_path)?;
+        let result = self
+            .run_build(&project_dir, project_path, &args, options, cancel)
+            .await;
+        guard.finish_build(&result);
+        result
+    }
+
+    /// Returns the command `build_project` would run with `options`, without running anything.
+    /// Invalid options fail the same way as in `build_project`; the project needn't be
+    /// initialized. Environment variable values are left out, as they may be secrets.
+    pub async fn build_command(
+        &self,
+        project_path: &str,
+        options: &BuildOptions,
+    ) -> Result<PlannedCommand> {
+        let args = checked_build_args(options)?;
+        let mut env_vars: Vec<String> = options.env_vars.keys().cloned().collect();
+        env_vars.sort();
+        self.planned_command(project_path, args, env_vars).await
+    }
+
+    /// Returns the command `upload_firmware` would run, without running anything. Fails like
+    /// `upload_firmware` for a missing espota port or an invalid environment.
+    pub async fn upload_command(
+        &self,
+        project_path: &str,
+        port: Option<&str>,
+        protocol: Option<UploadProtocol>,
+        environment: Option<&str>,
+    ) -> Result<PlannedCommand> {
+        let args = upload_args(port, protocol, environment)?;
+        self.planned_command(project_path, args, Vec::new()).await
+    }
+
+    /// Prefixes `args` with the binary and resolves the directory they'd run in.
+    async fn planned_command(
+        &self,
+        project_path: &str,
+        args: Vec<String>,
+        env_vars: Vec<String>,
+    ) -> Result<PlannedCommand> {
+        let working_dir = self.resolve_project_path(project_path).await?;
+        let mut argv = vec![self.binary().to_string()];
+        argv.extend(args);
+        Ok(PlannedCommand {
+            argv,
+            working_dir,
+            env_vars,
+        })
+    }
+
+    /// Waits up to `timeout` for the project's running build to finish and returns its result.
+    /// Returns `BuildWait::Idle` right away if no build is running, and also when the build
+    /// lock was held by an operation that doesn't build (such as `purge_project`).
+    pub async fn wait_for_build(&self, project_path: &str, timeout: Duration) -> BuildWait {
+        let Some(id) = self
+            .running_builds
+            .lock()
+            .unwrap()
+            .get(project_path)
+            .map(|(id, _)| *id)
+        else {
+            return BuildWait::Idle;
+        };
+        // The guard publishes before this sender could be dropped, and `wait_for` checks the
+        // current value first, so a build finishing right now isn't missed.
+        let mut finished = self
+            .finished_builds
+            .lock()
+            .unwrap()
+            .entry(project_path.to_string())
+            .or_insert_with(|| watch::channel(None).0)
+            .subscribe();
+        let waited = tokio::time::timeout(
+            timeout,
+            finished.wait_for(|latest| matches!(latest, Some((done, _)) if *done == id)),
+        )
+        .await;
+        match waited {
+            Err(_) => BuildWait::TimedOut,
+            Ok(Ok(latest)) => match latest.clone() {
+                Some((_, Some(result))) => BuildWait::Finished(result),
+                _ => BuildWait::Idle,
+            },
+            Ok(Err(_)) => BuildWait::Idle,
+        }
+    }
+
+    /// Builds the project and, only if that succeeded, uploads the firmware, like `build_project`
+    /// followed by `upload_firmware`. Both the build and the upload lock are held for the whole
+    /// sequence, so no other build, upload or erase of the project can run in between; either
+    /// being taken fails with `ServiceError::Conflict` before anything runs. Invalid options and
+    /// uninitialized projects fail the same way as in `build_project`. Failures of the phases
+    /// themselves are reported in the `DeployOutput`.
+    pub async fn deploy(
+        &self,
+        project_path: &str,
+        options: &BuildOptions,
+        port: Option<&str>,
+        protocol: Option<UploadProtocol>,
+    ) -> Result<DeployOutput> {
+        let build_args = checked_build_args(options)?;
+        let upload_args = upload_args(port, protocol, options.environment.as_deref())?;
+        let upload_args: Vec<&str> = upload_args.iter().map(String::as_str).collect();
+        let project_dir = self.resolve_built_project(project_path).await?;
+        let (build_guard, cancel) = self.track_build(project_path)?;
+        let _upload = self.track_upload(project_path)?;
+
+        let started = Instant::now();
+        let result = self
+            .run_build(&project_dir, project_path, &build_args, options, cancel)
+            .await;
+        build_guard.finish_build(&result);
+        let build = DeployPhase {
+            result,
+            duration: started.elapsed(),
+        };
+        if build.result.is_err() {
+            return Ok(DeployOutput {
+                build,
+                upload: None,
+            });
+        }
+
+        let started = Instant::now();
+        let result = match self.acquire_upload_slot().await {
+            Ok(_slot) => self.run_pio_command_raw(&project_dir, &upload_args).await,
+            Err(e) => Err(e),
+        };
+        Ok(DeployOutput {
+            build,
+            upload: Some(DeployPhase {
+                result,
+                duration: started.elapsed(),
+            }),
+        })
+    }
+
+    /// Resolves a project to build, failing with `ServiceError::NotInitialized` if it has no
+    /// platformio.ini.
+    async fn resolve_built_project(&self, project_path: &str) -> Result<PathBuf> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        if self.ensure_pio_project(&project_dir).await.is_err() {
+            return Err(ServiceError::NotInitialized(format!(
+                "project '{}' is not initialized (no platformio.ini); create it with POST /devices/{{id}}/scaffold",
+                project_path
+            ))
+            .into());
+        }
+        Ok(project_dir)
+    }
+
+    /// Runs a build whose lock the caller holds, in a build slot.
+    async fn run_build(
+        &self,
+        project_dir: &Path,
+        project_path: &str,
+        args: &[String],
+        options: &BuildOptions,
+        cancel: oneshot::Receiver<()>,
+    ) -> Result<BuildOutput> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let _slot = self.acquire_build_slot().await?;
+        let max_output = options.max_output_bytes.unwrap_or(self.max_output_bytes);
+        let output = self
+            .run_pio_command_with_cancel(
+                project_dir,
+                &args,
+                &options.env_vars,
+                Some(cancel),
+                Some(project_path),
+                max_output,
+                options.keep_ansi,
+            )
+            .await?;
+        let firmware_size = parse_firmware_size(&strip_ansi(&output));
+        let diagnostics = parse_diagnostics(&output);
+        Ok(BuildOutput {
+            output: truncate_output(output, max_output),
+            firmware_size,
+            diagnostics,
+        })
+    }
+
+    /// Returns up to the last `lines` lines of the project's most recent build output,
+    /// or `None` if no build has run for it since the server started.
+    pub fn build_log(&self, project_path: &str, lines: usize) -> Option<Vec<String>> {
+        let logs = self.build_logs.lock().unwrap();
+        let log = logs.get(project_path)?;
+        Some(
+            log.iter()
+                .skip(log.len().saturating_sub(lines))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Replaces the project's build log with the last `build_log_lines` lines of `output`.
+    fn record_build_log(&self, project_path: &str, output: &str) {
+        let mut log: VecDeque<String> = VecDeque::with_capacity(self.build_log_lines);
+        for line in output.lines() {
+            if log.len() == self.build_log_lines {
+                log.pop_front();
+            }
+            log.push_back(line.to_string());
+        }
+        self.build_logs
+            .lock()
+            .unwrap()
+            .insert(project_path.to_string(), log);
+    }
 
-        // Change to project directory and run command
-        let mut cmd = Command::new("platformio");
-        cmd.args(args)
-            .current_dir(project_path)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+    /// Kills the build currently running for the project, if any.
+    /// Returns whether a build was cancelled.
+    pub fn cancel_build(&self, project_path: &str) -> bool {
+        let handle = self.running_builds.lock().unwrap().remove(project_path);
+        match handle {
+            Some((_, kill)) => kill.send(()).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Registers a kill handle for a new build, rejecting a second concurrent build of the same project.
+    fn track_build(&self, project_path: &str) -> Result<(BuildGuard, oneshot::Receiver<()>)> {
+        let mut builds = self.running_builds.lock().unwrap();
+        if builds.contains_key(project_path) {
+            return Err(ServiceError::Conflict(
+                "A build is already running for this project".to_string(),
+            )
+            .into());
+        }
+        let id = NEXT_BUILD_ID.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        builds.insert(project_path.to_string(), (id, tx));
+        let guard = BuildGuard {
+            running_builds: self.running_builds.clone(),
+            finished_builds: self.finished_builds.clone(),
+            project_path: project_path.to_string(),
+            id,
+        };
+        Ok((guard, rx))
+    }
+
+    /// Takes the project's upload lock, failing with `ServiceError::Conflict` if an upload or
+    /// erase is already running for it, so a board is never flashed by two processes at once.
+    fn track_upload(&self, project_path: &str) -> Result<UploadGuard> {
+        if !self
+            .running_uploads
+            .lock()
+            .unwrap()
+            .insert(project_path.to_string())
+        {
+            return Err(ServiceError::Conflict(
+                "An upload is already running for this project".to_string(),
+            )
+            .into());
+        }
+        Ok(UploadGuard {
+            running_uploads: self.running_uploads.clone(),
+            project_path: project_path.to_string(),
+        })
+    }
 
-        let output = cmd
-            .output()
+    /// Upload firmware to ESP32 device
+    /// Without `protocol` the project's configured protocol (serial esptool by default) is used;
+    /// see `upload_args` for how each protocol treats `port`. `environment` selects the
+    /// `platformio.ini` environment to upload (`-e`). Fails with `ServiceError::Conflict`
+    /// while another upload or erase of the same project is running. Returns the exact output
+    /// bytes (see `run_pio_command_raw`).
+    pub async fn upload_firmware(
+        &self,
+        project_path: &str,
+        port: Option<&str>,
+        protocol: Option<UploadProtocol>,
+        environment: Option<&str>,
+    ) -> Result<Vec<u8>> {
+        let args = upload_args(port, protocol, environment)?;
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let project_dir = self.resolve_project_path(project_path).await?;
+        self.ensure_pio_project(&project_dir).await?;
+        let _upload = self.track_upload(project_path)?;
+        let _slot = self.acquire_upload_slot().await?;
+        self.run_pio_command_raw(&project_dir, &args).await
+    }
+
+    /// Uploads firmware like `upload_firmware`, but streams the output line by line as it is produced.
+    pub async fn upload_firmware_stream(
+        &self,
+        project_path: &str,
+        port: Option<&str>,
+        environment: Option<&str>,
+    ) -> Result<mpsc::Receiver<StreamEvent>> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        self.ensure_pio_project(&project_dir).await?;
+        let args = upload_args(port, None, environment)?;
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let upload = self.track_upload(project_path)?;
+        let slot = self.acquire_upload_slot().await?;
+        self.stream_pio_command(&project_dir, &args, (upload, slot))
             .await
-            .map_err(|e| anyhow!("Failed to execute platformio command: {}", e))?;
+    }
+
+    /// Runs the project's unit tests via `platformio test`, streaming the output line by line as
+    /// `upload_firmware_stream` does; `parse_test_summary` extracts the results. `port` is used
+    /// both to upload the test firmware and to read its results; `environment` selects the
+    /// `platformio.ini` environment to test (`-e`). Tests compile and upload firmware, so the
+    /// project's build and upload locks and a build slot are held until they finish.
+    pub async fn test_project_stream(
+        &self,
+        project_path: &str,
+        port: Option<&str>,
+        environment: Option<&str>,
+    ) -> Result<mpsc::Receiver<StreamEvent>> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        self.ensure_pio_project(&project_dir).await?;
+        let mut args = vec!["test".to_string()];
+        if let Some(env) = environment {
+            args.extend(environment_args(env)?);
+        }
+        if let Some(port) = port {
+            for flag in ["--upload-port", "--test-port"] {
+                args.extend([flag.to_string(), port.to_string()]);
+            }
+        }
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let (build, _cancel) = self.track_build(project_path)?;
+        let upload = self.track_upload(project_path)?;
+        let slot = self.acquire_build_slot().await?;
+        self.stream_pio_command(&project_dir, &args, (build, upload, slot))
+            .await
+    }
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
+    /// Erases the device's entire flash via `platformio run --target erase`. Shares the upload
+    /// lock, so it can't overlap an upload of the same project. Returns the exact output bytes.
+    pub async fn erase_flash(&self, project_path: &str, port: Option<&str>) -> Result<Vec<u8>> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        self.ensure_pio_project(&project_dir).await?;
+        let _upload = self.track_upload(project_path)?;
+        let mut args = vec!["run", "--target", "erase"];
+        if let Some(p) = port {
+            args.extend_from_slice(&["--upload-port", p]);
+        }
+        let _slot = self.acquire_upload_slot().await?;
+        self.run_pio_command_raw(&project_dir, &args).await
+    }
 
-        if output.status.success() {
-            Ok(format!("{}{}", stdout, stderr))
-        } else {
-            Err(anyhow!("PlatformIO command failed: {}\n{}", stdout, stderr))
+    /// Uploads the project's filesystem image (its `data/` directory, as SPIFFS or LittleFS per
+    /// `board_build.filesystem`) via `platformio run --target uploadfs`. Shares the upload lock
+    /// and slots with firmware uploads. Returns the exact output bytes.
+    pub async fn upload_fs(&self, project_path: &str, port: Option<&str>) -> Result<Vec<u8>> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        self.ensure_pio_project(&project_dir).await?;
+        let _upload = self.track_upload(project_path)?;
+        let mut args = vec!["run", "--target", "uploadfs"];
+        if let Some(p) = port {
+            args.extend_from_slice(&["--upload-port", p]);
         }
+        let _slot = self.acquire_upload_slot().await?;
+        self.run_pio_command_raw(&project_dir, &args).await
     }
 
-    /// Check if PlatformIO is installed
-    /// Verifies PlatformIO is installed by running `platformio --version`.
-    async fn check_pio_installed(&self) -> Result<()> {
-        let output = Command::new("platformio")
-            .arg("--version")
-            .output()
+    /// Resets the board on `port` by toggling the serial control lines the way esptool does:
+    /// DTR is released, then RTS (wired to EN on ESP32 dev boards) is pulsed to hold the chip in
+    /// reset for 100ms. A port that can't be opened fails with `ServiceError::InvalidInput`.
+    pub async fn reset_device(&self, port: &str) -> Result<String> {
+        let port = port.to_string();
+        tokio::task::spawn_blocking(move || {
+            let mut serial = serialport::new(&port, 115_200)
+                .timeout(Duration::from_millis(500))
+                .open()
+                .map_err(|e| {
+                    ServiceError::InvalidInput(format!(
+                        "Failed to open serial port {}: {}",
+                        port, e
+                    ))
+                })?;
+            serial.write_data_terminal_ready(false)?;
+            serial.write_request_to_send(true)?;
+            std::thread::sleep(Duration::from_millis(100));
+            serial.write_request_to_send(false)?;
+            Ok(format!("Reset device on {}", port))
+        })
+        .await
+        .map_err(|e| anyhow!("Reset task failed: {}", e))?
+    }
+
+    /// Clean the PlatformIO project
+    /// Cleans build files in the PlatformIO project.
+    pub async fn clean_project(&self, project_path: &str) -> Result<String> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        self.ensure_pio_project(&project_dir).await?;
+        self.run_pio_command(&project_dir, &["run", "--target", "clean"])
             .await
-            .map_err(|e| anyhow!("PlatformIO not found. Please install PlatformIO: {}", e))?;
+    }
 
-        if output.status.success() {
-            Ok(())
-        } else {
-            Err(anyhow!("PlatformIO installation check failed"))
+    /// Deletes the project's whole `.pio` directory (build output and downloaded libraries), for
+    /// when it is corrupt beyond what `clean_project` fixes. Returns the bytes reclaimed; 0 if
+    /// there was no `.pio`. Fails with `ServiceError::Conflict` while the project is building or
+    /// uploading, and with `ServiceError::InvalidInput` if `.pio` resolves outside the projects
+    /// root.
+    pub async fn purge_build_dir(&self, project_path: &str) -> Result<u64> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        let (_build, _) = self.track_build(project_path)?;
+        let _upload = self.track_upload(project_path)?;
+        let build_dir = match tokio::fs::canonicalize(project_dir.join(".pio")).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(anyhow!("Failed to resolve .pio directory: {}", e)),
+        };
+        let root = tokio::fs::canonicalize(&self.projects_root)
+            .await
+            .map_err(|e| anyhow!("Projects root is not accessible: {}", e))?;
+        if !build_dir.starts_with(&root) || build_dir == root {
+            return Err(ServiceError::InvalidInput(
+                ".pio directory resolves outside the projects root".to_string(),
+            )
+            .into());
         }
+        let reclaimed = dir_size(&build_dir).await;
+        tokio::fs::remove_dir_all(&build_dir)
+            .await
+            .map_err(|e| anyhow!("Failed to remove .pio directory: {}", e))?;
+        Ok(reclaimed)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Sums the sizes of the regular files in the project directory, separately counting its
+    /// `.pio` build directory. Symlinks aren't followed. Fails with `ServiceError::NotFound` if
+    /// the project directory doesn't exist.
+    pub async fn project_size(&self, project_path: &str) -> Result<ProjectSize> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        if !tokio::fs::symlink_metadata(&project_dir)
+            .await
+            .map(|m| m.is_dir())
+            .unwrap_or(false)
+        {
+            return Err(ServiceError::NotFound(format!(
+                "project directory '{}' does not exist",
+                project_path
+            ))
+            .into());
+        }
+        let total_bytes = dir_size(&project_dir).await;
+        let build_dir = project_dir.join(".pio");
+        let build_bytes = match tokio::fs::symlink_metadata(&build_dir).await {
+            Ok(meta) if meta.is_dir() => dir_size(&build_dir).await,
+            _ => 0,
+        };
+        Ok(ProjectSize {
+            total_bytes,
+            build_bytes,
+            source_bytes: total_bytes - build_bytes,
+        })
+    }
 
-    /// Test for PlatformIO installation check (expects failure in test env).
-    #[tokio::test]
-    async fn test_pio_check() {
-        let service = PlatformIOService::new();
-        // This will fail if PlatformIO is not installed, which is expected in test environment
-        let _ = service.check_pio_installed().await;
+    /// Moves a project directory to `new_path` with `tokio::fs::rename`, creating the new parent
+    /// directories. Both paths must resolve under the projects root. Fails with
+    /// `ServiceError::NotFound` if the project directory doesn't exist, `ServiceError::Conflict`
+    /// if `new_path` already exists or the project is building or uploading, and
+    /// `ServiceError::InvalidInput` if `new_path` is the project itself or inside it.
+    pub async fn move_project(&self, project_path: &str, new_path: &str) -> Result<()> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        let new_dir = self.resolve_project_path(new_path).await?;
+        let (_build, _) = self.track_build(project_path)?;
+        let _upload = self.track_upload(project_path)?;
+        if new_dir.starts_with(&project_dir) {
+            return Err(ServiceError::InvalidInput(format!(
+                "'{}' is inside the project directory",
+                new_path
+            ))
+            .into());
+        }
+        if !tokio::fs::metadata(&project_dir)
+            .await
+            .is_ok_and(|m| m.is_dir())
+        {
+            return Err(ServiceError::NotFound(format!(
+                "Project directory '{}' does not exist",
+                project_path
+            ))
+            .into());
+        }
+        if tokio::fs::symlink_metadata(&new_dir).await.is_ok() {
+            return Err(ServiceError::Conflict(format!("'{}' already exists", new_path)).into());
+        }
+        if let Some(parent) = new_dir.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| anyhow!("Failed to create parent directory: {}", e))?;
+        }
+        tokio::fs::rename(&project_dir, &new_dir)
+            .await
+            .map_err(|e| anyhow!("Failed to move project directory: {}", e))
+    }
+
+    /// Lists the project's installed packages that have newer releases, via `pkg outdated`.
+    /// Returns an empty list when everything is up to date.
+    pub async fn check_updates(&self, project_path: &str) -> Result<Vec<PackageUpdate>> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        self.ensure_pio_project(&project_dir).await?;
+        let output = self
+            .run_pio_command(&project_dir, &["pkg", "outdated"])
+            .await?;
+        Ok(parse_outdated_packages(&output))
+    }
+
+    /// Lists the libraries installed in the project, via `lib list --json-output`.
+    /// Returns an empty list when none are installed.
+    pub async fn list_libraries(&self, project_path: &str) -> Result<Vec<LibraryInfo>> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        self.ensure_pio_project(&project_dir).await?;
+        let output = self
+            .run_pio_command(&project_dir, &["lib", "list", "--json-output"])
+            .await?;
+        parse_library_list(&output)
+    }
+
+    /// Runs `<subcommand> <args...>` in the project directory and returns its output. The
+    /// subcommand and its action must be in `ALLOWED_SUBCOMMANDS` and no argument may redirect
+    /// the project directory or config (see `subcommand_args`); violations fail with
+    /// `ServiceError::Forbidden` before anything runs. The directory must exist, but needn't
+    /// be initialized.
+    pub async fn run_subcommand(
+        &self,
+        project_path: &str,
+        subcommand: &str,
+        args: &[String],
+    ) -> Result<String> {
+        let args = subcommand_args(subcommand, args)?;
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let project_dir = self.resolve_project_path(project_path).await?;
+        if !tokio::fs::metadata(&project_dir)
+            .await
+            .map(|m| m.is_dir())
+            .unwrap_or(false)
+        {
+            return Err(ServiceError::NotFound(format!(
+                "project directory '{}' does not exist",
+                project_path
+            ))
+            .into());
+        }
+        self.run_pio_command(&project_dir, &args).await
+    }
+
+    /// Lists the serial ports connected to the server, via `device list --serial --json-output`
+    /// run in the projects root.
+    pub async fn list_serial_ports(&self) -> Result<Vec<SerialPortInfo>> {
+        let root = self.resolve_project_path(".").await?;
+        let output = self
+            .run_pio_command(&root, &["device", "list", "--serial", "--json-output"])
+            .await?;
+        parse_serial_ports(&output)
+    }
+
+    /// Describes PlatformIO's environment on the server (core and Python versions, platform,
+    /// directories) via `system info --json-output` run in the projects root. The answer is
+    /// cached for a minute, since it only changes when the toolchain is reinstalled.
+    pub async fn system_info(&self) -> Result<SystemInfo> {
+        if let Some((fetched, info)) = self.system_info.lock().unwrap().as_ref() {
+            if fetched.elapsed() < SYSTEM_INFO_TTL {
+                return Ok(info.clone());
+            }
+        }
+        let root = self.resolve_project_path(".").await?;
+        let output = self
+            .run_pio_command(&root, &["system", "info", "--json-output"])
+            .await?;
+        let info = parse_system_info(&output)?;
+        *self.system_info.lock().unwrap() = Some((Instant::now(), info.clone()));
+        Ok(info)
+    }
+
+    /// Dumps the partition table of the board on `port`: esptool (run through PlatformIO's
+    /// `tool-esptoolpy` package) reads the table's flash region into a temp file, which is
+    /// decoded into CSV lines in the `gen_esp32part.py` format (see `format_partition_csv`). If
+    /// the region doesn't hold a valid table, esptool's own output is returned instead. Shares
+    /// the upload lock, as the serial port can't be used by an upload at the same time.
+    pub async fn read_partitions(&self, project_path: &str, port: &str) -> Result<String> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        self.ensure_pio_project(&project_dir).await?;
+        let _upload = self.track_upload(project_path)?;
+        let dump = std::env::temp_dir().join(format!("partitions-{}.bin", uuid::Uuid::new_v4()));
+        let dump_arg = dump.to_string_lossy().into_owned();
+        let offset = format!("{:#x}", PARTITION_TABLE_OFFSET);
+        let length = format!("{:#x}", PARTITION_TABLE_LEN);
+        let args = [
+            "pkg",
+            "exec",
+            "--package",
+            "tool-esptoolpy",
+            "--",
+            "esptool.py",
+            "--port",
+            port,
+            "read_flash",
+            &offset,
+            &length,
+            &dump_arg,
+        ];
+        let _slot = self.acquire_upload_slot().await?;
+        let output = self.run_pio_command(&project_dir, &args).await;
+        let table = tokio::fs::read(&dump).await;
+        let _ = tokio::fs::remove_file(&dump).await;
+        let output = output?;
+        match table.ok().and_then(|bytes| decode_partition_table(&bytes)) {
+            Some(entries) => Ok(format_partition_csv(&entries)),
+            None => Ok(output),
+        }
+    }
+
+    /// Runs `git pull --ff-only` in the project directory and returns its output, so builds pick
+    /// up the latest pushed source. Fails with `ServiceError::InvalidInput` unless the directory is
+    /// the top level of a git work tree, so a repository enclosing the projects root is never
+    /// pulled. Credential prompts are disabled; a remote that needs interactive authentication,
+    /// or a pull that can't fast-forward, fails with git's error output, and a pull still running
+    /// after `GIT_TIMEOUT` is killed. Like a build, the pull takes the project's build and upload
+    /// locks (`ServiceError::Conflict` while either is held) and a build slot, so sources never
+    /// change under a running build or upload.
+    pub async fn git_pull(&self, project_path: &str) -> Result<String> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        let is_repo_root = match run_git(&project_dir, &["rev-parse", "--show-toplevel"]).await {
+            Ok(toplevel) => tokio::fs::canonicalize(toplevel.trim())
+                .await
+                .is_ok_and(|toplevel| toplevel == project_dir),
+            Err(_) => false,
+        };
+        if !is_repo_root {
+            return Err(ServiceError::InvalidInput(
+                "Project directory is not a git repository".to_string(),
+            )
+            .into());
+        }
+        let (_build, _) = self.track_build(project_path)?;
+        let _upload = self.track_upload(project_path)?;
+        let _slot = self.acquire_build_slot().await?;
+        run_git(&project_dir, &["pull", "--ff-only"]).await
+    }
+
+    /// Get project information
+    /// Retrieves PlatformIO project configuration info.
+    pub async fn get_project_info(&self, project_path: &str) -> Result<String> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        self.ensure_pio_project(&project_dir).await?;
+        self.run_pio_command(&project_dir, &["project", "config"])
+            .await
+    }
+
+    /// Reads a custom board JSON from `board_json_path`, which is resolved and confined like a
+    /// project path. Fails with `ServiceError::InvalidInput` if the file is missing, isn't valid
+    /// JSON or lacks a usable `id` (see `parse_board_definition`).
+    pub async fn load_board_definition(&self, board_json_path: &str) -> Result<BoardDefinition> {
+        let path = self.resolve_project_path(board_json_path).await?;
+        let json = tokio::fs::read_to_string(&path).await.map_err(|e| {
+            ServiceError::InvalidInput(format!(
+                "Failed to read board definition '{}': {}",
+                board_json_path, e
+            ))
+        })?;
+        parse_board_definition(json)
+    }
+
+    /// Initialize a new PlatformIO project
+    /// Initializes a new PlatformIO project for the given board. The result is parsed from the
+    /// command output and the generated `platformio.ini`; whatever can't be parsed falls back to
+    /// the requested board, no environment and no created files.
+    ///
+    /// With `custom_board`, its JSON is first written to `boards/<id>.json` in the project, where
+    /// PlatformIO looks for boards before its registry; `board` must then equal the definition's
+    /// `id` (`ServiceError::InvalidInput` otherwise). A custom id that matches a registry board
+    /// shadows the registry one for this project only.
+    ///
+    /// With a template directory configured, its contents are copied into the project before
+    /// `project init` runs, so a template `platformio.ini` keeps its presets and init only adds
+    /// the board's environment if it's missing. Files already in the project are kept unless
+    /// `options.overwrite` is set.
+    ///
+    /// Init is safe to retry: when the project's `platformio.ini` already has an environment for
+    /// `board`, nothing is written or run and the result is `already_initialized`.
+    /// `options.force` re-runs the whole init anyway.
+    pub async fn init_project(
+        &self,
+        project_path: &str,
+        board: &str,
+        custom_board: Option<&BoardDefinition>,
+        options: &InitOptions,
+    ) -> Result<InitOutput> {
+        if let Some(definition) = custom_board {
+            if definition.id != board {
+                return Err(ServiceError::InvalidInput(format!(
+                    "board '{}' doesn't match the custom board definition id '{}'",
+                    board, definition.id
+                ))
+                .into());
+            }
+        }
+
+        // Create directory under the projects root if it doesn't exist
+        let project_dir = self.resolve_project_path(project_path).await?;
+        if !options.force {
+            let ini = tokio::fs::read_to_string(project_dir.join("platformio.ini"))
+                .await
+                .ok();
+            if let Some(env_name) = ini.as_deref().and_then(|ini| board_env(ini, board)) {
+                return Ok(InitOutput {
+                    output: format!("Project is already initialized for board {}", board),
+                    result: InitResult {
+                        board: board.to_string(),
+                        env_name: Some(env_name),
+                        created_files: Vec::new(),
+                        template_files: Vec::new(),
+                        already_initialized: true,
+                    },
+                });
+            }
+        }
+        tokio::fs::create_dir_all(&project_dir)
+            .await
+            .map_err(|e| anyhow!("Failed to create project directory: {}", e))?;
+
+        if let Some(definition) = custom_board {
+            let boards_dir = project_dir.join("boards");
+            tokio::fs::create_dir_all(&boards_dir)
+                .await
+                .map_err(|e| anyhow!("Failed to create boards directory: {}", e))?;
+            tokio::fs::write(
+                boards_dir.join(format!("{}.json", definition.id)),
+                &definition.json,
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to write board definition: {}", e))?;
+        }
+
+        let template_files = match &self.template_dir {
+            Some(template) => copy_template(template, &project_dir, options.overwrite).await?,
+            None => Vec::new(),
+        };
+
+        let output = self
+            .run_pio_command(&project_dir, &["project", "init", "--board", board])
+            .await?;
+        let ini = tokio::fs::read_to_string(project_dir.join("platformio.ini"))
+            .await
+            .ok();
+        let result = InitResult {
+            template_files,
+            ..parse_init_result(&output, ini.as_deref(), board)
+        };
+        Ok(InitOutput { output, result })
+    }
+
+    /// Create a basic ESP32 main.cpp file
+    /// Generates `src/<filename>` (`src/main.cpp` when `None`) from the named template (`blink`
+    /// when `None`). The filename must stay within `src/` and end in `.cpp` or `.ino`.
+    pub async fn create_basic_main(
+        &self,
+        project_path: &str,
+        template: Option<&str>,
+        filename: Option<&str>,
+    ) -> Result<()> {
+        let main_cpp_content = basic_main_content(template)?;
+        let filename = filename.unwrap_or(DEFAULT_MAIN_FILENAME);
+        let has_main_extension = Path::new(filename)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| MAIN_EXTENSIONS.contains(&ext));
+        if !has_main_extension {
+            return Err(ServiceError::InvalidInput(format!(
+                "Main file '{}' must have a .cpp or .ino extension",
+                filename
+            ))
+            .into());
+        }
+        let project_dir = self.resolve_project_path(project_path).await?;
+        let main_path = resolve_source_path(&project_dir, filename)?;
+        if let Some(parent) = main_path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| anyhow!("Failed to create src directory: {}", e))?;
+        }
+
+        tokio::fs::write(&main_path, main_cpp_content)
+            .await
+            .map_err(|e| anyhow!("Failed to write {}: {}", filename, e))?;
+
+        Ok(())
+    }
+
+    /// Runs `init_project` then `create_basic_main` as one operation and returns the combined
+    /// output. If either step fails and the project directory didn't exist beforehand, the
+    /// directory is removed again so a retry starts from scratch.
+    pub async fn scaffold_project(
+        &self,
+        project_path: &str,
+        board: &str,
+        template: Option<&str>,
+    ) -> Result<String> {
+        basic_main_content(template)?;
+        let project_dir = self.resolve_project_path(project_path).await?;
+        let existed = tokio::fs::metadata(&project_dir).await.is_ok();
+
+        let result = async {
+            let output = self
+                .init_project(project_path, board, None, &InitOptions::default())
+                .await?
+                .output;
+            self.create_basic_main(project_path, template, None).await?;
+            Ok(format!(
+                "{}\nCreated src/main.cpp from '{}' template",
+                output,
+                template.unwrap_or(DEFAULT_MAIN_TEMPLATE)
+            ))
+        }
+        .await;
+
+        if result.is_err() && !existed {
+            let _ = tokio::fs::remove_dir_all(&project_dir).await;
+        }
+        result
+    }
+
+    /// Lists the files under the project's `src/` directory as paths relative to `src/`, sorted.
+    /// Hidden entries are skipped and recursion stops after `MAX_SOURCE_DEPTH` levels.
+    /// Returns an empty list if `src/` doesn't exist yet.
+    pub async fn list_source_files(&self, project_path: &str) -> Result<Vec<String>> {
+        let src_dir = self.resolve_project_path(project_path).await?.join("src");
+        let mut files = Vec::new();
+        if !tokio::fs::metadata(&src_dir)
+            .await
+            .map(|m| m.is_dir())
+            .unwrap_or(false)
+        {
+            return Ok(files);
+        }
+
+        let mut pending = vec![(src_dir.clone(), 0)];
+        while let Some((dir, depth)) = pending.pop() {
+            let mut entries = tokio::fs::read_dir(&dir)
+                .await
+                .map_err(|e| anyhow!("Failed to read directory {}: {}", dir.display(), e))?;
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+                let path = entry.path();
+                let file_type = entry.file_type().await?;
+                if file_type.is_dir() {
+                    if depth + 1 < MAX_SOURCE_DEPTH {
+                        pending.push((path, depth + 1));
+                    }
+                } else if file_type.is_file() {
+                    if let Ok(relative) = path.strip_prefix(&src_dir) {
+                        files.push(relative.to_string_lossy().into_owned());
+                    }
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Reads a source file, addressed relative to `src/` like the paths from `list_source_files`.
+    /// Fails with `ServiceError::NotFound` if the file doesn't exist.
+    pub async fn read_file(&self, project_path: &str, relative_path: &str) -> Result<Vec<u8>> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        let path = resolve_source_path(&project_dir, relative_path)?;
+        match tokio::fs::read(&path).await {
+            Ok(contents) => Ok(contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(ServiceError::NotFound(format!("File '{}' not found", relative_path)).into())
+            }
+            Err(e) => Err(anyhow!("Failed to read {}: {}", relative_path, e)),
+        }
+    }
+
+    /// Locates the firmware image of the last build, `.pio/build/<env>/firmware.bin`, returning
+    /// the environment and the path. Without `env` the project must have exactly one built
+    /// environment; with several it fails with `ServiceError::InvalidInput` naming them. Fails with
+    /// `ServiceError::NotFound` if nothing has been built.
+    pub async fn find_firmware(
+        &self,
+        project_path: &str,
+        env: Option<&str>,
+    ) -> Result<(String, PathBuf)> {
+        let build_dir = self
+            .resolve_project_path(project_path)
+            .await?
+            .join(".pio")
+            .join("build");
+        if let Some(env) = env {
+            if !Device::is_valid_environment_name(env) {
+                return Err(
+                    ServiceError::InvalidInput(format!("Invalid environment '{}'", env)).into(),
+                );
+            }
+            let path = build_dir.join(env).join("firmware.bin");
+            if !tokio::fs::metadata(&path).await.is_ok_and(|m| m.is_file()) {
+                return Err(ServiceError::NotFound(format!(
+                    "No firmware has been built for environment '{}'",
+                    env
+                ))
+                .into());
+            }
+            return Ok((env.to_string(), path));
+        }
+
+        let mut built = Vec::new();
+        if let Ok(mut entries) = tokio::fs::read_dir(&build_dir).await {
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let path = entry.path().join("firmware.bin");
+                if tokio::fs::metadata(&path).await.is_ok_and(|m| m.is_file()) {
+                    built.push((entry.file_name().to_string_lossy().into_owned(), path));
+                }
+            }
+        }
+        built.sort();
+        match built.len() {
+            0 => Err(ServiceError::NotFound("No firmware has been built".to_string()).into()),
+            1 => Ok(built.remove(0)),
+            _ => {
+                let envs: Vec<&str> = built.iter().map(|(env, _)| env.as_str()).collect();
+                Err(ServiceError::InvalidInput(format!(
+                    "Firmware was built for several environments ({}); pass ?env=",
+                    envs.join(", ")
+                ))
+                .into())
+            }
+        }
+    }
+
+    /// Writes a source file relative to `src/`, creating parent directories as needed.
+    /// Contents larger than `MAX_SOURCE_FILE_BYTES` fail with `ServiceError::TooLarge`.
+    pub async fn write_file(
+        &self,
+        project_path: &str,
+        relative_path: &str,
+        contents: &[u8],
+    ) -> Result<()> {
+        let project_dir = self.resolve_project_path(project_path).await?;
+        let path = resolve_source_path(&project_dir, relative_path)?;
+        if contents.len() > MAX_SOURCE_FILE_BYTES {
+            return Err(ServiceError::TooLarge(format!(
+                "File exceeds the maximum size of {} bytes",
+                MAX_SOURCE_FILE_BYTES
+            ))
+            .into());
+        }
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| anyhow!("Failed to create directory: {}", e))?;
+        }
+        tokio::fs::write(&path, contents)
+            .await
+            .map_err(|e| anyhow!("Failed to write {}: {}", relative_path, e))?;
+        Ok(())
+    }
+
+    /// Run a PlatformIO command and return the output
+    /// Helper to execute a PlatformIO command and capture output. Invalid UTF-8 is replaced
+    /// with U+FFFD and ANSI escape codes are removed; this is the default for commands whose
+    /// output is only shown as text. Output beyond `max_output_bytes` is truncated (see
+    /// `truncate_output`).
+    async fn run_pio_command(&self, project_dir: &Path, args: &[&str]) -> Result<String> {
+        let max_output = self.max_output_bytes;
+        self.run_pio_command_with_cancel(
+            project_dir,
+            args,
+            &HashMap::new(),
+            None,
+            None,
+            max_output,
+            false,
+        )
+        .await
+        .map(|output| truncate_output(output, max_output))
+    }
+
+    /// Runs a PlatformIO command like `run_pio_command` but returns its exact stdout bytes
+    /// followed by its stderr bytes. Used for flashing (upload, erase), whose esptool output
+    /// can contain bytes that aren't UTF-8; a failure's error message is still lossy text,
+    /// stripped of ANSI escape codes and truncated to `max_output_bytes`.
+    async fn run_pio_command_raw(&self, project_dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+        let output = self
+            .execute_pio(project_dir, args, &HashMap::new(), None)
+            .await?;
+        if output.success {
+            let mut bytes = output.stdout;
+            bytes.extend_from_slice(&output.stderr);
+            Ok(bytes)
+        } else {
+            let text = format!(
+                "{}\n{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            );
+            let output = truncate_output(strip_ansi(&text), self.max_output_bytes);
+            Err(anyhow!("PlatformIO command failed: {}", output))
+        }
+    }
+
+    /// Runs a PlatformIO command with `env` set that is killed early if `cancel` fires.
+    /// With `log_as` the combined output replaces the stored build log for that project path.
+    /// Values from `env` are masked in the output (see `mask_env_values`), and ANSI escape codes
+    /// are removed unless `keep_ansi` is set (see `strip_ansi`). A failure's output is truncated
+    /// to `max_output` bytes in the error; successful output is returned in full so callers can
+    /// parse it before truncating.
+    #[allow(clippy::too_many_arguments)]
+    async fn run_pio_command_with_cancel(
+        &self,
+        project_dir: &Path,
+        args: &[&str],
+        env: &HashMap<String, String>,
+        cancel: Option<oneshot::Receiver<()>>,
+        log_as: Option<&str>,
+        max_output: usize,
+        keep_ansi: bool,
+    ) -> Result<String> {
+        let output = self.execute_pio(project_dir, args, env, cancel).await?;
+        let text = |bytes: &[u8]| {
+            let text = mask_env_values(&String::from_utf8_lossy(bytes), env);
+            if keep_ansi {
+                text
+            } else {
+                strip_ansi(&text)
+            }
+        };
+        let stdout = text(&output.stdout);
+        let stderr = text(&output.stderr);
+        if let Some(project_path) = log_as {
+            self.record_build_log(project_path, &format!("{}{}", stdout, stderr));
+        }
+
+        if output.success {
+            Ok(format!("{}{}", stdout, stderr))
+        } else {
+            let output = truncate_output(format!("{}\n{}", stdout, stderr), max_output);
+            Err(anyhow!("PlatformIO command failed: {}", output))
+        }
+    }
+
+    /// Runs a PlatformIO command with `env` set in `project_dir` and waits for it to exit,
+    /// stopping it early if `cancel` fires. Output is captured unmodified.
+    async fn execute_pio(
+        &self,
+        project_dir: &Path,
+        args: &[&str],
+        env: &HashMap<String, String>,
+        cancel: Option<oneshot::Receiver<()>>,
+    ) -> Result<RunOutput> {
+        self.check_command_dir(project_dir).await?;
+        // Check if platformio is installed
+        self.check_pio_installed().await?;
+
+        let run = self.runner.run(self.binary(), project_dir, args, env);
+        // Dropping the run future stops the command.
+        match cancel {
+            Some(cancel) => tokio::select! {
+                output = run => output,
+                Ok(()) = cancel => {
+                    return Err(ServiceError::Cancelled("Build cancelled".to_string()).into());
+                }
+            },
+            None => run.await,
+        }
+        .map_err(|e| anyhow!("Failed to execute platformio command: {}", e))
+    }
+
+    /// With the sandbox enabled, fails with `ServiceError::SandboxViolation` unless `dir`
+    /// canonicalizes to the projects root or a directory inside it.
+    async fn check_command_dir(&self, dir: &Path) -> Result<()> {
+        if !self.sandbox {
+            return Ok(());
+        }
+        let root = tokio::fs::canonicalize(&self.projects_root)
+            .await
+            .map_err(|e| anyhow!("Projects root is not accessible: {}", e))?;
+        let dir = tokio::fs::canonicalize(dir).await.map_err(|e| {
+            anyhow!(
+                "Command directory {} is not accessible: {}",
+                dir.display(),
+                e
+            )
+        })?;
+        if dir.starts_with(&root) {
+            Ok(())
+        } else {
+            Err(ServiceError::SandboxViolation(format!(
+                "refusing to run PlatformIO in {}, which is outside the projects root",
+                dir.display()
+            ))
+            .into())
+        }
+    }
+
+    /// Starts a PlatformIO command and forwards its combined stdout/stderr lines over a channel,
+    /// ending with `StreamEvent::Exit`. The command is stopped if the receiver is dropped.
+    /// `held` (process slot, locks) is kept until the command exits.
+    async fn stream_pio_command(
+        &self,
+        project_dir: &Path,
+        args: &[&str],
+        held: impl Send + 'static,
+    ) -> Result<mpsc::Receiver<StreamEvent>> {
+        self.check_command_dir(project_dir).await?;
+        self.check_pio_installed().await?;
+
+        let mut events = self
+            .runner
+            .stream(self.binary(), project_dir, args)
+            .await
+            .map_err(|e| anyhow!("Failed to execute platformio command: {}", e))?;
+        let (tx, rx) = mpsc::channel(64);
+
+        tokio::spawn(async move {
+            let _held = held;
+            while let Some(event) = events.recv().await {
+                if tx.send(event).await.is_err() {
+                    // Client went away; dropping `events` stops the command.
+                    return;
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+
+    /// Whether the project resolves under the projects root and already holds a `platformio.ini`.
+    pub async fn is_initialized(&self, project_path: &str) -> bool {
+        match self.resolve_project_path(project_path).await {
+            Ok(project_dir) => self.ensure_pio_project(&project_dir).await.is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Verifies `project_path` is a directory containing a `platformio.ini`, so a stale path
+    /// fails with `ServiceError::InvalidInput` instead of an opaque PlatformIO CLI error.
+    async fn ensure_pio_project(&self, project_dir: &Path) -> Result<()> {
+        let is_dir = tokio::fs::metadata(project_dir)
+            .await
+            .map(|m| m.is_dir())
+            .unwrap_or(false);
+        let has_ini = tokio::fs::metadata(project_dir.join("platformio.ini"))
+            .await
+            .map(|m| m.is_file())
+            .unwrap_or(false);
+        if is_dir && has_ini {
+            Ok(())
+        } else {
+            Err(ServiceError::InvalidInput(format!(
+                "project path '{}' does not exist or is not a PlatformIO project",
+                project_dir.display()
+            ))
+            .into())
+        }
+    }
+
+    /// The PlatformIO version, e.g. `"6.1.15"`. Served from the cache filled by
+    /// `check_pio_installed`, which is run first if needed; `None` if PlatformIO isn't installed.
+    pub async fn pio_version(&self) -> Option<String> {
+        if self.version.get().is_none() {
+            self.check_pio_installed().await.ok()?;
+        }
+        self.version.get().cloned()
+    }
+
+    /// Check if PlatformIO is installed
+    /// Verifies PlatformIO is installed by running `<binary> --version`. When the default
+    /// `platformio` binary isn't found, `pio` is tried; whichever answers is used from then on.
+    /// Fails with `ServiceError::PlatformIOUnavailable`, whose message says how to install it.
+    pub async fn check_pio_installed(&self) -> Result<()> {
+        let mut candidates = vec![self.binary()];
+        if self.resolved_binary.get().is_none() && self.configured_binary == DEFAULT_BINARY {
+            candidates.push(FALLBACK_BINARY);
+        }
+
+        let mut not_found = None;
+        for candidate in candidates {
+            let output = self
+                .runner
+                .run(candidate, Path::new("."), &["--version"], &HashMap::new())
+                .await;
+            match output {
+                Ok(output) if output.success => {
+                    let _ = self.resolved_binary.set(candidate.to_string());
+                    let _ = self
+                        .version
+                        .set(parse_pio_version(&String::from_utf8_lossy(&output.stdout)));
+                    return Ok(());
+                }
+                Ok(_) => {
+                    let message = format!(
+                        "PlatformIO installation check failed: `{} --version` exited with an error. {}",
+                        candidate, INSTALL_HINT
+                    );
+                    return Err(ServiceError::PlatformIOUnavailable(message).into());
+                }
+                Err(e) => not_found = Some(e),
+            }
+        }
+        Err(ServiceError::PlatformIOUnavailable(format!(
+            "PlatformIO not found ({}). {}",
+            not_found.map(|e| e.to_string()).unwrap_or_default(),
+            INSTALL_HINT
+        ))
+        .into())
+    }
+}
+
+/// Extracts the version from `--version` output like `PlatformIO Core, version 6.1.15`,
+/// falling back to the whole first line.
+fn parse_pio_version(output: &str) -> String {
+    let line = output.trim().lines().next().unwrap_or_default();
+    line.rsplit_once("version ")
+        .map(|(_, version)| version.trim())
+        .unwrap_or(line)
+        .to_string()
+}
+
+/// Env var values shorter than this aren't masked: they can't hide much, and replacing every
+/// occurrence of e.g. `1` would garble the output.
+const MIN_MASKED_VALUE_LEN: usize = 4;
+
+/// Cuts `text` to at most `max` bytes (at a character boundary) and appends
+/// `...[output truncated, N bytes omitted]`. Text within the limit is returned unchanged.
+fn truncate_output(mut text: String, max: usize) -> String {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let omitted = text.len() - end;
+    text.truncate(end);
+    text.push_str(&format!(
+        "\n...[output truncated, {} bytes omitted]",
+        omitted
+    ));
+    text
+}
+
+/// Removes ANSI escape sequences from `text`: CSI sequences such as colors (`ESC [ 1;31m`),
+/// OSC sequences such as hyperlinks (ended by BEL or `ESC \\`), and other escapes like `ESC ( B`.
+fn strip_ansi(text: &str) -> String {
+    if !text.contains('\x1b') {
+        return text.to_string();
+    }
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+        match chars.next() {
+            // Parameter and intermediate bytes up to a final byte in `@`..=`~`.
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            // Intermediate bytes such as the `(` of `ESC ( B`, then one final byte.
+            Some(c) if (' '..='/').contains(&c) => {
+                for c in chars.by_ref() {
+                    if !(' '..='/').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    stripped
+}
+
+/// Replaces every occurrence of an `env` value in `text` with `***`, longest values first.
+fn mask_env_values(text: &str, env: &HashMap<String, String>) -> String {
+    let mut values: Vec<&str> = env
+        .values()
+        .map(String::as_str)
+        .filter(|v| v.len() >= MIN_MASKED_VALUE_LEN)
+        .collect();
+    values.sort_by_key(|v| std::cmp::Reverse(v.len()));
+    let mut masked = text.to_string();
+    for value in values {
+        masked = masked.replace(value, "***");
+    }
+    masked
+}
+
+/// Total size of the regular files under `dir`, without following symlinks.
+/// Entries that can't be read are skipped.
+async fn dir_size(dir: &Path) -> u64 {
+    let mut total = 0;
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            match entry.metadata().await {
+                Ok(meta) if meta.is_dir() => pending.push(entry.path()),
+                Ok(meta) if meta.is_file() => total += meta.len(),
+                _ => {}
+            }
+        }
+    }
+    total
+}
+
+/// Recursively copies the files under `template` into `project_dir`, creating directories as
+/// needed. Existing files are left alone unless `overwrite` is set; symlinks are skipped.
+/// Returns the copied paths relative to `project_dir`, sorted.
+async fn copy_template(
+    template: &Path,
+    project_dir: &Path,
+    overwrite: bool,
+) -> Result<Vec<String>> {
+    let mut copied = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        let mut entries = tokio::fs::read_dir(template.join(&relative))
+            .await
+            .map_err(|e| anyhow!("Failed to read template directory: {}", e))?;
+        tokio::fs::create_dir_all(project_dir.join(&relative))
+            .await
+            .map_err(|e| anyhow!("Failed to create project directory: {}", e))?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| anyhow!("Failed to read template directory: {}", e))?
+        {
+            let path = relative.join(entry.file_name());
+            let file_type = entry
+                .file_type()
+                .await
+                .map_err(|e| anyhow!("Failed to read template directory: {}", e))?;
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file() {
+                let target = project_dir.join(&path);
+                if !overwrite && tokio::fs::symlink_metadata(&target).await.is_ok() {
+                    continue;
+                }
+                tokio::fs::copy(entry.path(), &target).await.map_err(|e| {
+                    anyhow!("Failed to copy template file {}: {}", path.display(), e)
+                })?;
+                copied.push(path.to_string_lossy().into_owned());
+            }
+        }
+    }
+    copied.sort();
+    Ok(copied)
+}
+
+/// One build/upload process per CPU, or a single one if the CPU count is unknown.
+fn default_max_concurrent() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// Runs `git` non-interactively in `dir`, returning its combined output or an error carrying it.
+async fn run_git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(GIT_TIMEOUT, output)
+        .await
+        .map_err(|_| {
+            anyhow!(
+                "git {} timed out after {}s",
+                args.join(" "),
+                GIT_TIMEOUT.as_secs()
+            )
+        })?
+        .map_err(|e| anyhow!("Failed to execute git: {}", e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() {
+        Ok(format!("{}{}", stdout, stderr))
+    } else {
+        Err(anyhow!("git command failed: {}\n{}", stdout, stderr))
+    }
+}
+
+/// `build_args`, after checking that every `env_vars` name is a valid variable name.
+fn checked_build_args(options: &BuildOptions) -> Result<Vec<String>> {
+    if let Some(name) = options
+        .env_vars
+        .keys()
+        .find(|k| !Device::is_valid_env_var_name(k))
+    {
+        return Err(ServiceError::InvalidInput(format!(
+            "'{}' is not a valid environment variable name",
+            name
+        ))
+        .into());
+    }
+    build_args(options)
+}
+
+/// `-e <env>` selecting one `platformio.ini` environment; names that aren't
+/// `Device::is_valid_environment_name` fail with `ServiceError::InvalidInput`.
+fn environment_args(env: &str) -> Result<[String; 2]> {
+    if !Device::is_valid_environment_name(env) {
+        return Err(ServiceError::InvalidInput(format!("Invalid environment '{}'", env)).into());
+    }
+    Ok(["-e".to_string(), env.to_string()])
+}
+
+/// Arguments for `platformio run`, checking the program size only with `dry_run`.
+///
+/// `build_flags` are joined with spaces into one `--project-option "build_flags=..."`, which
+/// replaces the environment's `build_flags` from `platformio.ini` for this build. Each flag must
+/// start with `-` and consist of ASCII letters, digits and `_ = . , : + / -`; whitespace, quotes
+/// and shell metacharacters fail with `ServiceError::InvalidInput`.
+fn build_args(options: &BuildOptions) -> Result<Vec<String>> {
+    let mut args = vec!["run".to_string()];
+    if options.dry_run {
+        args.extend(["--target".to_string(), "checkprogsize".to_string()]);
+    }
+    if options.verbose {
+        args.push("-v".to_string());
+    }
+    if let Some(env) = &options.environment {
+        args.extend(environment_args(env)?);
+    }
+    if !options.build_flags.is_empty() {
+        for flag in &options.build_flags {
+            let safe = flag.len() > 1
+                && flag.starts_with('-')
+                && flag
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_=.,:+/-".contains(c));
+            if !safe {
+                return Err(ServiceError::InvalidInput(format!(
+                    "build flag '{}' contains unsupported characters",
+                    flag
+                ))
+                .into());
+            }
+        }
+        args.push("--project-option".to_string());
+        args.push(format!("build_flags={}", options.build_flags.join(" ")));
+    }
+    Ok(args)
+}
+
+/// Validates a `run_subcommand` request against `ALLOWED_SUBCOMMANDS` and returns the full
+/// argument list. Arguments may only contain ASCII letters, digits and `_=.,:+/@-`, must not
+/// contain `..`, and must not start with one of `FORBIDDEN_OPTIONS`.
+fn subcommand_args(subcommand: &str, args: &[String]) -> Result<Vec<String>> {
+    let Some((_, actions)) = ALLOWED_SUBCOMMANDS
+        .iter()
+        .find(|(name, _)| *name == subcommand)
+    else {
+        return Err(
+            ServiceError::Forbidden(format!("subcommand '{}' is not allowed", subcommand)).into(),
+        );
+    };
+    if !actions.is_empty() {
+        let action = args.first().map(String::as_str).unwrap_or_default();
+        if !actions.contains(&action) {
+            return Err(ServiceError::Forbidden(format!(
+                "'{} {}' is not allowed; allowed actions: {}",
+                subcommand,
+                action,
+                actions.join(", ")
+            ))
+            .into());
+        }
+    }
+    for arg in args {
+        if let Some(option) = FORBIDDEN_OPTIONS.iter().find(|o| arg.starts_with(*o)) {
+            return Err(
+                ServiceError::Forbidden(format!("option '{}' is not allowed", option)).into(),
+            );
+        }
+        let safe = !arg.is_empty()
+            && !arg.contains("..")
+            && arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_=.,:+/@-".contains(c));
+        if !safe {
+            return Err(ServiceError::InvalidInput(format!(
+                "argument '{}' contains unsupported characters",
+                arg
+            ))
+            .into());
+        }
+    }
+    let mut full = vec![subcommand.to_string()];
+    full.extend(args.iter().cloned());
+    Ok(full)
+}
+
+/// Arguments for `platformio run --target upload`, with an optional explicit port, protocol and
+/// environment (see `environment_args`).
+/// `Espota` needs `port` (the board's IP address or hostname) and fails with
+/// `ServiceError::InvalidInput` without it; `EspProg` uploads over JTAG and ignores `port`.
+fn upload_args(
+    port: Option<&str>,
+    protocol: Option<UploadProtocol>,
+    environment: Option<&str>,
+) -> Result<Vec<String>> {
+    let mut args = vec![
+        "run".to_string(),
+        "--target".to_string(),
+        "upload".to_string(),
+    ];
+    if let Some(env) = environment {
+        args.extend(environment_args(env)?);
+    }
+    if let Some(protocol) = protocol {
+        args.push("--project-option".to_string());
+        args.push(format!("upload_protocol={}", protocol.as_str()));
+    }
+    let port = match protocol {
+        Some(UploadProtocol::EspProg) => None,
+        Some(UploadProtocol::Espota) if port.is_none() => {
+            return Err(ServiceError::InvalidInput(
+                "espota uploads need the board's IP address as the port".to_string(),
+            )
+            .into())
+        }
+        _ => port,
+    };
+    if let Some(p) = port {
+        args.extend(["--upload-port".to_string(), p.to_string()]);
+    }
+    Ok(args)
+}
+
+/// Extracts the percentage from esptool progress lines like `Writing at 0x00010000... (23 %)`.
+pub fn parse_upload_progress(line: &str) -> Option<u8> {
+    if !line.trim_start().starts_with("Writing at") {
+        return None;
+    }
+    let start = line.rfind('(')? + 1;
+    let end = start + line[start..].find('%')?;
+    line[start..end].trim().parse().ok()
+}
+
+/// Joins a client-supplied path onto the project's `src/` directory, rejecting empty, absolute,
+/// and `..` paths so the result can't escape the project.
+fn resolve_source_path(project_dir: &Path, relative_path: &str) -> Result<PathBuf> {
+    let relative = Path::new(relative_path);
+    let is_safe = !relative_path.is_empty()
+        && relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if !is_safe {
+        return Err(
+            ServiceError::InvalidInput(format!("Invalid file path '{}'", relative_path)).into(),
+        );
+    }
+    Ok(project_dir.join("src").join(relative))
+}
+
+/// The `main.cpp` source `create_basic_main` writes for a template name (`blink` when `None`).
+/// Unknown names are `ServiceError::InvalidInput`.
+pub fn basic_main_content(template: Option<&str>) -> Result<&'static str> {
+    match template.unwrap_or(DEFAULT_MAIN_TEMPLATE) {
+        "blink" => Ok(BLINK_MAIN),
+        "minimal" => Ok(MINIMAL_MAIN),
+        other => Err(ServiceError::InvalidInput(format!(
+            "unknown template '{}' (expected 'blink' or 'minimal')",
+            other
+        ))
+        .into()),
+    }
+}
+
+/// Validates a custom board JSON: it must parse as an object with a string `id` made of ASCII
+/// letters, digits, `_`, `-` and `.` (not starting with `.`), since the id becomes the file name
+/// in the project's `boards/` directory. Fails with `ServiceError::InvalidInput` otherwise.
+pub fn parse_board_definition(json: String) -> Result<BoardDefinition> {
+    let value: serde_json::Value = serde_json::from_str(&json)
+        .map_err(|e| ServiceError::InvalidInput(format!("Invalid board definition JSON: {}", e)))?;
+    let id = value.get("id").and_then(|id| id.as_str()).ok_or_else(|| {
+        ServiceError::InvalidInput("Board definition has no string 'id' field".to_string())
+    })?;
+    let valid = !id.is_empty()
+        && !id.starts_with('.')
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !valid {
+        return Err(
+            ServiceError::InvalidInput(format!("Invalid board definition id '{}'", id)).into(),
+        );
+    }
+    Ok(BoardDefinition {
+        id: id.to_string(),
+        json,
+    })
+}
+
+/// Builds an `InitResult` from `project init` output and the generated `platformio.ini`.
+/// Created entries are the `name - description` lines following PlatformIO's "have been created"
+/// header; the environment and board come from the first `[env:...]` section of the ini.
+pub fn parse_init_result(output: &str, ini: Option<&str>, requested_board: &str) -> InitResult {
+    let created_files = output
+        .lines()
+        .skip_while(|line| !line.contains("have been created"))
+        .skip(1)
+        .map_while(|line| line.split_once(" - ").map(|(name, _)| name.trim()))
+        .filter(|name| !name.is_empty() && !name.contains(char::is_whitespace))
+        .map(str::to_string)
+        .collect();
+
+    let (sections, _) = parse_ini_sections(ini.unwrap_or_default());
+    let env = sections
+        .iter()
+        .find_map(|section| Some((section.name.strip_prefix("env:")?, section)));
+
+    InitResult {
+        board: env
+            .and_then(|(_, section)| section.get("board"))
+            .unwrap_or(requested_board)
+            .to_string(),
+        env_name: env.map(|(name, _)| name.to_string()),
+        created_files,
+        template_files: Vec::new(),
+        already_initialized: false,
+    }
+}
+
+/// Name of the first `[env:...]` section of `ini` whose `board` is `board`.
+fn board_env(ini: &str, board: &str) -> Option<String> {
+    let (sections, _) = parse_ini_sections(ini);
+    sections.iter().find_map(|section| {
+        let name = section.name.strip_prefix("env:")?;
+        (section.get("board") == Some(board)).then(|| name.to_string())
+    })
+}
+
+/// One `[name]` section of an ini file with its `key = value` pairs, in file order.
+struct IniSection<'a> {
+    name: &'a str,
+    line: usize,
+    entries: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> IniSection<'a> {
+    /// Value of the last `key` entry in the section.
+    fn get(&self, key: &str) -> Option<&'a str> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| *v)
+    }
+}
+
+/// Splits ini text into sections. Blank lines, `;`/`#` comments and indented continuation
+/// lines of multi-line values are skipped; any other line that is neither a section header
+/// nor `key = value` is reported by (1-based) line number alongside the sections.
+fn parse_ini_sections(ini: &str) -> (Vec<IniSection<'_>>, Vec<String>) {
+    let mut sections: Vec<IniSection> = Vec::new();
+    let mut errors = Vec::new();
+    for (index, raw) in ini.lines().enumerate() {
+        let number = index + 1;
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push(IniSection {
+                name: name.trim(),
+                line: number,
+                entries: Vec::new(),
+            });
+            continue;
+        }
+        let continuation = raw.starts_with(char::is_whitespace)
+            && sections.last().is_some_and(|s| !s.entries.is_empty());
+        if continuation {
+            continue;
+        }
+        match (line.split_once('='), sections.last_mut()) {
+            (Some((key, _)), _) if key.trim().is_empty() => {
+                errors.push(format!("line {}: missing key before '='", number));
+            }
+            (Some((key, value)), Some(section)) => {
+                section.entries.push((key.trim(), value.trim()));
+            }
+            (Some(_), None) => {
+                errors.push(format!("line {}: key outside of any [section]", number));
+            }
+            (None, _) => {
+                errors.push(format!(
+                    "line {}: expected a [section] header or key = value",
+                    number
+                ));
+            }
+        }
+    }
+    (sections, errors)
+}
+
+/// Checks `platformio.ini` text without touching the filesystem and returns every problem found:
+/// malformed lines, duplicate sections, no `[env:...]` section, or an environment without a
+/// non-empty `board` and `platform`. Values inherited from `[env]` or an `extends`ed section
+/// count. An empty list means the text is valid.
+pub fn validate_platformio_ini(ini: &str) -> Vec<String> {
+    let (sections, mut errors) = parse_ini_sections(ini);
+
+    let mut seen = HashSet::new();
+    for section in &sections {
+        if !seen.insert(section.name) {
+            errors.push(format!(
+                "line {}: duplicate section [{}]",
+                section.line, section.name
+            ));
+        }
+    }
+
+    let find = |name: &str| sections.iter().find(|s| s.name == name);
+    // Looks up `key` in the section, then the sections it extends, then the `[env]` base.
+    let lookup = |section: &IniSection, key: &str| -> Option<String> {
+        let mut chain = vec![section];
+        let mut index = 0;
+        while let Some(current) = chain.get(index) {
+            if let Some(value) = current.get(key) {
+                return Some(value.to_string());
+            }
+            for parent in current.get("extends").unwrap_or_default().split(',') {
+                if let Some(parent) = find(parent.trim()) {
+                    if !chain.iter().any(|s| s.name == parent.name) {
+                        chain.push(parent);
+                    }
+                }
+            }
+            index += 1;
+        }
+        find("env")
+            .and_then(|base| base.get(key))
+            .map(str::to_string)
+    };
+
+    let envs: Vec<_> = sections
+        .iter()
+        .filter_map(|s| Some((s.name.strip_prefix("env:")?, s)))
+        .collect();
+    if envs.is_empty() {
+        errors.push("no [env:...] section found".to_string());
+    }
+    for (name, section) in envs {
+        if name.trim().is_empty() {
+            errors.push(format!("line {}: environment name is empty", section.line));
+            continue;
+        }
+        for key in ["board", "platform"] {
+            if lookup(section, key).is_none_or(|value| value.is_empty()) {
+                errors.push(format!(
+                    "line {}: [env:{}] has no {}",
+                    section.line, name, key
+                ));
+            }
+        }
+    }
+    errors
+}
+
+/// Parses the JSON printed by `lib list --json-output`: either one array of libraries or an object
+/// mapping each library storage directory to its array. Text around the JSON (such as deprecation
+/// warnings on stderr) is ignored; empty output means no libraries.
+pub fn parse_library_list(output: &str) -> Result<Vec<LibraryInfo>> {
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum LibraryList {
+        Flat(Vec<LibraryInfo>),
+        ByStorage(std::collections::BTreeMap<String, Vec<LibraryInfo>>),
+    }
+
+    let Some(start) = output.find(['[', '{']) else {
+        return Ok(Vec::new());
+    };
+    let list = serde_json::Deserializer::from_str(&output[start..])
+        .into_iter::<LibraryList>()
+        .next()
+        .ok_or_else(|| anyhow!("Empty library list output"))?
+        .map_err(|e| anyhow!("Failed to parse library list: {}", e))?;
+    Ok(match list {
+        LibraryList::Flat(libraries) => libraries,
+        LibraryList::ByStorage(storages) => storages.into_values().flatten().collect(),
+    })
+}
+
+/// Parses the JSON object printed by `system info --json-output`, which maps each item to its
+/// `title` and `value`, ignoring text before it. Unknown items are skipped.
+pub fn parse_system_info(output: &str) -> Result<SystemInfo> {
+    #[derive(serde::Deserialize)]
+    struct Item {
+        value: serde_json::Value,
+    }
+
+    let Some(start) = output.find('{') else {
+        return Err(anyhow!("No system info in PlatformIO output"));
+    };
+    let mut items: HashMap<String, Item> = serde_json::Deserializer::from_str(&output[start..])
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Empty system info output"))?
+        .map_err(|e| anyhow!("Failed to parse system info: {}", e))?;
+    let mut value = |key: &str| {
+        items.remove(key).map(|item| match item.value {
+            serde_json::Value::String(s) => s,
+            other => other.to_string(),
+        })
+    };
+    Ok(SystemInfo {
+        core_version: value("core_version"),
+        python_version: value("python_version"),
+        system: value("system"),
+        platform: value("platform"),
+        core_dir: value("core_dir"),
+        platformio_exe: value("platformio_exe"),
+        python_exe: value("python_exe"),
+    })
+}
+
+/// Parses the JSON array printed by `device list --serial --json-output`, ignoring text before
+/// it; empty output means no ports.
+pub fn parse_serial_ports(output: &str) -> Result<Vec<SerialPortInfo>> {
+    let Some(start) = output.find('[') else {
+        return Ok(Vec::new());
+    };
+    serde_json::Deserializer::from_str(&output[start..])
+        .into_iter::<Vec<SerialPortInfo>>()
+        .next()
+        .ok_or_else(|| anyhow!("Empty serial port list output"))?
+        .map_err(|e| anyhow!("Failed to parse serial port list: {}", e))
+}
+
+/// Resolves `project_path` against the canonical `root`, failing with
+/// `ServiceError::InvalidInput` if the result escapes it (see `resolve_project_path`).
+async fn confine_project_path(root: &Path, project_path: &str) -> Result<PathBuf> {
+    // Resolve `..` lexically first so paths that don't exist yet can still be checked.
+    let mut joined = PathBuf::new();
+    for component in root.join(project_path).components() {
+        match component {
+            Component::ParentDir => {
+                joined.pop();
+            }
+            Component::CurDir => {}
+            other => joined.push(other),
+        }
+    }
+
+    // Canonicalize the deepest existing ancestor so symlinks are followed.
+    let mut resolved = joined.clone();
+    for ancestor in joined.ancestors() {
+        if let Ok(canonical) = tokio::fs::canonicalize(ancestor).await {
+            // Joining an empty remainder would append a trailing `/`, which breaks file paths.
+            resolved = match joined.strip_prefix(ancestor) {
+                Ok(rest) if !rest.as_os_str().is_empty() => canonical.join(rest),
+                _ => canonical,
+            };
+            break;
+        }
+    }
+
+    if resolved.starts_with(root) {
+        Ok(resolved)
+    } else {
+        Err(ServiceError::InvalidInput(format!(
+            "project path '{}' is outside the projects root",
+            project_path
+        ))
+        .into())
+    }
+}
+
+/// Flash offset of the ESP32 partition table.
+const PARTITION_TABLE_OFFSET: u32 = 0x8000;
+
+/// Length of the partition table region: 3 KiB, i.e. up to 96 entries of 32 bytes.
+const PARTITION_TABLE_LEN: u32 = 0xC00;
+
+/// Decodes a binary ESP32 partition table: 32-byte little-endian entries starting with the
+/// magic `AA 50`, up to the MD5 entry (`EB EB`) or erased flash. Returns `None` if the first
+/// entry isn't a partition.
+pub fn decode_partition_table(bytes: &[u8]) -> Option<Vec<PartitionEntry>> {
+    let mut entries = Vec::new();
+    for entry in bytes.chunks_exact(32) {
+        if entry[..2] != [0xAA, 0x50] {
+            break;
+        }
+        let word = |at: usize| u32::from_le_bytes(entry[at..at + 4].try_into().unwrap());
+        let name = &entry[12..28];
+        let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        let (kind, subtype) = partition_type_names(entry[2], entry[3]);
+        entries.push(PartitionEntry {
+            name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+            kind,
+            subtype,
+            offset: word(4),
+            size: word(8),
+        });
+    }
+    (!entries.is_empty()).then_some(entries)
+}
+
+/// Names of a partition's type and subtype bytes as used in partition CSVs, falling back to
+/// hex for custom values.
+fn partition_type_names(kind: u8, subtype: u8) -> (String, String) {
+    let hex = |b: u8| format!("{:#04x}", b);
+    match kind {
+        0x00 => {
+            let subtype = match subtype {
+                0x00 => "factory".to_string(),
+                0x10..=0x1F => format!("ota_{}", subtype - 0x10),
+                0x20 => "test".to_string(),
+                _ => hex(subtype),
+            };
+            ("app".to_string(), subtype)
+        }
+        0x01 => {
+            let subtype = match subtype {
+                0x00 => "ota",
+                0x01 => "phy",
+                0x02 => "nvs",
+                0x03 => "coredump",
+                0x04 => "nvs_keys",
+                0x05 => "efuse",
+                0x80 => "esphttpd",
+                0x81 => "fat",
+                0x82 => "spiffs",
+                0x83 => "littlefs",
+                _ => return ("data".to_string(), hex(subtype)),
+            };
+            ("data".to_string(), subtype.to_string())
+        }
+        _ => (hex(kind), hex(subtype)),
+    }
+}
+
+/// Formats partitions as CSV in the layout of `gen_esp32part.py`, with hex offsets and sizes:
+/// a `# Name, Type, SubType, Offset, Size` header, then one line per partition.
+pub fn format_partition_csv(entries: &[PartitionEntry]) -> String {
+    let mut csv = String::from("# Name, Type, SubType, Offset, Size\n");
+    for e in entries {
+        csv.push_str(&format!(
+            "{},{},{},{:#x},{:#x}\n",
+            e.name, e.kind, e.subtype, e.offset, e.size
+        ));
+    }
+    csv
+}
+
+/// Parses partition CSV text such as `format_partition_csv` output or a project's
+/// `partitions.csv`. Offsets and sizes may be hex (`0x9000`), decimal, or use a `K`/`M`
+/// suffix; `#` lines are comments and extra columns (flags) are ignored. Returns `None` unless
+/// every other non-empty line is a complete entry and there is at least one.
+pub fn parse_partition_csv(text: &str) -> Option<Vec<PartitionEntry>> {
+    let number = |field: &str| -> Option<u32> {
+        let field = field.trim();
+        if let Some(hex) = field
+            .strip_prefix("0x")
+            .or_else(|| field.strip_prefix("0X"))
+        {
+            return u32::from_str_radix(hex, 16).ok();
+        }
+        let (digits, scale) = match field.chars().last()? {
+            'K' | 'k' => (&field[..field.len() - 1], 1024),
+            'M' | 'm' => (&field[..field.len() - 1], 1024 * 1024),
+            _ => (field, 1),
+        };
+        digits.parse::<u32>().ok()?.checked_mul(scale)
+    };
+    let mut entries = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.len() < 5 || fields[..3].iter().any(|f| f.is_empty()) {
+            return None;
+        }
+        entries.push(PartitionEntry {
+            name: fields[0].to_string(),
+            kind: fields[1].to_string(),
+            subtype: fields[2].to_string(),
+            offset: number(fields[3])?,
+            size: number(fields[4])?,
+        });
+    }
+    (!entries.is_empty()).then_some(entries)
+}
+
+/// Parses the table printed by `pkg outdated` into one entry per row. Columns are located by
+/// the `Package`, `Current` and `Latest` headers; output without that table yields no entries.
+pub fn parse_outdated_packages(output: &str) -> Vec<PackageUpdate> {
+    let mut lines = output.lines();
+    let columns = lines.by_ref().find_map(|line| {
+        let headers: Vec<&str> = line.split_whitespace().collect();
+        if headers.first() != Some(&"Package") {
+            return None;
+        }
+        let current = headers.iter().position(|h| *h == "Current")?;
+        let latest = headers.iter().position(|h| *h == "Latest")?;
+        Some((current, latest))
+    });
+    let Some((current, latest)) = columns else {
+        return Vec::new();
+    };
+
+    lines
+        .filter(|line| !line.trim_start().starts_with('-'))
+        .filter_map(|line| {
+            let cells: Vec<&str> = line.split_whitespace().collect();
+            Some(PackageUpdate {
+                package: cells.first()?.to_string(),
+                current: cells.get(current)?.to_string(),
+                latest: cells.get(latest)?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Extracts the `RAM:` and `Flash:` usage lines PlatformIO prints after a build.
+/// Returns `None` when neither line is present.
+pub fn parse_firmware_size(output: &str) -> Option<FirmwareSizeInfo> {
+    let mut ram = None;
+    let mut flash = None;
+    for line in output.lines() {
+        let line = line.trim_start();
+        if let Some(rest) = line.strip_prefix("RAM:") {
+            ram = parse_memory_usage(rest);
+        } else if let Some(rest) = line.strip_prefix("Flash:") {
+            flash = parse_memory_usage(rest);
+        }
+    }
+    if ram.is_none() && flash.is_none() {
+        return None;
+    }
+    Some(FirmwareSizeInfo { ram, flash })
+}
+
+/// Extracts the totals from the last `N test cases: ...` summary line of `platformio test`
+/// output, e.g. `==== 3 test cases: 1 failed, 2 succeeded in 00:00:04.512 ====`. Returns `None`
+/// when there is no summary, e.g. because the test runner crashed. ANSI color codes are ignored.
+pub fn parse_test_summary(output: &str) -> Option<TestSummary> {
+    strip_ansi(output).lines().rev().find_map(|line| {
+        let line = line.trim().trim_matches('=').trim();
+        let (total, rest) = line.split_once(" test case")?;
+        let counts = rest.trim_start_matches('s').strip_prefix(':')?;
+        let mut summary = TestSummary {
+            total: total.trim().parse().ok()?,
+            ..TestSummary::default()
+        };
+        let counts = counts.split(" in ").next().unwrap_or(counts);
+        for count in counts.split(',') {
+            let mut words = count.split_whitespace();
+            let (Some(n), Some(status)) = (words.next().and_then(|n| n.parse().ok()), words.next())
+            else {
+                continue;
+            };
+            match status {
+                "succeeded" | "passed" => summary.succeeded = n,
+                "failed" => summary.failed = n,
+                "errored" => summary.errored = n,
+                "skipped" | "ignored" => summary.skipped = n,
+                _ => {}
+            }
+        }
+        Some(summary)
+    })
+}
+
+/// Extracts GCC-style `file:line:col: severity: message` diagnostics from build output.
+/// Indented lines after a diagnostic (the source excerpt and caret) and its `note:` lines are
+/// kept as its `context`; `note:`s with no diagnostic before them are dropped. ANSI color codes
+/// are ignored.
+pub fn parse_diagnostics(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut in_diagnostic = false;
+    for line in strip_ansi(output).lines() {
+        match parse_diagnostic_line(line) {
+            Some(diagnostic) => {
+                diagnostics.push(diagnostic);
+                in_diagnostic = true;
+            }
+            None if in_diagnostic && is_diagnostic_continuation(line) => {
+                if let Some(last) = diagnostics.last_mut() {
+                    last.context.push(line.to_string());
+                }
+            }
+            None => in_diagnostic = false,
+        }
+    }
+    diagnostics
+}
+
+/// Parses one `file:line[:col]: severity: message` line; `note:` lines aren't diagnostics.
+fn parse_diagnostic_line(line: &str) -> Option<Diagnostic> {
+    let (at, marker, severity) = [
+        (": fatal error: ", DiagnosticSeverity::Error),
+        (": error: ", DiagnosticSeverity::Error),
+        (": warning: ", DiagnosticSeverity::Warning),
+    ]
+    .into_iter()
+    .filter_map(|(marker, severity)| Some((line.find(marker)?, marker, severity)))
+    .min_by_key(|(at, _, _)| *at)?;
+    let (location, message) = (&line[..at], &line[at + marker.len()..]);
+
+    let (rest, last) = location.rsplit_once(':')?;
+    let last = last.parse().ok()?;
+    let (file, line_number, column) = match rest.rsplit_once(':') {
+        Some((file, line_number)) => match line_number.parse() {
+            Ok(line_number) => (file, line_number, Some(last)),
+            Err(_) => (rest, last, None),
+        },
+        None => (rest, last, None),
+    };
+    if file.is_empty() || file.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(Diagnostic {
+        file: file.to_string(),
+        line: line_number,
+        column,
+        severity,
+        message: message.to_string(),
+        context: Vec::new(),
+    })
+}
+
+/// Whether `line` continues the diagnostic before it: an indented source excerpt or caret
+/// line, or a `file:line:col: note:` line.
+fn is_diagnostic_continuation(line: &str) -> bool {
+    line.starts_with([' ', '\t']) || line.contains(": note: ")
+}
+
+/// Parses `[==        ]  20.1% (used 263621 bytes from 1310720 bytes)`.
+fn parse_memory_usage(line: &str) -> Option<MemoryUsage> {
+    let after_bar = &line[line.find(']')? + 1..];
+    let percent = after_bar[..after_bar.find('%')?].trim().parse().ok()?;
+    let number_after = |marker: &str| -> Option<u64> {
+        after_bar
+            .split(marker)
+            .nth(1)?
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()
+    };
+    Some(MemoryUsage {
+        percent,
+        used_bytes: number_after("used ")?,
+        total_bytes: number_after("from ")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::MockPlatformIORunner;
+
+    /// Projects root for tests: a fresh directory under the system temp dir, deleted with
+    /// everything in it when the guard is dropped. Derefs to its path.
+    struct TempProjectsRoot {
+        path: PathBuf,
+    }
+
+    impl TempProjectsRoot {
+        /// Creates `<temp dir>/<prefix>-<uuid>` and the (possibly nested) `projects` inside it.
+        fn new(prefix: &str, projects: &[&str]) -> Self {
+            let path = std::env::temp_dir().join(format!("{}-{}", prefix, uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&path).expect("failed to create temp projects root");
+            for project in projects {
+                std::fs::create_dir_all(path.join(project)).expect("failed to create temp project");
+            }
+            Self { path }
+        }
+
+        /// Service rooted here that runs PlatformIO commands with `runner`.
+        fn service(&self, runner: Arc<dyn PlatformIORunner>) -> PlatformIOService {
+            PlatformIOService::new()
+                .with_projects_root(&self.path)
+                .with_runner(runner)
+        }
+    }
+
+    impl std::ops::Deref for TempProjectsRoot {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.path
+        }
+    }
+
+    impl AsRef<Path> for TempProjectsRoot {
+        fn as_ref(&self) -> &Path {
+            &self.path
+        }
+    }
+
+    impl Drop for TempProjectsRoot {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+
+    /// Test for PlatformIO installation check (expects failure in test env).
+    #[tokio::test]
+    async fn test_pio_check() {
+        let service = PlatformIOService::new();
+        // This will fail if PlatformIO is not installed, which is expected in test environment
+        let _ = service.check_pio_installed().await;
+    }
+
+    /// Test that the configured binary is used and remembered once it answers `--version`.
+    #[tokio::test]
+    async fn configured_binary_is_resolved() {
+        let missing = PlatformIOService::with_binary("definitely-not-platformio");
+        let err = missing.check_pio_installed().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::PlatformIOUnavailable(msg)) if msg.contains("PLATFORMIO_BIN")
+        ));
+        assert_eq!(missing.binary(), "definitely-not-platformio");
+
+        // `true --version` exits successfully, standing in for a working install.
+        let service = PlatformIOService::with_binary("true");
+        service.check_pio_installed().await.unwrap();
+        assert_eq!(service.binary(), "true");
+    }
+
+    /// Test that the startup check creates a missing root and rejects one that isn't a directory.
+    #[tokio::test]
+    async fn check_projects_root_requires_writable_dir() {
+        let root = TempProjectsRoot::new("pio-root", &[]);
+        let service = PlatformIOService::new().with_projects_root(root.join("projects"));
+        service.check_projects_root().await.unwrap();
+        let mut entries = tokio::fs::read_dir(root.join("projects")).await.unwrap();
+        assert!(entries.next_entry().await.unwrap().is_none());
+
+        tokio::fs::write(root.join("file"), "").await.unwrap();
+        let service = PlatformIOService::new().with_projects_root(root.join("file"));
+        assert!(service.check_projects_root().await.is_err());
+    }
+
+    /// Test that project paths resolve under the projects root and can't escape it.
+    #[tokio::test]
+    async fn project_paths_are_confined_to_root() {
+        let root = TempProjectsRoot::new("pio-root", &[]);
+        let service = PlatformIOService::new().with_projects_root(&*root);
+
+        let resolved = service.resolve_project_path("lab/board-1").await.unwrap();
+        let canonical_root = tokio::fs::canonicalize(&*root).await.unwrap();
+        assert_eq!(resolved, canonical_root.join("lab/board-1"));
+        let absolute = canonical_root.join("board-2");
+        assert!(service
+            .resolve_project_path(absolute.to_str().unwrap())
+            .await
+            .is_ok());
+
+        for escaping in ["../outside", "lab/../../outside", "/etc"] {
+            let err = service.resolve_project_path(escaping).await.unwrap_err();
+            assert!(
+                matches!(
+                    err.downcast_ref::<ServiceError>(),
+                    Some(ServiceError::InvalidInput(_))
+                ),
+                "{}",
+                escaping
+            );
+        }
+
+        std::os::unix::fs::symlink("/", root.join("escape")).unwrap();
+        assert!(service.resolve_project_path("escape/etc").await.is_err());
+    }
+
+    /// Test that a project directory swapped for a symlink out of the projects root after it was
+    /// resolved is refused when the command runs, unless the sandbox is disabled.
+    #[tokio::test]
+    async fn commands_refuse_symlinks_out_of_projects_root() {
+        let base = TempProjectsRoot::new("pio-sandbox", &["projects/lab", "outside"]);
+        let (root, outside) = (base.join("projects"), base.join("outside"));
+        let mock = Arc::new(MockPlatformIORunner::new());
+        let service = PlatformIOService::new()
+            .with_projects_root(&root)
+            .with_runner(mock.clone());
+
+        let project_dir = service.resolve_project_path("lab").await.unwrap();
+        service
+            .run_pio_command(&project_dir, &["run"])
+            .await
+            .unwrap();
+        tokio::fs::remove_dir(&project_dir).await.unwrap();
+        std::os::unix::fs::symlink(&outside, &project_dir).unwrap();
+        let err = service
+            .run_pio_command(&project_dir, &["run"])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::SandboxViolation(_))
+        ));
+        assert!(service
+            .stream_pio_command(&project_dir, &["run"], ())
+            .await
+            .is_err());
+        assert_eq!(mock.calls().iter().filter(|c| c[0] == "run").count(), 1);
+
+        let service = service.with_sandbox(false);
+        service
+            .run_pio_command(&project_dir, &["run"])
+            .await
+            .unwrap();
+        assert_eq!(mock.calls().iter().filter(|c| c[0] == "run").count(), 2);
+    }
+
+    /// Test that missing directories and directories without platformio.ini are rejected up front.
+    #[tokio::test]
+    async fn ensure_pio_project_requires_platformio_ini() {
+        let root = TempProjectsRoot::new("pio-check", &[]);
+        let service = PlatformIOService::new().with_projects_root(&*root);
+        let dir = root.join("p");
+        let path = dir.to_str().unwrap();
+
+        let err = service
+            .build_project(path, &BuildOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::NotInitialized(_))
+        ));
+
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        assert!(service.ensure_pio_project(&dir).await.is_err());
+        assert!(!service.is_initialized(path).await);
+        tokio::fs::write(dir.join("platformio.ini"), "[env:esp32dev]\n")
+            .await
+            .unwrap();
+        assert!(service.ensure_pio_project(&dir).await.is_ok());
+        assert!(service.is_initialized(path).await);
+    }
+
+    /// Test that source listing is relative to src/, skips hidden entries, and tolerates a missing src/.
+    #[tokio::test]
+    async fn list_source_files_walks_src() {
+        let root = TempProjectsRoot::new("pio-files", &[]);
+        let service = PlatformIOService::new().with_projects_root(&*root);
+        let dir = root.join("p");
+        let path = dir.to_str().unwrap();
+        assert!(service.list_source_files(path).await.unwrap().is_empty());
+
+        tokio::fs::create_dir_all(dir.join("src/drivers"))
+            .await
+            .unwrap();
+        tokio::fs::create_dir_all(dir.join("src/.cache"))
+            .await
+            .unwrap();
+        tokio::fs::write(dir.join("src/main.cpp"), "")
+            .await
+            .unwrap();
+        tokio::fs::write(dir.join("src/drivers/led.h"), "")
+            .await
+            .unwrap();
+        tokio::fs::write(dir.join("src/.hidden"), "").await.unwrap();
+        tokio::fs::write(dir.join("src/.cache/x.o"), "")
+            .await
+            .unwrap();
+
+        let files = service.list_source_files(path).await.unwrap();
+        assert_eq!(
+            files,
+            vec!["drivers/led.h".to_string(), "main.cpp".to_string()]
+        );
+    }
+
+    /// Test that `git_pull` fast-forwards a cloned project, refuses while the project is
+    /// uploading, and rejects non-repositories.
+    #[tokio::test]
+    async fn git_pull_updates_clone() {
+        let root = TempProjectsRoot::new("pio-git", &["origin", "plain"]);
+        let git = |dir: std::path::PathBuf, args: &'static [&'static str]| async move {
+            let status = Command::new("git")
+                .args(["-c", "user.name=lab", "-c", "user.email=lab@example.com"])
+                .args(args)
+                .current_dir(dir)
+                .output()
+                .await
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {:?}", args);
+        };
+        let origin = root.join("origin");
+        git(origin.clone(), &["init", "-q"]).await;
+        tokio::fs::write(origin.join("a.txt"), "a").await.unwrap();
+        git(origin.clone(), &["add", "."]).await;
+        git(origin.clone(), &["commit", "-qm", "first"]).await;
+        git(root.to_path_buf(), &["clone", "-q", "origin", "clone"]).await;
+        tokio::fs::write(origin.join("b.txt"), "b").await.unwrap();
+        git(origin.clone(), &["add", "."]).await;
+        git(origin.clone(), &["commit", "-qm", "second"]).await;
+
+        let service = PlatformIOService::new().with_projects_root(&*root);
+        {
+            let _upload = service.track_upload("clone").unwrap();
+            let err = service.git_pull("clone").await.unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ServiceError>(),
+                Some(ServiceError::Conflict(_))
+            ));
+        }
+        service.git_pull("clone").await.unwrap();
+        assert!(root.join("clone/b.txt").exists());
+        tokio::fs::create_dir_all(root.join("clone/src"))
+            .await
+            .unwrap();
+
+        for path in ["plain", "missing", "clone/src"] {
+            let err = service.git_pull(path).await.unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ServiceError>(),
+                Some(ServiceError::InvalidInput(_))
+            ));
+        }
+    }
+
+    /// Test that custom board JSON needs a file-name-safe string `id`.
+    #[test]
+    fn parse_board_definition_requires_id() {
+        let definition =
+            parse_board_definition(r#"{"id": "my_board-v2", "name": "My board"}"#.to_string())
+                .unwrap();
+        assert_eq!(definition.id, "my_board-v2");
+        for json in [
+            "not json",
+            r#"{"name": "x"}"#,
+            r#"{"id": 3}"#,
+            r#"{"id": "../evil"}"#,
+            r#"{"id": ".hidden"}"#,
+        ] {
+            let err = parse_board_definition(json.to_string()).unwrap_err();
+            assert!(
+                matches!(
+                    err.downcast_ref::<ServiceError>(),
+                    Some(ServiceError::InvalidInput(_))
+                ),
+                "{}",
+                json
+            );
+        }
+    }
+
+    /// Test that a custom board is copied into `boards/` and must match the requested board.
+    #[tokio::test]
+    async fn init_project_installs_custom_board() {
+        let root = TempProjectsRoot::new("pio-board-json", &[]);
+        tokio::fs::write(root.join("custom.json"), r#"{"id": "custom32"}"#)
+            .await
+            .unwrap();
+        let service = PlatformIOService::with_binary("true").with_projects_root(&*root);
+
+        let definition = service.load_board_definition("custom.json").await.unwrap();
+        let err = service
+            .init_project("p", "esp32dev", Some(&definition), &InitOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::InvalidInput(_))
+        ));
+
+        let init = service
+            .init_project("p", "custom32", Some(&definition), &InitOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(init.result.board, "custom32");
+        let copied = tokio::fs::read_to_string(root.join("p/boards/custom32.json"))
+            .await
+            .unwrap();
+        assert_eq!(copied, definition.json);
+        assert!(service.load_board_definition("missing.json").await.is_err());
+    }
+
+    /// Test that a project moves only to a free path under the root.
+    #[tokio::test]
+    async fn move_project_renames_directory() {
+        let root = TempProjectsRoot::new("pio-move", &["old/src", "taken"]);
+        let service = PlatformIOService::new().with_projects_root(&*root);
+
+        for (to, expected) in [
+            ("taken", "conflict"),
+            ("old/inner", "invalid"),
+            ("../escape", "invalid"),
+        ] {
+            let err = service.move_project("old", to).await.unwrap_err();
+            let matched = match err.downcast_ref::<ServiceError>() {
+                Some(ServiceError::Conflict(_)) => "conflict",
+                Some(ServiceError::InvalidInput(_)) => "invalid",
+                _ => "other",
+            };
+            assert_eq!(matched, expected, "{}", to);
+        }
+        let err = service.move_project("missing", "new").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::NotFound(_))
+        ));
+
+        service.move_project("old", "group/new").await.unwrap();
+        assert!(root.join("group/new/src").is_dir());
+        assert!(!root.join("old").exists());
+    }
+
+    /// Test that project size splits `.pio` from the rest and doesn't follow symlinks.
+    #[tokio::test]
+    async fn project_size_skips_symlinks() {
+        let root = TempProjectsRoot::new("pio-size", &["lab/src", "lab/.pio/build"]);
+        tokio::fs::write(root.join("lab/src/main.cpp"), "x".repeat(10))
+            .await
+            .unwrap();
+        tokio::fs::write(root.join("lab/.pio/build/firmware.bin"), "x".repeat(100))
+            .await
+            .unwrap();
+        tokio::fs::write(root.join("big.bin"), "x".repeat(1000))
+            .await
+            .unwrap();
+        std::os::unix::fs::symlink(root.join("big.bin"), root.join("lab/src/big.bin")).unwrap();
+        std::os::unix::fs::symlink(&root, root.join("lab/.pio/root")).unwrap();
+        let service = PlatformIOService::new().with_projects_root(&*root);
+
+        assert_eq!(
+            service.project_size("lab").await.unwrap(),
+            ProjectSize {
+                total_bytes: 110,
+                build_bytes: 100,
+                source_bytes: 10,
+            }
+        );
+        let err = service.project_size("missing").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::NotFound(_))
+        ));
+    }
+
+    /// Test that the template is copied recursively on init, keeping existing project files
+    /// unless overwriting.
+    #[tokio::test]
+    async fn init_project_copies_template() {
+        let root = TempProjectsRoot::new("pio-template", &[]);
+        let template = root.join("template");
+        tokio::fs::create_dir_all(template.join("lib/util"))
+            .await
+            .unwrap();
+        tokio::fs::write(template.join("platformio.ini"), "[env:esp32dev]\n")
+            .await
+            .unwrap();
+        tokio::fs::write(template.join("lib/util/util.h"), "#pragma once\n")
+            .await
+            .unwrap();
+        tokio::fs::create_dir_all(root.join("p")).await.unwrap();
+        tokio::fs::write(root.join("p/platformio.ini"), "; mine\n")
+            .await
+            .unwrap();
+        let service = PlatformIOService::with_binary("true")
+            .with_projects_root(&*root)
+            .with_template_dir(&template);
+
+        let init = service
+            .init_project("p", "esp32dev", None, &InitOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            init.result.template_files,
+            vec!["lib/util/util.h".to_string()]
+        );
+        assert!(root.join("p/lib/util/util.h").is_file());
+        assert_eq!(
+            tokio::fs::read_to_string(root.join("p/platformio.ini"))
+                .await
+                .unwrap(),
+            "; mine\n"
+        );
+
+        let init = service
+            .init_project(
+                "p",
+                "esp32dev",
+                None,
+                &InitOptions {
+                    overwrite: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            init.result.template_files,
+            vec!["lib/util/util.h".to_string(), "platformio.ini".to_string()]
+        );
+        assert_eq!(
+            tokio::fs::read_to_string(root.join("p/platformio.ini"))
+                .await
+                .unwrap(),
+            "[env:esp32dev]\n"
+        );
+    }
+
+    /// Test that init returns early for a project already set up for the board unless forced.
+    #[tokio::test]
+    async fn init_project_is_idempotent() {
+        let root = TempProjectsRoot::new("pio-reinit", &["p"]);
+        tokio::fs::write(
+            root.join("p/platformio.ini"),
+            "[platformio]\ndefault_envs = lab\n\n[env:lab]\nboard = esp32dev\n",
+        )
+        .await
+        .unwrap();
+        let mock = Arc::new(MockPlatformIORunner::new());
+        let service = root.service(mock.clone());
+        let init_calls = || {
+            mock.calls()
+                .iter()
+                .filter(|args| args.starts_with(&["project".to_string(), "init".to_string()]))
+                .count()
+        };
+
+        let init = service
+            .init_project("p", "esp32dev", None, &InitOptions::default())
+            .await
+            .unwrap();
+        assert!(init.result.already_initialized);
+        assert_eq!(init.result.env_name.as_deref(), Some("lab"));
+        assert_eq!(init_calls(), 0);
+
+        let init = service
+            .init_project(
+                "p",
+                "esp32dev",
+                None,
+                &InitOptions {
+                    force: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(!init.result.already_initialized);
+        assert_eq!(init_calls(), 1);
+        let init = service
+            .init_project("p", "nodemcuv2", None, &InitOptions::default())
+            .await
+            .unwrap();
+        assert!(!init.result.already_initialized);
+        assert_eq!(init_calls(), 2);
+    }
+
+    /// Test that captured `project init` output and its platformio.ini are parsed, and that
+    /// unparseable output falls back to the requested board.
+    #[test]
+    fn parse_init_result_from_output() {
+        let output = "\
+The following files/directories have been created in /srv/projects/lab-1
+include - Put project header files here
+lib - Put project specific (private) libraries here
+src - Put project source files (*.h, *.cpp) here
+platformio.ini - Project Configuration File
+Resolving esp32dev dependencies...
+Already up-to-date.
+Project has been successfully initialized!
+";
+        let ini = "\
+; PlatformIO Project Configuration File
+
+[env:esp32dev]
+platform = espressif32
+board = esp32dev
+framework = arduino
+";
+        assert_eq!(
+            parse_init_result(output, Some(ini), "requested"),
+            InitResult {
+                board: "esp32dev".to_string(),
+                env_name: Some("esp32dev".to_string()),
+                created_files: vec![
+                    "include".to_string(),
+                    "lib".to_string(),
+                    "src".to_string(),
+                    "platformio.ini".to_string(),
+                ],
+                template_files: Vec::new(),
+                already_initialized: false,
+            }
+        );
+        assert_eq!(
+            parse_init_result("garbled", None, "esp32dev"),
+            InitResult {
+                board: "esp32dev".to_string(),
+                env_name: None,
+                created_files: Vec::new(),
+                template_files: Vec::new(),
+                already_initialized: false,
+            }
+        );
+    }
+
+    /// Test that a valid ini passes (with inherited and multi-line values) and that missing
+    /// environments, keys and malformed lines are all reported.
+    #[test]
+    fn validate_platformio_ini_reports_problems() {
+        let valid = "\
+; comment
+[env]
+platform = espressif32
+
+[common]
+board = esp32dev
+
+[env:lab]
+extends = common
+build_flags =
+    -DDEBUG
+    -DLAB=1
+";
+        assert!(validate_platformio_ini(valid).is_empty());
+
+        assert_eq!(
+            validate_platformio_ini("[platformio]\ndefault_envs = lab\n"),
+            vec!["no [env:...] section found".to_string()]
+        );
+        assert_eq!(
+            validate_platformio_ini("stray = 1\n[env:lab]\nplatform =\nnonsense\n[env:lab]\n"),
+            vec![
+                "line 1: key outside of any [section]".to_string(),
+                "line 4: expected a [section] header or key = value".to_string(),
+                "line 5: duplicate section [env:lab]".to_string(),
+                "line 2: [env:lab] has no board".to_string(),
+                "line 2: [env:lab] has no platform".to_string(),
+                "line 5: [env:lab] has no board".to_string(),
+                "line 5: [env:lab] has no platform".to_string(),
+            ]
+        );
+    }
+
+    /// Test that both `lib list` JSON layouts are parsed, ignoring surrounding warnings, and
+    /// that a project without libraries yields nothing.
+    #[test]
+    fn parse_library_list_layouts() {
+        let flat = r#"[{"name": "ArduinoJson", "version": "6.21.3", "description": "JSON library", "id": 64}]
+Warning! `pio lib list` is deprecated"#;
+        let arduino_json = LibraryInfo {
+            name: "ArduinoJson".to_string(),
+            version: "6.21.3".to_string(),
+            description: Some("JSON library".to_string()),
+        };
+        assert_eq!(
+            parse_library_list(flat).unwrap(),
+            vec![arduino_json.clone()]
+        );
+
+        let by_storage = r#"{"/p/.pio/libdeps/esp32dev": [{"name": "ArduinoJson", "version": "6.21.3", "description": "JSON library"}], "/p/lib": [{"name": "Local"}]}"#;
+        assert_eq!(
+            parse_library_list(by_storage).unwrap(),
+            vec![
+                arduino_json,
+                LibraryInfo {
+                    name: "Local".to_string(),
+                    version: String::new(),
+                    description: None,
+                },
+            ]
+        );
+
+        assert!(parse_library_list("[]").unwrap().is_empty());
+        assert!(parse_library_list("{}").unwrap().is_empty());
+        assert!(parse_library_list("").unwrap().is_empty());
+        assert!(parse_library_list("[{\"version\": 1}]").is_err());
+    }
+
+    /// Test that the `pkg outdated` table is parsed and an up-to-date project yields nothing.
+    #[test]
+    fn parse_outdated_packages_table() {
+        let output = "\
+Semantic Versioning color legend:
+<Major Update>      backward-incompatible updates
+
+Package                  Current    Wanted    Latest    Type      Environments
+-----------------------  ---------  --------  --------  --------  --------------
+espressif32              5.0.0      5.0.0     6.4.0     Platform  esp32dev
+tool-esptoolpy           1.40201.0  1.40201.0 1.40501.0 Tool      esp32dev
+";
+        assert_eq!(
+            parse_outdated_packages(output),
+            vec![
+                PackageUpdate {
+                    package: "espressif32".to_string(),
+                    current: "5.0.0".to_string(),
+                    latest: "6.4.0".to_string(),
+                },
+                PackageUpdate {
+                    package: "tool-esptoolpy".to_string(),
+                    current: "1.40201.0".to_string(),
+                    latest: "1.40501.0".to_string(),
+                },
+            ]
+        );
+        assert!(parse_outdated_packages("Everything is up-to-date!\n").is_empty());
+    }
+
+    /// Test that the requested board wins over the default and that one of them is required.
+    #[test]
+    fn resolve_board_falls_back_to_default() {
+        let service = PlatformIOService::with_binary("true");
+        assert!(service.resolve_board(None).is_err());
+        assert_eq!(service.resolve_board(Some("esp32dev")).unwrap(), "esp32dev");
+
+        let service = service.with_default_board("esp32-s3-devkitc-1");
+        assert_eq!(service.resolve_board(None).unwrap(), "esp32-s3-devkitc-1");
+        assert_eq!(service.resolve_board(Some("esp32dev")).unwrap(), "esp32dev");
+    }
+
+    /// Test that template names resolve to their sources and unknown names are rejected.
+    #[test]
+    fn basic_main_content_templates() {
+        assert_eq!(basic_main_content(None).unwrap(), BLINK_MAIN);
+        assert_eq!(basic_main_content(Some("blink")).unwrap(), BLINK_MAIN);
+        assert!(basic_main_content(Some("minimal"))
+            .unwrap()
+            .contains("void loop()"));
+        let err = basic_main_content(Some("nope")).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::InvalidInput(_))
+        ));
+    }
+
+    /// Test that scaffolding writes main.cpp and removes a new project directory when a step fails.
+    #[tokio::test]
+    async fn scaffold_project_rolls_back_on_failure() {
+        let root = TempProjectsRoot::new("pio-scaffold", &[]);
+        let dir = root.join("p");
+        let path = dir.to_str().unwrap();
+
+        let service = PlatformIOService::with_binary("true").with_projects_root(&*root);
+        let err = service
+            .scaffold_project(path, "esp32dev", Some("nope"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::InvalidInput(_))
+        ));
+        assert!(!dir.exists());
+
+        let output = service
+            .scaffold_project(path, "esp32dev", Some("minimal"))
+            .await
+            .unwrap();
+        assert!(output.contains("'minimal' template"));
+        let main = tokio::fs::read_to_string(dir.join("src/main.cpp"))
+            .await
+            .unwrap();
+        assert_eq!(main, MINIMAL_MAIN);
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        let failing = PlatformIOService::with_binary("false").with_projects_root(&*root);
+        assert!(failing
+            .scaffold_project(path, "esp32dev", None)
+            .await
+            .is_err());
+        assert!(!dir.exists());
+    }
+
+    /// Test that file paths can't escape src/ and that reads/writes round-trip.
+    #[tokio::test]
+    async fn read_and_write_files() {
+        for bad in ["", "../platformio.ini", "/etc/passwd", "lib/../../x"] {
+            assert!(
+                resolve_source_path(Path::new("/tmp/p"), bad).is_err(),
+                "{}",
+                bad
+            );
+        }
+
+        let root = TempProjectsRoot::new("pio-rw", &[]);
+        let service = PlatformIOService::new().with_projects_root(&*root);
+        let dir = root.join("p");
+        let path = dir.to_str().unwrap();
+
+        let err = service.read_file(path, "main.cpp").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::NotFound(_))
+        ));
+
+        service
+            .write_file(path, "lib/util.h", b"#pragma once")
+            .await
+            .unwrap();
+        assert_eq!(
+            service.read_file(path, "lib/util.h").await.unwrap(),
+            b"#pragma once"
+        );
+
+        let oversized = vec![b'x'; MAX_SOURCE_FILE_BYTES + 1];
+        let err = service
+            .write_file(path, "big.cpp", &oversized)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::TooLarge(_))
+        ));
+    }
+
+    /// Test that create-main writes the requested entry file and rejects other extensions and
+    /// paths outside src/.
+    #[tokio::test]
+    async fn create_basic_main_honors_filename() {
+        let root = TempProjectsRoot::new("pio-main", &[]);
+        let service = PlatformIOService::new().with_projects_root(&*root);
+        let dir = root.join("p");
+        let path = dir.to_str().unwrap();
+
+        service.create_basic_main(path, None, None).await.unwrap();
+        assert!(dir.join("src/main.cpp").is_file());
+        service
+            .create_basic_main(path, Some("minimal"), Some("app/lab.ino"))
+            .await
+            .unwrap();
+        assert!(dir.join("src/app/lab.ino").is_file());
+
+        for bad in ["main.h", "main", "../main.cpp", "/tmp/main.cpp"] {
+            let err = service
+                .create_basic_main(path, None, Some(bad))
+                .await
+                .unwrap_err();
+            assert!(
+                matches!(
+                    err.downcast_ref::<ServiceError>(),
+                    Some(ServiceError::InvalidInput(_))
+                ),
+                "{}",
+                bad
+            );
+        }
+        assert!(!dir.join("main.cpp").exists());
+    }
+
+    /// Test firmware size parsing against captured PlatformIO build output.
+    #[test]
+    fn parse_firmware_size_from_build_output() {
+        let output = r#"Linking .pio/build/esp32dev/firmware.elf
+Retrieving maximum program size .pio/build/esp32dev/firmware.elf
+Checking size .pio/build/esp32dev/firmware.elf
+Advanced Memory Usage is available via "PlatformIO Home > Project Inspect"
+RAM:   [=         ]   6.5% (used 21312 bytes from 327680 bytes)
+Flash: [==        ]  20.1% (used 263621 bytes from 1310720 bytes)
+Building .pio/build/esp32dev/firmware.bin
+esptool.py v4.5.1
+Creating esp32 image...
+Successfully created esp32 image.
+========================= [SUCCESS] Took 12.34 seconds ========================="#;
+        let size = parse_firmware_size(output).unwrap();
+        assert_eq!(
+            size.ram,
+            Some(MemoryUsage {
+                percent: 6.5,
+                used_bytes: 21312,
+                total_bytes: 327680,
+            })
+        );
+        assert_eq!(
+            size.flash,
+            Some(MemoryUsage {
+                percent: 20.1,
+                used_bytes: 263621,
+                total_bytes: 1310720,
+            })
+        );
+        assert_eq!(
+            parse_firmware_size("Compiling .pio/build/esp32dev/src/main.cpp.o"),
+            None
+        );
+    }
+
+    /// Test that the summary line of `platformio test` output is parsed, colored or not.
+    #[test]
+    fn parse_test_summary_from_test_output() {
+        let output = "Testing...\n\
+            test/test_main.cpp:12: test_led_on\t[PASSED]\n\
+            test/test_main.cpp:20: test_led_off: Expected 0 Was 1\t[FAILED]\n\
+            ============ \u{1b}[1m3 test cases: \u{1b}[31m1 failed\u{1b}[0m, 1 skipped, 1 succeeded in 00:00:04.512 ============\n";
+        assert_eq!(
+            parse_test_summary(output),
+            Some(TestSummary {
+                total: 3,
+                succeeded: 1,
+                failed: 1,
+                errored: 0,
+                skipped: 1,
+            })
+        );
+        let passed = parse_test_summary("1 test cases: 1 succeeded in 00:00:01.002").unwrap();
+        assert!(passed.passed() && passed.total == 1);
+        assert_eq!(
+            parse_test_summary("Guru Meditation Error: Core 1 panic'ed (LoadProhibited)"),
+            None
+        );
+    }
+
+    /// Test that unit tests run `platformio test` on the environment and port as a stream.
+    #[tokio::test]
+    async fn test_project_stream_runs_pio_test() {
+        let root = TempProjectsRoot::new("pio-test", &["p"]);
+        tokio::fs::write(root.join("p/platformio.ini"), "[env:esp32dev]\n")
+            .await
+            .unwrap();
+        let mock = Arc::new(MockPlatformIORunner::new().respond(
+            &["test"],
+            RunOutput::ok("1 test cases: 1 succeeded in 00:00:01.002\n"),
+        ));
+        let service = root.service(mock.clone());
+
+        let mut rx = service
+            .test_project_stream("p", Some("/dev/ttyUSB0"), Some("esp32dev"))
+            .await
+            .unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        assert_eq!(
+            events,
+            [
+                StreamEvent::Line("1 test cases: 1 succeeded in 00:00:01.002".to_string()),
+                StreamEvent::Exit { success: true },
+            ]
+        );
+        assert_eq!(
+            mock.calls().last().unwrap(),
+            &[
+                "test",
+                "-e",
+                "esp32dev",
+                "--upload-port",
+                "/dev/ttyUSB0",
+                "--test-port",
+                "/dev/ttyUSB0"
+            ]
+        );
+        assert!(service
+            .test_project_stream("p", None, Some("a b"))
+            .await
+            .is_err());
+    }
+
+    /// Test that a test run is refused while a build or upload of the project is running.
+    #[tokio::test]
+    async fn test_project_stream_takes_project_locks() {
+        let root = TempProjectsRoot::new("pio-test-locks", &["p"]);
+        tokio::fs::write(root.join("p/platformio.ini"), "[env:esp32dev]\n")
+            .await
+            .unwrap();
+        let service = root.service(Arc::new(MockPlatformIORunner::new()));
+
+        let build = service.track_build("p").unwrap();
+        let err = service
+            .test_project_stream("p", None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Conflict(_))
+        ));
+        drop(build);
+
+        let _upload = service.track_upload("p").unwrap();
+        let err = service
+            .test_project_stream("p", None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Conflict(_))
+        ));
+    }
+
+    /// Test diagnostic parsing against captured compiler output with warnings, a multi-line
+    /// error and a fatal error.
+    #[test]
+    fn parse_diagnostics_from_compiler_output() {
+        let output = r#"Compiling .pio/build/esp32dev/src/main.cpp.o
+src/main.cpp: In function 'void setup()':
+src/main.cpp:7:9: warning: unused variable 'unused' [-Wunused-variable]
+    7 |     int unused = 0;
+      |         ^~~~~~
+src/main.cpp:9:5: error: 'Serail' was not declared in this scope; did you mean 'Serial'?
+    9 |     Serail.begin(115200);
+      |     ^~~~~~
+      |     Serial
+src/main.cpp:3:7: note: 'Serial' declared here
+src/sensor.cpp:12: warning: "LED_PIN" redefined
+src/wifi.cpp:1:10: fatal error: WiFiManager.h: No such file or directory
+compilation terminated.
+*** [.pio/build/esp32dev/src/main.cpp.o] Error 1
+========================= [FAILED] Took 3.21 seconds ========================="#;
+        let diagnostics = parse_diagnostics(output);
+        assert_eq!(diagnostics.len(), 4);
+        assert_eq!(
+            diagnostics[0],
+            Diagnostic {
+                file: "src/main.cpp".to_string(),
+                line: 7,
+                column: Some(9),
+                severity: DiagnosticSeverity::Warning,
+                message: "unused variable 'unused' [-Wunused-variable]".to_string(),
+                context: vec![
+                    "    7 |     int unused = 0;".to_string(),
+                    "      |         ^~~~~~".to_string(),
+                ],
+            }
+        );
+        assert_eq!(diagnostics[1].severity, DiagnosticSeverity::Error);
+        assert_eq!((diagnostics[1].line, diagnostics[1].column), (9, Some(5)));
+        assert_eq!(diagnostics[1].context.len(), 4);
+        assert_eq!(
+            diagnostics[1].context[3],
+            "src/main.cpp:3:7: note: 'Serial' declared here"
+        );
+        assert_eq!(
+            (diagnostics[2].file.as_str(), diagnostics[2].line),
+            ("src/sensor.cpp", 12)
+        );
+        assert_eq!(diagnostics[2].column, None);
+        assert_eq!(diagnostics[3].severity, DiagnosticSeverity::Error);
+        assert_eq!(
+            diagnostics[3].message,
+            "WiFiManager.h: No such file or directory"
+        );
+        assert!(diagnostics[3].context.is_empty());
+        assert!(parse_diagnostics("Compiling .pio/build/esp32dev/src/main.cpp.o").is_empty());
+    }
+
+    /// Test that the version number is taken from `--version` output and cached.
+    #[tokio::test]
+    async fn pio_version_is_parsed_and_cached() {
+        assert_eq!(
+            parse_pio_version("PlatformIO Core, version 6.1.15\n"),
+            "6.1.15"
+        );
+        assert_eq!(parse_pio_version("7.0.0"), "7.0.0");
+
+        use std::os::unix::fs::PermissionsExt;
+        let script = std::env::temp_dir().join(format!("fake-pio-{}", uuid::Uuid::new_v4()));
+        tokio::fs::write(
+            &script,
+            "#!/bin/sh\necho 'PlatformIO Core, version 6.1.15'\n",
+        )
+        .await
+        .unwrap();
+        tokio::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))
+            .await
+            .unwrap();
+        let service = PlatformIOService::with_binary(script.to_str().unwrap());
+        assert_eq!(service.pio_version().await.as_deref(), Some("6.1.15"));
+        // Cached: still answered after the binary is gone.
+        tokio::fs::remove_file(&script).await.unwrap();
+        assert_eq!(service.pio_version().await.as_deref(), Some("6.1.15"));
+        let missing = PlatformIOService::with_binary("/nonexistent/platformio");
+        assert_eq!(missing.pio_version().await, None);
+    }
+
+    /// Test that only allowlisted subcommands and actions run, with redirecting options and
+    /// unsafe arguments rejected before PlatformIO is invoked.
+    #[tokio::test]
+    async fn run_subcommand_enforces_allowlist() {
+        let root = TempProjectsRoot::new("pio-subcmd", &["lab"]);
+        let mock = Arc::new(MockPlatformIORunner::new().respond(
+            &["system", "info"],
+            RunOutput::ok("PlatformIO Core 6.1.15\n"),
+        ));
+        let service = root.service(mock.clone());
+        let run = |subcommand: &'static str, args: &'static [&'static str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let service = service.clone();
+            async move { service.run_subcommand("lab", subcommand, &args).await }
+        };
+
+        assert_eq!(
+            run("system", &["info"]).await.unwrap(),
+            "PlatformIO Core 6.1.15\n"
+        );
+        for (subcommand, args, expected) in [
+            ("run", &["--target", "upload"][..], "forbidden"),
+            ("system", &["prune"][..], "forbidden"),
+            ("pkg", &["list", "-d", "/etc"][..], "forbidden"),
+            ("pkg", &["list", "--project-dir=/"][..], "forbidden"),
+            ("pkg", &["list", "-d/"][..], "forbidden"),
+            ("pkg", &["list", "-c/etc/x.ini"][..], "forbidden"),
+            (
+                "pkg",
+                &["list", "--project-conf=/etc/x.ini"][..],
+                "forbidden",
+            ),
+            (
+                "pkg",
+                &["list", "--json-output-path/tmp/x"][..],
+                "forbidden",
+            ),
+            ("boards", &["esp32; rm -rf /"][..], "invalid"),
+            ("boards", &["../../etc"][..], "invalid"),
+        ] {
+            let err = run(subcommand, args).await.unwrap_err();
+            let matched = match err.downcast_ref::<ServiceError>() {
+                Some(ServiceError::Forbidden(_)) => "forbidden",
+                Some(ServiceError::InvalidInput(_)) => "invalid",
+                _ => "other",
+            };
+            assert_eq!(matched, expected, "{} {:?}", subcommand, args);
+        }
+        let calls = mock.calls();
+        assert_eq!(calls.last().unwrap(), &["system", "info"]);
+        assert!(!calls
+            .iter()
+            .any(|c| c[0] != "system" && c[0] != "--version"));
+    }
+
+    /// Test that oversized build output is cut at the default or per-request limit, with a
+    /// marker counting the omitted bytes, and that failures are truncated too.
+    #[tokio::test]
+    async fn build_output_is_truncated() {
+        let root = TempProjectsRoot::new("pio-truncate", &["ok", "bad"]);
+        for project in ["ok", "bad"] {
+            tokio::fs::write(
+                root.join(project).join("platformio.ini"),
+                "[env:esp32dev]\n",
+            )
+            .await
+            .unwrap();
+        }
+        let huge = "x".repeat(DEFAULT_MAX_OUTPUT_BYTES + 100);
+        let mock = MockPlatformIORunner::new().respond(&["run"], RunOutput::ok(huge.clone()));
+        let service = root.service(Arc::new(mock));
+
+        let build = service
+            .build_project("ok", &BuildOptions::default())
+            .await
+            .unwrap();
+        assert!(build.diagnostics.is_empty());
+        assert_eq!(
+            build.output,
+            format!(
+                "{}\n...[output truncated, 100 bytes omitted]",
+                &huge[..DEFAULT_MAX_OUTPUT_BYTES]
+            )
+        );
+        let options = BuildOptions {
+            max_output_bytes: Some(10),
+            ..Default::default()
+        };
+        let build = service.build_project("ok", &options).await.unwrap();
+        assert_eq!(
+            build.output,
+            format!(
+                "xxxxxxxxxx\n...[output truncated, {} bytes omitted]",
+                huge.len() - 10
+            )
+        );
+
+        let failing = MockPlatformIORunner::new().respond(&["run"], RunOutput::failed(huge));
+        let service = service.with_runner(Arc::new(failing));
+        let err = service.build_project("bad", &options).await.unwrap_err();
+        assert!(err.to_string().ends_with("bytes omitted]"), "{}", err);
+        assert!(err.to_string().len() < 100, "{}", err);
+        assert_eq!(
+            truncate_output("é".to_string(), 1),
+            "\n...[output truncated, 2 bytes omitted]"
+        );
+    }
+
+    /// Test that `-v` is only passed to `platformio run` for verbose builds.
+    #[test]
+    fn build_args_verbose_flag() {
+        let args = |dry_run, verbose| {
+            build_args(&BuildOptions {
+                dry_run,
+                verbose,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+        assert_eq!(args(false, false), vec!["run"]);
+        assert_eq!(args(false, true), vec!["run", "-v"]);
+        assert_eq!(args(true, false), vec!["run", "--target", "checkprogsize"]);
+        assert_eq!(
+            args(true, true),
+            vec!["run", "--target", "checkprogsize", "-v"]
+        );
+    }
+
+    /// Test that each upload protocol sets `upload_protocol` and handles the port as documented.
+    #[test]
+    fn upload_args_protocols() {
+        assert_eq!(
+            upload_args(Some("/dev/ttyUSB0"), None, None).unwrap(),
+            vec!["run", "--target", "upload", "--upload-port", "/dev/ttyUSB0"]
+        );
+        assert_eq!(
+            upload_args(Some("192.168.1.50"), Some(UploadProtocol::Espota), None).unwrap()[3..],
+            [
+                "--project-option",
+                "upload_protocol=espota",
+                "--upload-port",
+                "192.168.1.50"
+            ]
+        );
+        assert!(upload_args(None, Some(UploadProtocol::Espota), None).is_err());
+        assert_eq!(
+            upload_args(Some("/dev/ttyUSB0"), Some(UploadProtocol::EspProg), None).unwrap()[3..],
+            ["--project-option", "upload_protocol=esp-prog"]
+        );
+        assert_eq!(
+            UploadProtocol::parse("esptool").unwrap(),
+            UploadProtocol::Esptool
+        );
+        assert!(matches!(
+            UploadProtocol::parse("stlink")
+                .unwrap_err()
+                .downcast_ref::<ServiceError>(),
+            Some(ServiceError::InvalidInput(_))
+        ));
+    }
+
+    /// Test that build flags become one `build_flags` project option and unsafe ones are rejected.
+    #[test]
+    fn build_args_build_flags() {
+        let with_flags = |flags: &[&str]| {
+            build_args(&BuildOptions {
+                build_flags: flags.iter().map(|f| f.to_string()).collect(),
+                ..Default::default()
+            })
+        };
+        assert_eq!(with_flags(&[]).unwrap(), vec!["run"]);
+        assert_eq!(
+            with_flags(&["-DDEBUG=1"]).unwrap(),
+            vec!["run", "--project-option", "build_flags=-DDEBUG=1"]
+        );
+        assert_eq!(
+            with_flags(&["-DDEBUG=1", "-Os"]).unwrap()[2],
+            "build_flags=-DDEBUG=1 -Os"
+        );
+        for flag in [
+            "-DA=1 -DB=2",
+            "-DX=$(reboot)",
+            "-DS=\"x\"",
+            "DEBUG",
+            "-D;ls",
+            "-",
+        ] {
+            let err = with_flags(&[flag]).unwrap_err();
+            assert!(
+                matches!(
+                    err.downcast_ref::<ServiceError>(),
+                    Some(ServiceError::InvalidInput(_))
+                ),
+                "{}",
+                flag
+            );
+        }
+    }
+
+    /// Test esptool progress parsing, including lines that carry no percentage.
+    #[test]
+    fn parse_upload_progress_lines() {
+        assert_eq!(
+            parse_upload_progress("Writing at 0x00010000... (23 %)"),
+            Some(23)
+        );
+        assert_eq!(
+            parse_upload_progress("Writing at 0x0004d2a1... (100 %)"),
+            Some(100)
+        );
+        assert_eq!(parse_upload_progress("Hash of data verified."), None);
+        assert_eq!(parse_upload_progress("Writing at 0x00010000..."), None);
+    }
+
+    /// Test that build logs keep only the newest lines, at least one, and are replaced by
+    /// each build.
+    #[test]
+    fn build_log_ring_buffer() {
+        let service = PlatformIOService::new().with_build_log_lines(3);
+        assert!(service.build_log("/tmp/p", 10).is_none());
+
+        service.record_build_log("/tmp/p", "a\nb\nc\nd\ne");
+        assert_eq!(
+            service.build_log("/tmp/p", 10).unwrap(),
+            vec!["c", "d", "e"]
+        );
+        assert_eq!(service.build_log("/tmp/p", 1).unwrap(), vec!["e"]);
+
+        service.record_build_log("/tmp/p", "x");
+        assert_eq!(service.build_log("/tmp/p", 10).unwrap(), vec!["x"]);
+
+        let service = PlatformIOService::new().with_build_log_lines(0);
+        service.record_build_log("/tmp/p", "a\nb");
+        assert_eq!(service.build_log("/tmp/p", 10).unwrap(), vec!["b"]);
+    }
+
+    /// Test that cancelling signals the tracked build and that the guard cleans up its entry.
+    #[test]
+    fn cancel_build_tracking() {
+        let service = PlatformIOService::new();
+        assert!(!service.cancel_build("/tmp/project"));
+
+        let (guard, mut cancel) = service.track_build("/tmp/project").unwrap();
+        assert!(service.track_build("/tmp/project").is_err());
+        assert!(service.cancel_build("/tmp/project"));
+        assert!(cancel.try_recv().is_ok());
+        drop(guard);
+
+        let (guard, _cancel) = service.track_build("/tmp/project").unwrap();
+        drop(guard);
+        assert!(!service.cancel_build("/tmp/project"));
+    }
+
+    /// Test that waiting returns idle without a build, times out while one runs, and otherwise
+    /// yields the result it finished with.
+    #[tokio::test]
+    async fn wait_for_build_returns_result() {
+        let service = PlatformIOService::new();
+        let timeout = Duration::from_millis(50);
+        assert!(matches!(
+            service.wait_for_build("/tmp/project", timeout).await,
+            BuildWait::Idle
+        ));
+
+        let (guard, _cancel) = service.track_build("/tmp/project").unwrap();
+        assert!(matches!(
+            service.wait_for_build("/tmp/project", timeout).await,
+            BuildWait::TimedOut
+        ));
+        let waiter = {
+            let service = service.clone();
+            tokio::spawn(async move {
+                service
+                    .wait_for_build("/tmp/project", Duration::from_secs(5))
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        guard.finish_build(&Err(anyhow!("compile error")));
+        drop(guard);
+        match waiter.await.unwrap() {
+            BuildWait::Finished(Err(e)) => assert_eq!(e, "compile error"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // A lock released without a build result leaves nothing to report.
+        let (guard, _cancel) = service.track_build("/tmp/project").unwrap();
+        let waiter = {
+            let service = service.clone();
+            tokio::spawn(async move {
+                service
+                    .wait_for_build("/tmp/project", Duration::from_secs(5))
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(guard);
+        assert!(matches!(waiter.await.unwrap(), BuildWait::Idle));
+    }
+
+    /// Test that builds and uploads are limited separately, so a busy build slot never holds up
+    /// an upload.
+    #[tokio::test]
+    async fn build_and_upload_slots_are_separate() {
+        let service = PlatformIOService::new()
+            .with_max_concurrent_builds(2)
+            .with_max_concurrent_uploads(1);
+        let builds = [
+            service.acquire_build_slot().await.unwrap(),
+            service.acquire_build_slot().await.unwrap(),
+        ];
+        let upload = tokio::time::timeout(Duration::from_secs(1), service.acquire_upload_slot())
+            .await
+            .expect("upload waited on the build slots")
+            .unwrap();
+
+        let waiting =
+            tokio::time::timeout(Duration::from_millis(50), service.acquire_upload_slot());
+        assert!(waiting.await.is_err());
+        assert_eq!(service.queued_operations(), 0);
+        drop(upload);
+        let _upload = service.acquire_upload_slot().await.unwrap();
+        drop(builds);
+        let _build = service.acquire_build_slot().await.unwrap();
+    }
+
+    /// Test that builds beyond the concurrency limit queue instead of failing, and that no more
+    /// than the permitted number of PlatformIO processes ever run at once.
+    #[tokio::test]
+    async fn process_slots_limit_concurrency() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = TempProjectsRoot::new("pio-slots", &[]);
+        let events = root.join("events.log");
+        let script = root.join("fake-pio");
+        tokio::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\n[ \"$1\" = --version ] && exit 0\n\
+                 echo start >> {log}\nsleep 0.2\necho end >> {log}\n",
+                log = events.display()
+            ),
+        )
+        .await
+        .unwrap();
+        tokio::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))
+            .await
+            .unwrap();
+
+        let service = PlatformIOService::with_binary(script.to_str().unwrap())
+            .with_projects_root(&*root)
+            .with_max_concurrent(2);
+        let mut builds = Vec::new();
+        for i in 0..5 {
+            let project = format!("p{}", i);
+            tokio::fs::create_dir_all(root.join(&project))
+                .await
+                .unwrap();
+            tokio::fs::write(
+                root.join(&project).join("platformio.ini"),
+                "[env:esp32dev]\n",
+            )
+            .await
+            .unwrap();
+            let service = service.clone();
+            builds.push(tokio::spawn(async move {
+                service
+                    .build_project(&project, &BuildOptions::default())
+                    .await
+            }));
+        }
+        for build in builds {
+            assert!(build.await.unwrap().is_ok());
+        }
+        assert_eq!(service.queued_operations(), 0);
+
+        let log = tokio::fs::read_to_string(&events).await.unwrap();
+        let (mut running, mut peak) = (0, 0);
+        for event in log.lines() {
+            running += if event == "start" { 1 } else { -1 };
+            peak = peak.max(running);
+        }
+        assert_eq!(log.lines().count(), 10);
+        assert!(peak <= 2, "peak concurrency {}", peak);
+    }
+
+    /// Test that the firmware is found by environment, and that ambiguity or absence is reported.
+    #[tokio::test]
+    async fn find_firmware_picks_environment() {
+        let root = TempProjectsRoot::new("pio-firmware", &[]);
+        let service = PlatformIOService::new().with_projects_root(&*root);
+        let not_found = service.find_firmware("p", None).await.unwrap_err();
+        assert!(matches!(
+            not_found.downcast_ref::<ServiceError>(),
+            Some(ServiceError::NotFound(_))
+        ));
+
+        for env in ["esp32dev", "esp32s3"] {
+            let dir = root.join("p/.pio/build").join(env);
+            tokio::fs::create_dir_all(&dir).await.unwrap();
+            tokio::fs::write(dir.join("firmware.bin"), env)
+                .await
+                .unwrap();
+        }
+        tokio::fs::create_dir_all(root.join("p/.pio/build/native"))
+            .await
+            .unwrap();
+        let ambiguous = service.find_firmware("p", None).await.unwrap_err();
+        assert!(ambiguous.to_string().contains("esp32dev, esp32s3"));
+        let (env, path) = service.find_firmware("p", Some("esp32s3")).await.unwrap();
+        assert_eq!(env, "esp32s3");
+        assert!(path.ends_with(".pio/build/esp32s3/firmware.bin"));
+        assert!(service.find_firmware("p", Some("native")).await.is_err());
+        assert!(service.find_firmware("p", Some("../p")).await.is_err());
+
+        tokio::fs::remove_dir_all(root.join("p/.pio/build/esp32s3"))
+            .await
+            .unwrap();
+        let (env, _) = service.find_firmware("p", None).await.unwrap();
+        assert_eq!(env, "esp32dev");
+    }
+
+    /// Test that purging removes `.pio`, reports its size, and is a no-op when it's absent.
+    #[tokio::test]
+    async fn purge_build_dir_removes_pio() {
+        let root = TempProjectsRoot::new("pio-purge", &[]);
+        let build = root.join("p/.pio/build/esp32dev");
+        tokio::fs::create_dir_all(&build).await.unwrap();
+        tokio::fs::write(build.join("firmware.bin"), vec![0u8; 1000])
+            .await
+            .unwrap();
+        tokio::fs::write(root.join("p/.pio/libdeps.txt"), "abc")
+            .await
+            .unwrap();
+        tokio::fs::write(root.join("p/platformio.ini"), "[env:esp32dev]\n")
+            .await
+            .unwrap();
+
+        let service = PlatformIOService::new().with_projects_root(&*root);
+        let upload = service.track_upload("p").unwrap();
+        let err = service.purge_build_dir("p").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Conflict(_))
+        ));
+        drop(upload);
+        assert_eq!(service.purge_build_dir("p").await.unwrap(), 1003);
+        assert!(!root.join("p/.pio").exists());
+        assert!(root.join("p/platformio.ini").exists());
+        assert_eq!(service.purge_build_dir("p").await.unwrap(), 0);
+    }
+
+    /// Test that build env vars reach PlatformIO but their values are masked in the output.
+    #[tokio::test]
+    async fn build_env_vars_are_set_and_masked() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = TempProjectsRoot::new("pio-env", &["p"]);
+        tokio::fs::write(root.join("p/platformio.ini"), "[env:esp32dev]\n")
+            .await
+            .unwrap();
+        let script = root.join("fake-pio");
+        tokio::fs::write(&script, "#!/bin/sh\necho \"ssid=$WIFI_SSID pin=$PIN\"\n")
+            .await
+            .unwrap();
+        tokio::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))
+            .await
+            .unwrap();
+
+        let service =
+            PlatformIOService::with_binary(script.to_str().unwrap()).with_projects_root(&*root);
+        let options = BuildOptions {
+            env_vars: HashMap::from([
+                ("WIFI_SSID".to_string(), "lab-secret".to_string()),
+                ("PIN".to_string(), "42".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let build = service.build_project("p", &options).await.unwrap();
+        assert!(build.output.contains("ssid=*** pin=42"), "{}", build.output);
+        assert_eq!(
+            service.build_log("p", 10).unwrap(),
+            vec!["ssid=*** pin=42".to_string()]
+        );
+
+        let invalid = BuildOptions {
+            env_vars: HashMap::from([("BAD-NAME".to_string(), "x".to_string())]),
+            ..Default::default()
+        };
+        assert!(service.build_project("p", &invalid).await.is_err());
+    }
+
+    /// Test that `system info` JSON is parsed and the answer is served from the cache.
+    #[tokio::test]
+    async fn system_info_is_parsed_and_cached() {
+        let json = r#"{"core_version": {"title": "PlatformIO Core", "value": "6.1.15"}, "python_version": {"title": "Python", "value": "3.11.4-final.0"}, "system": {"title": "System Type", "value": "linux_x86_64"}, "platform": {"title": "Platform", "value": "Linux-6.2.0-x86_64-with-glibc2.35"}, "core_dir": {"title": "PlatformIO Core Directory", "value": "/home/lab/.platformio"}, "platformio_exe": {"title": "PlatformIO Core Executable", "value": "/home/lab/.platformio/penv/bin/platformio"}, "global_lib_nums": {"title": "Global Libraries", "value": 0}}"#;
+        let mock =
+            Arc::new(MockPlatformIORunner::new().respond(&["system", "info"], RunOutput::ok(json)));
+        let service = PlatformIOService::new()
+            .with_projects_root(std::env::temp_dir())
+            .with_runner(mock.clone());
+
+        let info = service.system_info().await.unwrap();
+        assert_eq!(info.core_version.as_deref(), Some("6.1.15"));
+        assert_eq!(info.python_version.as_deref(), Some("3.11.4-final.0"));
+        assert_eq!(info.system.as_deref(), Some("linux_x86_64"));
+        assert_eq!(info.core_dir.as_deref(), Some("/home/lab/.platformio"));
+        assert_eq!(info.python_exe, None);
+        assert_eq!(service.system_info().await.unwrap(), info);
+        let fetches = mock.calls().iter().filter(|c| c[0] == "system").count();
+        assert_eq!(fetches, 1);
+
+        assert!(parse_system_info("Error: no such command").is_err());
+    }
+
+    /// Test that colored build output is returned as plain text unless `keep_ansi` is set.
+    #[tokio::test]
+    async fn build_output_strips_ansi_codes() {
+        let root = TempProjectsRoot::new("pio-ansi", &["p"]);
+        tokio::fs::write(root.join("p/platformio.ini"), "[env:esp32dev]\n")
+            .await
+            .unwrap();
+        let colored = "\x1b[1;33msrc/main.cpp:3:5: warning: unused variable 'x'\x1b[0m\n\
+                       \x1b]8;;https://docs.platformio.org\x07docs\x1b]8;;\x1b\\\n\
+                       \x1b[32m[SUCCESS]\x1b[0m Took 1.00 seconds\n";
+        let mock = MockPlatformIORunner::new().respond(&["run"], RunOutput::ok(colored));
+        let service = root.service(Arc::new(mock));
+
+        let build = service
+            .build_project("p", &BuildOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            build.output,
+            "src/main.cpp:3:5: warning: unused variable 'x'\ndocs\n[SUCCESS] Took 1.00 seconds\n"
+        );
+        assert_eq!(build.diagnostics.len(), 1);
+
+        let raw = BuildOptions {
+            keep_ansi: true,
+            ..Default::default()
+        };
+        let build = service.build_project("p", &raw).await.unwrap();
+        assert_eq!(build.output, colored);
+        assert_eq!(build.diagnostics[0].message, "unused variable 'x'");
+        assert_eq!(strip_ansi("plain \x1b(Btext\x1bc"), "plain text");
+    }
+
+    /// Test that a second upload of a project is rejected while the first is running.
+    #[tokio::test]
+    async fn concurrent_uploads_of_a_project_conflict() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = TempProjectsRoot::new("pio-upload", &["p"]);
+        tokio::fs::write(root.join("p/platformio.ini"), "[env:esp32dev]\n")
+            .await
+            .unwrap();
+        let script = root.join("fake-pio");
+        tokio::fs::write(&script, "#!/bin/sh\nsleep 0.3\n")
+            .await
+            .unwrap();
+        tokio::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))
+            .await
+            .unwrap();
+
+        let service =
+            PlatformIOService::with_binary(script.to_str().unwrap()).with_projects_root(&*root);
+        let first = {
+            let service = service.clone();
+            tokio::spawn(async move { service.upload_firmware("p", None, None, None).await })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let err = service
+            .upload_firmware("p", None, None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Conflict(_))
+        ));
+        assert!(first.await.unwrap().is_ok());
+        assert!(service.upload_firmware("p", None, None, None).await.is_ok());
+    }
+
+    /// Test that filesystem uploads target `uploadfs` on the port and take the upload lock.
+    #[tokio::test]
+    async fn upload_fs_runs_uploadfs_target() {
+        let root = TempProjectsRoot::new("pio-uploadfs", &["p"]);
+        tokio::fs::write(root.join("p/platformio.ini"), "[env:esp32dev]\n")
+            .await
+            .unwrap();
+        let mock = Arc::new(MockPlatformIORunner::new().respond(
+            &["run", "--target", "uploadfs"],
+            RunOutput::ok("Building SPIFFS image\n"),
+        ));
+        let service = root.service(mock.clone());
+
+        let output = service.upload_fs("p", Some("/dev/ttyUSB0")).await.unwrap();
+        assert_eq!(output, b"Building SPIFFS image\n");
+        assert_eq!(
+            mock.calls().last().unwrap(),
+            &[
+                "run",
+                "--target",
+                "uploadfs",
+                "--upload-port",
+                "/dev/ttyUSB0"
+            ]
+        );
+
+        let _upload = service.track_upload("p").unwrap();
+        let err = service.upload_fs("p", None).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::Conflict(_))
+        ));
+    }
+
+    /// Test that flashing output keeps bytes that aren't valid UTF-8.
+    #[tokio::test]
+    async fn upload_output_is_raw_bytes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = TempProjectsRoot::new("pio-raw", &["p"]);
+        tokio::fs::write(root.join("p/platformio.ini"), "[env:esp32dev]\n")
+            .await
+            .unwrap();
+        let script = root.join("fake-pio");
+        tokio::fs::write(
+            &script,
+            "#!/bin/sh\nprintf 'ok \\377\\376'\nprintf 'done' >&2\n",
+        )
+        .await
+        .unwrap();
+        tokio::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))
+            .await
+            .unwrap();
+
+        let service =
+            PlatformIOService::with_binary(script.to_str().unwrap()).with_projects_root(&*root);
+        assert_eq!(
+            service
+                .upload_firmware("p", None, None, None)
+                .await
+                .unwrap(),
+            b"ok \xff\xfedone"
+        );
+        assert_eq!(
+            service.erase_flash("p", None).await.unwrap(),
+            b"ok \xff\xfedone"
+        );
+    }
+
+    /// Test that a failed flash reports its output truncated to `max_output_bytes`.
+    #[tokio::test]
+    async fn raw_command_failure_is_truncated() {
+        let root = TempProjectsRoot::new("pio-raw-fail", &["p"]);
+        tokio::fs::write(root.join("p/platformio.ini"), "[env:esp32dev]\n")
+            .await
+            .unwrap();
+        let huge = "x".repeat(1000);
+        let mock = MockPlatformIORunner::new().respond(&["run"], RunOutput::failed(huge));
+        let service = root.service(Arc::new(mock)).with_max_output_bytes(10);
+
+        let err = service.erase_flash("p", None).await.unwrap_err();
+        assert!(err.to_string().ends_with("bytes omitted]"), "{}", err);
+        assert!(err.to_string().len() < 100, "{}", err);
+    }
+
+    /// Test that a failed flash's error message has ANSI escape codes removed.
+    #[tokio::test]
+    async fn raw_command_failure_strips_ansi() {
+        let root = TempProjectsRoot::new("pio-raw-ansi", &["p"]);
+        tokio::fs::write(root.join("p/platformio.ini"), "[env:esp32dev]\n")
+            .await
+            .unwrap();
+        let mock = MockPlatformIORunner::new().respond(
+            &["run"],
+            RunOutput::failed("\x1b[31mFailed to connect to ESP32\x1b[0m\n"),
+        );
+        let service = root.service(Arc::new(mock));
+
+        let err = service.erase_flash("p", None).await.unwrap_err();
+        assert!(!err.to_string().contains('\x1b'), "{:?}", err.to_string());
+        assert!(
+            err.to_string().contains("Failed to connect to ESP32"),
+            "{}",
+            err
+        );
+    }
+
+    /// Test that a binary partition table decodes and round-trips through its CSV form.
+    #[test]
+    fn partition_table_decodes_and_parses() {
+        let entry = |kind: u8, subtype: u8, offset: u32, size: u32, name: &str| {
+            let mut bytes = vec![0xAA, 0x50, kind, subtype];
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            bytes.extend_from_slice(&size.to_le_bytes());
+            let mut label = [0u8; 16];
+            label[..name.len()].copy_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&label);
+            bytes.extend_from_slice(&[0; 4]);
+            bytes
+        };
+        let mut table = entry(0x01, 0x02, 0x9000, 0x5000, "nvs");
+        table.extend(entry(0x00, 0x10, 0x10000, 0x140000, "app0"));
+        table.extend(entry(0x01, 0x82, 0x290000, 0x160000, "spiffs"));
+        table.extend([0xEB, 0xEB].iter().chain([0xFF; 30].iter()));
+        table.extend([0xFF; 64]);
+
+        let entries = decode_partition_table(&table).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[1],
+            PartitionEntry {
+                name: "app0".to_string(),
+                kind: "app".to_string(),
+                subtype: "ota_0".to_string(),
+                offset: 0x10000,
+                size: 0x140000,
+            }
+        );
+        let csv = format_partition_csv(&entries);
+        assert!(csv.contains("nvs,data,nvs,0x9000,0x5000\n"), "{}", csv);
+        assert_eq!(parse_partition_csv(&csv).unwrap(), entries);
+        assert!(decode_partition_table(&[0xFF; 64]).is_none());
+
+        let project_csv = "# Name, Type, SubType, Offset, Size, Flags\nnvs, data, nvs, 36864, 20K,\nfactory, app, factory, 0x10000, 1M,\n";
+        let parsed = parse_partition_csv(project_csv).unwrap();
+        assert_eq!((parsed[0].offset, parsed[0].size), (0x9000, 0x5000));
+        assert_eq!(parsed[1].size, 0x100000);
+        assert!(parse_partition_csv("A fatal error occurred: Failed to connect").is_none());
+    }
+
+    /// Test that resetting through a port that doesn't exist is reported as invalid input.
+    #[tokio::test]
+    async fn reset_device_requires_openable_port() {
+        let service = PlatformIOService::new();
+        let err = service
+            .reset_device("/dev/does-not-exist")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ServiceError>(),
+            Some(ServiceError::InvalidInput(_))
+        ));
     }
 }
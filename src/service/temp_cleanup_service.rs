@@ -0,0 +1,137 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use tokio::task::JoinHandle;
+
+/// Subdirectory of the system temp dir used when `UPLOAD_TEMP_DIR` is unset.
+const DEFAULT_DIR_NAME: &str = "iot-remote-lab-uploads";
+
+/// Age after which a temp upload file is removed when `UPLOAD_TEMP_RETENTION_SECS` is unset.
+const DEFAULT_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// Time between cleanup cycles.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Service removing temp upload files that outlived their request, e.g. because the client
+/// disconnected mid-upload. Uploads write into `dir`; anything there older than `retention`
+/// is considered orphaned.
+#[derive(Debug, Clone)]
+pub struct TempCleanupService {
+    dir: PathBuf,
+    retention: Duration,
+}
+
+impl TempCleanupService {
+    /// Constructor cleaning `dir` of files older than `retention`.
+    pub fn new(dir: impl Into<PathBuf>, retention: Duration) -> Self {
+        Self {
+            dir: dir.into(),
+            retention,
+        }
+    }
+
+    /// Constructor using `UPLOAD_TEMP_DIR` (default `iot-remote-lab-uploads` in the system temp
+    /// dir) and `UPLOAD_TEMP_RETENTION_SECS` (default 3600).
+    pub fn from_env() -> Self {
+        let dir = std::env::var("UPLOAD_TEMP_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| std::env::temp_dir().join(DEFAULT_DIR_NAME));
+        let retention = std::env::var("UPLOAD_TEMP_RETENTION_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_RETENTION);
+        Self::new(dir, retention)
+    }
+
+    /// Directory temp upload files are written to and cleaned from.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// How old a temp upload file must be before it is removed.
+    pub fn retention(&self) -> Duration {
+        self.retention
+    }
+
+    /// Removes the regular files directly in `dir` last modified more than `retention` ago,
+    /// returning how many were removed. Subdirectories and symlinks are left alone; a missing
+    /// `dir` means nothing to clean.
+    pub async fn sweep(&self) -> Result<usize> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(anyhow!("Failed to read {}: {}", self.dir.display(), e)),
+        };
+        let now = SystemTime::now();
+        let mut removed = 0;
+        while let Some(entry) = entries.next_entry().await? {
+            let Ok(meta) = entry.metadata().await else {
+                continue;
+            };
+            let expired = meta
+                .modified()
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .is_some_and(|age| age > self.retention);
+            if meta.is_file() && expired && tokio::fs::remove_file(entry.path()).await.is_ok() {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Spawns a task running `sweep` once a minute, logging how many files each cycle removed.
+    pub fn spawn(self: &Arc<Self>) -> JoinHandle<()> {
+        let service = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                match service.sweep().await {
+                    Ok(removed) => tracing::info!(
+                        removed,
+                        dir = %service.dir.display(),
+                        "Cleaned up orphaned temp upload files"
+                    ),
+                    Err(e) => tracing::warn!(error = %e, "Temp upload cleanup failed"),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that only regular files past the retention are removed.
+    #[tokio::test]
+    async fn sweep_removes_expired_files() {
+        let dir = std::env::temp_dir().join(format!("temp-cleanup-{}", uuid::Uuid::new_v4()));
+        let service = TempCleanupService::new(&dir, Duration::from_secs(60));
+        assert_eq!(service.sweep().await.unwrap(), 0);
+
+        tokio::fs::create_dir_all(dir.join("nested")).await.unwrap();
+        for name in ["old.bin", "fresh.bin", "nested/old.bin"] {
+            tokio::fs::write(dir.join(name), "firmware").await.unwrap();
+        }
+        let hour_ago = SystemTime::now() - Duration::from_secs(60 * 60);
+        for name in ["old.bin", "nested/old.bin"] {
+            std::fs::File::options()
+                .write(true)
+                .open(dir.join(name))
+                .unwrap()
+                .set_modified(hour_ago)
+                .unwrap();
+        }
+
+        assert_eq!(service.sweep().await.unwrap(), 1);
+        assert!(!dir.join("old.bin").exists());
+        assert!(dir.join("fresh.bin").exists());
+        assert!(dir.join("nested/old.bin").exists());
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
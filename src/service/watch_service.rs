@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::path::{Component, Path};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::service::{BuildOptions, BuildOutput, PlatformIOService, ServiceError};
+
+/// Quiet period after the last filesystem event before a rebuild is triggered.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Outcome of the most recent watcher-triggered build: the build output, or the error message.
+pub type LatestBuild = Result<BuildOutput, String>;
+
+/// A running watcher; dropping it stops filesystem notifications and the rebuild task.
+struct WatchHandle {
+    _watcher: RecommendedWatcher,
+    task: JoinHandle<()>,
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Service that rebuilds a device's project whenever its source files change.
+/// At most one watcher runs per device; results of watcher builds are kept in memory.
+#[derive(Clone)]
+pub struct WatchService {
+    pio_service: Arc<PlatformIOService>,
+    watchers: Arc<Mutex<HashMap<Uuid, WatchHandle>>>,
+    latest_builds: Arc<Mutex<HashMap<Uuid, LatestBuild>>>,
+}
+
+impl WatchService {
+    /// Constructor for WatchService, injecting the PlatformIO service used for rebuilds.
+    pub fn new(pio_service: Arc<PlatformIOService>) -> Self {
+        Self {
+            pio_service,
+            watchers: Arc::default(),
+            latest_builds: Arc::default(),
+        }
+    }
+
+    /// Starts watching `project_path` for the device. Changes are debounced by 300ms and then
+    /// trigger `build_project` with `options`. Fails with `ServiceError::Conflict` if the
+    /// device is already watched.
+    pub async fn start(
+        &self,
+        device_id: Uuid,
+        project_path: &str,
+        options: BuildOptions,
+    ) -> Result<()> {
+        let project_dir = self.pio_service.resolve_project_path(project_path).await?;
+        let mut watchers = self.watchers.lock().unwrap();
+        if watchers.contains_key(&device_id) {
+            return Err(
+                ServiceError::Conflict("Device is already being watched".to_string()).into(),
+            );
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let root = project_dir.clone();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+            if let Ok(event) = res {
+                if event.paths.iter().any(|p| is_watched_path(&root, p)) {
+                    let _ = tx.send(());
+                }
+            }
+        })
+        .map_err(|e| anyhow!("Failed to create file watcher: {}", e))?;
+        watcher
+            .watch(&project_dir, RecursiveMode::Recursive)
+            .map_err(|e| anyhow!("Failed to watch {}: {}", project_path, e))?;
+
+        let task = tokio::spawn(rebuild_loop(
+            rx,
+            self.pio_service.clone(),
+            self.latest_builds.clone(),
+            device_id,
+            project_path.to_string(),
+            options,
+        ));
+        watchers.insert(
+            device_id,
+            WatchHandle {
+                _watcher: watcher,
+                task,
+            },
+        );
+        Ok(())
+    }
+
+    /// Stops the device's watcher. Returns whether a watcher was running.
+    pub fn stop(&self, device_id: Uuid) -> bool {
+        self.watchers.lock().unwrap().remove(&device_id).is_some()
+    }
+
+    /// Stops every watcher; called on server shutdown.
+    pub fn stop_all(&self) {
+        self.watchers.lock().unwrap().clear();
+    }
+
+    /// Returns the result of the most recent watcher-triggered build for the device, if any.
+    pub fn latest_build(&self, device_id: Uuid) -> Option<LatestBuild> {
+        self.latest_builds.lock().unwrap().get(&device_id).cloned()
+    }
+}
+
+/// Waits for change notifications, lets them settle for `DEBOUNCE`, then rebuilds and records the result.
+async fn rebuild_loop(
+    mut rx: mpsc::UnboundedReceiver<()>,
+    pio_service: Arc<PlatformIOService>,
+    latest_builds: Arc<Mutex<HashMap<Uuid, LatestBuild>>>,
+    device_id: Uuid,
+    project_path: String,
+    options: BuildOptions,
+) {
+    while rx.recv().await.is_some() {
+        while let Ok(Some(())) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {}
+
+        let result = pio_service
+            .build_project(&project_path, &options)
+            .await
+            .map_err(|e| e.to_string());
+        latest_builds.lock().unwrap().insert(device_id, result);
+    }
+}
+
+/// Ignores build output under `.pio` and hidden files so a rebuild can't retrigger itself.
+fn is_watched_path(root: &Path, path: &Path) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    !relative.components().any(|c| match c {
+        Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that only one watcher runs per device and that a change triggers a recorded build.
+    #[tokio::test]
+    async fn watch_triggers_build() {
+        let pio_service = PlatformIOService::new().with_projects_root(std::env::temp_dir());
+        let service = WatchService::new(Arc::new(pio_service));
+        let dir = std::env::temp_dir().join(format!("pio-watch-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(dir.join("src")).await.unwrap();
+        let path = dir.to_str().unwrap();
+        let device_id = Uuid::new_v4();
+
+        service
+            .start(device_id, path, BuildOptions::default())
+            .await
+            .unwrap();
+        assert!(service
+            .start(device_id, path, BuildOptions::default())
+            .await
+            .is_err());
+        assert!(service.latest_build(device_id).is_none());
+
+        tokio::fs::write(dir.join("src/main.cpp"), "void setup() {}")
+            .await
+            .unwrap();
+        let mut latest = None;
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            latest = service.latest_build(device_id);
+            if latest.is_some() {
+                break;
+            }
+        }
+        // No platformio.ini, so the triggered build fails its project check.
+        assert!(matches!(latest, Some(Err(_))));
+
+        assert!(service.stop(device_id));
+        assert!(!service.stop(device_id));
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}